@@ -1,7 +1,10 @@
 use std::io::Write;
 
 use brotlic::{BrotliEncoderOptions, CompressorWriter, Quality, WindowSize};
-use haproxy_api::{Core, FilterMethod, FilterResult, Headers, HttpMessage, Txn, UserFilter};
+use haproxy_api::{
+    mime::MimeMatcher, negotiate, Core, FilterMethod, FilterResult, Headers, HttpMessage, Txn,
+    UserFilter,
+};
 use mlua::prelude::*;
 
 #[derive(Default)]
@@ -16,7 +19,7 @@ struct BrotliFilterOptions {
     quality: u8,
     window: u8,
     offload: bool,
-    content_types: Vec<String>,
+    content_types: MimeMatcher,
 }
 
 impl LuaUserData for BrotliFilterOptions {}
@@ -27,7 +30,7 @@ impl Default for BrotliFilterOptions {
             quality: 5,
             window: 18,
             offload: false,
-            content_types: Vec::new(),
+            content_types: MimeMatcher::new(),
         }
     }
 }
@@ -62,21 +65,13 @@ impl BrotliFilter {
             .any(|v| v.contains("no-transform"));
         // Check content type
         if !skip_encoding {
-            let content_type = headers
-                .get_first::<String>("content-type")?
-                .unwrap_or_default()
-                .to_ascii_lowercase();
-            skip_encoding = content_type.is_empty() || content_type.starts_with("multipart");
-            if !skip_encoding {
-                let mut found = self.options.content_types.is_empty();
-                for prefix in &self.options.content_types {
-                    if content_type.starts_with(prefix) {
-                        found = true;
-                        break;
-                    }
+            skip_encoding = match headers.content_type()? {
+                Some(mime) if !mime.is_multipart() => {
+                    let types = &self.options.content_types;
+                    !(types.is_empty() || types.matches(&mime))
                 }
-                skip_encoding = !found;
-            }
+                _ => true,
+            };
         }
         if skip_encoding {
             return Ok(());
@@ -117,30 +112,7 @@ impl BrotliFilter {
 
     fn prefer_brotli_encoding(headers: Headers) -> LuaResult<bool> {
         let accept_encoding = headers.get::<String>("accept-encoding")?;
-        let vals = accept_encoding
-            .iter()
-            .flat_map(|v| v.split(',').map(str::trim))
-            .filter_map(|v| {
-                let (enc, qval) = match v.split_once(";q=") {
-                    Some((e, q)) => (e, q),
-                    None => return Some((v, 1.0f32)),
-                };
-                let qval = match qval.parse::<f32>() {
-                    Ok(f) if f <= 1.0 => f, // q-values over 1 are unacceptable,
-                    _ => return None,
-                };
-                Some((enc, qval))
-            });
-
-        let (mut preferred_encoding, mut max_qval) = ("", 0.);
-        for (enc, qval) in vals {
-            if qval > max_qval {
-                (preferred_encoding, max_qval) = (enc, qval);
-            } else if qval == max_qval && enc == "br" {
-                preferred_encoding = "br";
-            }
-        }
-        Ok(preferred_encoding == "br")
+        Ok(negotiate::best_encoding(&accept_encoding, &["br"]).is_some())
     }
 
     fn parse_args(args: LuaTable) -> LuaResult<BrotliFilterOptions> {
@@ -154,10 +126,7 @@ impl BrotliFilter {
             match &*arg? {
                 "offload" => options.offload = true,
                 arg if arg.starts_with("type:") => {
-                    options.content_types = arg[5..]
-                        .split(',')
-                        .map(|s| s.trim().to_ascii_lowercase())
-                        .collect();
+                    options.content_types = MimeMatcher::from_list(&arg[5..]);
                 }
                 arg if arg.starts_with("quality:") => {
                     let mut quality = arg[8..].trim().parse::<u8>().unwrap_or_default();
@@ -167,14 +136,8 @@ impl BrotliFilter {
                     options.quality = quality;
                 }
                 arg if arg.starts_with("window:") => {
-                    let mut window = arg[7..].trim().parse::<u8>().unwrap_or_default();
-                    if window < 10 {
-                        window = 10;
-                    }
-                    if window > 24 {
-                        window = 24;
-                    }
-                    options.window = window;
+                    let window = arg[7..].trim().parse::<u8>().unwrap_or_default();
+                    options.window = window.clamp(10, 24);
                 }
                 _ => {}
             }
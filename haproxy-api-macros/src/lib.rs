@@ -0,0 +1,201 @@
+//! Procedural macros for [haproxy-api].
+//!
+//! Use them through the `macros` feature of `haproxy-api`.
+//!
+//! [haproxy-api]: https://crates.io/crates/haproxy-api
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, ToTokens};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Error, Expr, ExprLit, ItemFn, Lit, Result, Token};
+
+// Parsed arguments in the form `"name", key = value, ...`
+struct Args {
+    name: String,
+    params: Vec<(String, Lit)>,
+}
+
+impl Args {
+    fn parse(attr: TokenStream, allowed: &[&str]) -> Result<Self> {
+        let exprs =
+            syn::parse::Parser::parse(Punctuated::<Expr, Token![,]>::parse_terminated, attr)?;
+        let mut iter = exprs.into_iter();
+        let name = match iter.next() {
+            Some(Expr::Lit(ExprLit {
+                lit: Lit::Str(s), ..
+            })) => s.value(),
+            Some(expr) => return Err(Error::new_spanned(expr, "expected name string literal")),
+            None => return Err(Error::new(Span::call_site(), "missing name")),
+        };
+        let mut params = Vec::new();
+        for expr in iter {
+            let Expr::Assign(assign) = &expr else {
+                return Err(Error::new_spanned(expr, "expected `key = value`"));
+            };
+            let key = assign.left.to_token_stream().to_string();
+            if !allowed.contains(&key.as_str()) {
+                let msg = format!("unknown parameter `{key}`, expected one of: {allowed:?}");
+                return Err(Error::new_spanned(&assign.left, msg));
+            }
+            let Expr::Lit(ExprLit { lit, .. }) = &*assign.right else {
+                return Err(Error::new_spanned(&assign.right, "expected a literal"));
+            };
+            params.push((key, lit.clone()));
+        }
+        Ok(Args { name, params })
+    }
+
+    fn get(&self, key: &str) -> Option<&Lit> {
+        self.params.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+fn submit(item: &ItemFn, register: TokenStream2) -> TokenStream {
+    quote! {
+        #item
+
+        ::haproxy_api::__private::inventory::submit! {
+            ::haproxy_api::__private::Registration::new(|core| #register)
+        }
+    }
+    .into()
+}
+
+fn expand(
+    attr: TokenStream,
+    item: TokenStream,
+    allowed: &[&str],
+    f: impl FnOnce(&Args, &ItemFn) -> Result<TokenStream2>,
+) -> TokenStream {
+    let item = parse_macro_input!(item as ItemFn);
+    let result = Args::parse(attr, allowed).and_then(|args| f(&args, &item));
+    match result {
+        Ok(register) => submit(&item, register),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Registers the function as a sample fetch (see `Core::register_fetches`).
+///
+/// ```ignore
+/// #[haproxy_api::fetch("first_header")]
+/// fn first_header(_: &Lua, (txn, name): (Txn, String)) -> Result<Option<String>> {
+///     txn.http()?.req_get_headers()?.get_first(&name)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn fetch(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(attr, item, &[], |args, item| {
+        let (name, ident) = (&args.name, &item.sig.ident);
+        Ok(quote!(core.register_fetches(#name, #ident)))
+    })
+}
+
+/// Registers the function as a converter (see `Core::register_converters`).
+#[proc_macro_attribute]
+pub fn converter(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(attr, item, &[], |args, item| {
+        let (name, ident) = (&args.name, &item.sig.ident);
+        Ok(quote!(core.register_converters(#name, #ident)))
+    })
+}
+
+/// Registers the function as an action (see `Core::register_action`).
+///
+/// Parameters:
+/// - `on`: comma-separated list of `tcp-req`, `tcp-res`, `http-req`, `http-res` (required)
+/// - `nb_args`: number of arguments (default `0`)
+///
+/// ```ignore
+/// #[haproxy_api::action("set_tag", on = "http-req,http-res", nb_args = 1)]
+/// fn set_tag(_: &Lua, (txn, tag): (Txn, String)) -> Result<()> { ... }
+/// ```
+#[proc_macro_attribute]
+pub fn action(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(attr, item, &["on", "nb_args"], |args, item| {
+        let (name, ident) = (&args.name, &item.sig.ident);
+        let actions = match args.get("on") {
+            Some(Lit::Str(on)) => on
+                .value()
+                .split(',')
+                .map(|action| match action.trim() {
+                    "tcp-req" => Ok(quote!(::haproxy_api::Action::TcpReq)),
+                    "tcp-res" => Ok(quote!(::haproxy_api::Action::TcpRes)),
+                    "http-req" => Ok(quote!(::haproxy_api::Action::HttpReq)),
+                    "http-res" => Ok(quote!(::haproxy_api::Action::HttpRes)),
+                    other => Err(Error::new_spanned(on, format!("unknown action `{other}`"))),
+                })
+                .collect::<Result<Vec<_>>>()?,
+            Some(lit) => return Err(Error::new_spanned(lit, "expected a string")),
+            None => return Err(Error::new(Span::call_site(), "missing `on` parameter")),
+        };
+        let nb_args = match args.get("nb_args") {
+            Some(Lit::Int(n)) => n.base10_parse::<usize>()?,
+            Some(lit) => return Err(Error::new_spanned(lit, "expected an integer")),
+            None => 0,
+        };
+        Ok(quote!(core.register_action(#name, &[#(#actions),*], #nb_args, #ident)))
+    })
+}
+
+/// Registers the function as a service (see `Core::register_service`).
+///
+/// Parameters:
+/// - `mode`: `tcp` or `http` (default `http`)
+#[proc_macro_attribute]
+pub fn service(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(attr, item, &["mode"], |args, item| {
+        let (name, ident) = (&args.name, &item.sig.ident);
+        let mode = match args.get("mode") {
+            Some(Lit::Str(mode)) => match mode.value().as_str() {
+                "tcp" => quote!(::haproxy_api::ServiceMode::Tcp),
+                "http" => quote!(::haproxy_api::ServiceMode::Http),
+                other => return Err(Error::new_spanned(mode, format!("unknown mode `{other}`"))),
+            },
+            Some(lit) => return Err(Error::new_spanned(lit, "expected a string")),
+            None => quote!(::haproxy_api::ServiceMode::Http),
+        };
+        Ok(quote!(core.register_service(#name, #mode, #ident)))
+    })
+}
+
+/// Marks the module entry point.
+///
+/// Expands to `#[mlua::lua_module]` (with `skip_memory_check` unless other arguments are given)
+/// and registers all functions marked with `fetch`, `converter`, `action` and `service`
+/// attributes before running the function body.
+///
+/// ```ignore
+/// #[haproxy_api::module]
+/// fn haproxy_my_module(lua: &Lua) -> Result<bool> {
+///     Ok(true)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn module(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut item = parse_macro_input!(item as ItemFn);
+    let attr = TokenStream2::from(attr);
+    let attr = match attr.is_empty() {
+        true => quote!(skip_memory_check),
+        false => attr,
+    };
+    let Some(syn::FnArg::Typed(arg)) = item.sig.inputs.first_mut() else {
+        let err = Error::new_spanned(&item.sig, "expected a function with `lua: &Lua` argument");
+        return err.to_compile_error().into();
+    };
+    // Bind the argument to a known name, the pattern (eg. `_`) is not always an expression
+    let pat = std::mem::replace(&mut *arg.pat, syn::parse_quote!(__haproxy_api_lua));
+    let stmts = &item.block.stmts;
+    let block = quote!({
+        ::haproxy_api::Core::new(__haproxy_api_lua)?.register_collected()?;
+        let #pat = __haproxy_api_lua;
+        #(#stmts)*
+    });
+    *item.block = syn::parse2(block).expect("valid block");
+    quote! {
+        #[::mlua::lua_module(#attr)]
+        #item
+    }
+    .into()
+}
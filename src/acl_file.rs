@@ -0,0 +1,229 @@
+use std::collections::BTreeSet;
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use mlua::{Error, Result};
+
+use crate::Core;
+
+/// A handle to an ACL file loaded by HAProxy, tracking its entries.
+///
+/// HAProxy does not provide a way to list the ACL entries from Lua, so the handle keeps
+/// the known entries: the ones read from the file (see [`AclFile::from_file`]) and the ones
+/// changed through the handle. Entries changed by other means (eg. the runtime API) are not seen.
+///
+/// Bulk operations apply every change and report all the failed ones at once, as a
+/// [`BulkError`] (wrapped in [`mlua::Error::ExternalError`]).
+///
+/// ```ignore
+/// let mut blocklist = AclFile::from_file("/etc/haproxy/blocklist.acl")?;
+/// let changes = blocklist.replace_all(&core, fetched_ips)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct AclFile {
+    filename: String,
+    entries: BTreeSet<String>,
+}
+
+/// The number of entries changed by a bulk operation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// The number of added (or updated) entries.
+    pub added: usize,
+    /// The number of deleted entries.
+    pub removed: usize,
+}
+
+impl Changes {
+    /// Returns true if nothing was changed.
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0
+    }
+}
+
+/// Failures of a bulk ACL or map operation.
+#[derive(Debug, Clone)]
+pub struct BulkError {
+    /// The failed keys with their errors.
+    pub failures: Vec<(String, Error)>,
+    /// The changes applied successfully.
+    pub changes: Changes,
+}
+
+impl fmt::Display for BulkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} operation(s) failed", self.failures.len())?;
+        if let Some((key, err)) = self.failures.first() {
+            write!(f, " (first: '{key}': {err})")?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for BulkError {}
+
+impl BulkError {
+    // Returns the changes, or the error if anything failed
+    pub(crate) fn check(failures: Vec<(String, Error)>, changes: Changes) -> Result<Changes> {
+        if failures.is_empty() {
+            return Ok(changes);
+        }
+        Err(Error::external(BulkError { failures, changes }))
+    }
+}
+
+impl AclFile {
+    /// Creates a handle to the ACL file `filename` without known entries.
+    ///
+    /// The `filename` must be the same as used in the HAProxy configuration.
+    pub fn new(filename: impl Into<String>) -> Self {
+        AclFile {
+            filename: filename.into(),
+            entries: BTreeSet::new(),
+        }
+    }
+
+    /// Creates a handle to the ACL file `filename`, reading the entries from it.
+    pub fn from_file(filename: impl Into<String>) -> io::Result<Self> {
+        let filename = filename.into();
+        let entries = read_lines(Path::new(&filename))?.into_iter().collect();
+        Ok(AclFile { filename, entries })
+    }
+
+    /// Returns the ACL file name.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Returns true if the entry is known.
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains(key)
+    }
+
+    /// Returns the number of known entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if there are no known entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over the known entries, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    /// Adds the entry.
+    pub fn add(&mut self, core: &Core, key: &str) -> Result<()> {
+        core.add_acl(&self.filename, key)?;
+        self.entries.insert(key.to_string());
+        Ok(())
+    }
+
+    /// Deletes the entry.
+    pub fn del(&mut self, core: &Core, key: &str) -> Result<()> {
+        core.del_acl(&self.filename, key)?;
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    /// Adds the entries that are not known yet.
+    pub fn add_many<I, S>(&mut self, core: &Core, keys: I) -> Result<Changes>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut changes = Changes::default();
+        let mut failures = Vec::new();
+        for key in keys {
+            let key = key.as_ref();
+            if self.contains(key) {
+                continue;
+            }
+            match self.add(core, key) {
+                Ok(()) => changes.added += 1,
+                Err(err) => failures.push((key.to_string(), err)),
+            }
+        }
+        BulkError::check(failures, changes)
+    }
+
+    /// Deletes the known entries.
+    pub fn del_many<I, S>(&mut self, core: &Core, keys: I) -> Result<Changes>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut changes = Changes::default();
+        let mut failures = Vec::new();
+        for key in keys {
+            let key = key.as_ref();
+            if !self.contains(key) {
+                continue;
+            }
+            match self.del(core, key) {
+                Ok(()) => changes.removed += 1,
+                Err(err) => failures.push((key.to_string(), err)),
+            }
+        }
+        BulkError::check(failures, changes)
+    }
+
+    /// Replaces the entries with `keys`, adding the missing ones and deleting the others.
+    ///
+    /// New entries are added before the old ones are deleted, so the entries present in both
+    /// sets never disappear.
+    pub fn replace_all<I, S>(&mut self, core: &Core, keys: I) -> Result<Changes>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let keys = keys
+            .into_iter()
+            .map(Into::into)
+            .collect::<BTreeSet<String>>();
+        let stale = self.entries.difference(&keys).cloned().collect::<Vec<_>>();
+        let added = self.add_many(core, &keys);
+        let removed = self.del_many(core, &stale);
+        merge_results(added, removed)
+    }
+}
+
+// Combines the results of two bulk operations
+pub(crate) fn merge_results(a: Result<Changes>, b: Result<Changes>) -> Result<Changes> {
+    let split = |result: Result<Changes>| match result {
+        Ok(changes) => Ok((changes, Vec::new())),
+        Err(Error::ExternalError(err)) => match err.downcast_ref::<BulkError>() {
+            Some(err) => Ok((err.changes, err.failures.clone())),
+            None => Err(Error::ExternalError(err)),
+        },
+        Err(err) => Err(err),
+    };
+    let (a, mut failures) = split(a)?;
+    let (b, failures_b) = split(b)?;
+    failures.extend(failures_b);
+    let changes = Changes {
+        added: a.added + b.added,
+        removed: a.removed + b.removed,
+    };
+    BulkError::check(failures, changes)
+}
+
+// Reads the lines from the ACL or map file
+pub(crate) fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    Ok(parse_lines(&fs::read_to_string(path)?))
+}
+
+// Splits the ACL or map file content into lines, skipping comments and empty lines
+pub(crate) fn parse_lines(content: &str) -> Vec<String> {
+    (content.lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
@@ -18,6 +18,8 @@ use mlua::{
 };
 use rustc_hash::FxBuildHasher;
 
+use crate::MaybeSend;
+
 // Using `u16` will give us max 65536 receivers to store.
 // If for any reason future was not picked up by the notification listener,
 // receiver will be overwritten on the counter reset (and memory released).
@@ -29,9 +31,10 @@ const PER_WORKER_POOL_SIZE: usize = 512;
 // Link between future id and the corresponding receiver (used to signal when the future is ready)
 static FUTURE_RX_MAP: OnceLock<DashMap<FutureId, Receiver<()>, FxBuildHasher>> = OnceLock::new();
 
+static RUNTIME: OnceLock<runtime::Runtime> = OnceLock::new();
+
 /// Returns the global tokio runtime.
 pub fn runtime() -> &'static runtime::Runtime {
-    static RUNTIME: OnceLock<runtime::Runtime> = OnceLock::new();
     RUNTIME.get_or_init(|| {
         runtime::Builder::new_multi_thread()
             .enable_all()
@@ -40,6 +43,11 @@ pub fn runtime() -> &'static runtime::Runtime {
     })
 }
 
+// Returns the global tokio runtime if it's already created
+pub(crate) fn runtime_if_started() -> Option<&'static runtime::Runtime> {
+    RUNTIME.get()
+}
+
 // Find first free port
 fn get_notification_port() -> u16 {
     static NOTIFICATION_PORT: OnceLock<u16> = OnceLock::new();
@@ -119,7 +127,7 @@ pub fn create_async_function<'lua, A, R, F, FR>(lua: &'lua Lua, func: F) -> Resu
 where
     A: FromLuaMulti<'lua> + 'static,
     R: IntoLuaMulti<'lua> + Send + 'static,
-    F: Fn(A) -> FR + 'static,
+    F: Fn(A) -> FR + MaybeSend + 'static,
     FR: Future<Output = Result<R>> + Send + 'static,
 {
     let port = get_notification_port();
@@ -0,0 +1,217 @@
+//! `Authorization` header parsing.
+//!
+//! Supports the `Basic` (RFC 7617) and `Bearer` (RFC 6750) schemes, other schemes are
+//! returned as is. Credentials should be compared using [`constant_time_eq`] (or
+//! [`BasicCredentials::verify`]) to avoid leaking information through timing.
+//!
+//! ```ignore
+//! match Authorization::from_txn(&txn)? {
+//!     Some(Authorization::Basic(creds)) if creds.verify("admin", &password) => { /* allow */ }
+//!     Some(Authorization::Bearer(token)) => { /* validate the token */ }
+//!     _ => { /* reply with 401 */ }
+//! }
+//! ```
+
+use mlua::Result;
+
+use crate::{Headers, Txn};
+
+/// Credentials of the `Basic` authentication scheme.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BasicCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl BasicCredentials {
+    /// Compares the credentials with `username` and `password` in constant time.
+    pub fn verify(&self, username: &str, password: &str) -> bool {
+        // Evaluate both to not reveal which one is wrong
+        let username_ok = constant_time_eq(self.username.as_bytes(), username.as_bytes());
+        let password_ok = constant_time_eq(self.password.as_bytes(), password.as_bytes());
+        username_ok & password_ok
+    }
+}
+
+// Do not print the password
+impl std::fmt::Debug for BasicCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BasicCredentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// A parsed `Authorization` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Authorization {
+    /// The `Basic` scheme with decoded credentials.
+    Basic(BasicCredentials),
+    /// The `Bearer` scheme with a token.
+    Bearer(String),
+    /// Any other scheme with raw credentials.
+    Other { scheme: String, credentials: String },
+}
+
+impl Authorization {
+    /// Parses an `Authorization` header value.
+    ///
+    /// Returns `None` if the value is malformed (eg. invalid base64 for the `Basic` scheme).
+    pub fn parse(header: &str) -> Option<Self> {
+        let header = header.trim();
+        let (scheme, credentials) = header.split_once(' ').unwrap_or((header, ""));
+        let credentials = credentials.trim();
+        if scheme.is_empty() {
+            return None;
+        }
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(base64_decode(credentials)?).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+            return Some(Authorization::Basic(BasicCredentials {
+                username: username.to_string(),
+                password: password.to_string(),
+            }));
+        }
+        if scheme.eq_ignore_ascii_case("bearer") {
+            if credentials.is_empty() || credentials.contains(' ') {
+                return None;
+            }
+            return Some(Authorization::Bearer(credentials.to_string()));
+        }
+        Some(Authorization::Other {
+            scheme: scheme.to_string(),
+            credentials: credentials.to_string(),
+        })
+    }
+
+    /// Parses the first `Authorization` header.
+    pub fn from_headers(headers: &Headers) -> Result<Option<Self>> {
+        let header = headers.get_first::<String>("authorization")?;
+        Ok(header.as_deref().and_then(Self::parse))
+    }
+
+    /// Parses the first `Authorization` header of the request.
+    pub fn from_txn(txn: &Txn) -> Result<Option<Self>> {
+        Self::from_headers(&txn.http()?.req_get_headers()?)
+    }
+
+    /// Returns the `Basic` credentials, if any.
+    pub fn basic(&self) -> Option<&BasicCredentials> {
+        match self {
+            Authorization::Basic(creds) => Some(creds),
+            _ => None,
+        }
+    }
+
+    /// Returns the `Bearer` token, if any.
+    pub fn bearer(&self) -> Option<&str> {
+        match self {
+            Authorization::Bearer(token) => Some(token),
+            _ => None,
+        }
+    }
+}
+
+/// Compares two byte strings in constant time (with respect to their content).
+///
+/// The length of the strings is not secret.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    // Prevent the compiler from short-circuiting the loop
+    std::hint::black_box(diff) == 0
+}
+
+// Decodes standard base64 with optional padding
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+            b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+
+    let input = input.trim_end_matches('=').as_bytes();
+    if input.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    for chunk in input.chunks(4) {
+        let mut acc = 0u32;
+        for &c in chunk {
+            acc = (acc << 6) | value(c)?;
+        }
+        // Align partial chunks to 24 bits
+        acc <<= 6 * (4 - chunk.len()) as u32;
+        let bytes = acc.to_be_bytes();
+        out.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(username: &str, password: &str) -> Authorization {
+        Authorization::Basic(BasicCredentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_parse_basic() {
+        let auth = Authorization::parse("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+        assert_eq!(auth, Some(basic("Aladdin", "open sesame")));
+        // Unpadded, case-insensitive scheme, colon in the password
+        let auth = Authorization::parse("  basic dXNlcjpwYTpzcw ");
+        assert_eq!(auth, Some(basic("user", "pa:ss")));
+        assert_eq!(Authorization::parse("Basic Og=="), Some(basic("", "")));
+
+        assert_eq!(Authorization::parse("Basic"), None);
+        assert_eq!(Authorization::parse("Basic dXNlcg=="), None); // no colon
+        assert_eq!(Authorization::parse("Basic dXNl*jpwYXNz"), None);
+        assert_eq!(Authorization::parse("Basic QQQQQ"), None); // invalid length
+        assert_eq!(Authorization::parse("Basic /w=="), None); // not UTF-8
+    }
+
+    #[test]
+    fn test_parse_other() {
+        let auth = Authorization::parse("Bearer mF_9.B5f-4.1JqM").unwrap();
+        assert_eq!(auth.bearer(), Some("mF_9.B5f-4.1JqM"));
+        assert_eq!(auth.basic(), None);
+        assert_eq!(Authorization::parse("Bearer"), None);
+        assert_eq!(Authorization::parse("Bearer a b"), None);
+
+        let auth = Authorization::parse("Digest username=\"a\", nonce=\"b\"");
+        let expected = Authorization::Other {
+            scheme: "Digest".to_string(),
+            credentials: "username=\"a\", nonce=\"b\"".to_string(),
+        };
+        assert_eq!(auth, Some(expected));
+        assert_eq!(Authorization::parse(""), None);
+    }
+
+    #[test]
+    fn test_verify() {
+        let creds = BasicCredentials {
+            username: "admin".to_string(),
+            password: "secret".to_string(),
+        };
+        assert!(creds.verify("admin", "secret"));
+        assert!(!creds.verify("admin", "secreT"));
+        assert!(!creds.verify("root", "secret"));
+        assert!(!format!("{creds:?}").contains("secret"));
+
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"a", b"ab"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::Arc;
+
+use mlua::{AnyUserData, Error, Lua, Result, String as LuaString, Table, UserData, Value};
+
+use crate::{Core, FilterMethod, FilterResult, HttpMessage, LogLevel, Txn, UserFilter};
+
+/// An action applied when the response body exceeds the limit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BodyLimitAction {
+    /// Abort the transaction.
+    Abort,
+    /// Log a warning and let the response through.
+    Log,
+}
+
+/// Configuration of [`BodyLimitFilter`].
+#[derive(Debug, Clone)]
+pub struct BodyLimits {
+    request: Option<u64>,
+    response: Option<u64>,
+    response_action: BodyLimitAction,
+    request_var: Option<String>,
+    response_var: Option<String>,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        BodyLimits {
+            request: None,
+            response: None,
+            response_action: BodyLimitAction::Abort,
+            request_var: None,
+            response_var: None,
+        }
+    }
+}
+
+impl BodyLimits {
+    /// Creates a new configuration without limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum request body size. Larger requests are rejected with 413.
+    pub fn request(mut self, limit: u64) -> Self {
+        self.request = Some(limit);
+        self
+    }
+
+    /// Sets the maximum response body size and the action to apply when it's exceeded.
+    pub fn response(mut self, limit: u64, action: BodyLimitAction) -> Self {
+        self.response = Some(limit);
+        self.response_action = action;
+        self
+    }
+
+    /// Stores the observed request body size in the variable `name` (eg. `txn.req_body_size`).
+    pub fn request_var(mut self, name: &str) -> Self {
+        self.request_var = Some(name.to_string());
+        self
+    }
+
+    /// Stores the observed response body size in the variable `name` (eg. `txn.res_body_size`).
+    pub fn response_var(mut self, name: &str) -> Self {
+        self.response_var = Some(name.to_string());
+        self
+    }
+
+    /// Parses the configuration from the filter arguments.
+    ///
+    /// Supported arguments: `req:<bytes>`, `res:<bytes>`, `res-action:<abort|log>`,
+    /// `req-var:<name>`, `res-var:<name>`.
+    pub fn from_args(args: &Table) -> Result<Self> {
+        let args = args.clone().sequence_values::<String>();
+        Self::parse_args(&args.collect::<Result<Vec<_>>>()?)
+    }
+
+    pub(crate) fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<Self> {
+        let mut limits = Self::new();
+        for arg in args {
+            let arg = arg.as_ref();
+            let invalid = || Error::runtime(format!("body limit: invalid argument '{arg}'"));
+            let (key, value) = arg.split_once(':').ok_or_else(invalid)?;
+            match key {
+                "req" => limits.request = Some(value.parse().map_err(|_| invalid())?),
+                "res" => limits.response = Some(value.parse().map_err(|_| invalid())?),
+                "res-action" => {
+                    limits.response_action = match value {
+                        "abort" => BodyLimitAction::Abort,
+                        "log" => BodyLimitAction::Log,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "req-var" => limits.request_var = Some(value.to_string()),
+                "res-var" => limits.response_var = Some(value.to_string()),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(limits)
+    }
+
+    /// Registers [`BodyLimitFilter`] with this configuration under the `name`.
+    ///
+    /// The filter arguments (if any) are ignored.
+    pub fn register(self, core: &Core, name: &str) -> Result<()> {
+        let limits = core.lua.create_userdata(SharedLimits(Arc::new(self)))?;
+        core.register_filter_with::<BodyLimitFilter>(name, 1.0, Some(Value::UserData(limits)))
+    }
+}
+
+// Limits shared between filter instances
+struct SharedLimits(Arc<BodyLimits>);
+
+impl UserData for SharedLimits {}
+
+/// A filter that enforces limits on request and response body sizes.
+///
+/// Requests exceeding the limit are rejected with `413 Payload Too Large`.
+/// The announced `content-length` is checked first, so such requests are rejected before
+/// receiving the body. A malformed `content-length` is not rejected by the filter
+/// (HAProxy validates it itself), the body size is then checked as the data arrive.
+/// The observed body size can be exposed as a variable.
+///
+/// ```text
+/// filter lua.body-limit req:1048576 res:10485760 res-action:log req-var:txn.req_body_size
+/// ```
+pub struct BodyLimitFilter {
+    limits: Arc<BodyLimits>,
+    request_size: u64,
+    response_size: u64,
+}
+
+impl BodyLimitFilter {
+    fn reject_request(txn: &Txn) -> Result<()> {
+        let reply = txn.reply()?;
+        reply.set_status(413, None)?;
+        reply.add_header("content-length", "0")?;
+        txn.done(Some(reply))
+    }
+
+    fn limit(&self, resp: bool) -> Option<u64> {
+        match resp {
+            false => self.limits.request,
+            true => self.limits.response,
+        }
+    }
+
+    fn set_size_var(&self, txn: &Txn, resp: bool, size: u64) -> Result<()> {
+        let var = match resp {
+            false => &self.limits.request_var,
+            true => &self.limits.response_var,
+        };
+        if let Some(var) = var {
+            txn.set_var(var, size)?;
+        }
+        Ok(())
+    }
+
+    fn exceeded(&self, txn: &Txn, resp: bool, size: u64) -> Result<()> {
+        self.set_size_var(txn, resp, size)?;
+        match (resp, self.limits.response_action) {
+            (false, _) => Self::reject_request(txn),
+            (true, BodyLimitAction::Abort) => txn.done(None),
+            (true, BodyLimitAction::Log) => txn.log(
+                LogLevel::Warning,
+                format!("body limit: response body size exceeds the limit ({size} bytes)"),
+            ),
+        }
+    }
+}
+
+impl UserFilter for BodyLimitFilter {
+    const METHODS: u8 = FilterMethod::HTTP_HEADERS | FilterMethod::HTTP_PAYLOAD;
+
+    // `txn:done()` must be propagated to HAProxy
+    const CONTINUE_IF_ERROR: bool = false;
+
+    fn new(_: &Lua, args: Table) -> Result<Self> {
+        // Fetch ready parsed configuration
+        let limits = match args.raw_get::<_, Option<AnyUserData>>(0)? {
+            Some(ud) => ud.borrow::<SharedLimits>()?.0.clone(),
+            None => {
+                let limits = Arc::new(BodyLimits::from_args(&args)?);
+                args.raw_set(0, SharedLimits(limits.clone()))?;
+                limits
+            }
+        };
+        Ok(BodyLimitFilter {
+            limits,
+            request_size: 0,
+            response_size: 0,
+        })
+    }
+
+    fn http_headers(&mut self, lua: &Lua, txn: Txn, msg: HttpMessage) -> Result<FilterResult> {
+        let resp = msg.is_resp()?;
+        let track_size = match resp {
+            false => self.limits.request_var.is_some(),
+            true => self.limits.response_var.is_some(),
+        };
+        let limit = self.limit(resp);
+        if limit.is_none() && !track_size {
+            return Ok(FilterResult::Continue);
+        }
+
+        if let Some(limit) = limit {
+            let headers = msg.get_headers()?;
+            let content_length = headers.get_first::<LuaString>("content-length")?;
+            let content_length = content_length.as_ref().and_then(|v| v.to_str().ok());
+            if let Some(len) = content_length.and_then(parse_content_length) {
+                if len > limit {
+                    self.exceeded(&txn, resp, len)?;
+                    return Ok(FilterResult::Continue);
+                }
+            }
+        }
+
+        if msg.eom()? {
+            self.set_size_var(&txn, resp, 0)?;
+            return Ok(FilterResult::Continue);
+        }
+        Self::register_data_filter(lua, txn, msg.channel()?)?;
+        Ok(FilterResult::Continue)
+    }
+
+    fn http_payload(&mut self, lua: &Lua, txn: Txn, msg: HttpMessage) -> Result<Option<usize>> {
+        let resp = msg.is_resp()?;
+        let input = msg.input()? as u64;
+        let size = match resp {
+            false => &mut self.request_size,
+            true => &mut self.response_size,
+        };
+        // All available data are forwarded, so each byte is counted once
+        *size += input;
+        let size = *size;
+
+        if let Some(limit) = self.limit(resp) {
+            if size > limit {
+                self.exceeded(&txn, resp, size)?;
+                Self::unregister_data_filter(lua, txn, msg.channel()?)?;
+                return Ok(None);
+            }
+        }
+        if msg.eom()? {
+            self.set_size_var(&txn, resp, size)?;
+        }
+        Ok(None)
+    }
+}
+
+// Parses the `content-length` value, accepting a list of identical values (RFC 9110, 8.6)
+fn parse_content_length(value: &str) -> Option<u64> {
+    let mut values = value.split(',').map(|v| v.trim().parse::<u64>().ok());
+    let first = values.next()??;
+    values.all(|v| v == Some(first)).then_some(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_content_length() {
+        assert_eq!(parse_content_length("0"), Some(0));
+        assert_eq!(parse_content_length(" 1024 "), Some(1024));
+        assert_eq!(parse_content_length("42, 42"), Some(42));
+        assert_eq!(parse_content_length("42, 43"), None);
+        assert_eq!(parse_content_length(""), None);
+        assert_eq!(parse_content_length("-1"), None);
+        assert_eq!(parse_content_length("1e3"), None);
+        assert_eq!(parse_content_length("99999999999999999999999"), None);
+    }
+}
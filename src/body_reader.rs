@@ -0,0 +1,119 @@
+use std::io::{self, BufRead, Read};
+
+use mlua::Result;
+
+use crate::HttpMessage;
+
+/// An adapter exposing the HTTP payload as [`std::io::Read`] and [`std::io::BufRead`].
+///
+/// The reader is fed from [`UserFilter::http_payload`] with [`BodyReader::feed_message`]
+/// and keeps track of the data consumed by the downstream code, so that they can be forwarded.
+/// When the buffered data are exhausted before the end of message, reads return
+/// [`io::ErrorKind::WouldBlock`]; the filter should then return to HAProxy and wait for more data.
+///
+/// ```ignore
+/// fn http_payload(&mut self, _: &Lua, _: Txn, msg: HttpMessage) -> Result<Option<usize>> {
+///     self.reader.feed_message(&msg)?;
+///     match self.parser.parse(&mut self.reader) {
+///         Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+///         Err(err) => return Err(Error::external(err)),
+///         Ok(_) => {}
+///     }
+///     Ok(Some(self.reader.take_forward()))
+/// }
+/// ```
+///
+/// [`UserFilter::http_payload`]: crate::UserFilter::http_payload
+#[derive(Debug, Default)]
+pub struct BodyReader {
+    buf: Vec<u8>,
+    pos: usize,
+    // Bytes copied from the message but not forwarded yet (they stay in the message buffer)
+    unforwarded: usize,
+    // Bytes consumed by the reader but not forwarded yet
+    consumed: usize,
+    eom: bool,
+}
+
+impl BodyReader {
+    /// Creates a new empty reader.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copies the new incoming data of the HTTP message into the reader.
+    ///
+    /// Returns the number of bytes added.
+    pub fn feed_message(&mut self, msg: &HttpMessage) -> Result<usize> {
+        let offset = self.unforwarded as isize;
+        let added = msg.body_with(Some(offset), Some(-1), |data| {
+            self.feed(data);
+            data.len()
+        })?;
+        self.unforwarded += added;
+        self.eom = msg.eom()?;
+        Ok(added)
+    }
+
+    // Appends a chunk of data to the internal buffer
+    fn feed(&mut self, data: &[u8]) {
+        // Reclaim space occupied by already consumed data
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Returns the number of consumed bytes that can be forwarded and resets the counter.
+    ///
+    /// The result is intended to be returned from [`UserFilter::http_payload`].
+    ///
+    /// [`UserFilter::http_payload`]: crate::UserFilter::http_payload
+    pub fn take_forward(&mut self) -> usize {
+        let len = self.consumed;
+        self.consumed = 0;
+        self.unforwarded -= len;
+        len
+    }
+
+    /// Returns the number of buffered bytes that were not read yet.
+    pub fn available(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Returns true if the end of message was reached.
+    pub fn is_eom(&self) -> bool {
+        self.eom
+    }
+
+    /// Returns true if the end of message was reached and all data were read.
+    pub fn is_finished(&self) -> bool {
+        self.eom && self.available() == 0
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for BodyReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buf.len() && !self.eom {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.available());
+        self.pos += amt;
+        self.consumed += amt;
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::{self, Write};
+
+use mlua::{Error, Result};
+
+use crate::{Channel, HttpMessage};
+
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+#[derive(Clone, Copy)]
+enum Target<'a, 'lua> {
+    Message(&'a HttpMessage<'lua>),
+    Channel(&'a Channel<'lua>),
+}
+
+/// A buffered [`std::io::Write`] adapter that emits data into an HTTP message or channel.
+///
+/// Small writes are collected in a buffer and copied into the incoming data of the target
+/// when the buffer is full, on [`flush`](Write::flush) or when the writer is dropped.
+/// Data are either appended at the end of incoming data, or inserted at the given offset,
+/// which is advanced after each copy to keep the written data in order.
+///
+/// Errors during the drop are ignored, call [`BodyWriter::finish`] to handle them.
+///
+/// ```ignore
+/// let mut writer = BodyWriter::for_message(&msg);
+/// serde_json::to_writer(&mut writer, &value)?;
+/// writer.finish()?;
+/// ```
+pub struct BodyWriter<'a, 'lua> {
+    target: Target<'a, 'lua>,
+    offset: Option<isize>,
+    buf: Vec<u8>,
+    capacity: usize,
+    written: usize,
+}
+
+impl<'a, 'lua> BodyWriter<'a, 'lua> {
+    /// Creates a writer appending data at the end of the HTTP message incoming data.
+    pub fn for_message(msg: &'a HttpMessage<'lua>) -> Self {
+        Self::new(Target::Message(msg))
+    }
+
+    /// Creates a writer appending data at the end of the channel incoming data.
+    pub fn for_channel(chn: &'a Channel<'lua>) -> Self {
+        Self::new(Target::Channel(chn))
+    }
+
+    fn new(target: Target<'a, 'lua>) -> Self {
+        BodyWriter {
+            target,
+            offset: None,
+            buf: Vec::new(),
+            capacity: DEFAULT_CAPACITY,
+            written: 0,
+        }
+    }
+
+    /// Inserts data starting at the `offset` of incoming data instead of appending them.
+    pub fn at_offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset as isize);
+        self
+    }
+
+    /// Sets the buffer capacity (8 KiB by default).
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Returns the number of bytes copied into the target so far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Flushes the buffered data and returns the total number of bytes written.
+    pub fn finish(mut self) -> Result<usize> {
+        self.flush_buf()?;
+        Ok(self.written)
+    }
+
+    fn flush_buf(&mut self) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let len = match (self.target, self.offset) {
+            (Target::Message(msg), None) => msg.append(&self.buf)?,
+            (Target::Message(msg), Some(offset)) => msg.insert(&self.buf, Some(offset))?,
+            (Target::Channel(chn), None) => chn.append(&self.buf)?,
+            (Target::Channel(chn), Some(offset)) => chn.insert(&self.buf, Some(offset))?,
+        };
+        if len > 0 {
+            let len = len as usize;
+            self.buf.drain(..len);
+            self.written += len;
+            if let Some(offset) = self.offset.as_mut() {
+                *offset += len as isize;
+            }
+        }
+        if !self.buf.is_empty() {
+            return Err(Error::runtime("not enough room in the buffer"));
+        }
+        Ok(())
+    }
+}
+
+impl Write for BodyWriter<'_, '_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.buf.len() + data.len() > self.capacity {
+            self.flush_buf().map_err(io::Error::other)?;
+        }
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= self.capacity {
+            self.flush_buf().map_err(io::Error::other)?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buf().map_err(io::Error::other)
+    }
+}
+
+impl Drop for BodyWriter<'_, '_> {
+    fn drop(&mut self) {
+        let _ = self.flush_buf();
+    }
+}
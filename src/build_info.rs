@@ -0,0 +1,292 @@
+use std::fmt::{self, Write as _};
+
+use mlua::{Error, Lua, Result, Table, TableExt, Value, Variadic};
+
+use crate::{Core, LUA_VERSION};
+
+// Registry table with names of the registered functions, grouped by kind
+const REGISTRATIONS_KEY: &str = "__HAPROXY_REGISTRATIONS";
+
+/// Kind of a component registered in HAProxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ComponentKind {
+    Fetch,
+    Converter,
+    Action,
+    Service,
+    Filter,
+    Cli,
+}
+
+impl ComponentKind {
+    // All kinds, in the order they are printed
+    const ALL: [ComponentKind; 6] = [
+        ComponentKind::Fetch,
+        ComponentKind::Converter,
+        ComponentKind::Action,
+        ComponentKind::Service,
+        ComponentKind::Filter,
+        ComponentKind::Cli,
+    ];
+
+    /// Returns the kind name.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ComponentKind::Fetch => "fetch",
+            ComponentKind::Converter => "converter",
+            ComponentKind::Action => "action",
+            ComponentKind::Service => "service",
+            ComponentKind::Filter => "filter",
+            ComponentKind::Cli => "cli",
+        }
+    }
+
+    // Key in the registrations table (also used as the section name)
+    const fn key(self) -> &'static str {
+        match self {
+            ComponentKind::Fetch => "fetches",
+            ComponentKind::Converter => "converters",
+            ComponentKind::Action => "actions",
+            ComponentKind::Service => "services",
+            ComponentKind::Filter => "filters",
+            ComponentKind::Cli => "cli",
+        }
+    }
+}
+
+impl fmt::Display for ComponentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A component registered through the [`Core`] `register_*` methods.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Component {
+    /// The component kind.
+    pub kind: ComponentKind,
+    /// The registered name (the command path for the CLI commands).
+    pub name: String,
+}
+
+/// Build information of a module, printed by the `show rust-module <name>` CLI command.
+///
+/// See [`Core::register_build_info`].
+#[derive(Debug, Clone)]
+pub struct BuildInfo {
+    name: String,
+    version: String,
+    git_hash: Option<String>,
+    features: Vec<String>,
+}
+
+impl BuildInfo {
+    /// Creates a new build information with the module `name` and `version`.
+    ///
+    /// The [`build_info!`](crate::build_info) macro fills them from the Cargo package.
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        BuildInfo {
+            name: name.into(),
+            version: version.into(),
+            git_hash: None,
+            features: Vec::new(),
+        }
+    }
+
+    /// Sets the git commit hash the module is built from.
+    pub fn git_hash(mut self, hash: Option<&str>) -> Self {
+        self.git_hash = hash.filter(|hash| !hash.is_empty()).map(String::from);
+        self
+    }
+
+    /// Sets the enabled module features.
+    pub fn features<I, S>(mut self, features: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.features = features.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Returns the module name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn render(&self, lua: &Lua) -> Result<String> {
+        let mut out = String::new();
+        let _ = writeln!(out, "name: {}", self.name);
+        let _ = writeln!(out, "version: {}", self.version);
+        let git_hash = self.git_hash.as_deref().unwrap_or("unknown");
+        let _ = writeln!(out, "git: {git_hash}");
+        let _ = writeln!(out, "features: {}", self.features.join(", "));
+        let _ = writeln!(out, "haproxy-api: {}", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(out, "haproxy-api features: {}", crate_features().join(", "));
+        let _ = writeln!(out, "lua: {LUA_VERSION}");
+        let _ = writeln!(out, "async runtime: {}", runtime_status());
+        let components = Core::new(lua)?.registered_components()?;
+        for kind in ComponentKind::ALL {
+            let names = (components.iter())
+                .filter(|c| c.kind == kind)
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>();
+            let _ = writeln!(out, "{}: {}", kind.key(), names.join(", "));
+        }
+        Ok(out)
+    }
+}
+
+/// Creates a [`BuildInfo`] with the name and version of the current Cargo package.
+///
+/// The git hash is taken from the `GIT_HASH` environment variable at compile time, which can be
+/// set by the module build script:
+///
+/// ```ignore
+/// // build.rs
+/// fn main() {
+///     let output = std::process::Command::new("git")
+///         .args(["rev-parse", "--short", "HEAD"])
+///         .output();
+///     if let Ok(output) = output {
+///         let hash = String::from_utf8_lossy(&output.stdout);
+///         println!("cargo:rustc-env=GIT_HASH={}", hash.trim());
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::BuildInfo::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+            .git_hash(option_env!("GIT_HASH"))
+    };
+}
+
+impl<'lua> Core<'lua> {
+    /// Registers the `show rust-module <name>` CLI command printing the module build information,
+    /// the enabled features, the async runtime status and the names of the registered functions.
+    pub fn register_build_info(&self, info: BuildInfo) -> Result<()> {
+        let name = info.name.clone();
+        let path = ["show", "rust-module", &name];
+        let usage = format!("show rust-module {name} : show the module build information");
+        let func = self
+            .lua
+            .create_function(move |lua, args: (Table, Variadic<Value>)| {
+                args.0.call_method::<_, ()>("send", info.render(lua)?)
+            })?;
+        self.track_registration(ComponentKind::Cli, &path.join(" "))?;
+        self.call_function("register_cli", (path, usage, func))
+    }
+
+    /// Returns the components registered through the `register_*` methods, in the registration
+    /// order within each kind.
+    pub fn registered_components(&self) -> Result<Vec<Component>> {
+        let lua = self.lua;
+        let mut components = Vec::new();
+        let Some(registrations) = lua.named_registry_value::<Option<Table>>(REGISTRATIONS_KEY)?
+        else {
+            return Ok(components);
+        };
+        for kind in ComponentKind::ALL {
+            let names = registrations.raw_get::<_, Option<Vec<String>>>(kind.key())?;
+            for name in names.unwrap_or_default() {
+                components.push(Component { kind, name });
+            }
+        }
+        Ok(components)
+    }
+
+    /// Registers the `show rust-components` CLI command listing the registered components.
+    pub fn register_components_cli(&self) -> Result<()> {
+        let path = ["show", "rust-components"];
+        let usage = "show rust-components : list the registered Rust components";
+        let func = self
+            .lua
+            .create_function(|lua, args: (Table, Variadic<Value>)| {
+                let mut out = String::new();
+                for component in Core::new(lua)?.registered_components()? {
+                    let _ = writeln!(out, "{}\t{}", component.kind, component.name);
+                }
+                args.0.call_method::<_, ()>("send", out)
+            })?;
+        self.track_registration(ComponentKind::Cli, &path.join(" "))?;
+        self.call_function("register_cli", (path, usage, func))
+    }
+
+    // Remembers the name of the registered component, failing on duplicates
+    // (HAProxy would silently use one of them)
+    pub(crate) fn track_registration(&self, kind: ComponentKind, name: &str) -> Result<()> {
+        let lua = self.lua;
+        let registrations = match lua.named_registry_value::<Option<Table>>(REGISTRATIONS_KEY)? {
+            Some(registrations) => registrations,
+            None => {
+                let registrations = lua.create_table()?;
+                lua.set_named_registry_value(REGISTRATIONS_KEY, &registrations)?;
+                registrations
+            }
+        };
+        let names = match registrations.raw_get::<_, Option<Table>>(kind.key())? {
+            Some(names) => names,
+            None => {
+                let names = lua.create_table()?;
+                registrations.raw_set(kind.key(), &names)?;
+                names
+            }
+        };
+        for registered in names.clone().sequence_values::<String>() {
+            if registered? == name {
+                let msg = format!("{kind} '{name}' is already registered");
+                return Err(Error::runtime(msg));
+            }
+        }
+        names.raw_push(name)
+    }
+}
+
+fn crate_features() -> Vec<&'static str> {
+    let features = [
+        ("async", cfg!(feature = "async")),
+        ("lua53", cfg!(feature = "lua53")),
+        ("lua54", cfg!(feature = "lua54")),
+        ("luajit", cfg!(feature = "luajit")),
+        ("faults", cfg!(feature = "faults")),
+        ("haproxy24", cfg!(feature = "haproxy24")),
+        ("haproxy26", cfg!(feature = "haproxy26")),
+        ("haproxy28", cfg!(feature = "haproxy28")),
+        ("haproxy30", cfg!(feature = "haproxy30")),
+        ("http", cfg!(feature = "http")),
+        ("http-body", cfg!(feature = "http-body")),
+        ("log", cfg!(feature = "log")),
+        ("macros", cfg!(feature = "macros")),
+        ("serde", cfg!(feature = "serde")),
+        ("send", cfg!(feature = "send")),
+        ("tower", cfg!(feature = "tower")),
+        ("tracing", cfg!(feature = "tracing")),
+        ("config", cfg!(feature = "config")),
+        ("toml", cfg!(feature = "toml")),
+        ("yaml", cfg!(feature = "yaml")),
+    ];
+    (features.into_iter())
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect()
+}
+
+#[cfg(feature = "async")]
+fn runtime_status() -> String {
+    match crate::r#async::runtime_if_started() {
+        Some(rt) => {
+            let metrics = rt.metrics();
+            format!(
+                "running ({} workers, {} alive tasks)",
+                metrics.num_workers(),
+                metrics.num_alive_tasks()
+            )
+        }
+        None => "not started".into(),
+    }
+}
+
+#[cfg(not(feature = "async"))]
+fn runtime_status() -> String {
+    "disabled".into()
+}
@@ -2,6 +2,8 @@ use std::ops::Deref;
 
 use mlua::{FromLua, IntoLua, Lua, Result, String as LuaString, Table, TableExt, Value};
 
+use crate::LuaBytes;
+
 /// The "Channel" class contains all functions to manipulate channels.
 ///
 /// Please refer to HAProxy documentation to get more information.
@@ -35,6 +37,32 @@ impl<'lua> Channel<'lua> {
         }
     }
 
+    /// Returns `length` bytes of incoming data from the channel buffer, starting at the `offset`,
+    /// as a [`LuaBytes`] byte string borrowed from Lua.
+    #[inline]
+    pub fn data_bytes(
+        &self,
+        offset: Option<isize>,
+        length: Option<isize>,
+    ) -> Result<Option<LuaBytes<'lua>>> {
+        Ok(self.data(offset, length)?.map(LuaBytes::from))
+    }
+
+    /// Calls `f` with `length` bytes of incoming data from the channel buffer, starting at the `offset`,
+    /// without copying them out of the Lua string.
+    ///
+    /// If there is no data, `f` receives an empty slice.
+    #[inline]
+    pub fn data_with<R>(
+        &self,
+        offset: Option<isize>,
+        length: Option<isize>,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R> {
+        let data = self.data(offset, length)?;
+        Ok(f(data.as_ref().map(|s| s.as_bytes()).unwrap_or_default()))
+    }
+
     /// Forwards `length` bytes of data from the channel buffer.
     /// Returns the amount of data forwarded and must not be called from an action to avoid yielding.
     #[inline]
@@ -0,0 +1,108 @@
+use std::fmt::Write as _;
+use std::time::SystemTime;
+
+use mlua::{Result, String as LuaString};
+
+use crate::{date, Txn};
+
+/// Information about the TLS client certificate presented on the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertInfo {
+    /// The subject distinguished name (`ssl_c_s_dn`).
+    pub subject_dn: String,
+    /// The common name of the subject (`ssl_c_s_dn(CN)`).
+    pub subject_cn: Option<String>,
+    /// The issuer distinguished name (`ssl_c_i_dn`).
+    pub issuer_dn: String,
+    /// The serial number in hex (`ssl_c_serial`).
+    pub serial: String,
+    /// The start of the validity period (`ssl_c_notbefore`).
+    pub not_before: Option<SystemTime>,
+    /// The end of the validity period (`ssl_c_notafter`).
+    pub not_after: Option<SystemTime>,
+    /// The verification result code, `0` if the certificate was verified (`ssl_c_verify`).
+    pub verify: i64,
+    /// The SHA-1 digest of the DER-encoded certificate in hex (`ssl_c_sha1`).
+    pub sha1: String,
+    /// The SHA-256 digest of the DER-encoded certificate in hex (`ssl_c_der,sha2(256)`).
+    pub sha256: String,
+}
+
+impl ClientCertInfo {
+    /// Returns true if the certificate was successfully verified.
+    pub fn is_verified(&self) -> bool {
+        self.verify == 0
+    }
+
+    /// Returns true if `now` is within the certificate validity period.
+    pub fn is_valid_at(&self, now: SystemTime) -> bool {
+        self.not_before.is_some_and(|t| t <= now) && self.not_after.is_some_and(|t| now <= t)
+    }
+}
+
+impl<'lua> Txn<'lua> {
+    /// Returns information about the TLS client certificate, or `None` if the client
+    /// did not present one.
+    pub fn client_cert(&self) -> Result<Option<ClientCertInfo>> {
+        let used: Option<bool> = self.f.get("ssl_c_used", ())?;
+        if used != Some(true) {
+            return Ok(None);
+        }
+        let binary = |name| -> Result<String> {
+            let data: Option<LuaString> = self.f.get(name, ())?;
+            Ok(data.map(|d| to_hex(d.as_bytes())).unwrap_or_default())
+        };
+        let time = |name| -> Result<Option<SystemTime>> {
+            let time: Option<String> = self.f.get(name, ())?;
+            Ok(time.as_deref().and_then(parse_asn1_time))
+        };
+        let der: Option<LuaString> = self.f.get("ssl_c_der", ())?;
+        let sha256 = match der {
+            Some(der) => {
+                let digest: Option<LuaString> = self.c.get("sha2", (der, 256))?;
+                digest.map(|d| to_hex(d.as_bytes())).unwrap_or_default()
+            }
+            None => String::new(),
+        };
+        Ok(Some(ClientCertInfo {
+            subject_dn: self.f.get_str("ssl_c_s_dn", ())?,
+            subject_cn: self.f.get("ssl_c_s_dn", "CN")?,
+            issuer_dn: self.f.get_str("ssl_c_i_dn", ())?,
+            serial: binary("ssl_c_serial")?,
+            not_before: time("ssl_c_notbefore")?,
+            not_after: time("ssl_c_notafter")?,
+            verify: self
+                .f
+                .get::<_, Option<i64>>("ssl_c_verify", ())?
+                .unwrap_or(-1),
+            sha1: binary("ssl_c_sha1")?,
+            sha256,
+        }))
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    let mut hex = String::with_capacity(data.len() * 2);
+    for b in data {
+        let _ = write!(hex, "{b:02X}");
+    }
+    hex
+}
+
+// Parses ASN.1 UTCTime (`YYMMDDhhmmssZ`) or GeneralizedTime (`YYYYMMDDhhmmssZ`)
+fn parse_asn1_time(s: &str) -> Option<SystemTime> {
+    let s = s.strip_suffix('Z')?;
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (year, rest) = match s.len() {
+        12 => {
+            let yy = s[..2].parse::<i64>().ok()?;
+            (if yy < 50 { 2000 + yy } else { 1900 + yy }, &s[2..])
+        }
+        14 => (s[..4].parse::<i64>().ok()?, &s[4..]),
+        _ => return None,
+    };
+    let num = |i: usize| rest[i..i + 2].parse::<i64>().ok();
+    date::from_civil(year, num(0)?, num(2)?, num(4)?, num(6)?, num(8)?)
+}
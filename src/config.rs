@@ -0,0 +1,498 @@
+//! Module configuration loaded at startup.
+//!
+//! The configuration is read from a JSON, TOML (`toml` feature) or YAML (`yaml` feature) file,
+//! optionally overridden by environment variables, deserialized with serde and validated in a
+//! [`Core::register_init`] function, so HAProxy refuses to start with an invalid configuration.
+//! Callbacks access it through a global [`Config`] handle.
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct Settings {
+//!     upstream: String,
+//!     timeout_ms: u64,
+//! }
+//!
+//! static SETTINGS: Config<Settings> = Config::new();
+//!
+//! #[mlua::lua_module(skip_memory_check)]
+//! fn haproxy_my_module(lua: &Lua) -> Result<bool> {
+//!     let core = Core::new(lua)?;
+//!     let loader = ConfigLoader::new()
+//!         .path("/etc/haproxy/my_module.toml")
+//!         .path_env("MY_MODULE_CONFIG")
+//!         .env_prefix("MY_MODULE_");
+//!     SETTINGS.register_with(&core, loader, |settings| {
+//!         if settings.timeout_ms == 0 {
+//!             return Err(Error::runtime("timeout_ms must be positive"));
+//!         }
+//!         Ok(())
+//!     })?;
+//!
+//!     core.register_action("route", &[Action::HttpReq], 0, |_, txn: Txn| {
+//!         txn.set_var("txn.upstream", &*SETTINGS.get().upstream)
+//!     })?;
+//!     Ok(true)
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+use std::{env, fmt, fs};
+
+use mlua::{Error, Function, Lua, Result, Table, TableExt, Variadic};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::{ComponentKind, Core, LogLevel};
+
+/// Format of the configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    Json,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl Format {
+    /// Detects the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            #[cfg(feature = "toml")]
+            "toml" => Some(Format::Toml),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    fn parse(self, s: &str) -> std::result::Result<Value, String> {
+        match self {
+            Format::Json => serde_json::from_str(s).map_err(|err| err.to_string()),
+            #[cfg(feature = "toml")]
+            Format::Toml => toml::from_str(s).map_err(|err| err.to_string()),
+            #[cfg(feature = "yaml")]
+            Format::Yaml => serde_yaml::from_str(s).map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// Describes where the configuration is loaded from.
+///
+/// The file path is taken from (in order of priority) the [`path_env`] environment variable,
+/// the `config=<path>` argument passed to [`args`] or the [`path`].
+/// Without a path the configuration is built from the environment variables only.
+///
+/// [`path_env`]: ConfigLoader::path_env
+/// [`args`]: ConfigLoader::args
+/// [`path`]: ConfigLoader::path
+#[derive(Debug, Clone, Default)]
+pub struct ConfigLoader {
+    path: Option<PathBuf>,
+    arg_path: Option<PathBuf>,
+    path_env: Option<String>,
+    env_prefix: Option<String>,
+    format: Option<Format>,
+}
+
+impl ConfigLoader {
+    /// Creates a new loader without any sources.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the default configuration file path.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Sets the environment variable overriding the configuration file path.
+    pub fn path_env(mut self, name: impl Into<String>) -> Self {
+        self.path_env = Some(name.into());
+        self
+    }
+
+    /// Takes the configuration file path from the `config=<path>` argument.
+    ///
+    /// HAProxy passes `lua-load` (and `lua-load-per-thread`) arguments to the loaded Lua file as
+    /// `...`, which can forward them to the module.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.arg_path = args.into_iter().find_map(|arg| {
+            let path = arg.as_ref().strip_prefix("config=")?;
+            Some(PathBuf::from(path))
+        });
+        self
+    }
+
+    /// Enables overriding configuration keys by environment variables with the `prefix`.
+    ///
+    /// Variable names are lowercased after removing the prefix, and `__` separates the nested
+    /// keys, eg. `MY_MODULE_SERVER__PORT=8080` sets `server.port` to `8080`.
+    /// Values are parsed as numbers or booleans when possible, otherwise kept as strings.
+    pub fn env_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.env_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Sets the configuration file format, instead of detecting it from the file extension.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Returns the configuration file path, if any.
+    pub fn resolve_path(&self) -> Option<PathBuf> {
+        let env_path = (self.path_env.as_ref())
+            .and_then(env::var_os)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        env_path
+            .or_else(|| self.arg_path.clone())
+            .or_else(|| self.path.clone())
+    }
+
+    /// Loads and deserializes the configuration.
+    pub fn load<T: DeserializeOwned>(&self) -> Result<T> {
+        let mut value = match self.resolve_path() {
+            Some(path) => self.read(&path)?,
+            None => Value::Object(Map::new()),
+        };
+        if let Some(prefix) = &self.env_prefix {
+            // Skip the variable holding the file path, it may share the prefix
+            let vars = env::vars().filter(|(name, _)| Some(name) != self.path_env.as_ref());
+            apply_env(&mut value, prefix, vars)?;
+        }
+        serde_json::from_value(value).map_err(|err| config_error(format_args!("{err}")))
+    }
+
+    fn read(&self, path: &Path) -> Result<Value> {
+        let format = (self.format.or_else(|| Format::from_path(path)))
+            .ok_or_else(|| config_error(format_args!("unknown format of '{}'", path.display())))?;
+        let data = fs::read_to_string(path)
+            .map_err(|err| config_error(format_args!("cannot read '{}': {err}", path.display())))?;
+        format
+            .parse(&data)
+            .map_err(|err| config_error(format_args!("cannot parse '{}': {err}", path.display())))
+    }
+}
+
+fn apply_env(
+    value: &mut Value,
+    prefix: &str,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<()> {
+    for (name, val) in vars {
+        let Some(name) = name.strip_prefix(prefix).filter(|name| !name.is_empty()) else {
+            continue;
+        };
+        let mut target = &mut *value;
+        for key in name.split("__") {
+            let Value::Object(map) = target else {
+                return Err(config_error(format_args!(
+                    "cannot override '{prefix}{name}': parent is not a table"
+                )));
+            };
+            target = (map.entry(key.to_lowercase())).or_insert_with(|| Value::Object(Map::new()));
+        }
+        *target = match serde_json::from_str(&val) {
+            Ok(v @ (Value::Bool(_) | Value::Number(_))) => v,
+            _ => Value::String(val),
+        };
+    }
+    Ok(())
+}
+
+fn config_error(msg: fmt::Arguments) -> Error {
+    Error::runtime(format!("invalid configuration: {msg}"))
+}
+
+fn validation_error(err: Error) -> Error {
+    match err {
+        Error::RuntimeError(msg) => config_error(format_args!("{msg}")),
+        err => config_error(format_args!("{err}")),
+    }
+}
+
+/// A global handle to the module configuration.
+///
+/// The configuration is loaded once (the first Lua state running the init functions wins) and
+/// shared by all threads.
+pub struct Config<T> {
+    value: OnceLock<T>,
+}
+
+impl<T> Config<T> {
+    /// Creates a new empty handle.
+    pub const fn new() -> Self {
+        Config {
+            value: OnceLock::new(),
+        }
+    }
+
+    /// Returns the loaded configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is not loaded yet, which is possible only when called
+    /// during the module loading.
+    #[track_caller]
+    pub fn get(&self) -> &T {
+        self.try_get().expect("configuration is not loaded")
+    }
+
+    /// Returns the loaded configuration, or `None` if it's not loaded yet.
+    pub fn try_get(&self) -> Option<&T> {
+        self.value.get()
+    }
+}
+
+impl<T> Default for Config<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Config<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Config").field(&self.value.get()).finish()
+    }
+}
+
+impl<T: DeserializeOwned + Send + Sync + 'static> Config<T> {
+    /// Registers an init function loading the configuration using the `loader`.
+    pub fn register(&'static self, core: &Core, loader: ConfigLoader) -> Result<()> {
+        self.register_with(core, loader, |_| Ok(()))
+    }
+
+    /// Registers an init function loading the configuration using the `loader` and checking it
+    /// with the `validate` function.
+    ///
+    /// An error prevents HAProxy from starting.
+    pub fn register_with<F>(
+        &'static self,
+        core: &Core,
+        loader: ConfigLoader,
+        validate: F,
+    ) -> Result<()>
+    where
+        F: Fn(&T) -> Result<()> + Send + 'static,
+    {
+        core.register_init(move |_| {
+            if self.value.get().is_some() {
+                return Ok(());
+            }
+            let value = loader.load::<T>()?;
+            validate(&value).map_err(validation_error)?;
+            let _ = self.value.set(value);
+            Ok(())
+        })
+    }
+}
+
+type Validator<T> = Box<dyn Fn(&T) -> Result<()> + Send + Sync>;
+
+/// A global handle to the module configuration that can be replaced at runtime.
+///
+/// Callbacks read the configuration through an [`Arc`] snapshot, so a replacement never affects
+/// the value they are working with. A new value can be set by the `set rust-config <name> <json>`
+/// CLI command (see [`DynamicConfig::register_cli`]) or by reloading the configuration file when
+/// it changes (see [`DynamicConfig::watch`]), without reloading HAProxy.
+///
+/// ```ignore
+/// static FLAGS: DynamicConfig<Flags> = DynamicConfig::new();
+///
+/// let loader = ConfigLoader::new().path("/etc/haproxy/flags.json");
+/// FLAGS.register(&core, loader.clone())?;
+/// FLAGS.register_cli(&core, "flags")?;
+/// FLAGS.watch(&core, loader, Duration::from_secs(5))?;
+///
+/// // in a callback
+/// let flags = FLAGS.load();
+/// ```
+pub struct DynamicConfig<T> {
+    value: RwLock<Option<Arc<T>>>,
+    validator: OnceLock<Validator<T>>,
+    // Modification time of the loaded file, shared by the watchers of all Lua states
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl<T> DynamicConfig<T> {
+    /// Creates a new empty handle.
+    pub const fn new() -> Self {
+        DynamicConfig {
+            value: RwLock::new(None),
+            validator: OnceLock::new(),
+            modified: Mutex::new(None),
+        }
+    }
+
+    /// Returns a snapshot of the current configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is not loaded yet, which is possible only when called
+    /// during the module loading.
+    #[track_caller]
+    pub fn load(&self) -> Arc<T> {
+        self.try_load().expect("configuration is not loaded")
+    }
+
+    /// Returns a snapshot of the current configuration, or `None` if it's not loaded yet.
+    pub fn try_load(&self) -> Option<Arc<T>> {
+        let value = self.value.read().unwrap_or_else(PoisonError::into_inner);
+        value.clone()
+    }
+
+    /// Replaces the configuration, returning the previous value.
+    ///
+    /// The value is not validated.
+    pub fn store(&self, value: T) -> Option<Arc<T>> {
+        let mut current = self.value.write().unwrap_or_else(PoisonError::into_inner);
+        current.replace(Arc::new(value))
+    }
+
+    // Validates the value and replaces the configuration
+    fn update(&self, value: T) -> Result<()> {
+        if let Some(validate) = self.validator.get() {
+            validate(&value).map_err(validation_error)?;
+        }
+        self.store(value);
+        Ok(())
+    }
+}
+
+impl<T> Default for DynamicConfig<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DynamicConfig<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynamicConfig")
+            .field(&self.try_load())
+            .finish()
+    }
+}
+
+impl<T: DeserializeOwned + Send + Sync + 'static> DynamicConfig<T> {
+    /// Registers an init function loading the initial configuration using the `loader`.
+    pub fn register(&'static self, core: &Core, loader: ConfigLoader) -> Result<()> {
+        self.register_with(core, loader, |_| Ok(()))
+    }
+
+    /// Registers an init function loading the initial configuration using the `loader`.
+    ///
+    /// The `validate` function checks the initial configuration (an error prevents HAProxy
+    /// from starting) and every replacement made by the CLI command or the file watcher.
+    pub fn register_with<F>(
+        &'static self,
+        core: &Core,
+        loader: ConfigLoader,
+        validate: F,
+    ) -> Result<()>
+    where
+        F: Fn(&T) -> Result<()> + Send + Sync + 'static,
+    {
+        // Only the first Lua state sets the validator
+        let _ = self.validator.set(Box::new(validate));
+        core.register_init(move |_| {
+            if self.try_load().is_some() {
+                return Ok(());
+            }
+            let modified = loader.resolve_path().and_then(|path| modified(&path));
+            self.update(loader.load()?)?;
+            *self.modified.lock().unwrap_or_else(PoisonError::into_inner) = modified;
+            Ok(())
+        })
+    }
+
+    /// Registers the `set rust-config <name> <json>` CLI command replacing the configuration.
+    pub fn register_cli(&'static self, core: &Core, name: &str) -> Result<()> {
+        let path = ["set", "rust-config", name];
+        let usage = format!("set rust-config {name} <json> : replace the module configuration");
+        let nwords = path.len();
+        let func =
+            core.lua
+                .create_function(move |_, (applet, words): (Table, Variadic<String>)| {
+                    // The command is split on spaces, the rest of the words are the JSON value
+                    let json = words.get(nwords..).unwrap_or_default().join(" ");
+                    let resp = match serde_json::from_str::<T>(&json) {
+                        Ok(value) => match self.update(value) {
+                            Ok(()) => "configuration updated\n".to_string(),
+                            Err(err) => format!("{err}\n"),
+                        },
+                        Err(err) => format!("invalid configuration: {err}\n"),
+                    };
+                    applet.call_method::<_, ()>("send", resp)
+                })?;
+        core.track_registration(ComponentKind::Cli, &path.join(" "))?;
+        core.call_function("register_cli", (path, usage, func))
+    }
+
+    /// Registers a task reloading the configuration file when its modification time changes.
+    ///
+    /// The file is checked every `interval`. Invalid configurations are logged and ignored.
+    pub fn watch(
+        &'static self,
+        core: &Core,
+        loader: ConfigLoader,
+        interval: Duration,
+    ) -> Result<()> {
+        let lua = core.lua;
+        let check = lua.create_function(move |lua, ()| {
+            let Some(path) = loader.resolve_path() else {
+                return Ok(());
+            };
+            let modified = modified(&path);
+            {
+                let mut last = self.modified.lock().unwrap_or_else(PoisonError::into_inner);
+                if modified.is_none() || *last == modified {
+                    return Ok(());
+                }
+                *last = modified;
+            }
+            match loader.load().and_then(|value| self.update(value)) {
+                Ok(()) => log(
+                    lua,
+                    LogLevel::Notice,
+                    format!("configuration reloaded from '{}'", path.display()),
+                ),
+                Err(err) => log(lua, LogLevel::Err, err.to_string()),
+            }
+        })?;
+        let task: Function = lua
+            .load(
+                r#"
+                local check, interval = ...
+                local msleep = core.msleep
+                return function()
+                    while true do
+                        check()
+                        msleep(interval)
+                    end
+                end
+                "#,
+            )
+            .set_name("=config_watch")
+            .call((check, interval.as_millis().max(1) as u64))?;
+        core.call_function("register_task", task)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn log(lua: &Lua, level: LogLevel, msg: String) -> Result<()> {
+    Core::new(lua)?.log(level, msg)
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use mlua::{FromLua, IntoLuaMulti, Result};
+
+use crate::Txn;
+
+/// Transport quality metrics of the client and server connections.
+///
+/// Fields are `None` when the fetch is not supported by the HAProxy version or the
+/// information is not available (eg. no server connection yet, or not a TCP connection).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnInfo {
+    /// The client (frontend) connection metrics (`fc_*` fetches).
+    pub client: TransportInfo,
+    /// The server (backend) connection metrics (`bc_*` fetches).
+    pub server: TransportInfo,
+    /// The number of bytes received from the client (`bytes_in`).
+    pub bytes_in: Option<u64>,
+    /// The number of bytes sent to the client (`bytes_out`).
+    pub bytes_out: Option<u64>,
+}
+
+/// TCP metrics of a connection, as reported by the kernel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransportInfo {
+    /// The smoothed round trip time (`fc_rtt` or `bc_rtt`).
+    pub rtt: Option<Duration>,
+    /// The round trip time variance (`fc_rttvar` or `bc_rttvar`).
+    pub rtt_var: Option<Duration>,
+    /// The number of retransmitted packets (`fc_retrans` or `bc_retrans`).
+    pub retrans: Option<u32>,
+    /// The number of lost packets (`fc_lost` or `bc_lost`).
+    pub lost: Option<u32>,
+}
+
+impl<'lua> Txn<'lua> {
+    /// Returns the transport metrics of the client and server connections in one read.
+    pub fn conn_info(&self) -> Result<ConnInfo> {
+        Ok(ConnInfo {
+            client: self.transport_info("fc")?,
+            server: self.transport_info("bc")?,
+            bytes_in: self.fetch_if_supported("bytes_in", ())?,
+            bytes_out: self.fetch_if_supported("bytes_out", ())?,
+        })
+    }
+
+    fn transport_info(&self, prefix: &str) -> Result<TransportInfo> {
+        let micros = |name: &str| -> Result<Option<Duration>> {
+            let name = format!("{prefix}_{name}");
+            let value: Option<u64> = self.fetch_if_supported(&name, "us")?;
+            Ok(value.map(Duration::from_micros))
+        };
+        Ok(TransportInfo {
+            rtt: micros("rtt")?,
+            rtt_var: micros("rttvar")?,
+            retrans: self.fetch_if_supported(&format!("{prefix}_retrans"), ())?,
+            lost: self.fetch_if_supported(&format!("{prefix}_lost"), ())?,
+        })
+    }
+
+    fn fetch_if_supported<A, R>(&self, name: &str, args: A) -> Result<Option<R>>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLua<'lua>,
+    {
+        if !self.f.has(name)? {
+            return Ok(None);
+        }
+        self.f.get_opt(name, args)
+    }
+}
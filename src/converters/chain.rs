@@ -0,0 +1,57 @@
+use mlua::{FromLua, IntoLua, IntoLuaMulti, Result, TableExt, Value};
+
+use super::Converters;
+
+/// A pipeline of converters applied to a value, like a converter chain in HAProxy configuration.
+///
+/// Intermediate results stay Lua values, and a missing result (`nil`) skips the rest of the
+/// chain. Errors are reported by [`ConverterChain::finish`].
+///
+/// ```ignore
+/// let id: Option<String> = txn.c
+///     .chain(path)
+///     .apply("lower")
+///     .apply_args("field", (2, "/"))
+///     .apply("base64")
+///     .finish()?;
+/// ```
+pub struct ConverterChain<'a, 'lua> {
+    converters: &'a Converters<'lua>,
+    value: Result<Value<'lua>>,
+}
+
+impl<'lua> Converters<'lua> {
+    /// Starts a converter chain with the input `value`.
+    pub fn chain(&self, value: impl IntoLua<'lua>) -> ConverterChain<'_, 'lua> {
+        ConverterChain {
+            converters: self,
+            value: value.into_lua(self.lua),
+        }
+    }
+}
+
+impl<'lua> ConverterChain<'_, 'lua> {
+    /// Applies the converter `name` without arguments.
+    #[inline]
+    pub fn apply(self, name: &str) -> Self {
+        self.apply_args(name, ())
+    }
+
+    /// Applies the converter `name` with the `args`.
+    pub fn apply_args(mut self, name: &str, args: impl IntoLuaMulti<'lua>) -> Self {
+        self.value = match self.value {
+            Ok(Value::Nil) => Ok(Value::Nil),
+            Ok(value) => (args.into_lua_multi(self.converters.lua)).and_then(|mut args| {
+                args.push_front(value);
+                self.converters.class.call_method(name, args)
+            }),
+            Err(err) => Err(err),
+        };
+        self
+    }
+
+    /// Returns the result of the chain converted to `R`.
+    pub fn finish<R: FromLua<'lua>>(self) -> Result<R> {
+        R::from_lua(self.value?, self.converters.lua)
+    }
+}
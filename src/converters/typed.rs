@@ -0,0 +1,116 @@
+use std::net::IpAddr;
+
+use mlua::{IntoLuaMulti, Result, TableExt, Value};
+
+use super::Converters;
+use crate::sample::FromSample;
+
+impl<'lua> Converters<'lua> {
+    // Calls the converter with the binary input and arguments
+    fn convert<A, R>(&self, name: &str, input: &[u8], args: A) -> Result<R>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromSample<'lua>,
+    {
+        let input = self.lua.create_string(input)?;
+        let mut args = args.into_lua_multi(self.lua)?;
+        args.push_front(Value::String(input));
+        let value = self.class.call_method::<_, Value>(name, args)?;
+        R::from_sample(value, self.lua)
+    }
+
+    /// Converts the string to lowercase (`lower`).
+    #[inline]
+    pub fn lower(&self, input: &str) -> Result<String> {
+        self.convert("lower", input.as_bytes(), ())
+    }
+
+    /// Converts the string to uppercase (`upper`).
+    #[inline]
+    pub fn upper(&self, input: &str) -> Result<String> {
+        self.convert("upper", input.as_bytes(), ())
+    }
+
+    /// Encodes the binary input using base64 (`base64`).
+    #[inline]
+    pub fn base64(&self, input: impl AsRef<[u8]>) -> Result<String> {
+        self.convert("base64", input.as_ref(), ())
+    }
+
+    /// Decodes the base64 string (`b64dec`).
+    ///
+    /// Returns `None` if the input is not valid base64.
+    #[inline]
+    pub fn b64dec(&self, input: &str) -> Result<Option<Vec<u8>>> {
+        self.convert("b64dec", input.as_bytes(), ())
+    }
+
+    /// Encodes the binary input as uppercase hexadecimal string (`hex`).
+    #[inline]
+    pub fn hex(&self, input: impl AsRef<[u8]>) -> Result<String> {
+        self.convert("hex", input.as_ref(), ())
+    }
+
+    /// Returns the SHA-1 digest of the binary input (`sha1`).
+    #[inline]
+    pub fn sha1(&self, input: impl AsRef<[u8]>) -> Result<Vec<u8>> {
+        self.convert("sha1", input.as_ref(), ())
+    }
+
+    /// Returns the SHA-2 digest of the binary input (`sha2`).
+    ///
+    /// Valid `bits` values are 224, 256, 384 and 512.
+    #[inline]
+    pub fn sha2(&self, input: impl AsRef<[u8]>, bits: u16) -> Result<Vec<u8>> {
+        self.convert("sha2", input.as_ref(), bits)
+    }
+
+    /// Returns the CRC32 hash of the binary input (`crc32`).
+    #[inline]
+    pub fn crc32(&self, input: impl AsRef<[u8]>) -> Result<u32> {
+        self.convert("crc32", input.as_ref(), ())
+    }
+
+    /// Decodes the URL-encoded string (`url_dec`).
+    #[inline]
+    pub fn url_dec(&self, input: &str) -> Result<Option<String>> {
+        self.convert("url_dec", input.as_bytes(), ())
+    }
+
+    /// Percent-encodes the string for use in a query string (`url_enc`).
+    #[inline]
+    pub fn url_enc(&self, input: impl AsRef<[u8]>) -> Result<String> {
+        self.convert("url_enc", input.as_ref(), ())
+    }
+
+    /// Extracts the value at the JSON `path` (eg. `$.user.id`) of the JSON input (`json_query`).
+    ///
+    /// Returns `None` if the path does not exist.
+    #[inline]
+    pub fn json_query(&self, input: &str, path: &str) -> Result<Option<String>> {
+        self.convert("json_query", input.as_bytes(), path)
+    }
+
+    /// Returns the field number `index` (starting at 1, negative counts from the end) of the
+    /// string split by any of the `separators` characters (`field`).
+    #[inline]
+    pub fn field(&self, input: &str, index: i32, separators: &str) -> Result<Option<String>> {
+        self.convert("field", input.as_bytes(), (index, separators))
+    }
+
+    /// Returns the word number `index` (starting at 1, negative counts from the end) of the
+    /// string split by any of the `separators` characters, ignoring empty words (`word`).
+    #[inline]
+    pub fn word(&self, input: &str, index: i32, separators: &str) -> Result<Option<String>> {
+        self.convert("word", input.as_bytes(), (index, separators))
+    }
+
+    /// Applies the network mask of `bits` length to the IP address (`ipmask`).
+    ///
+    /// The mask is applied to IPv4 addresses, IPv6 addresses are masked by `bits6`.
+    #[inline]
+    pub fn ipmask(&self, addr: IpAddr, bits: u8, bits6: u8) -> Result<Option<IpAddr>> {
+        let addr = addr.to_string();
+        self.convert("ipmask", addr.as_bytes(), (bits, bits6))
+    }
+}
@@ -0,0 +1,439 @@
+//! Cookie parsing and manipulation utilities.
+//!
+//! [`Cookies`] parses the `Cookie` request header, [`SetCookie`] parses and builds
+//! `Set-Cookie` response headers. [`Http`] and [`HttpMessage`] get helpers to modify
+//! individual cookies without clobbering others.
+
+use std::fmt;
+use std::time::Duration;
+
+use mlua::Result;
+
+use crate::{Headers, Http, HttpMessage};
+
+/// Cookies sent in the `Cookie` request header(s), in order of appearance.
+///
+/// Pairs are kept as received, so serializing the cookies back (using [`fmt::Display`])
+/// preserves the pairs that were not modified, including quoted values and malformed pairs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cookies(Vec<String>);
+
+// Splits a `name=value` pair, returns `None` for malformed pairs (without `=` or name)
+fn split_pair(pair: &str) -> Option<(&str, &str)> {
+    let (name, value) = pair.split_once('=')?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| (name, value.trim()))
+}
+
+// Removes the optional double quotes around a cookie value
+fn unquote(value: &str) -> &str {
+    (value.strip_prefix('"'))
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+impl Cookies {
+    /// Parses a `Cookie` header value (`name1=value1; name2=value2`).
+    ///
+    /// Malformed pairs (without `=`) are not returned as cookies, but are kept when
+    /// the cookies are serialized back.
+    pub fn parse(header: &str) -> Self {
+        let mut cookies = Cookies::default();
+        cookies.extend_from(header);
+        cookies
+    }
+
+    /// Collects cookies from all `Cookie` headers.
+    pub fn from_headers(headers: &Headers) -> Result<Self> {
+        let mut cookies = Cookies::default();
+        for header in headers.get::<String>("cookie")? {
+            cookies.extend_from(&header);
+        }
+        Ok(cookies)
+    }
+
+    fn extend_from(&mut self, header: &str) {
+        let pairs = header.split(';').map(str::trim).filter(|p| !p.is_empty());
+        self.0.extend(pairs.map(str::to_string));
+    }
+
+    /// Returns the value of the first cookie `name`, without the surrounding quotes.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// Returns true if the cookie `name` is present.
+    pub fn contains(&self, name: &str) -> bool {
+        self.iter().any(|(n, _)| n == name)
+    }
+
+    /// Replaces all cookies `name` by only one containing `value`.
+    ///
+    /// The `value` is stored as is, it should be quoted by the caller if needed.
+    pub fn set(&mut self, name: &str, value: &str) {
+        let mut found = false;
+        self.0.retain_mut(|pair| {
+            if split_pair(pair).is_none_or(|(n, _)| n != name) {
+                return true;
+            }
+            if found {
+                return false;
+            }
+            found = true;
+            *pair = format!("{name}={value}");
+            true
+        });
+        if !found {
+            self.0.push(format!("{name}={value}"));
+        }
+    }
+
+    /// Removes all cookies `name`. Returns true if any cookie was removed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.0.len();
+        (self.0).retain(|pair| split_pair(pair).is_none_or(|(n, _)| n != name));
+        self.0.len() != len
+    }
+
+    /// Returns an iterator over cookie (name, value) pairs, with values unquoted.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        (self.0.iter())
+            .filter_map(|pair| split_pair(pair))
+            .map(|(name, value)| (name, unquote(value)))
+    }
+
+    /// Returns the number of cookies.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns true if there are no cookies.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Display for Cookies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, pair) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            f.write_str(pair)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `SameSite` cookie attribute.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// A `Set-Cookie` response header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetCookie {
+    pub name: String,
+    pub value: String,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub max_age: Option<i64>,
+    pub expires: Option<String>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>,
+}
+
+impl SetCookie {
+    /// Creates a new cookie `name` with `value` and no attributes.
+    pub fn new(name: &str, value: &str) -> Self {
+        SetCookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Creates a cookie that removes `name` from the client (empty value and `Max-Age=0`).
+    pub fn removal(name: &str) -> Self {
+        Self::new(name, "").max_age(Duration::ZERO)
+    }
+
+    /// Sets the `Path` attribute.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    /// Sets the `Domain` attribute.
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    /// Sets the `Max-Age` attribute.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age.as_secs() as i64);
+        self
+    }
+
+    /// Sets the `Secure` attribute.
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    /// Sets the `HttpOnly` attribute.
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    /// Sets the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Parses a `Set-Cookie` header value.
+    ///
+    /// Unknown attributes are ignored. Returns `None` if the header has no cookie name.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let mut cookie = SetCookie::new(name, value.trim());
+        for attr in parts {
+            let (key, value) = match attr.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => (attr.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "path" => cookie.path = Some(value.to_string()),
+                "domain" => cookie.domain = Some(value.to_string()),
+                "max-age" => cookie.max_age = value.parse().ok(),
+                "expires" => cookie.expires = Some(value.to_string()),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    cookie.same_site = match value.to_ascii_lowercase().as_str() {
+                        "strict" => Some(SameSite::Strict),
+                        "lax" => Some(SameSite::Lax),
+                        "none" => Some(SameSite::None),
+                        _ => None,
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(cookie)
+    }
+}
+
+impl fmt::Display for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={max_age}")?;
+        }
+        if let Some(expires) = &self.expires {
+            write!(f, "; Expires={expires}")?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+// Returns `Set-Cookie` header values that do not belong to the cookie `name`
+fn other_set_cookies(headers: &Headers, name: &str) -> Result<(bool, Vec<String>)> {
+    let values = headers.get::<String>("set-cookie")?;
+    let len = values.len();
+    let others = values
+        .into_iter()
+        .filter(|v| SetCookie::parse(v).is_none_or(|c| c.name != name))
+        .collect::<Vec<_>>();
+    Ok((others.len() != len, others))
+}
+
+impl<'lua> HttpMessage<'lua> {
+    /// Returns cookies sent in the `Cookie` request header(s).
+    pub fn cookies(&self) -> Result<Cookies> {
+        Cookies::from_headers(&self.get_headers()?)
+    }
+
+    /// Sets the request cookie `name` to `value`, keeping other cookies.
+    pub fn set_cookie(&self, name: &str, value: &str) -> Result<()> {
+        let mut cookies = self.cookies()?;
+        cookies.set(name, value);
+        self.set_header("cookie", cookies.to_string())
+    }
+
+    /// Removes the request cookie `name`, keeping other cookies.
+    pub fn del_cookie(&self, name: &str) -> Result<()> {
+        let mut cookies = self.cookies()?;
+        if cookies.remove(name) {
+            match cookies.to_string() {
+                value if value.is_empty() => self.del_header("cookie")?,
+                value => self.set_header("cookie", value)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the `Set-Cookie` response header, replacing any existing one for the same cookie.
+    pub fn add_set_cookie(&self, cookie: &SetCookie) -> Result<()> {
+        self.del_set_cookie(&cookie.name)?;
+        self.add_header("set-cookie", cookie.to_string())
+    }
+
+    /// Removes `Set-Cookie` response headers for the cookie `name`.
+    pub fn del_set_cookie(&self, name: &str) -> Result<()> {
+        let (found, others) = other_set_cookies(&self.get_headers()?, name)?;
+        if found {
+            self.del_header("set-cookie")?;
+            for value in others {
+                self.add_header("set-cookie", value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'lua> Http<'lua> {
+    /// Returns cookies sent in the `Cookie` request header(s).
+    pub fn req_cookies(&self) -> Result<Cookies> {
+        Cookies::from_headers(&self.req_get_headers()?)
+    }
+
+    /// Sets the request cookie `name` to `value`, keeping other cookies.
+    pub fn req_set_cookie(&self, name: &str, value: &str) -> Result<()> {
+        let mut cookies = self.req_cookies()?;
+        cookies.set(name, value);
+        self.req_set_header("cookie", cookies.to_string())
+    }
+
+    /// Removes the request cookie `name`, keeping other cookies.
+    pub fn req_del_cookie(&self, name: &str) -> Result<()> {
+        let mut cookies = self.req_cookies()?;
+        if cookies.remove(name) {
+            match cookies.to_string() {
+                value if value.is_empty() => self.req_del_header("cookie")?,
+                value => self.req_set_header("cookie", value)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the `Set-Cookie` response header, replacing any existing one for the same cookie.
+    pub fn res_add_set_cookie(&self, cookie: &SetCookie) -> Result<()> {
+        self.res_del_set_cookie(&cookie.name)?;
+        self.res_add_header("set-cookie", cookie.to_string())
+    }
+
+    /// Removes `Set-Cookie` response headers for the cookie `name`.
+    pub fn res_del_set_cookie(&self, name: &str) -> Result<()> {
+        let (found, others) = other_set_cookies(&self.res_get_headers()?, name)?;
+        if found {
+            self.res_del_header("set-cookie")?;
+            for value in others {
+                self.res_add_header("set-cookie", value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cookies() {
+        let cookies = Cookies::parse(r#"a=1; b="quoted value";c=;  d = x "#);
+        assert_eq!(cookies.get("a"), Some("1"));
+        assert_eq!(cookies.get("b"), Some("quoted value"));
+        assert_eq!(cookies.get("c"), Some(""));
+        assert_eq!(cookies.get("d"), Some("x"));
+        assert_eq!(cookies.get("e"), None);
+        assert_eq!(cookies.len(), 4);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut cookies = Cookies::parse(r#"a="quoted"; flag; b=2; =x"#);
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies.to_string(), r#"a="quoted"; flag; b=2; =x"#);
+
+        cookies.set("b", "3");
+        assert_eq!(cookies.to_string(), r#"a="quoted"; flag; b=3; =x"#);
+        cookies.set("c", r#""new""#);
+        assert_eq!(cookies.get("c"), Some("new"));
+
+        assert!(cookies.remove("a"));
+        assert!(!cookies.remove("a"));
+        assert_eq!(cookies.to_string(), r#"flag; b=3; =x; c="new""#);
+    }
+
+    #[test]
+    fn test_set_deduplicates() {
+        let mut cookies = Cookies::parse("a=1; b=2; a=3");
+        cookies.set("a", "4");
+        assert_eq!(cookies.to_string(), "a=4; b=2");
+    }
+
+    #[test]
+    fn test_set_cookie() {
+        let cookie =
+            SetCookie::parse("sid=abc; Path=/; Max-Age=60; Secure; HttpOnly; SameSite=lax")
+                .unwrap();
+        assert_eq!(cookie.name, "sid");
+        assert_eq!(cookie.value, "abc");
+        assert_eq!(cookie.path.as_deref(), Some("/"));
+        assert_eq!(cookie.max_age, Some(60));
+        assert!(cookie.secure && cookie.http_only);
+        assert_eq!(cookie.same_site, Some(SameSite::Lax));
+        assert_eq!(
+            cookie.to_string(),
+            "sid=abc; Path=/; Max-Age=60; Secure; HttpOnly; SameSite=Lax"
+        );
+
+        assert_eq!(SetCookie::parse("=abc"), None);
+        assert_eq!(SetCookie::removal("sid").to_string(), "sid=; Max-Age=0");
+    }
+}
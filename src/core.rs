@@ -2,16 +2,21 @@ use std::collections::HashMap;
 #[cfg(feature = "async")]
 use std::future::Future;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use mlua::{AnyUserData, AsChunk, FromLuaMulti, IntoLua, Lua, Result, Table, TableExt, Value};
+use mlua::{
+    AnyUserData, AsChunk, FromLuaMulti, Function, IntoLua, IntoLuaMulti, Lua, Result, Table,
+    TableExt, Value,
+};
 
+use crate::error::{CallbackKind, Error};
 use crate::filter::UserFilterWrapper;
-use crate::{Proxy, UserFilter};
+use crate::{ComponentKind, MaybeSend, Proxy, UserFilter};
 
 /// The "Core" class contains all the HAProxy core functions.
 #[derive(Clone)]
 pub struct Core<'lua> {
-    lua: &'lua Lua,
+    pub(crate) lua: &'lua Lua,
     class: Table<'lua>,
 }
 
@@ -46,7 +51,8 @@ pub enum ServiceMode {
     Http,
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Log levels, ordered from the most to the least severe.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum LogLevel {
     Emerg,
     Alert,
@@ -156,7 +162,8 @@ impl<'lua> Core<'lua> {
         A: FromLuaMulti<'lua>,
         F: Fn(&'lua Lua, A) -> Result<()> + Send + 'static,
     {
-        let func = self.lua.create_function(func)?;
+        let func = self.create_callback(CallbackKind::Action, name, func)?;
+        self.track_registration(ComponentKind::Action, name)?;
         let actions = actions.iter().map(|act| act.as_str()).collect::<Vec<_>>();
         self.class
             .call_function("register_action", (name, actions, func, nb_args))
@@ -165,6 +172,7 @@ impl<'lua> Core<'lua> {
     /// Registers an asynchronous function executed as an action.
     ///
     /// See [`Core::register_action`] for more details.
+    #[cfg(feature = "async")]
     pub fn register_async_action<F, A, FR>(
         &self,
         name: &str,
@@ -173,11 +181,20 @@ impl<'lua> Core<'lua> {
         func: F,
     ) -> Result<()>
     where
-        F: Fn(A) -> FR + 'static,
+        F: Fn(A) -> FR + MaybeSend + 'static,
         A: FromLuaMulti<'lua> + 'static,
         FR: Future<Output = Result<()>> + Send + 'static,
     {
-        let func = crate::r#async::create_async_function(self.lua, func)?;
+        let cb_name = name.to_string();
+        let func = crate::r#async::create_async_function(self.lua, move |args| {
+            let fut = func(args);
+            let name = cb_name.clone();
+            async move {
+                let res = fut.await;
+                res.map_err(|err| Error::callback(CallbackKind::Action, &name, err))
+            }
+        })?;
+        self.track_registration(ComponentKind::Action, name)?;
         let actions = actions.iter().map(|act| act.as_str()).collect::<Vec<_>>();
         self.class
             .call_function("register_action", (name, actions, func, nb_args))
@@ -197,6 +214,7 @@ impl<'lua> Core<'lua> {
         S: AsChunk<'lua, 'a>,
     {
         let func = self.lua.load(code).into_function()?;
+        self.track_registration(ComponentKind::Action, name)?;
         self.class
             .call_function("register_action", (name, actions.to_vec(), func, nb_args))
     }
@@ -209,7 +227,8 @@ impl<'lua> Core<'lua> {
         R: IntoLua<'lua>,
         F: Fn(&'lua Lua, A) -> Result<R> + Send + 'static,
     {
-        let func = self.lua.create_function(func)?;
+        let func = self.create_callback(CallbackKind::Converter, name, func)?;
+        self.track_registration(ComponentKind::Converter, name)?;
         self.class
             .call_function("register_converters", (name, func))
     }
@@ -222,6 +241,7 @@ impl<'lua> Core<'lua> {
         S: AsChunk<'lua, 'a>,
     {
         let func = self.lua.load(code).into_function()?;
+        self.track_registration(ComponentKind::Converter, name)?;
         self.class
             .call_function("register_converters", (name, func))
     }
@@ -234,7 +254,8 @@ impl<'lua> Core<'lua> {
         R: IntoLua<'lua>,
         F: Fn(&'lua Lua, A) -> Result<R> + Send + 'static,
     {
-        let func = self.lua.create_function(func)?;
+        let func = self.create_callback(CallbackKind::Fetch, name, func)?;
+        self.track_registration(ComponentKind::Fetch, name)?;
         self.class.call_function("register_fetches", (name, func))
     }
 
@@ -246,17 +267,49 @@ impl<'lua> Core<'lua> {
         S: AsChunk<'lua, 'a>,
     {
         let func = self.lua.load(code).into_function()?;
+        self.track_registration(ComponentKind::Fetch, name)?;
         self.class.call_function("register_fetches", (name, func))
     }
 
     /// Registers a custom filter that implements [`UserFilter`] trait.
-    pub fn register_filter<T: UserFilter + 'static>(&self, name: &str) -> Result<()> {
+    pub fn register_filter<T: UserFilter + MaybeSend + 'static>(&self, name: &str) -> Result<()> {
+        self.register_filter_sampled::<T>(name, T::SAMPLE_RATE)
+    }
+
+    /// Registers a custom filter that implements [`UserFilter`] trait and is instantiated
+    /// only for a fraction of streams defined by `rate` (between 0.0 and 1.0).
+    ///
+    /// Streams that are not sampled do not get the filter attached (`new` returns nil to HAProxy).
+    pub fn register_filter_sampled<T: UserFilter + MaybeSend + 'static>(
+        &self,
+        name: &str,
+        rate: f64,
+    ) -> Result<()> {
+        self.register_filter_with::<T>(name, rate, None)
+    }
+
+    /// Registers a custom filter, optionally storing the `preset` value at index `0`
+    /// of the filter arguments table (where filters keep their parsed configuration).
+    pub(crate) fn register_filter_with<T: UserFilter + MaybeSend + 'static>(
+        &self,
+        name: &str,
+        rate: f64,
+        preset: Option<Value<'lua>>,
+    ) -> Result<()> {
         let lua = self.lua;
-        let func = lua.create_function(|_, (class, args): (Table, Table)| {
+        let preset = match preset {
+            Some(preset) => Some(lua.create_registry_value(preset)?),
+            None => None,
+        };
+        let func = lua.create_function(move |lua, (class, args): (Table, Table)| {
+            if let Some(preset) = &preset {
+                args.raw_set(0, lua.registry_value::<Value>(preset)?)?;
+            }
             class.raw_set("args", args)?;
             Ok(class)
         });
-        let filter_class = UserFilterWrapper::<T>::make_class(lua)?;
+        let filter_class = UserFilterWrapper::<T>::make_class(lua, rate)?;
+        self.track_registration(ComponentKind::Filter, name)?;
         self.class
             .call_function("register_filter", (name, filter_class, func))
     }
@@ -268,6 +321,24 @@ impl<'lua> Core<'lua> {
         S: AsChunk<'lua, 'a>,
     {
         let func = self.lua.load(code).into_function()?;
+        self.track_registration(ComponentKind::Service, name)?;
+        let mode = match mode {
+            ServiceMode::Tcp => "tcp",
+            ServiceMode::Http => "http",
+        };
+        self.class
+            .call_function("register_service", (name, mode, func))
+    }
+
+    /// Registers a function executed as a service.
+    /// The function receives the `AppletTCP` or `AppletHTTP` object (depending on the `mode`).
+    /// All the registered service can be used in HAProxy with the prefix `lua.`.
+    pub fn register_service<F>(&self, name: &str, mode: ServiceMode, func: F) -> Result<()>
+    where
+        F: Fn(&'lua Lua, Table<'lua>) -> Result<()> + MaybeSend + 'static,
+    {
+        let func = self.create_callback(CallbackKind::Service, name, func)?;
+        self.track_registration(ComponentKind::Service, name)?;
         let mode = match mode {
             ServiceMode::Tcp => "tcp",
             ServiceMode::Http => "http",
@@ -276,6 +347,18 @@ impl<'lua> Core<'lua> {
             .call_function("register_service", (name, mode, func))
     }
 
+    /// Registers all functions marked with `fetch`, `converter`, `action` and `service`
+    /// attribute macros.
+    ///
+    /// This is called automatically by the `module` attribute macro.
+    #[cfg(feature = "macros")]
+    pub fn register_collected(&self) -> Result<()> {
+        for registration in inventory::iter::<crate::__private::Registration> {
+            (registration.register)(self)?;
+        }
+        Ok(())
+    }
+
     /// Registers a function executed after the configuration parsing.
     /// This is useful to check any parameters.
     pub fn register_init<F>(&self, func: F) -> Result<()>
@@ -286,6 +369,28 @@ impl<'lua> Core<'lua> {
         self.class.call_function("register_init", func)
     }
 
+    /// Registers a function executed once per Lua state, after the configuration parsing.
+    ///
+    /// With `lua-load-per-thread` every HAProxy thread has its own Lua state, where the module
+    /// is loaded independently. The function receives the thread number (`0` for the state shared
+    /// by all threads) and is guaranteed to run exactly once in each state, which makes it
+    /// suitable for installing per-thread caches, loggers or pools.
+    pub fn on_thread_init<F>(&self, func: F) -> Result<()>
+    where
+        F: Fn(&'lua Lua, u16) -> Result<()> + Send + 'static,
+    {
+        let done = AtomicBool::new(false);
+        let func = self.lua.create_function(move |lua, ()| {
+            if done.swap(true, Ordering::Relaxed) {
+                return Ok(());
+            }
+            let core: Table = lua.globals().get("core")?;
+            let thread: Option<u16> = core.get("thread")?;
+            func(lua, thread.unwrap_or(0))
+        })?;
+        self.class.call_function("register_init", func)
+    }
+
     /// Registers and start an independent task.
     /// The task is started when the HAProxy main scheduler starts.
     pub fn register_task<F>(&self, func: F) -> Result<()>
@@ -300,7 +405,7 @@ impl<'lua> Core<'lua> {
     #[cfg(feature = "async")]
     pub fn register_async_task<F, FR>(&self, func: F) -> Result<()>
     where
-        F: Fn() -> FR + 'static,
+        F: Fn() -> FR + MaybeSend + 'static,
         FR: Future<Output = Result<()>> + Send + 'static,
     {
         let func = crate::r#async::create_async_function(self.lua, move |()| func())?;
@@ -324,6 +429,7 @@ impl<'lua> Core<'lua> {
         S: AsChunk<'lua, 'a>,
     {
         let func = self.lua.load(code).into_function()?;
+        self.track_registration(ComponentKind::Cli, &path.join(" "))?;
         self.class
             .call_function("register_cli", (path, usage, func))
     }
@@ -347,6 +453,8 @@ impl<'lua> Core<'lua> {
         self.class.call_function("match_addr", (addr1, addr2))
     }
 
+    /// Registers a function that will be called on specific system events.
+    /// This is HAProxy >=2.8 feature.
     pub fn event_sub<'a, S>(&self, event_types: &[&str], code: S) -> Result<()>
     where
         S: AsChunk<'lua, 'a>,
@@ -356,6 +464,26 @@ impl<'lua> Core<'lua> {
     }
 }
 
+impl<'lua> Core<'lua> {
+    // Creates a Lua function from the callback, adding the context to its errors
+    fn create_callback<A, R, F>(
+        &self,
+        kind: CallbackKind,
+        name: &str,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: Fn(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
+    {
+        let name = name.to_string();
+        self.lua.create_function(move |lua, args| {
+            func(lua, args).map_err(|err| Error::callback(kind, &name, err))
+        })
+    }
+}
+
 impl<'lua> Deref for Core<'lua> {
     type Target = Table<'lua>;
 
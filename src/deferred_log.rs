@@ -0,0 +1,81 @@
+use std::sync::Mutex;
+
+use mlua::{Function, Lua, Result, TableExt};
+
+use crate::{Core, LogLevel};
+
+// Messages waiting to be logged by the HAProxy task
+static PENDING: Mutex<Pending> = Mutex::new(Pending {
+    messages: Vec::new(),
+    dropped: 0,
+});
+
+// Maximum number of pending messages, the extra ones are dropped
+const MAX_PENDING: usize = 10_000;
+
+// How often (in milliseconds) pending messages are flushed to the HAProxy log
+const FLUSH_INTERVAL: u32 = 100;
+
+struct Pending {
+    messages: Vec<(LogLevel, String)>,
+    dropped: usize,
+}
+
+/// Queues the message to be logged through `core.log()` by the flush task.
+///
+/// Can be called from any thread.
+pub(crate) fn push(level: LogLevel, msg: String) {
+    let mut pending = PENDING.lock().unwrap_or_else(|err| err.into_inner());
+    if pending.messages.len() < MAX_PENDING {
+        pending.messages.push((level, msg));
+    } else {
+        pending.dropped += 1;
+    }
+}
+
+/// Registers a HAProxy task (once per Lua state) that flushes queued messages.
+pub(crate) fn register_flush_task(lua: &Lua) -> Result<()> {
+    const KEY: &str = "__HAPROXY_DEFERRED_LOG_TASK";
+    if lua.named_registry_value::<bool>(KEY)? {
+        return Ok(());
+    }
+
+    let drain = lua.create_function(|lua, ()| {
+        let (messages, dropped) = {
+            let mut pending = PENDING.lock().unwrap_or_else(|err| err.into_inner());
+            let dropped = std::mem::take(&mut pending.dropped);
+            (std::mem::take(&mut pending.messages), dropped)
+        };
+        let result = lua.create_table_with_capacity(messages.len() * 2 + 2, 0)?;
+        if dropped > 0 {
+            let msg = format!("{dropped} log message(s) dropped");
+            result.raw_push(LogLevel::Warning)?;
+            result.raw_push(msg)?;
+        }
+        for (level, msg) in messages {
+            result.raw_push(level)?;
+            result.raw_push(msg)?;
+        }
+        Ok(result)
+    })?;
+    let task: Function = lua
+        .load(
+            r#"
+            local drain, interval = ...
+            local log, msleep = core.log, core.msleep
+            return function()
+                while true do
+                    local messages = drain()
+                    for i = 1, #messages, 2 do
+                        log(messages[i], messages[i + 1])
+                    end
+                    msleep(interval)
+                end
+            end
+            "#,
+        )
+        .set_name("=deferred_log")
+        .call((drain, FLUSH_INTERVAL))?;
+    Core::new(lua)?.call_function::<_, ()>("register_task", task)?;
+    lua.set_named_registry_value(KEY, true)
+}
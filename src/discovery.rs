@@ -0,0 +1,565 @@
+//! Service discovery: keeps backend servers in sync with a source of endpoints.
+//!
+//! An [`EndpointSource`] is polled on the async runtime, and a HAProxy task reconciles the
+//! backend servers with the resolved endpoints:
+//!
+//! - servers whose address is still resolved are set ready (and their weight updated)
+//! - free servers (eg. created by `server-template`) get the new addresses and are set ready
+//! - servers whose address is gone are set to maintenance
+//!
+//! An empty list of endpoints is ignored by default (see [`Reconciler::allow_empty`]),
+//! so a source failing open does not put the whole backend to maintenance.
+//!
+//! When a [`RuntimeClient`] is configured, servers are added when there are no free servers
+//! left, and the (dynamic) servers added by the reconciler are deleted when not needed anymore.
+//!
+//! ```ignore
+//! let source = DnsSource::srv("_http._tcp.app.service.consul");
+//! Reconciler::new("app", source)
+//!     .interval(Duration::from_secs(5))
+//!     .runtime_client(RuntimeClient::new("unix@/run/haproxy.sock".parse()?))
+//!     .start(&core)?;
+//! ```
+//!
+//! The reconciler should be started in a single Lua state (`lua-load`, not `lua-load-per-thread`).
+
+use std::collections::{BTreeSet, HashSet};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use futures_util::future::{self, BoxFuture};
+use mlua::{Function, Lua, Result, Table, TableExt};
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+use crate::runtime_api::RuntimeClient;
+use crate::server;
+use crate::{deferred_log, Core, LogLevel, Proxy, Weight};
+
+mod dns;
+#[cfg(feature = "serde")]
+mod http_json;
+
+pub use dns::DnsSource;
+#[cfg(feature = "serde")]
+pub use http_json::HttpJsonSource;
+
+/// A resolved endpoint of a service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Endpoint {
+    /// The endpoint address.
+    pub addr: SocketAddr,
+    /// The server weight, or `None` to keep the current one.
+    pub weight: Option<u32>,
+}
+
+impl Endpoint {
+    /// Creates a new endpoint without a weight.
+    pub fn new(ip: IpAddr, port: u16) -> Self {
+        Endpoint {
+            addr: SocketAddr::new(ip, port),
+            weight: None,
+        }
+    }
+
+    /// Sets the server weight.
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = Some(weight);
+        self
+    }
+}
+
+/// A source of the service endpoints.
+pub trait EndpointSource: Send + Sync + 'static {
+    /// Returns the current endpoints of the service.
+    fn endpoints(&self) -> BoxFuture<'_, io::Result<Vec<Endpoint>>>;
+}
+
+/// A source returning a fixed list of endpoints.
+impl EndpointSource for Vec<Endpoint> {
+    fn endpoints(&self) -> BoxFuture<'_, io::Result<Vec<Endpoint>>> {
+        let endpoints = self.clone();
+        Box::pin(async move { Ok(endpoints) })
+    }
+}
+
+/// Keeps the servers of a backend in sync with an [`EndpointSource`].
+pub struct Reconciler {
+    backend: String,
+    source: Arc<dyn EndpointSource>,
+    interval: Duration,
+    runtime: Option<RuntimeClient>,
+    server_prefix: String,
+    allow_empty: bool,
+}
+
+impl Reconciler {
+    /// Creates a new reconciler for the `backend` servers.
+    pub fn new(backend: impl Into<String>, source: impl EndpointSource) -> Self {
+        Reconciler {
+            backend: backend.into(),
+            source: Arc::new(source),
+            interval: Duration::from_secs(10),
+            runtime: None,
+            server_prefix: "srv".into(),
+            allow_empty: false,
+        }
+    }
+
+    /// Sets how often the source is polled and the servers are reconciled (10 seconds by default).
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the runtime API client used to add and delete servers.
+    pub fn runtime_client(mut self, client: RuntimeClient) -> Self {
+        self.runtime = Some(client);
+        self
+    }
+
+    /// Sets the name prefix of the servers added through the runtime API (`srv` by default).
+    pub fn server_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.server_prefix = prefix.into();
+        self
+    }
+
+    /// Sets whether an empty list of endpoints puts all the servers to maintenance.
+    ///
+    /// By default (`false`) an empty list is ignored and the servers are kept as they are.
+    pub fn allow_empty(mut self, allow: bool) -> Self {
+        self.allow_empty = allow;
+        self
+    }
+
+    /// Starts polling the source and reconciling the servers.
+    pub fn start(self, core: &Core) -> Result<()> {
+        let lua = core.lua;
+        let shared = Arc::new(Shared::default());
+        let interval = self.interval;
+
+        // Poll the source on the async runtime
+        let (commands, commands_rx) = mpsc::unbounded_channel::<Command>();
+        let commands_rx = Mutex::new(Some(commands_rx));
+        let (source, poller_shared) = (self.source.clone(), shared.clone());
+        let (runtime, backend) = (self.runtime.clone(), self.backend.clone());
+        core.register_async_task(move || {
+            let (source, shared) = (source.clone(), poller_shared.clone());
+            let (runtime, backend) = (runtime.clone(), backend.clone());
+            let commands_rx = (commands_rx.lock())
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+            async move {
+                let Some(mut commands_rx) = commands_rx else {
+                    return Ok(());
+                };
+                let poll = async {
+                    let mut ticker = tokio::time::interval(interval);
+                    loop {
+                        ticker.tick().await;
+                        match source.endpoints().await {
+                            Ok(endpoints) => *shared.desired() = Some(endpoints),
+                            Err(err) => {
+                                let msg =
+                                    format!("discovery for backend '{backend}' failed: {err}");
+                                deferred_log::push(LogLevel::Warning, msg);
+                            }
+                        }
+                    }
+                };
+                let execute = async {
+                    while let Some(cmd) = commands_rx.recv().await {
+                        if let Some(runtime) = &runtime {
+                            cmd.execute(runtime, &backend).await;
+                        }
+                        shared.pending().remove(&cmd.key());
+                    }
+                };
+                future::join(poll, execute).await;
+                Ok(())
+            }
+        })?;
+
+        // Apply the changes in the HAProxy context
+        let step = lua.create_function(move |lua, ()| {
+            let result = self.reconcile(lua, &shared, &commands);
+            if let Err(err) = result {
+                let msg = format!("discovery for backend '{}' failed: {err}", self.backend);
+                Core::new(lua)?.log(LogLevel::Warning, msg)?;
+            }
+            Ok(())
+        })?;
+        let task: Function = lua
+            .load(
+                r#"
+                local step, interval = ...
+                local msleep = core.msleep
+                return function()
+                    while true do
+                        step()
+                        msleep(interval)
+                    end
+                end
+                "#,
+            )
+            .set_name("=discovery")
+            .call((step, interval.as_millis().max(1) as u64))?;
+        deferred_log::register_flush_task(lua)?;
+        core.call_function("register_task", task)
+    }
+
+    fn reconcile(
+        &self,
+        lua: &Lua,
+        shared: &Shared,
+        commands: &UnboundedSender<Command>,
+    ) -> Result<()> {
+        let Some(desired) = shared.desired().clone() else {
+            return Ok(());
+        };
+        let backends: Table = Core::new(lua)?.get("backends")?;
+        let Some(proxy) = backends.get::<_, Option<Proxy>>(self.backend.as_str())? else {
+            return Err(mlua::Error::runtime("backend not found"));
+        };
+
+        let mut servers = Vec::new();
+        let mut states = Vec::new();
+        for item in proxy.servers_iter()? {
+            let (name, server) = item?;
+            let status: Option<String> = server.get_stats()?.get("status")?;
+            states.push(ServerState {
+                name,
+                addr: server::parse_addr(&server.get_addr()?),
+                maint: status.is_some_and(|s| s.starts_with("MAINT")),
+                dynamic: server.is_dynamic()?,
+                weight: server.get_weight()?,
+            });
+            servers.push(server);
+        }
+
+        let send = |cmd: Command| {
+            if shared.pending().insert(cmd.key()) {
+                let _ = commands.send(cmd);
+            }
+        };
+        let changes = plan(
+            &states,
+            &desired,
+            &self.server_prefix,
+            self.runtime.is_some(),
+            self.allow_empty,
+        );
+        for change in changes {
+            let server = |name: &str| {
+                let i = states
+                    .iter()
+                    .position(|s| s.name == name)
+                    .expect("known server");
+                &servers[i]
+            };
+            match change {
+                Change::Ready {
+                    server: name,
+                    weight,
+                } => {
+                    let server = server(&name);
+                    if let Some(weight) = weight {
+                        server.set_weight(Weight::Absolute(weight))?;
+                    }
+                    server.set_ready()?;
+                }
+                Change::Assign {
+                    server: name,
+                    endpoint,
+                } => {
+                    let server = server(&name);
+                    let ip = endpoint.addr.ip().to_string();
+                    server.set_addr(ip, Some(endpoint.addr.port()))?;
+                    if let Some(weight) = endpoint.weight {
+                        server.set_weight(Weight::Absolute(weight))?;
+                    }
+                    server.set_ready()?;
+                }
+                Change::Maint { server: name } => server(&name).set_maint()?,
+                Change::Add { server, endpoint } => {
+                    send(Command::Add { server, endpoint });
+                }
+                Change::Delete { server } => send(Command::Delete { server }),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    // The last resolved endpoints, `None` until the source is polled successfully
+    desired: Mutex<Option<Vec<Endpoint>>>,
+    // Runtime API commands sent to the async task and not completed yet
+    pending: Mutex<HashSet<String>>,
+}
+
+impl Shared {
+    fn desired(&self) -> std::sync::MutexGuard<'_, Option<Vec<Endpoint>>> {
+        self.desired.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+enum Command {
+    Add { server: String, endpoint: Endpoint },
+    Delete { server: String },
+}
+
+impl Command {
+    fn key(&self) -> String {
+        match self {
+            Command::Add { server, .. } => format!("add {server}"),
+            Command::Delete { server } => format!("del {server}"),
+        }
+    }
+
+    async fn execute(&self, runtime: &RuntimeClient, backend: &str) {
+        // Commands with the confirmation printed on success (other commands print nothing)
+        let cmds = match self {
+            Command::Add { server, endpoint } => {
+                let mut add = format!("add server {backend}/{server} {}", endpoint.addr);
+                if let Some(weight) = endpoint.weight {
+                    add.push_str(&format!(" weight {weight}"));
+                }
+                vec![
+                    (add, "New server registered."),
+                    (format!("enable server {backend}/{server}"), ""),
+                ]
+            }
+            Command::Delete { server } => {
+                vec![(format!("del server {backend}/{server}"), "Server deleted.")]
+            }
+        };
+        for (cmd, confirmation) in cmds {
+            let error = match runtime.command(&cmd).await {
+                Ok(output) if output.trim() == confirmation => continue,
+                Ok(output) => output.trim().to_string(),
+                Err(err) => err.to_string(),
+            };
+            deferred_log::push(LogLevel::Warning, format!("'{cmd}' failed: {error}"));
+            return;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ServerState {
+    name: String,
+    addr: Option<SocketAddr>,
+    maint: bool,
+    dynamic: bool,
+    weight: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    Ready { server: String, weight: Option<u32> },
+    Assign { server: String, endpoint: Endpoint },
+    Maint { server: String },
+    Add { server: String, endpoint: Endpoint },
+    Delete { server: String },
+}
+
+// Computes the changes bringing the servers to the desired endpoints
+fn plan(
+    servers: &[ServerState],
+    desired: &[Endpoint],
+    prefix: &str,
+    runtime: bool,
+    allow_empty: bool,
+) -> Vec<Change> {
+    let mut changes = Vec::new();
+    if desired.is_empty() && !allow_empty {
+        return changes;
+    }
+    let mut seen = HashSet::new();
+    let desired = (desired.iter())
+        .filter(|ep| seen.insert(ep.addr))
+        .collect::<Vec<_>>();
+
+    // Servers already having a desired address
+    let mut matched = HashSet::new();
+    let mut unmatched_endpoints = Vec::new();
+    for endpoint in desired {
+        let server =
+            (servers.iter()).find(|s| s.addr == Some(endpoint.addr) && !matched.contains(&s.name));
+        match server {
+            Some(server) => {
+                matched.insert(server.name.clone());
+                let weight = endpoint.weight.filter(|&w| w != server.weight);
+                if server.maint || weight.is_some() {
+                    changes.push(Change::Ready {
+                        server: server.name.clone(),
+                        weight,
+                    });
+                }
+            }
+            None => unmatched_endpoints.push(*endpoint),
+        }
+    }
+
+    // Reuse free servers (in maintenance first), then add new ones
+    let mut free = (servers.iter())
+        .filter(|s| !matched.contains(&s.name))
+        .collect::<Vec<_>>();
+    free.sort_by_key(|s| (!s.maint, s.name.clone()));
+    let mut free = free.into_iter();
+    let mut names = servers
+        .iter()
+        .map(|s| s.name.clone())
+        .collect::<BTreeSet<_>>();
+    for endpoint in unmatched_endpoints {
+        if let Some(server) = free.next() {
+            changes.push(Change::Assign {
+                server: server.name.clone(),
+                endpoint,
+            });
+        } else if runtime {
+            let name = (1..)
+                .map(|i| format!("{prefix}{i}"))
+                .find(|name| !names.contains(name))
+                .expect("free server name");
+            names.insert(name.clone());
+            changes.push(Change::Add {
+                server: name,
+                endpoint,
+            });
+        }
+    }
+
+    // Disable the servers left, deleting the ones added by the reconciler
+    for server in free {
+        if !server.maint {
+            changes.push(Change::Maint {
+                server: server.name.clone(),
+            });
+        } else if runtime && server.dynamic && server.name.starts_with(prefix) {
+            changes.push(Change::Delete {
+                server: server.name.clone(),
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(port: u16) -> Endpoint {
+        Endpoint::new(IpAddr::from([10, 0, 0, 1]), port)
+    }
+
+    fn server(name: &str, port: Option<u16>, maint: bool, dynamic: bool) -> ServerState {
+        ServerState {
+            name: name.into(),
+            addr: port.map(|port| endpoint(port).addr),
+            maint,
+            dynamic,
+            weight: 1,
+        }
+    }
+
+    #[test]
+    fn test_plan_add() {
+        let servers = [server("srv1", Some(1), false, true)];
+        let desired = [endpoint(1), endpoint(2), endpoint(2)];
+        assert_eq!(plan(&servers, &desired, "srv", false, false), []);
+        assert_eq!(
+            plan(&servers, &desired, "srv", true, false),
+            [Change::Add {
+                server: "srv2".into(),
+                endpoint: endpoint(2),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plan_remove() {
+        let servers = [
+            server("srv1", Some(1), false, true),
+            server("srv2", Some(2), false, true),
+            server("srv3", Some(3), true, true),
+            server("static1", Some(4), true, false),
+        ];
+        let desired = [endpoint(1)];
+        assert_eq!(
+            plan(&servers, &desired, "srv", false, false),
+            [Change::Maint {
+                server: "srv2".into()
+            }]
+        );
+        assert_eq!(
+            plan(&servers, &desired, "srv", true, false),
+            [
+                Change::Delete {
+                    server: "srv3".into()
+                },
+                Change::Maint {
+                    server: "srv2".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_reuse_slot() {
+        let servers = [
+            server("app1", Some(1), true, false),
+            server("app2", Some(2), false, false),
+            server("app3", None, true, false),
+            server("app4", Some(4), false, false),
+        ];
+        let desired = [endpoint(1).with_weight(5), endpoint(5)];
+        assert_eq!(
+            plan(&servers, &desired, "srv", true, false),
+            [
+                Change::Ready {
+                    server: "app1".into(),
+                    weight: Some(5),
+                },
+                Change::Assign {
+                    server: "app3".into(),
+                    endpoint: endpoint(5),
+                },
+                Change::Maint {
+                    server: "app2".into()
+                },
+                Change::Maint {
+                    server: "app4".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_empty() {
+        let servers = [
+            server("srv1", Some(1), false, true),
+            server("srv2", Some(2), true, true),
+        ];
+        assert_eq!(plan(&servers, &[], "srv", true, false), []);
+        assert_eq!(
+            plan(&servers, &[], "srv", true, true),
+            [
+                Change::Delete {
+                    server: "srv2".into()
+                },
+                Change::Maint {
+                    server: "srv1".into()
+                },
+            ]
+        );
+        assert_eq!(plan(&[], &[], "srv", true, true), []);
+    }
+}
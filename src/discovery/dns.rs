@@ -0,0 +1,228 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::future::BoxFuture;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::timeout;
+
+use super::{Endpoint, EndpointSource};
+
+// Timeout of a single DNS query
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+/// Endpoints resolved from DNS.
+///
+/// Host names are resolved with the system resolver (A/AAAA records), SRV records are queried
+/// from the first `nameserver` of `/etc/resolv.conf` (or the one set with [`DnsSource::nameserver`]).
+#[derive(Debug, Clone)]
+pub struct DnsSource {
+    query: Query,
+    nameserver: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone)]
+enum Query {
+    Host(String, u16),
+    Srv(String),
+}
+
+impl DnsSource {
+    /// Resolves the A/AAAA records of the `host`, using the `port` for all endpoints.
+    pub fn host(host: impl Into<String>, port: u16) -> Self {
+        DnsSource {
+            query: Query::Host(host.into(), port),
+            nameserver: None,
+        }
+    }
+
+    /// Resolves the SRV records of the `name` (eg. `_http._tcp.app.example.com`).
+    ///
+    /// Only the records with the lowest priority are used, their weights are applied
+    /// to the servers.
+    pub fn srv(name: impl Into<String>) -> Self {
+        DnsSource {
+            query: Query::Srv(name.into()),
+            nameserver: None,
+        }
+    }
+
+    /// Sets the nameserver used for the SRV queries.
+    pub fn nameserver(mut self, addr: SocketAddr) -> Self {
+        self.nameserver = Some(addr);
+        self
+    }
+
+    async fn resolve(&self) -> io::Result<Vec<Endpoint>> {
+        match &self.query {
+            Query::Host(host, port) => {
+                let addrs = lookup_host((host.as_str(), *port)).await?;
+                Ok(addrs
+                    .map(|addr| Endpoint::new(addr.ip(), addr.port()))
+                    .collect())
+            }
+            Query::Srv(name) => {
+                let nameserver = match self.nameserver {
+                    Some(addr) => addr,
+                    None => system_nameserver(),
+                };
+                let records = query_srv(nameserver, name).await?;
+                let Some(priority) = records.iter().map(|r| r.priority).min() else {
+                    return Ok(Vec::new());
+                };
+                let mut endpoints = Vec::new();
+                for record in records.iter().filter(|r| r.priority == priority) {
+                    let addrs = lookup_host((record.target.as_str(), record.port)).await?;
+                    for addr in addrs {
+                        let endpoint = Endpoint::new(addr.ip(), addr.port());
+                        endpoints.push(endpoint.with_weight(record.weight.into()));
+                    }
+                }
+                Ok(endpoints)
+            }
+        }
+    }
+}
+
+impl EndpointSource for DnsSource {
+    fn endpoints(&self) -> BoxFuture<'_, io::Result<Vec<Endpoint>>> {
+        Box::pin(self.resolve())
+    }
+}
+
+fn system_nameserver() -> SocketAddr {
+    let conf = fs::read_to_string("/etc/resolv.conf").unwrap_or_default();
+    let ip = (conf.lines())
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .find_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    SocketAddr::new(ip, 53)
+}
+
+#[derive(Debug)]
+struct SrvRecord {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: String,
+}
+
+async fn query_srv(nameserver: SocketAddr, name: &str) -> io::Result<Vec<SrvRecord>> {
+    let bind = match nameserver {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(nameserver).await?;
+
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos() as u16);
+    socket.send(&build_query(id, name)?).await?;
+
+    let mut buf = vec![0; 4096];
+    loop {
+        let len = timeout(QUERY_TIMEOUT, socket.recv(&mut buf))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "DNS query timed out"))??;
+        // Ignore the stray responses
+        if len >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+            return parse_response(&buf[..len]);
+        }
+    }
+}
+
+fn build_query(id: u16, name: &str) -> io::Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(name.len() + 18);
+    query.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid DNS name"));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+fn parse_response(msg: &[u8]) -> io::Result<Vec<SrvRecord>> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "invalid DNS response");
+    let u16_at = |pos: usize| -> io::Result<u16> {
+        let bytes = msg.get(pos..pos + 2).ok_or_else(invalid)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    let flags = u16_at(2)?;
+    match flags & 0x000f {
+        0 => {}
+        // Name error (NXDOMAIN)
+        3 => return Ok(Vec::new()),
+        rcode => {
+            let msg = format!("DNS query failed with rcode {rcode}");
+            return Err(Error::other(msg));
+        }
+    }
+    let (questions, answers) = (u16_at(4)?, u16_at(6)?);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(msg, pos, &mut String::new())? + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        pos = read_name(msg, pos, &mut String::new())?;
+        let (rtype, rdlen) = (u16_at(pos)?, u16_at(pos + 8)? as usize);
+        let rdata = pos + 10;
+        if msg.len() < rdata + rdlen {
+            return Err(invalid());
+        }
+        if rtype == TYPE_SRV && rdlen >= 7 {
+            let mut target = String::new();
+            read_name(msg, rdata + 6, &mut target)?;
+            records.push(SrvRecord {
+                priority: u16_at(rdata)?,
+                weight: u16_at(rdata + 2)?,
+                port: u16_at(rdata + 4)?,
+                target,
+            });
+        }
+        pos = rdata + rdlen;
+    }
+    Ok(records)
+}
+
+// Reads a (possibly compressed) name at `pos`, returning the position after it
+fn read_name(msg: &[u8], mut pos: usize, name: &mut String) -> io::Result<usize> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "invalid DNS name");
+    let mut end = None;
+    // Bound the number of jumps to protect against pointer loops
+    for _ in 0..128 {
+        let len = *msg.get(pos).ok_or_else(invalid)? as usize;
+        match len {
+            0 => return Ok(end.unwrap_or(pos + 1)),
+            _ if len & 0xc0 == 0xc0 => {
+                let low = *msg.get(pos + 1).ok_or_else(invalid)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = (len & 0x3f) << 8 | low;
+            }
+            _ => {
+                let label = msg.get(pos + 1..pos + 1 + len).ok_or_else(invalid)?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&String::from_utf8_lossy(label));
+                pos += 1 + len;
+            }
+        }
+    }
+    Err(invalid())
+}
@@ -0,0 +1,66 @@
+use std::io::{self, Error, ErrorKind};
+use std::net::IpAddr;
+
+use futures_util::future::BoxFuture;
+use serde_json::Value as JsonValue;
+
+use super::{Endpoint, EndpointSource};
+use crate::http_fetch::{self, HttpUrl};
+
+/// Endpoints fetched from an HTTP endpoint returning a JSON array:
+///
+/// ```json
+/// [{"address": "10.0.0.1", "port": 8080, "weight": 10}, {"address": "10.0.0.2", "port": 8080}]
+/// ```
+///
+/// The `weight` is optional. Only plain `http://` URLs are supported.
+#[derive(Debug, Clone)]
+pub struct HttpJsonSource {
+    url: HttpUrl,
+}
+
+impl HttpJsonSource {
+    /// Creates a new source fetching the `url`.
+    pub fn new(url: &str) -> io::Result<Self> {
+        let url = HttpUrl::parse(url)?;
+        Ok(HttpJsonSource { url })
+    }
+
+    async fn fetch(&self) -> io::Result<Vec<Endpoint>> {
+        let headers = [("Accept", "application/json")];
+        let response = http_fetch::get(&self.url, &headers).await?;
+        if response.status != 200 {
+            let msg = format!("unexpected HTTP status {}", response.status);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let body: JsonValue = serde_json::from_slice(&response.body)?;
+        parse_endpoints(&body)
+    }
+}
+
+impl EndpointSource for HttpJsonSource {
+    fn endpoints(&self) -> BoxFuture<'_, io::Result<Vec<Endpoint>>> {
+        Box::pin(self.fetch())
+    }
+}
+
+fn parse_endpoints(body: &JsonValue) -> io::Result<Vec<Endpoint>> {
+    let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+    let items = (body.as_array()).ok_or_else(|| invalid("expected a JSON array".into()))?;
+    let mut endpoints = Vec::with_capacity(items.len());
+    for item in items {
+        let address = (item.get("address").and_then(JsonValue::as_str))
+            .ok_or_else(|| invalid(format!("missing 'address' in {item}")))?;
+        let ip: IpAddr =
+            (address.parse()).map_err(|_| invalid(format!("invalid address '{address}'")))?;
+        let port = (item.get("port").and_then(JsonValue::as_u64))
+            .and_then(|port| u16::try_from(port).ok())
+            .ok_or_else(|| invalid(format!("missing or invalid 'port' in {item}")))?;
+        let mut endpoint = Endpoint::new(ip, port);
+        if let Some(weight) = item.get("weight").and_then(JsonValue::as_u64) {
+            endpoint = endpoint.with_weight(weight.min(u32::MAX as u64) as u32);
+        }
+        endpoints.push(endpoint);
+    }
+    Ok(endpoints)
+}
@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+use std::ffi::c_void;
+use std::fmt::{self, Write as _};
+
+use mlua::{Table, Value};
+
+use crate::{Channel, Headers, Http, HttpMessage, Proxy, Reply, Server, StickTable, Txn};
+
+// Maximum nesting level of the dumped tables
+const MAX_DEPTH: usize = 3;
+
+// Maximum number of dumped entries per table
+const MAX_ITEMS: usize = 32;
+
+// Maximum length (in bytes) of the dumped strings
+const MAX_STRING_LEN: usize = 128;
+
+/// Renders the Lua table contents (without metatables), bounded in depth and size.
+pub(crate) fn dump_table(table: &Table) -> String {
+    let mut out = String::new();
+    let mut seen = HashSet::new();
+    let _ = write_table(&mut out, table, 0, &mut seen);
+    out
+}
+
+fn write_table(
+    out: &mut String,
+    table: &Table,
+    depth: usize,
+    seen: &mut HashSet<*const c_void>,
+) -> fmt::Result {
+    if depth >= MAX_DEPTH {
+        return out.write_str("{...}");
+    }
+    if !seen.insert(table.to_pointer()) {
+        return out.write_str("<cycle>");
+    }
+
+    let mut entries = Vec::new();
+    let mut total = 0;
+    for (key, value) in table.clone().pairs::<Value, Value>().flatten() {
+        total += 1;
+        if entries.len() < MAX_ITEMS {
+            let mut k = String::new();
+            write_key(&mut k, &key)?;
+            entries.push((k, value));
+        }
+    }
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    out.write_char('{')?;
+    for (i, (key, value)) in entries.iter().enumerate() {
+        if i > 0 {
+            out.write_str(", ")?;
+        }
+        write!(out, "{key} = ")?;
+        write_value(out, value, depth, seen)?;
+    }
+    if total > entries.len() {
+        write!(out, ", ... ({} more)", total - entries.len())?;
+    }
+    seen.remove(&table.to_pointer());
+    out.write_char('}')
+}
+
+fn write_key(out: &mut String, key: &Value) -> fmt::Result {
+    if let Value::String(s) = key {
+        if let Ok(s) = s.to_str() {
+            let is_ident = s
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if is_ident {
+                return out.write_str(s);
+            }
+        }
+    }
+    out.write_char('[')?;
+    write_scalar(out, key)?;
+    out.write_char(']')
+}
+
+fn write_value(
+    out: &mut String,
+    value: &Value,
+    depth: usize,
+    seen: &mut HashSet<*const c_void>,
+) -> fmt::Result {
+    match value {
+        Value::Table(t) => write_table(out, t, depth + 1, seen),
+        value => write_scalar(out, value),
+    }
+}
+
+fn write_scalar(out: &mut String, value: &Value) -> fmt::Result {
+    match value {
+        Value::Nil => out.write_str("nil"),
+        Value::Boolean(b) => write!(out, "{b}"),
+        Value::Integer(i) => write!(out, "{i}"),
+        Value::Number(n) => write!(out, "{n}"),
+        Value::String(s) => {
+            let bytes = s.as_bytes();
+            let s = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_STRING_LEN)]);
+            write!(out, "{s:?}")?;
+            if bytes.len() > MAX_STRING_LEN {
+                write!(out, "... ({} bytes)", bytes.len())?;
+            }
+            Ok(())
+        }
+        Value::Table(_) => out.write_str("<table>"),
+        Value::Function(_) => out.write_str("<function>"),
+        Value::UserData(_) => out.write_str("<userdata>"),
+        Value::LightUserData(_) => out.write_str("<lightuserdata>"),
+        Value::Thread(_) => out.write_str("<thread>"),
+        _ => out.write_str("<unknown>"),
+    }
+}
+
+macro_rules! impl_debug {
+    ($($ty:ident),* $(,)?) => {
+        $(
+            impl fmt::Debug for $ty<'_> {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    write!(f, "{} {}", stringify!($ty), dump_table(self))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_dump {
+    ($($ty:ident),* $(,)?) => {
+        $(
+            impl<'lua> $ty<'lua> {
+                /// Returns the underlying Lua table contents, for troubleshooting.
+                ///
+                /// Nested tables are rendered up to a limited depth and number of entries.
+                pub fn dump(&self) -> String {
+                    dump_table(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_debug!(
+    Channel,
+    Headers,
+    Http,
+    HttpMessage,
+    Proxy,
+    Reply,
+    Server,
+    StickTable,
+    Txn
+);
+
+// `StickTable::dump` is the HAProxy method returning the table entries
+impl_dump!(
+    Channel,
+    Headers,
+    Http,
+    HttpMessage,
+    Proxy,
+    Reply,
+    Server,
+    Txn
+);
@@ -0,0 +1,194 @@
+use mlua::{Error, Function, Result, Table, Value};
+
+use crate::{Capability, Core, HaproxyVersion};
+
+/// A set of requirements checked by [`Core::validate_environment`].
+///
+/// By default it includes every `core` function this crate calls (depending on the enabled
+/// features) and the HAProxy version matching the `haproxyXX` features.
+#[derive(Debug, Clone)]
+pub struct Requirements {
+    min_version: Option<HaproxyVersion>,
+    // Dotted path (eg. `core.register_filter`) and minimum number of parameters
+    functions: Vec<(String, usize)>,
+    capabilities: Vec<Capability>,
+}
+
+impl Default for Requirements {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Requirements {
+    /// Creates requirements for the functionality of this crate.
+    pub fn new() -> Self {
+        let mut req = Requirements {
+            min_version: target_version(),
+            functions: Vec::new(),
+            capabilities: Vec::new(),
+        };
+        for (name, nargs) in [
+            ("register_action", 3),
+            ("register_converters", 2),
+            ("register_fetches", 2),
+            ("register_filter", 3),
+            ("register_service", 3),
+            ("register_init", 1),
+            ("register_task", 1),
+            ("register_cli", 3),
+            ("log", 2),
+            ("get_info", 0),
+            ("now", 0),
+        ] {
+            req = req.function(&format!("core.{name}"), nargs);
+        }
+        #[cfg(feature = "async")]
+        {
+            req = req.function("core.msleep", 1).function("core.tcp", 0);
+        }
+        #[cfg(feature = "haproxy24")]
+        {
+            req = req.capability(Capability::Thread);
+        }
+        #[cfg(feature = "haproxy28")]
+        {
+            req = req.capability(Capability::Events);
+        }
+        req
+    }
+
+    /// Creates empty requirements.
+    pub fn empty() -> Self {
+        Requirements {
+            min_version: None,
+            functions: Vec::new(),
+            capabilities: Vec::new(),
+        }
+    }
+
+    /// Requires HAProxy `version` or newer.
+    pub fn min_version(mut self, version: HaproxyVersion) -> Self {
+        self.min_version = Some(version);
+        self
+    }
+
+    /// Requires a global Lua function by its dotted `path` (eg. `core.httpclient`),
+    /// accepting at least `nargs` arguments.
+    ///
+    /// The number of arguments can be checked only for functions implemented in Lua.
+    pub fn function(mut self, path: &str, nargs: usize) -> Self {
+        match self.functions.iter_mut().find(|(p, _)| p == path) {
+            Some((_, n)) => *n = (*n).max(nargs),
+            None => self.functions.push((path.to_string(), nargs)),
+        }
+        self
+    }
+
+    /// Requires the optional API `capability`.
+    pub fn capability(mut self, capability: Capability) -> Self {
+        if !self.capabilities.contains(&capability) {
+            self.capabilities.push(capability);
+        }
+        self
+    }
+}
+
+// Oldest HAProxy version selected by the `haproxyXX` features
+fn target_version() -> Option<HaproxyVersion> {
+    if cfg!(feature = "haproxy30") {
+        Some(HaproxyVersion::new(3, 0, 0))
+    } else if cfg!(feature = "haproxy28") {
+        Some(HaproxyVersion::new(2, 8, 0))
+    } else if cfg!(feature = "haproxy26") {
+        Some(HaproxyVersion::new(2, 6, 0))
+    } else if cfg!(feature = "haproxy24") {
+        Some(HaproxyVersion::new(2, 4, 0))
+    } else {
+        None
+    }
+}
+
+impl<'lua> Core<'lua> {
+    /// Checks that the running HAProxy satisfies the `requirements`.
+    ///
+    /// Intended to be called from the module entry point (or `register_init`).
+    /// All problems are reported together in a single error.
+    pub fn validate_environment(&self, requirements: &Requirements) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if let Some(min_version) = requirements.min_version {
+            match self.haproxy_version() {
+                Ok(version) if version < min_version => problems.push(format!(
+                    "HAProxy {min_version} or newer is required, running {version}"
+                )),
+                Ok(_) => {}
+                Err(err) => problems.push(format!("cannot detect HAProxy version: {err}")),
+            }
+        }
+
+        let globals = self.lua.globals();
+        for (path, nargs) in &requirements.functions {
+            match lookup(globals.clone(), path)? {
+                Value::Function(func) => {
+                    if let Some(nparams) = lua_arity(self, &func)? {
+                        if nparams < *nargs {
+                            problems.push(format!(
+                                "function '{path}' accepts {nparams} argument(s), {nargs} expected"
+                            ));
+                        }
+                    }
+                }
+                Value::Nil => problems.push(format!("function '{path}' is missing")),
+                value => problems.push(format!(
+                    "'{path}' is a {}, function expected",
+                    value.type_name()
+                )),
+            }
+        }
+
+        for &capability in &requirements.capabilities {
+            if !self.supports(capability)? {
+                problems.push(format!("capability {capability:?} is not supported"));
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let mut msg = "HAProxy environment does not meet the module requirements:".to_string();
+        for problem in problems {
+            msg.push_str("\n  - ");
+            msg.push_str(&problem);
+        }
+        Err(Error::runtime(msg))
+    }
+}
+
+// Resolves a dotted path starting from the globals table
+fn lookup<'lua>(globals: Table<'lua>, path: &str) -> Result<Value<'lua>> {
+    let mut value = Value::Table(globals);
+    for key in path.split('.') {
+        value = match value {
+            Value::Table(table) => table.get(key)?,
+            _ => return Ok(Value::Nil),
+        };
+    }
+    Ok(value)
+}
+
+// Returns the number of fixed parameters of a non-vararg Lua function
+fn lua_arity(core: &Core, func: &Function) -> Result<Option<usize>> {
+    if func.info().what != "Lua" {
+        return Ok(None);
+    }
+    let getinfo = match lookup(core.lua.globals(), "debug.getinfo")? {
+        Value::Function(getinfo) => getinfo,
+        _ => return Ok(None),
+    };
+    let info: Table = getinfo.call((func.clone(), "u"))?;
+    if info.get::<_, Option<bool>>("isvararg")?.unwrap_or(true) {
+        return Ok(None);
+    }
+    info.get("nparams")
+}
@@ -0,0 +1,166 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+
+use mlua::Error as LuaError;
+
+/// Kind of a Rust callback registered in HAProxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CallbackKind {
+    Fetch,
+    Converter,
+    Action,
+    Service,
+    Filter,
+}
+
+impl CallbackKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            CallbackKind::Fetch => "fetch",
+            CallbackKind::Converter => "converter",
+            CallbackKind::Action => "action",
+            CallbackKind::Service => "service",
+            CallbackKind::Filter => "filter",
+        }
+    }
+}
+
+impl fmt::Display for CallbackKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error with HAProxy-specific context, wrapping [`mlua::Error`].
+///
+/// Errors returned from registered callbacks are wrapped automatically, so the message reported
+/// by HAProxy tells which callback failed (eg. `filter 'MyFilter' (http_payload): ...`).
+/// It converts to [`mlua::Error`] and back transparently.
+///
+/// The crate API keeps returning [`mlua::Result`]: the context is only added at the callback
+/// boundary, use [`Error::find`] to get it back from a [`mlua::Error`].
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: Option<CallbackKind>,
+    name: Option<String>,
+    method: Option<String>,
+    source: LuaError,
+}
+
+impl Error {
+    /// Wraps the Lua error without any context.
+    pub fn new(err: LuaError) -> Self {
+        Error {
+            kind: None,
+            name: None,
+            method: None,
+            source: err,
+        }
+    }
+
+    /// Sets the callback kind and the registered name.
+    pub fn with_callback(mut self, kind: CallbackKind, name: impl Into<String>) -> Self {
+        self.kind = Some(kind);
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the method (filter callback or Lua method) that failed.
+    pub fn with_method(mut self, method: impl Into<String>) -> Self {
+        self.method = Some(method.into());
+        self
+    }
+
+    /// Returns the callback kind, if known.
+    pub fn kind(&self) -> Option<CallbackKind> {
+        self.kind
+    }
+
+    /// Returns the registered callback name, if known.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the failed method, if known.
+    ///
+    /// It's set for filter callbacks (eg. `http_payload`) or with [`Error::with_method`].
+    pub fn method(&self) -> Option<&str> {
+        self.method.as_deref()
+    }
+
+    /// Returns the underlying Lua error.
+    pub fn lua_error(&self) -> &LuaError {
+        &self.source
+    }
+
+    /// Finds the `Error` carried inside of a Lua error (eg. returned from a nested callback).
+    pub fn find(err: &LuaError) -> Option<&Error> {
+        match err {
+            LuaError::ExternalError(err) => err.downcast_ref(),
+            LuaError::CallbackError { cause, .. } => Error::find(cause),
+            LuaError::WithContext { cause, .. } => Error::find(cause),
+            _ => None,
+        }
+    }
+
+    // Wraps a Lua error returned from a callback, keeping an existing context
+    pub(crate) fn callback(kind: CallbackKind, name: &str, err: LuaError) -> LuaError {
+        Error::callback_method(kind, name, None, err)
+    }
+
+    pub(crate) fn callback_method(
+        kind: CallbackKind,
+        name: &str,
+        method: Option<&str>,
+        err: LuaError,
+    ) -> LuaError {
+        if Error::find(&err).is_some() {
+            return err;
+        }
+        let mut err = Error::new(err).with_callback(kind, name);
+        err.method = method.map(str::to_string);
+        err.into()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut context = Vec::new();
+        if let Some(kind) = self.kind {
+            context.push(kind.to_string());
+        }
+        if let Some(name) = &self.name {
+            context.push(format!("'{name}'"));
+        }
+        if let Some(method) = &self.method {
+            context.push(format!("({method})"));
+        }
+        if !context.is_empty() {
+            write!(f, "{}: ", context.join(" "))?;
+        }
+        write!(f, "{}", self.source)
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<LuaError> for Error {
+    fn from(err: LuaError) -> Self {
+        match Error::find(&err) {
+            Some(inner) => inner.clone(),
+            None => Error::new(err),
+        }
+    }
+}
+
+impl From<Error> for LuaError {
+    fn from(err: Error) -> Self {
+        LuaError::ExternalError(Arc::new(err))
+    }
+}
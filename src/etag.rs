@@ -0,0 +1,216 @@
+//! Entity tags and conditional requests (RFC 9110).
+//!
+//! [`ETag`] parses and compares strong and weak entity tags. [`is_not_modified`] evaluates
+//! `If-None-Match` and `If-Modified-Since` request headers against the response headers:
+//!
+//! ```ignore
+//! let req_headers = txn.http()?.req_get_headers()?;
+//! if etag::is_not_modified(&req_headers, &msg.get_headers()?)? {
+//!     msg.set_not_modified()?;
+//! }
+//! ```
+
+use std::fmt;
+
+use mlua::Result;
+
+use crate::{date, Headers, HttpMessage};
+
+/// An entity tag (eg. `"xyzzy"` or `W/"xyzzy"`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ETag {
+    /// The opaque tag without quotes.
+    pub tag: String,
+    /// True for weak validators.
+    pub weak: bool,
+}
+
+impl ETag {
+    /// Creates a strong entity tag.
+    pub fn strong(tag: impl Into<String>) -> Self {
+        ETag {
+            tag: tag.into(),
+            weak: false,
+        }
+    }
+
+    /// Creates a weak entity tag.
+    pub fn weak(tag: impl Into<String>) -> Self {
+        ETag {
+            tag: tag.into(),
+            weak: true,
+        }
+    }
+
+    /// Parses an entity tag. Returns `None` if the value is not properly quoted.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (weak, value) = match value.strip_prefix("W/") {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        let tag = value.strip_prefix('"')?.strip_suffix('"')?;
+        if tag.contains('"') {
+            return None;
+        }
+        Some(ETag {
+            tag: tag.to_string(),
+            weak,
+        })
+    }
+
+    /// Parses a comma-separated list of entity tags, skipping invalid ones.
+    pub fn parse_list(value: &str) -> Vec<Self> {
+        // Tags may contain commas, so split only outside of the quotes
+        let mut items = Vec::new();
+        let (mut start, mut quoted) = (0, false);
+        for (i, c) in value.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    items.push(&value[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        items.push(&value[start..]);
+        items.into_iter().filter_map(Self::parse).collect()
+    }
+
+    /// Strong comparison: both tags must be strong and equal.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Weak comparison: tags must be equal regardless of their weakness.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Returns the weak version of this tag.
+    pub fn into_weak(self) -> Self {
+        ETag::weak(self.tag)
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+/// Returns true if the `If-None-Match` header values match the `etag` (using weak comparison).
+///
+/// `*` matches any existing representation.
+pub fn if_none_match<S: AsRef<str>>(values: &[S], etag: Option<&ETag>) -> bool {
+    let Some(etag) = etag else {
+        return false;
+    };
+    values.iter().any(|value| {
+        let value = value.as_ref();
+        value.trim() == "*" || ETag::parse_list(value).iter().any(|t| t.weak_eq(etag))
+    })
+}
+
+/// Evaluates conditional request headers against the response headers.
+///
+/// Returns true if the response can be replaced by `304 Not Modified`. As required by
+/// RFC 9110, `If-Modified-Since` is ignored when `If-None-Match` is present.
+pub fn is_not_modified(request: &Headers, response: &Headers) -> Result<bool> {
+    let if_none_match = request.get::<String>("if-none-match")?;
+    if !if_none_match.is_empty() {
+        let etag = response.get_first::<String>("etag")?;
+        let etag = etag.as_deref().and_then(ETag::parse);
+        return Ok(self::if_none_match(&if_none_match, etag.as_ref()));
+    }
+
+    let if_modified_since = request.get_first::<String>("if-modified-since")?;
+    let last_modified = response.get_first::<String>("last-modified")?;
+    let since = if_modified_since.as_deref().and_then(date::parse_http_date);
+    let modified = last_modified.as_deref().and_then(date::parse_http_date);
+    Ok(matches!((since, modified), (Some(since), Some(modified)) if modified <= since))
+}
+
+impl<'lua> HttpMessage<'lua> {
+    /// Returns the parsed `ETag` header.
+    pub fn etag(&self) -> Result<Option<ETag>> {
+        let etag = self.get_headers()?.get_first::<String>("etag")?;
+        Ok(etag.as_deref().and_then(ETag::parse))
+    }
+
+    /// Turns a strong `ETag` header into a weak one.
+    ///
+    /// Should be called by filters that transform the response body (eg. compression),
+    /// as the representation is no longer byte-for-byte identical.
+    pub fn weaken_etag(&self) -> Result<()> {
+        match self.etag()? {
+            Some(etag) if !etag.weak => self.set_header("etag", etag.into_weak().to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Turns the response into `304 Not Modified`.
+    ///
+    /// Removes the incoming body data and the headers describing the payload framing.
+    pub fn set_not_modified(&self) -> Result<()> {
+        self.set_status(304, None)?;
+        for name in ["content-length", "transfer-encoding"] {
+            self.del_header(name)?;
+        }
+        if self.input()? > 0 {
+            self.remove(None, None)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ETag::parse(r#" "xyzzy" "#), Some(ETag::strong("xyzzy")));
+        assert_eq!(ETag::parse(r#"W/"xyzzy""#), Some(ETag::weak("xyzzy")));
+        assert_eq!(ETag::parse(r#""""#), Some(ETag::strong("")));
+        assert_eq!(ETag::parse("xyzzy"), None);
+        assert_eq!(ETag::parse(r#"w/"xyzzy""#), None);
+        assert_eq!(ETag::parse(r#""a"b""#), None);
+
+        let list = ETag::parse_list(r#""a", W/"b,c" , invalid, "d""#);
+        assert_eq!(
+            list,
+            vec![ETag::strong("a"), ETag::weak("b,c"), ETag::strong("d")]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(ETag::strong("a").to_string(), r#""a""#);
+        assert_eq!(ETag::strong("a").into_weak().to_string(), r#"W/"a""#);
+    }
+
+    #[test]
+    fn test_compare() {
+        let (strong, weak) = (ETag::strong("1"), ETag::weak("1"));
+        assert!(strong.strong_eq(&strong));
+        assert!(!strong.strong_eq(&weak));
+        assert!(!weak.strong_eq(&weak));
+        assert!(strong.weak_eq(&weak));
+        assert!(!strong.weak_eq(&ETag::strong("2")));
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let etag = ETag::strong("v1");
+        assert!(if_none_match(&[r#"W/"v1""#], Some(&etag)));
+        assert!(if_none_match(&[r#""v0""#, r#""x", "v1""#], Some(&etag)));
+        assert!(if_none_match(&["*"], Some(&etag)));
+        assert!(!if_none_match(&["*"], None));
+        assert!(!if_none_match(&[r#""v2""#], Some(&etag)));
+    }
+}
@@ -0,0 +1,104 @@
+use std::any::{type_name, TypeId};
+
+use mlua::{Result, Table, UserDataRef, UserDataRefMut, Value};
+
+use crate::{MaybeSend, Txn};
+
+// Registry key of the table holding the extensions of every HAProxy Lua context
+const EXT_REGISTRY_KEY: &str = "__HAPROXY_TXN_EXTENSIONS";
+
+impl<'lua> Txn<'lua> {
+    /// Inserts the `value` into the transaction extensions, returning the previous value
+    /// of the same type.
+    ///
+    /// Extensions are a per-transaction type map, so independent modules (or a filter and
+    /// an action) can attach their own data without conflicts.
+    /// Like the value set by [`Txn::set_priv`] (which is left untouched), they belong to the
+    /// HAProxy Lua context of the stream and are released with it. Filters have their own
+    /// context, so they don't see the extensions inserted by actions.
+    pub fn ext_insert<T: MaybeSend + 'static>(&self, value: T) -> Result<Option<T>> {
+        let ext = self.extensions(true)?.expect("extensions table");
+        let key = ext_key::<T>();
+        let old = match ext.raw_get::<_, Value>(key.as_str())? {
+            Value::UserData(ud) => ud.take::<T>().ok(),
+            _ => None,
+        };
+        ext.raw_set(key, self.lua.create_any_userdata(value)?)?;
+        Ok(old)
+    }
+
+    /// Returns a reference to the extension of type `T`.
+    pub fn ext_get<T: 'static>(&self) -> Result<Option<UserDataRef<'lua, T>>> {
+        match self.extensions(false)? {
+            Some(ext) => ext.raw_get(ext_key::<T>()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns a mutable reference to the extension of type `T`.
+    pub fn ext_get_mut<T: 'static>(&self) -> Result<Option<UserDataRefMut<'lua, T>>> {
+        match self.extensions(false)? {
+            Some(ext) => ext.raw_get(ext_key::<T>()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns true if the transaction has the extension of type `T`.
+    pub fn ext_contains<T: 'static>(&self) -> Result<bool> {
+        match self.extensions(false)? {
+            Some(ext) => ext.contains_key(ext_key::<T>()),
+            None => Ok(false),
+        }
+    }
+
+    /// Removes the extension of type `T`, returning it.
+    pub fn ext_remove<T: 'static>(&self) -> Result<Option<T>> {
+        let Some(ext) = self.extensions(false)? else {
+            return Ok(None);
+        };
+        let key = ext_key::<T>();
+        match ext.raw_get::<_, Value>(key.as_str())? {
+            Value::UserData(ud) => {
+                ext.raw_set(key, Value::Nil)?;
+                Ok(ud.take::<T>().ok())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the extensions table of the current HAProxy Lua context, creating it if requested.
+    ///
+    /// HAProxy runs all the Lua callbacks of a stream in a dedicated coroutine, destroyed at
+    /// the end of the stream. The tables are kept in a registry table with weak keys, indexed
+    /// by that coroutine, so they are collected together with it.
+    pub(crate) fn extensions(&self, create: bool) -> Result<Option<Table<'lua>>> {
+        let lua = self.lua;
+        let registry = match lua.named_registry_value::<Option<Table>>(EXT_REGISTRY_KEY)? {
+            Some(registry) => registry,
+            None if !create => return Ok(None),
+            None => {
+                let registry = lua.create_table()?;
+                let mt = lua.create_table()?;
+                mt.raw_set("__mode", "k")?;
+                registry.set_metatable(Some(mt));
+                lua.set_named_registry_value(EXT_REGISTRY_KEY, &registry)?;
+                registry
+            }
+        };
+        let thread = Value::Thread(lua.current_thread());
+        match registry.raw_get::<_, Option<Table>>(thread.clone())? {
+            Some(ext) => Ok(Some(ext)),
+            None if !create => Ok(None),
+            None => {
+                let ext = lua.create_table()?;
+                registry.raw_set(thread, &ext)?;
+                Ok(Some(ext))
+            }
+        }
+    }
+}
+
+// Extensions are keyed by type (type name is added for readability)
+fn ext_key<T: 'static>() -> String {
+    format!("{}#{:?}", type_name::<T>(), TypeId::of::<T>())
+}
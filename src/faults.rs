@@ -0,0 +1,270 @@
+//! Fault injection for chaos testing.
+//!
+//! [`FaultFilter`] can delay or abort requests, rewrite response status codes and corrupt
+//! response bodies. Faults are selected per request by rules matching request headers,
+//! or looked up in a map, so they can be switched at runtime without touching backends:
+//!
+//! ```text
+//! filter lua.faults header:x-chaos map:/etc/haproxy/faults.map,path delay:200@10 status:503@1
+//! ```
+//!
+//! The map (or the header) value is a fault specification, see [`Fault::parse`].
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use mlua::{AnyUserData, Error, Lua, Result, Table, UserData, Value};
+
+use crate::filter::random;
+use crate::{Core, FilterMethod, FilterResult, HttpMessage, Txn, UserFilter};
+
+/// A fault to inject.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Fault {
+    /// Delays the request for the given duration.
+    Delay(Duration),
+    /// Aborts the stream.
+    Abort,
+    /// Rewrites the response status code.
+    Status(u16),
+    /// Corrupts the response body.
+    Corrupt,
+}
+
+impl Fault {
+    /// Parses a fault specification: `delay:<ms>`, `abort`, `status:<code>` or `corrupt`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (kind, value) = spec.trim().split_once(':').unwrap_or((spec.trim(), ""));
+        match kind {
+            "delay" => Some(Fault::Delay(Duration::from_millis(value.parse().ok()?))),
+            "abort" => Some(Fault::Abort),
+            "status" => Some(Fault::Status(value.parse().ok()?)),
+            "corrupt" => Some(Fault::Corrupt),
+            _ => None,
+        }
+    }
+}
+
+/// A rule that injects a fault into a given percentage of requests.
+#[derive(Debug, Clone)]
+pub struct FaultRule {
+    fault: Fault,
+    percent: f64,
+    header: Option<(String, Option<String>)>,
+}
+
+impl FaultRule {
+    /// Creates a new rule injecting the `fault` into all requests.
+    pub fn new(fault: Fault) -> Self {
+        FaultRule {
+            fault,
+            percent: 100.0,
+            header: None,
+        }
+    }
+
+    /// Sets the percentage (0-100) of matching requests to inject the fault into.
+    pub fn percent(mut self, percent: f64) -> Self {
+        self.percent = percent.clamp(0.0, 100.0);
+        self
+    }
+
+    /// Applies the rule only to requests with the header `name` (and optionally its `value`).
+    pub fn when_header(mut self, name: &str, value: Option<&str>) -> Self {
+        self.header = Some((name.to_ascii_lowercase(), value.map(|v| v.to_string())));
+        self
+    }
+}
+
+/// Fault injection configuration.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    rules: Vec<FaultRule>,
+    header: Option<String>,
+    map: Option<(String, String)>,
+}
+
+impl Faults {
+    /// Creates an empty configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a fault rule.
+    pub fn rule(mut self, rule: FaultRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Reads a fault specification from the request header `name`.
+    pub fn from_header(mut self, name: &str) -> Self {
+        self.header = Some(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Looks up a fault specification in the map `filename` using the result of
+    /// the sample fetch `fetch` (eg. `path` or `req.hdr(host)`) as a key.
+    pub fn from_map(mut self, filename: &str, fetch: &str) -> Self {
+        self.map = Some((filename.to_string(), fetch.to_string()));
+        self
+    }
+
+    /// Parses the configuration from the filter arguments.
+    ///
+    /// Supported arguments: `header:<name>`, `map:<file>,<fetch>` and static rules
+    /// `<fault spec>[@<percent>]` (eg. `delay:200@10`).
+    pub fn from_args(args: &Table) -> Result<Self> {
+        let mut faults = Self::new();
+        for arg in args.clone().sequence_values::<String>() {
+            let arg = arg?;
+            let invalid = || Error::runtime(format!("faults: invalid argument '{arg}'"));
+            if let Some(name) = arg.strip_prefix("header:") {
+                faults = faults.from_header(name);
+            } else if let Some(map) = arg.strip_prefix("map:") {
+                let (filename, fetch) = map.split_once(',').ok_or_else(invalid)?;
+                faults = faults.from_map(filename, fetch);
+            } else {
+                let (spec, percent) = arg.split_once('@').unwrap_or((&arg, "100"));
+                let fault = Fault::parse(spec).ok_or_else(invalid)?;
+                let percent = percent.parse().map_err(|_| invalid())?;
+                faults = faults.rule(FaultRule::new(fault).percent(percent));
+            }
+        }
+        Ok(faults)
+    }
+
+    /// Registers [`FaultFilter`] with this configuration under the `name`.
+    ///
+    /// The filter arguments (if any) are ignored.
+    pub fn register(self, core: &Core, name: &str) -> Result<()> {
+        let faults = core.lua.create_userdata(SharedFaults(Arc::new(self)))?;
+        core.register_filter_with::<FaultFilter>(name, 1.0, Some(Value::UserData(faults)))
+    }
+
+    // Selects faults to inject into the current request
+    fn select(&self, txn: &Txn, msg: &HttpMessage) -> Result<Vec<Fault>> {
+        let mut faults = Vec::new();
+        let headers = msg.get_headers()?;
+
+        if let Some(name) = &self.header {
+            if let Some(spec) = headers.get_first::<String>(name)? {
+                faults.extend(spec.split(',').filter_map(Fault::parse));
+            }
+        }
+        if let Some((filename, fetch)) = &self.map {
+            let key = txn.f.get_str(fetch, ())?;
+            let spec: Option<String> = txn.c.get("map", (key, filename.as_str()))?;
+            if let Some(spec) = spec {
+                faults.extend(spec.split(',').filter_map(Fault::parse));
+            }
+        }
+        for rule in &self.rules {
+            if let Some((name, value)) = &rule.header {
+                let values = headers.get::<String>(name)?;
+                let matched = match value {
+                    Some(value) => values.iter().any(|v| v == value),
+                    None => !values.is_empty(),
+                };
+                if !matched {
+                    continue;
+                }
+            }
+            if random() * 100.0 < rule.percent {
+                faults.push(rule.fault);
+            }
+        }
+        Ok(faults)
+    }
+}
+
+// Configuration shared between filter instances
+struct SharedFaults(Arc<Faults>);
+
+impl UserData for SharedFaults {}
+
+/// A filter that injects faults according to [`Faults`] configuration.
+pub struct FaultFilter {
+    faults: Arc<Faults>,
+    delay_until: Option<Instant>,
+    status: Option<u16>,
+    corrupt: bool,
+}
+
+impl UserFilter for FaultFilter {
+    const METHODS: u8 = FilterMethod::HTTP_HEADERS | FilterMethod::HTTP_PAYLOAD;
+
+    // `txn:done()` must be propagated to HAProxy
+    const CONTINUE_IF_ERROR: bool = false;
+
+    fn new(_: &Lua, args: Table) -> Result<Self> {
+        // Fetch ready parsed configuration
+        let faults = match args.raw_get::<_, Option<AnyUserData>>(0)? {
+            Some(ud) => ud.borrow::<SharedFaults>()?.0.clone(),
+            None => {
+                let faults = Arc::new(Faults::from_args(&args)?);
+                args.raw_set(0, SharedFaults(faults.clone()))?;
+                faults
+            }
+        };
+        Ok(FaultFilter {
+            faults,
+            delay_until: None,
+            status: None,
+            corrupt: false,
+        })
+    }
+
+    fn http_headers(&mut self, lua: &Lua, txn: Txn, msg: HttpMessage) -> Result<FilterResult> {
+        if msg.is_resp()? {
+            if let Some(status) = self.status {
+                msg.set_status(status, None)?;
+            }
+            if self.corrupt && !msg.eom()? {
+                Self::register_data_filter(lua, txn, msg.channel()?)?;
+            }
+            return Ok(FilterResult::Continue);
+        }
+
+        // Waiting for the delay to expire
+        if let Some(deadline) = self.delay_until {
+            let now = Instant::now();
+            if now < deadline {
+                Self::wake_time(lua, (deadline - now).as_millis() as u64 + 1)?;
+                return Ok(FilterResult::Wait);
+            }
+            return Ok(FilterResult::Continue);
+        }
+
+        let mut delay = Duration::ZERO;
+        for fault in self.faults.select(&txn, &msg)? {
+            match fault {
+                Fault::Delay(d) => delay = delay.max(d),
+                Fault::Abort => return txn.done(None).map(|_| FilterResult::Continue),
+                Fault::Status(status) => self.status = Some(status),
+                Fault::Corrupt => self.corrupt = true,
+            }
+        }
+        if !delay.is_zero() {
+            self.delay_until = Some(Instant::now() + delay);
+            Self::wake_time(lua, delay.as_millis() as u64)?;
+            return Ok(FilterResult::Wait);
+        }
+        Ok(FilterResult::Continue)
+    }
+
+    fn http_payload(&mut self, _: &Lua, _: Txn, msg: HttpMessage) -> Result<Option<usize>> {
+        if !self.corrupt || !msg.is_resp()? {
+            return Ok(None);
+        }
+        if let Some(chunk) = msg.body(None, Some(-1))? {
+            let mut data = chunk.as_bytes().to_vec();
+            if !data.is_empty() {
+                // Flip bits of a random byte
+                let pos = ((random() * data.len() as f64) as usize).min(data.len() - 1);
+                data[pos] ^= 0xFF;
+                msg.set(data, None, None)?;
+            }
+        }
+        Ok(None)
+    }
+}
@@ -1,33 +1,82 @@
-use mlua::{FromLua, IntoLuaMulti, Lua, Result, Table, TableExt, Value};
+use mlua::{FromLua, IntoLuaMulti, Lua, Result, String as LuaString, Table, TableExt, Value};
+
+mod memo;
+mod tracked;
+mod typed;
+
+pub use memo::MemoFetches;
+pub use tracked::TrackedCounters;
 
 /// The "Fetches" class allows to call a lot of internal HAProxy sample fetches.
+///
+/// Common fetches are also available as typed methods (eg. [`Fetches::src`]).
 #[derive(Clone)]
-pub struct Fetches<'lua>(Table<'lua>);
+pub struct Fetches<'lua> {
+    pub(crate) lua: &'lua Lua,
+    pub(crate) class: Table<'lua>,
+}
 
 impl<'lua> Fetches<'lua> {
     /// Executes an internal haproxy sample fetch.
+    ///
+    /// Strings are not guaranteed to be valid UTF-8 (eg. payload or certificate samples),
+    /// use `mlua::String` as the result type to keep them intact.
     #[inline]
     pub fn get<A, R>(&self, name: &str, args: A) -> Result<R>
     where
         A: IntoLuaMulti<'lua>,
         R: FromLua<'lua>,
     {
-        self.0.call_method(name, args)
+        self.class.call_method(name, args)
+    }
+
+    /// The same as `get` but returns `None` if the sample is not available.
+    ///
+    /// Lua errors (eg. unknown fetch or invalid arguments) are still returned as errors.
+    #[inline]
+    pub fn get_opt<A, R>(&self, name: &str, args: A) -> Result<Option<R>>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLua<'lua>,
+    {
+        match self.class.call_method::<_, Value>(name, args)? {
+            Value::Nil => Ok(None),
+            value => R::from_lua(value, self.lua).map(Some),
+        }
     }
 
     /// The same as `get` but always returns string.
+    ///
+    /// Fails if the result is not valid UTF-8, use [`get_bytes`] for binary samples.
+    ///
+    /// [`get_bytes`]: Self::get_bytes
     #[inline]
     pub fn get_str<A>(&self, name: &str, args: A) -> Result<String>
     where
         A: IntoLuaMulti<'lua>,
     {
-        Ok((self.0.call_method::<_, Option<_>>(name, args)?).unwrap_or_default())
+        Ok((self.class.call_method::<_, Option<_>>(name, args)?).unwrap_or_default())
+    }
+
+    /// The same as `get` but always returns a (binary-safe) Lua string.
+    ///
+    /// Returns an empty string if the result is missing.
+    #[inline]
+    pub fn get_bytes<A>(&self, name: &str, args: A) -> Result<LuaString<'lua>>
+    where
+        A: IntoLuaMulti<'lua>,
+    {
+        match self.class.call_method::<_, Option<LuaString>>(name, args)? {
+            Some(s) => Ok(s),
+            None => self.lua.create_string(""),
+        }
     }
 }
 
 impl<'lua> FromLua<'lua> for Fetches<'lua> {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
-        Ok(Fetches(Table::from_lua(value, lua)?))
+        let class = Table::from_lua(value, lua)?;
+        Ok(Fetches { lua, class })
     }
 }
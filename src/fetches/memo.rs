@@ -0,0 +1,80 @@
+use mlua::{FromLua, Result, Table, Value, Variadic};
+
+use crate::Txn;
+
+// Key of the fetch cache table in the transaction extensions
+const FETCH_CACHE_KEY: &str = "fetch_cache";
+
+/// Sample fetches memoized for the transaction lifetime.
+///
+/// Results are cached in the transaction extensions (see [`Txn::ext_insert`]), so actions
+/// running at different stages of the same transaction execute every fetch only once.
+/// Use it only for fetches that do not change during the transaction (eg. `src`, `path`
+/// or `req.hdr`).
+///
+/// ```ignore
+/// let host: Option<String> = txn.memo().get_opt("req_hdr", &["host"])?;
+/// ```
+#[derive(Clone, Copy)]
+pub struct MemoFetches<'a, 'lua> {
+    txn: &'a Txn<'lua>,
+}
+
+impl<'lua> Txn<'lua> {
+    /// Returns the memoized sample fetches of the transaction.
+    #[inline]
+    pub fn memo(&self) -> MemoFetches<'_, 'lua> {
+        MemoFetches { txn: self }
+    }
+}
+
+impl<'lua> MemoFetches<'_, 'lua> {
+    /// Executes the sample fetch `name` with the `args`, or returns the cached result.
+    pub fn get<R: FromLua<'lua>>(&self, name: &str, args: &[&str]) -> Result<R> {
+        R::from_lua(self.get_value(name, args)?, self.txn.lua)
+    }
+
+    /// The same as `get` but returns `None` if the sample is not available.
+    pub fn get_opt<R: FromLua<'lua>>(&self, name: &str, args: &[&str]) -> Result<Option<R>> {
+        match self.get_value(name, args)? {
+            Value::Nil => Ok(None),
+            value => R::from_lua(value, self.txn.lua).map(Some),
+        }
+    }
+
+    fn get_value(&self, name: &str, args: &[&str]) -> Result<Value<'lua>> {
+        let cache = self.cache()?;
+        let mut key = String::from(name);
+        for arg in args {
+            key.push('\0');
+            key.push_str(arg);
+        }
+        match cache.raw_get::<_, Value>(key.as_str())? {
+            // The cache table itself marks a missing sample
+            Value::Table(t) if t == cache => return Ok(Value::Nil),
+            Value::Nil => {}
+            value => return Ok(value),
+        }
+        let value: Value = self
+            .txn
+            .f
+            .get(name, Variadic::from_iter(args.iter().copied()))?;
+        match &value {
+            Value::Nil => cache.raw_set(key, &cache)?,
+            value => cache.raw_set(key, value)?,
+        }
+        Ok(value)
+    }
+
+    fn cache(&self) -> Result<Table<'lua>> {
+        let ext = self.txn.extensions(true)?.expect("extensions table");
+        match ext.raw_get::<_, Option<Table>>(FETCH_CACHE_KEY)? {
+            Some(cache) => Ok(cache),
+            None => {
+                let cache = self.txn.lua.create_table()?;
+                ext.raw_set(FETCH_CACHE_KEY, &cache)?;
+                Ok(cache)
+            }
+        }
+    }
+}
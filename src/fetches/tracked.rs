@@ -0,0 +1,100 @@
+use mlua::Result;
+
+use super::Fetches;
+use crate::Txn;
+
+/// Typed access to the stick-table counters tracked by `track-sc<n>` rules.
+///
+/// Wraps the `sc_*(<ctr>)` sample fetch family. Counters of untracked entries are `None`.
+///
+/// ```ignore
+/// let sc = txn.sc(0);
+/// if sc.http_req_rate()?.unwrap_or(0) > 100 {
+///     sc.inc_gpc0()?;
+/// }
+/// ```
+#[derive(Clone, Copy)]
+pub struct TrackedCounters<'a, 'lua> {
+    fetches: &'a Fetches<'lua>,
+    index: u8,
+}
+
+impl<'lua> Fetches<'lua> {
+    /// Returns the counters tracked by the `track-sc<index>` rules.
+    #[inline]
+    pub fn sc(&self, index: u8) -> TrackedCounters<'_, 'lua> {
+        TrackedCounters {
+            fetches: self,
+            index,
+        }
+    }
+}
+
+impl<'lua> Txn<'lua> {
+    /// Returns the counters tracked by the `track-sc<index>` rules.
+    ///
+    /// See [`Fetches::sc`].
+    #[inline]
+    pub fn sc(&self, index: u8) -> TrackedCounters<'_, 'lua> {
+        self.f.sc(index)
+    }
+}
+
+macro_rules! counters {
+    ($($(#[$meta:meta])* $method:ident($name:literal) -> $ty:ty;)*) => {
+        impl TrackedCounters<'_, '_> {
+            $(
+                $(#[$meta])*
+                #[inline]
+                pub fn $method(&self) -> Result<Option<$ty>> {
+                    self.fetches.get_opt($name, self.index)
+                }
+            )*
+        }
+    };
+}
+
+counters! {
+    /// Returns true if the counter is currently tracked (`sc_tracked`).
+    tracked("sc_tracked") -> bool;
+    /// Cumulative number of incoming connections (`sc_conn_cnt`).
+    conn_cnt("sc_conn_cnt") -> u64;
+    /// Number of concurrent connections (`sc_conn_cur`).
+    conn_cur("sc_conn_cur") -> u64;
+    /// Incoming connection rate (`sc_conn_rate`).
+    conn_rate("sc_conn_rate") -> u64;
+    /// Cumulative number of incoming sessions (`sc_sess_cnt`).
+    sess_cnt("sc_sess_cnt") -> u64;
+    /// Incoming session rate (`sc_sess_rate`).
+    sess_rate("sc_sess_rate") -> u64;
+    /// Cumulative number of HTTP requests (`sc_http_req_cnt`).
+    http_req_cnt("sc_http_req_cnt") -> u64;
+    /// HTTP request rate (`sc_http_req_rate`).
+    http_req_rate("sc_http_req_rate") -> u64;
+    /// Cumulative number of HTTP errors (`sc_http_err_cnt`).
+    http_err_cnt("sc_http_err_cnt") -> u64;
+    /// HTTP error rate (`sc_http_err_rate`).
+    http_err_rate("sc_http_err_rate") -> u64;
+    /// Rate of bytes received from the client (`sc_bytes_in_rate`).
+    bytes_in_rate("sc_bytes_in_rate") -> u64;
+    /// Rate of bytes sent to the client (`sc_bytes_out_rate`).
+    bytes_out_rate("sc_bytes_out_rate") -> u64;
+    /// Value of the first general purpose counter (`sc_get_gpc0`).
+    gpc0("sc_get_gpc0") -> u64;
+    /// Increment rate of the first general purpose counter (`sc_gpc0_rate`).
+    gpc0_rate("sc_gpc0_rate") -> u64;
+    /// Value of the second general purpose counter (`sc_get_gpc1`).
+    gpc1("sc_get_gpc1") -> u64;
+    /// Increment rate of the second general purpose counter (`sc_gpc1_rate`).
+    gpc1_rate("sc_gpc1_rate") -> u64;
+    /// Value of the first general purpose tag (`sc_get_gpt0`).
+    gpt0("sc_get_gpt0") -> u64;
+    /// Increments the first general purpose counter, returning the new value (`sc_inc_gpc0`).
+    inc_gpc0("sc_inc_gpc0") -> u64;
+    /// Increments the second general purpose counter, returning the new value (`sc_inc_gpc1`).
+    inc_gpc1("sc_inc_gpc1") -> u64;
+    /// Clears the first general purpose counter, returning its previous value (`sc_clr_gpc0`).
+    clr_gpc0("sc_clr_gpc0") -> u64;
+    /// Clears the second general purpose counter, returning its previous value (`sc_clr_gpc1`).
+    clr_gpc1("sc_clr_gpc1") -> u64;
+}
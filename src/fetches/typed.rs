@@ -0,0 +1,66 @@
+use std::net::IpAddr;
+
+use mlua::{Result, TableExt, Value};
+
+use super::Fetches;
+use crate::sample::FromSample;
+
+macro_rules! typed_fetches {
+    ($($(#[$meta:meta])* $method:ident($name:literal) -> $ty:ty;)*) => {
+        impl<'lua> Fetches<'lua> {
+            $(
+                $(#[$meta])*
+                #[inline]
+                pub fn $method(&self) -> Result<$ty> {
+                    let value = self.class.call_method::<_, Value>($name, ())?;
+                    FromSample::from_sample(value, self.lua)
+                }
+            )*
+        }
+    };
+}
+
+typed_fetches! {
+    /// Source IP address of the client connection (`src`).
+    src("src") -> Option<IpAddr>;
+    /// Source port of the client connection (`src_port`).
+    src_port("src_port") -> Option<u16>;
+    /// Destination IP address of the client connection (`dst`).
+    dst("dst") -> Option<IpAddr>;
+    /// Destination port of the client connection (`dst_port`).
+    dst_port("dst_port") -> Option<u16>;
+    /// Name of the frontend (`fe_name`).
+    fe_name("fe_name") -> Option<String>;
+    /// Name of the backend (`be_name`).
+    be_name("be_name") -> Option<String>;
+    /// Name of the server (`srv_name`).
+    srv_name("srv_name") -> Option<String>;
+    /// Returns true if the client connection uses SSL/TLS (`ssl_fc`).
+    ssl_fc("ssl_fc") -> bool;
+    /// Server name indication sent by the client (`ssl_fc_sni`).
+    ssl_fc_sni("ssl_fc_sni") -> Option<String>;
+    /// SSL/TLS protocol version of the client connection (`ssl_fc_protocol`).
+    ssl_fc_protocol("ssl_fc_protocol") -> Option<String>;
+    /// ALPN protocol negotiated with the client (`ssl_fc_alpn`).
+    ssl_fc_alpn("ssl_fc_alpn") -> Option<String>;
+    /// HTTP request method (`method`).
+    method("method") -> Option<String>;
+    /// HTTP request path, without the query string (`path`).
+    path("path") -> Option<String>;
+    /// HTTP request query string, without the question mark (`query`).
+    query("query") -> Option<String>;
+    /// HTTP request URL (`url`).
+    url("url") -> Option<String>;
+    /// HTTP request host and path, without the query string (`base`).
+    base("base") -> Option<String>;
+    /// HTTP request version, eg. `1.1` (`req.ver`).
+    req_ver("req_ver") -> Option<String>;
+    /// HTTP response status code (`status`).
+    status("status") -> Option<u16>;
+    /// Unique ID of the request, generated using `unique-id-format` (`unique-id`).
+    unique_id("unique_id") -> Option<String>;
+    /// Number of bytes in the request buffer (`req.len`).
+    req_len("req_len") -> u64;
+    /// Number of bytes in the response buffer (`res.len`).
+    res_len("res_len") -> u64;
+}
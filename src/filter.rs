@@ -1,9 +1,16 @@
 use std::any::type_name;
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::ops::{Deref, DerefMut};
 
-use mlua::{AnyUserData, IntoLua, Lua, Result, Table, TableExt, UserData, Value, Variadic};
+use mlua::{
+    AnyUserData, FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, Lua, Result, Table, TableExt,
+    UserData, Value, Variadic,
+};
 
-use crate::{Channel, Core, HttpMessage, LogLevel, Txn};
+use crate::error::{CallbackKind, Error};
+use crate::{Channel, Core, HttpMessage, LogLevel, MaybeSend, Proxy, ProxyMode, Txn};
 
 /// Represents methods available to call in [`UserFilter`].
 pub struct FilterMethod;
@@ -14,10 +21,40 @@ impl FilterMethod {
     pub const HTTP_HEADERS: u8 = 0b00000100;
     pub const HTTP_PAYLOAD: u8 = 0b00001000;
     pub const HTTP_END: u8 = 0b00010000;
+    pub const ATTACH: u8 = 0b00100000;
 
     pub const ALL: u8 = u8::MAX;
 }
 
+/// Direction of the channel (or HTTP message) a filter callback is called for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+impl Direction {
+    /// Returns true if the direction is [`Direction::Request`].
+    #[inline]
+    pub fn is_request(self) -> bool {
+        self == Direction::Request
+    }
+
+    /// Returns true if the direction is [`Direction::Response`].
+    #[inline]
+    pub fn is_response(self) -> bool {
+        self == Direction::Response
+    }
+
+    #[inline]
+    pub(crate) fn from_resp(resp: bool) -> Self {
+        match resp {
+            true => Direction::Response,
+            false => Direction::Request,
+        }
+    }
+}
+
 /// A code that filter callback functions may return.
 pub enum FilterResult {
     /// A filtering step is finished for filter.
@@ -38,6 +75,123 @@ impl FilterResult {
     }
 }
 
+/// Describes where a filter instance is attached to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterAttachment {
+    /// The filter is declared on a frontend (or a `listen` section).
+    Frontend,
+    /// The filter is declared on a backend.
+    Backend,
+}
+
+/// Information about the stream a filter instance is attached to.
+///
+/// It's resolved once, on the first callback of the filter instance, and passed to [`UserFilter::attach`].
+#[derive(Debug, Clone)]
+pub struct FilterContext {
+    /// Name of the proxy the filter is declared on.
+    pub proxy: String,
+    /// Name of the frontend the stream belongs to.
+    pub frontend: String,
+    /// Name of the backend the stream is assigned to at the moment of attachment.
+    pub backend: String,
+    /// Name of the listener which accepted the connection (if it's named).
+    pub listener: Option<String>,
+    /// Whether the filter is attached on a frontend or on a backend.
+    pub attachment: FilterAttachment,
+    /// Mode of the proxy the filter is declared on.
+    pub mode: ProxyMode,
+}
+
+impl FilterContext {
+    fn from_txn(lua: &Lua, txn: &Txn) -> Result<Self> {
+        let frontend = txn.f.get_str("fe_name", ())?;
+        let backend = txn.f.get_str("be_name", ())?;
+        let listener = txn.f.get::<_, Option<String>>("so_name", ())?;
+
+        // HAProxy attaches backend filters only when the stream is assigned to a backend other
+        // than its frontend, and until then the backend is the frontend itself.
+        // Proxy ids are compared as a frontend and a backend may share the same name.
+        let fe_id: i64 = txn.f.get("fe_id", ())?;
+        let be_id: i64 = txn.f.get("be_id", ())?;
+        let (attachment, proxy, proxies) = if be_id != fe_id {
+            (FilterAttachment::Backend, backend.clone(), "backends")
+        } else {
+            (FilterAttachment::Frontend, frontend.clone(), "frontends")
+        };
+
+        let proxies = Core::new(lua)?.get::<_, Table>(proxies)?;
+        let mode = match proxies.get::<_, Option<Proxy>>(proxy.as_str())? {
+            Some(proxy) => proxy.get_mode()?,
+            None => ProxyMode::Unknown,
+        };
+
+        Ok(FilterContext {
+            proxy,
+            frontend,
+            backend,
+            listener,
+            attachment,
+            mode,
+        })
+    }
+}
+
+/// A handle to the Lua table backing a filter instance on a stream.
+///
+/// It can be used to share values with Lua code working with the same filter instance
+/// or to call Lua helpers registered on the filter class.
+/// The integer key `1` is reserved.
+#[derive(Clone)]
+pub struct FilterHandle<'lua>(pub(crate) Table<'lua>);
+
+impl<'lua> FilterHandle<'lua> {
+    /// Returns the value stored under the `key`.
+    #[inline]
+    pub fn get<V: FromLua<'lua>>(&self, key: &str) -> Result<V> {
+        self.0.raw_get(key)
+    }
+
+    /// Stores the `value` under the `key`.
+    #[inline]
+    pub fn set<V: IntoLua<'lua>>(&self, key: &str, value: V) -> Result<()> {
+        self.0.raw_set(key, value)
+    }
+
+    /// Returns the filter class table (shared by all instances of the filter).
+    #[inline]
+    pub fn class(&self) -> Option<Table<'lua>> {
+        self.0.get_metatable()
+    }
+
+    /// Calls the Lua method `name` registered on the filter class (or instance),
+    /// passing the filter table as the first argument.
+    #[inline]
+    pub fn call_method<A, R>(&self, name: &str, args: A) -> Result<R>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        self.0.call_method(name, args)
+    }
+}
+
+impl<'lua> Deref for FilterHandle<'lua> {
+    type Target = Table<'lua>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'lua> IntoLua<'lua> for FilterHandle<'lua> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(self.0))
+    }
+}
+
 /// A flag corresponding to the filter flag FLT_CFG_FL_HTX.
 /// When it is set for a filter, it means the filter is able to filter HTTP streams.
 const FLT_CFG_FL_HTX: u8 = 1;
@@ -48,21 +202,62 @@ pub trait UserFilter: Sized {
     /// By default ALL
     const METHODS: u8 = FilterMethod::ALL;
 
+    /// Sets methods available for this filter on the request channel.
+    /// By default [`UserFilter::METHODS`]
+    const REQUEST_METHODS: u8 = Self::METHODS;
+
+    /// Sets methods available for this filter on the response channel.
+    /// By default [`UserFilter::METHODS`]
+    const RESPONSE_METHODS: u8 = Self::METHODS;
+
     /// Continue execution if a filter callback returns an error.
     const CONTINUE_IF_ERROR: bool = true;
 
+    /// A fraction of streams (between 0.0 and 1.0) the filter is instantiated for.
+    /// Streams that are not sampled do not get the filter attached at all.
+    /// By default 1.0 (all streams).
+    const SAMPLE_RATE: f64 = 1.0;
+
     /// Creates a new instance of filter.
+    ///
+    /// The instance is created when the filter is attached to a stream.
+    /// If it fails, the filter is ignored for the stream.
     fn new(lua: &Lua, args: Table) -> Result<Self>;
 
+    /// Called once, before the first `start_analyze` call, with the context describing
+    /// the proxy and the stream the filter is attached to.
+    ///
+    /// HAProxy creates filter instances before the stream information is available,
+    /// so it cannot be passed to [`UserFilter::new`].
+    /// It's called only if [`FilterMethod::ATTACH`] is set.
+    fn attach(&mut self, lua: &Lua, ctx: &FilterContext) -> Result<()> {
+        let _ = (lua, ctx);
+        Ok(())
+    }
+
     /// Called when the analysis starts on the channel `chn`.
-    fn start_analyze(&mut self, lua: &Lua, txn: Txn, chn: Channel) -> Result<FilterResult> {
-        let _ = (lua, txn, chn);
+    /// The `dir` indicates which side the channel belongs to.
+    fn start_analyze(
+        &mut self,
+        lua: &Lua,
+        txn: Txn,
+        chn: Channel,
+        dir: Direction,
+    ) -> Result<FilterResult> {
+        let _ = (lua, txn, chn, dir);
         Ok(FilterResult::Continue)
     }
 
     /// Called when the analysis ends on the channel `chn`.
-    fn end_analyze(&mut self, lua: &Lua, txn: Txn, chn: Channel) -> Result<FilterResult> {
-        let _ = (lua, txn, chn);
+    /// The `dir` indicates which side the channel belongs to.
+    fn end_analyze(
+        &mut self,
+        lua: &Lua,
+        txn: Txn,
+        chn: Channel,
+        dir: Direction,
+    ) -> Result<FilterResult> {
+        let _ = (lua, txn, chn, dir);
         Ok(FilterResult::Continue)
     }
 
@@ -92,7 +287,7 @@ pub trait UserFilter: Sized {
     /// It may be called at any time from any callback functions proceeding the data analysis.
     fn register_data_filter(lua: &Lua, txn: Txn, chn: Channel) -> Result<()> {
         let global_filter = lua.globals().raw_get::<_, Table>("filter")?;
-        global_filter.call_function("register_data_filter", (txn.r#priv, chn))?;
+        global_filter.call_function::<_, ()>("register_data_filter", (txn.r#priv, chn))?;
         Ok(())
     }
 
@@ -100,25 +295,47 @@ pub trait UserFilter: Sized {
     /// It may be called at any time from any callback functions.
     fn unregister_data_filter(lua: &Lua, txn: Txn, chn: Channel) -> Result<()> {
         let filter = lua.globals().raw_get::<_, Table>("filter")?;
-        filter.call_function("unregister_data_filter", (txn.r#priv, chn))?;
+        filter.call_function::<_, ()>("unregister_data_filter", (txn.r#priv, chn))?;
         Ok(())
     }
 
     /// Set the pause timeout to the specified time, defined in milliseconds.
     fn wake_time(lua: &Lua, milliseconds: u64) -> Result<()> {
         let filter = lua.globals().raw_get::<_, Table>("filter")?;
-        filter.call_function("wake_time", milliseconds)?;
+        filter.call_function::<_, ()>("wake_time", milliseconds)?;
         Ok(())
     }
 }
 
-pub(crate) struct UserFilterWrapper<T>(T);
+/// Returns a random number in the range [0, 1).
+///
+/// A per-thread counter is hashed with the randomly keyed std hasher (SipHash), so no extra
+/// dependency is needed. The quality is enough for sampling and fault injection, which are
+/// the only users, but it must not be used where unpredictability matters.
+pub(crate) fn random() -> f64 {
+    thread_local! {
+        static STATE: (RandomState, Cell<u64>) = (RandomState::new(), Cell::new(0));
+    }
+    STATE.with(|(state, counter)| {
+        counter.set(counter.get().wrapping_add(1));
+        let mut hasher = state.build_hasher();
+        hasher.write_u64(counter.get());
+        (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+pub(crate) struct UserFilterWrapper<T> {
+    filter: T,
+    attached: bool,
+}
 
 impl<T> UserFilterWrapper<T>
 where
-    T: UserFilter + 'static,
+    T: UserFilter + MaybeSend + 'static,
 {
-    pub(crate) fn make_class(lua: &Lua) -> Result<Table> {
+    const METHODS: u8 = T::REQUEST_METHODS | T::RESPONSE_METHODS;
+
+    pub(crate) fn make_class(lua: &Lua, sample_rate: f64) -> Result<Table<'_>> {
         let class = lua.create_table()?;
         class.raw_set("__index", &class)?;
 
@@ -133,6 +350,10 @@ where
         class.raw_set(
             "new",
             lua.create_function(move |lua, class: Table| {
+                if sample_rate < 1.0 && random() >= sample_rate {
+                    // The stream is not sampled, HAProxy ignores the filter for it
+                    return Ok(Value::Nil);
+                }
                 let args = class.raw_get("args")?;
                 let filter = match T::new(lua, args) {
                     Ok(filter) => filter,
@@ -143,57 +364,91 @@ where
                         return Ok(Value::Nil);
                     }
                 };
-                let this = lua.create_sequence_from([Self(filter)])?;
+                let this = lua.create_sequence_from([Self {
+                    filter,
+                    attached: false,
+                }])?;
                 let class = lua.registry_value::<Table>(&class_key)?;
                 this.set_metatable(Some(class));
                 Ok(Value::Table(this))
             })?,
         )?;
 
-        if T::METHODS & FilterMethod::START_ANALYZE != 0 {
+        if Self::METHODS & (FilterMethod::START_ANALYZE | FilterMethod::ATTACH) != 0 {
             class.raw_set(
                 "start_analyze",
                 lua.create_function(|lua, (t, mut txn, chn): (Table, Txn, Channel)| {
-                    let ud = t.raw_get::<_, AnyUserData>(1)?;
+                    let Some(ud) = Self::instance(&t)? else {
+                        return Ok(FilterResult::Continue.code());
+                    };
                     let mut this = ud.borrow_mut::<Self>()?;
+                    if Self::METHODS & FilterMethod::ATTACH != 0 && !this.attached {
+                        this.attached = true;
+                        let res = FilterContext::from_txn(lua, &txn)
+                            .and_then(|ctx| this.filter.attach(lua, &ctx));
+                        if let Err(err) = res {
+                            Self::process_result(lua, "attach", Err(err))?;
+                        }
+                    }
+                    let dir = Direction::from_resp(chn.is_resp()?);
+                    if !Self::is_enabled(FilterMethod::START_ANALYZE, dir) {
+                        return Ok(FilterResult::Continue.code());
+                    }
                     txn.r#priv = Value::Table(t);
-                    Self::process_result(lua, this.start_analyze(lua, txn, chn))
+                    let res = this.start_analyze(lua, txn, chn, dir);
+                    Self::process_result(lua, "start_analyze", res)
                 })?,
             )?;
         }
 
-        if T::METHODS & FilterMethod::END_ANALYZE != 0 {
+        if Self::METHODS & FilterMethod::END_ANALYZE != 0 {
             class.raw_set(
                 "end_analyze",
                 lua.create_function(|lua, (t, mut txn, chn): (Table, Txn, Channel)| {
-                    let ud = t.raw_get::<_, AnyUserData>(1)?;
+                    let Some(ud) = Self::instance(&t)? else {
+                        return Ok(FilterResult::Continue.code());
+                    };
+                    let dir = Direction::from_resp(chn.is_resp()?);
+                    if !Self::is_enabled(FilterMethod::END_ANALYZE, dir) {
+                        return Ok(FilterResult::Continue.code());
+                    }
                     let mut this = ud.borrow_mut::<Self>()?;
                     txn.r#priv = Value::Table(t);
-                    Self::process_result(lua, this.end_analyze(lua, txn, chn))
+                    Self::process_result(lua, "end_analyze", this.end_analyze(lua, txn, chn, dir))
                 })?,
             )?;
         }
 
-        if T::METHODS & FilterMethod::HTTP_HEADERS != 0 {
+        if Self::METHODS & FilterMethod::HTTP_HEADERS != 0 {
             class.raw_set(
                 "http_headers",
                 lua.create_function(|lua, (t, mut txn, msg): (Table, Txn, HttpMessage)| {
-                    let ud = t.raw_get::<_, AnyUserData>(1)?;
+                    let Some(ud) = Self::instance(&t)? else {
+                        return Ok(FilterResult::Continue.code());
+                    };
+                    if !Self::is_msg_enabled(FilterMethod::HTTP_HEADERS, &msg)? {
+                        return Ok(FilterResult::Continue.code());
+                    }
                     let mut this = ud.borrow_mut::<Self>()?;
                     txn.r#priv = Value::Table(t);
-                    Self::process_result(lua, this.http_headers(lua, txn, msg))
+                    Self::process_result(lua, "http_headers", this.http_headers(lua, txn, msg))
                 })?,
             )?;
         }
 
-        if T::METHODS & FilterMethod::HTTP_PAYLOAD != 0 {
+        if Self::METHODS & FilterMethod::HTTP_PAYLOAD != 0 {
             class.raw_set(
                 "http_payload",
                 lua.create_function(|lua, (t, mut txn, msg): (Table, Txn, HttpMessage)| {
-                    let ud = t.raw_get::<_, AnyUserData>(1)?;
+                    let mut res = Variadic::new();
+                    let Some(ud) = Self::instance(&t)? else {
+                        return Ok(res);
+                    };
+                    if !Self::is_msg_enabled(FilterMethod::HTTP_PAYLOAD, &msg)? {
+                        return Ok(res);
+                    }
                     let mut this = ud.borrow_mut::<Self>()?;
                     txn.r#priv = Value::Table(t);
-                    let mut res = Variadic::new();
                     match this.http_payload(lua, txn, msg) {
                         Ok(Some(len)) => {
                             res.push(len.into_lua(lua)?);
@@ -207,21 +462,26 @@ where
                                 );
                             }
                         }
-                        Err(err) => return Err(err),
+                        Err(err) => return Err(Self::callback_error("http_payload", err)),
                     };
                     Ok(res)
                 })?,
             )?;
         }
 
-        if T::METHODS & FilterMethod::HTTP_END != 0 {
+        if Self::METHODS & FilterMethod::HTTP_END != 0 {
             class.raw_set(
                 "http_end",
                 lua.create_function(|lua, (t, mut txn, msg): (Table, Txn, HttpMessage)| {
-                    let ud = t.raw_get::<_, AnyUserData>(1)?;
+                    let Some(ud) = Self::instance(&t)? else {
+                        return Ok(FilterResult::Continue.code());
+                    };
+                    if !Self::is_msg_enabled(FilterMethod::HTTP_END, &msg)? {
+                        return Ok(FilterResult::Continue.code());
+                    }
                     let mut this = ud.borrow_mut::<Self>()?;
                     txn.r#priv = Value::Table(t);
-                    Self::process_result(lua, this.http_end(lua, txn, msg))
+                    Self::process_result(lua, "http_end", this.http_end(lua, txn, msg))
                 })?,
             )?;
         }
@@ -229,8 +489,40 @@ where
         Ok(class)
     }
 
+    /// Returns true if the `method` is enabled for the direction `dir`.
     #[inline]
-    fn process_result(lua: &Lua, res: Result<FilterResult>) -> Result<i8> {
+    fn is_enabled(method: u8, dir: Direction) -> bool {
+        match dir {
+            Direction::Request => T::REQUEST_METHODS & method != 0,
+            Direction::Response => T::RESPONSE_METHODS & method != 0,
+        }
+    }
+
+    /// Returns true if the `method` is enabled for the HTTP message `msg` direction.
+    ///
+    /// The message direction is checked only if the method masks differ.
+    #[inline]
+    fn is_msg_enabled(method: u8, msg: &HttpMessage) -> Result<bool> {
+        if T::REQUEST_METHODS & method == T::RESPONSE_METHODS & method {
+            return Ok(true);
+        }
+        Ok(Self::is_enabled(
+            method,
+            Direction::from_resp(msg.is_resp()?),
+        ))
+    }
+
+    /// Returns the user filter instance stored in the filter table `t`.
+    #[inline]
+    fn instance<'lua>(t: &Table<'lua>) -> Result<Option<AnyUserData<'lua>>> {
+        match t.raw_get::<_, Value>(1)? {
+            Value::UserData(ud) => Ok(Some(ud)),
+            _ => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn process_result(lua: &Lua, method: &str, res: Result<FilterResult>) -> Result<i8> {
         match res {
             Ok(res) => Ok(res.code()),
             Err(err) if T::CONTINUE_IF_ERROR => {
@@ -242,25 +534,31 @@ where
                 }
                 Ok(FilterResult::Continue.code())
             }
-            Err(err) => Err(err),
+            Err(err) => Err(Self::callback_error(method, err)),
         }
     }
+
+    /// Adds the filter name and the `method` to the error context.
+    #[inline]
+    fn callback_error(method: &str, err: mlua::Error) -> mlua::Error {
+        Error::callback_method(CallbackKind::Filter, type_name::<T>(), Some(method), err)
+    }
 }
 
-impl<T> UserData for UserFilterWrapper<T> where T: UserFilter + 'static {}
+impl<T> UserData for UserFilterWrapper<T> where T: UserFilter + MaybeSend + 'static {}
 
 impl<T> Deref for UserFilterWrapper<T> {
     type Target = T;
 
     #[inline]
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.filter
     }
 }
 
 impl<T> DerefMut for UserFilterWrapper<T> {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.filter
     }
 }
@@ -0,0 +1,305 @@
+use std::any::type_name;
+
+use mlua::{Lua, Result, Table};
+
+use crate::filter::random;
+use crate::{
+    Channel, Core, Direction, FilterContext, FilterMethod, FilterResult, HttpMessage, LogLevel,
+    Txn, UserFilter,
+};
+
+// Filter argument separating the arguments of the chain stages
+const STAGE_SEPARATOR: &str = "|";
+
+/// A combinator that composes several [`UserFilter`] implementations under a single filter.
+///
+/// Stages are instantiated together and share one filter instance per stream.
+/// Each callback invokes the stages in order; the chain stops as soon as a stage
+/// returns something other than [`FilterResult::Continue`].
+/// For `http_payload` the smallest amount of data to forward wins, and a stage returning
+/// `Some(0)` stops the chain.
+///
+/// The HTTP message cannot be narrowed for the following stages, so after a stage returns
+/// `Some(n)` the rest of the chain still sees the whole input, and the data kept back are
+/// seen again on the next call. A stage returning `Some(n)` must therefore be the last one
+/// in the chain inspecting the payload (stages counting `msg.input()`, like
+/// [`BodyLimitFilter`](crate::BodyLimitFilter), would count the kept back data twice).
+///
+/// Data filtering registered by one stage enables `http_payload` calls for every stage
+/// that declares [`FilterMethod::HTTP_PAYLOAD`].
+///
+/// The filter arguments are split between the stages by the `|` argument: every stage gets
+/// its own arguments table (stages without arguments get an empty one). Each stage is
+/// instantiated according to its own [`UserFilter::SAMPLE_RATE`].
+///
+/// ```ignore
+/// core.register_filter::<FilterChain<(BodyLimitFilter, HeaderRewriteFilter)>>("pipeline")?;
+/// ```
+///
+/// ```text
+/// filter lua.pipeline req:1048576 | req-set:x-forwarded-proto=https
+/// ```
+pub struct FilterChain<T: ChainStages>(T::Stages);
+
+/// Tuples of [`UserFilter`]s that can be composed by [`FilterChain`].
+#[doc(hidden)]
+pub trait ChainStages {
+    /// The stage instances, `None` for stages not sampled for the stream.
+    type Stages;
+}
+
+// Splits the filter arguments into (at most) `count` groups separated by `STAGE_SEPARATOR`
+fn split_stage_args<S: AsRef<str>>(args: &[S], count: usize) -> Result<Vec<&[S]>> {
+    let groups = args
+        .split(|arg| arg.as_ref() == STAGE_SEPARATOR)
+        .collect::<Vec<_>>();
+    if groups.len() > count {
+        let msg = format!(
+            "filter chain: {} argument groups for {count} stages",
+            groups.len()
+        );
+        return Err(mlua::Error::runtime(msg));
+    }
+    Ok(groups)
+}
+
+// Returns the arguments tables of the stages, cached at index `0` of the chain arguments
+fn stage_args<'lua>(lua: &'lua Lua, args: &Table<'lua>, count: usize) -> Result<Table<'lua>> {
+    if let Some(tables) = args.raw_get::<_, Option<Table>>(0)? {
+        return Ok(tables);
+    }
+    let values = (args.clone().sequence_values::<String>()).collect::<Result<Vec<_>>>()?;
+    let tables = lua.create_table_with_capacity(count, 0)?;
+    let groups = split_stage_args(&values, count)?;
+    for i in 0..count {
+        let group = groups.get(i).copied().unwrap_or_default();
+        tables.raw_push(lua.create_sequence_from(group.iter().map(String::as_str))?)?;
+    }
+    args.raw_set(0, &tables)?;
+    Ok(tables)
+}
+
+#[inline]
+fn stage_sampled<T: UserFilter>() -> bool {
+    T::SAMPLE_RATE >= 1.0 || random() < T::SAMPLE_RATE
+}
+
+#[inline]
+fn stage_enabled<T: UserFilter>(method: u8, dir: Direction) -> bool {
+    match dir {
+        Direction::Request => T::REQUEST_METHODS & method != 0,
+        Direction::Response => T::RESPONSE_METHODS & method != 0,
+    }
+}
+
+// Resolves the message direction only if the stage masks differ
+#[inline]
+fn stage_msg_enabled<T: UserFilter>(
+    method: u8,
+    msg: &HttpMessage,
+    dir: &mut Option<Direction>,
+) -> Result<bool> {
+    if T::REQUEST_METHODS & method == T::RESPONSE_METHODS & method {
+        return Ok(T::REQUEST_METHODS & method != 0);
+    }
+    let dir = match *dir {
+        Some(dir) => dir,
+        None => *dir.insert(Direction::from_resp(msg.is_resp()?)),
+    };
+    Ok(stage_enabled::<T>(method, dir))
+}
+
+#[inline]
+fn stage_error<T: UserFilter>(lua: &Lua, err: mlua::Error) -> Result<()> {
+    if !T::CONTINUE_IF_ERROR {
+        return Err(err);
+    }
+    if let Ok(core) = Core::new(lua) {
+        let _ = core.log(
+            LogLevel::Err,
+            format!("Filter '{}': {}", type_name::<T>(), err),
+        );
+    }
+    Ok(())
+}
+
+#[inline]
+fn stage_result<T: UserFilter>(lua: &Lua, res: Result<FilterResult>) -> Result<FilterResult> {
+    match res {
+        Ok(res) => Ok(res),
+        Err(err) => stage_error::<T>(lua, err).map(|_| FilterResult::Continue),
+    }
+}
+
+macro_rules! impl_filter_chain {
+    ($count:literal: $($name:ident $idx:tt),+) => {
+        impl<$($name: UserFilter),+> ChainStages for ($($name,)+) {
+            type Stages = ($(Option<$name>,)+);
+        }
+
+        impl<$($name: UserFilter),+> UserFilter for FilterChain<($($name,)+)> {
+            const METHODS: u8 = $($name::METHODS)|+;
+            const REQUEST_METHODS: u8 = $($name::REQUEST_METHODS)|+;
+            const RESPONSE_METHODS: u8 = $($name::RESPONSE_METHODS)|+;
+
+            // Errors are handled according to each stage settings
+            const CONTINUE_IF_ERROR: bool = false;
+
+            fn new(lua: &Lua, args: Table) -> Result<Self> {
+                let args = stage_args(lua, &args, $count)?;
+                Ok(FilterChain(($(
+                    match stage_sampled::<$name>() {
+                        true => Some($name::new(lua, args.raw_get($idx + 1)?)?),
+                        false => None,
+                    },
+                )+)))
+            }
+
+            fn attach(&mut self, lua: &Lua, ctx: &FilterContext) -> Result<()> {
+                $(
+                    if let Some(stage) = &mut self.0.$idx {
+                        if $name::METHODS & FilterMethod::ATTACH != 0 {
+                            if let Err(err) = stage.attach(lua, ctx) {
+                                stage_error::<$name>(lua, err)?;
+                            }
+                        }
+                    }
+                )+
+                Ok(())
+            }
+
+            fn start_analyze(
+                &mut self,
+                lua: &Lua,
+                txn: Txn,
+                chn: Channel,
+                dir: Direction,
+            ) -> Result<FilterResult> {
+                $(
+                    match &mut self.0.$idx {
+                        Some(stage) if stage_enabled::<$name>(FilterMethod::START_ANALYZE, dir) => {
+                            let res = stage.start_analyze(lua, txn.clone(), chn.clone(), dir);
+                            match stage_result::<$name>(lua, res)? {
+                                FilterResult::Continue => {}
+                                res => return Ok(res),
+                            }
+                        }
+                        _ => {}
+                    }
+                )+
+                Ok(FilterResult::Continue)
+            }
+
+            fn end_analyze(
+                &mut self,
+                lua: &Lua,
+                txn: Txn,
+                chn: Channel,
+                dir: Direction,
+            ) -> Result<FilterResult> {
+                $(
+                    match &mut self.0.$idx {
+                        Some(stage) if stage_enabled::<$name>(FilterMethod::END_ANALYZE, dir) => {
+                            let res = stage.end_analyze(lua, txn.clone(), chn.clone(), dir);
+                            match stage_result::<$name>(lua, res)? {
+                                FilterResult::Continue => {}
+                                res => return Ok(res),
+                            }
+                        }
+                        _ => {}
+                    }
+                )+
+                Ok(FilterResult::Continue)
+            }
+
+            fn http_headers(&mut self, lua: &Lua, txn: Txn, msg: HttpMessage) -> Result<FilterResult> {
+                let mut dir = None;
+                $(
+                    if let Some(stage) = &mut self.0.$idx {
+                        if stage_msg_enabled::<$name>(FilterMethod::HTTP_HEADERS, &msg, &mut dir)? {
+                            let res = stage.http_headers(lua, txn.clone(), msg.clone());
+                            match stage_result::<$name>(lua, res)? {
+                                FilterResult::Continue => {}
+                                res => return Ok(res),
+                            }
+                        }
+                    }
+                )+
+                Ok(FilterResult::Continue)
+            }
+
+            fn http_payload(&mut self, lua: &Lua, txn: Txn, msg: HttpMessage) -> Result<Option<usize>> {
+                let mut dir = None;
+                let mut result: Option<usize> = None;
+                $(
+                    if let Some(stage) = &mut self.0.$idx {
+                        if stage_msg_enabled::<$name>(FilterMethod::HTTP_PAYLOAD, &msg, &mut dir)? {
+                            match stage.http_payload(lua, txn.clone(), msg.clone()) {
+                                Ok(Some(0)) => return Ok(Some(0)),
+                                Ok(Some(len)) => result = Some(result.map_or(len, |r| r.min(len))),
+                                Ok(None) => {}
+                                Err(err) => stage_error::<$name>(lua, err)?,
+                            }
+                        }
+                    }
+                )+
+                Ok(result)
+            }
+
+            fn http_end(&mut self, lua: &Lua, txn: Txn, msg: HttpMessage) -> Result<FilterResult> {
+                let mut dir = None;
+                $(
+                    if let Some(stage) = &mut self.0.$idx {
+                        if stage_msg_enabled::<$name>(FilterMethod::HTTP_END, &msg, &mut dir)? {
+                            let res = stage.http_end(lua, txn.clone(), msg.clone());
+                            match stage_result::<$name>(lua, res)? {
+                                FilterResult::Continue => {}
+                                res => return Ok(res),
+                            }
+                        }
+                    }
+                )+
+                Ok(FilterResult::Continue)
+            }
+        }
+    };
+}
+
+impl_filter_chain!(2: A 0, B 1);
+impl_filter_chain!(3: A 0, B 1, C 2);
+impl_filter_chain!(4: A 0, B 1, C 2, D 3);
+impl_filter_chain!(5: A 0, B 1, C 2, D 3, E 4);
+impl_filter_chain!(6: A 0, B 1, C 2, D 3, E 4, F 5);
+impl_filter_chain!(7: A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_filter_chain!(8: A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BodyLimits, HeaderRewriteRules};
+
+    #[test]
+    fn test_split_stage_args() {
+        let args = ["a", "|", "b", "c", "|"];
+        let groups = split_stage_args(&args, 3).unwrap();
+        assert_eq!(groups, vec![&["a"][..], &["b", "c"], &[]]);
+        let groups = split_stage_args::<&str>(&[], 2).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(groups[0].is_empty());
+        assert!(split_stage_args(&args, 2).is_err());
+    }
+
+    #[test]
+    fn test_builtin_stages() {
+        // FilterChain<(BodyLimitFilter, HeaderRewriteFilter)>
+        let args = ["req:1024", "res-action:log", "|", "res-del:server"];
+        let groups = split_stage_args(&args, 2).unwrap();
+        let limits = BodyLimits::parse_args(groups[0]).unwrap();
+        assert!(format!("{limits:?}").contains("request: Some(1024)"));
+        let rules = HeaderRewriteRules::parse_args(groups[1]).unwrap();
+        assert!(format!("{rules:?}").contains("\"server\""));
+
+        // Stages reject the arguments of each other
+        assert!(BodyLimits::parse_args(&args).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! `Forwarded` and `X-Forwarded-*` headers parsing.
+//!
+//! Parses RFC 7239 `Forwarded`, `X-Forwarded-For` and `X-Forwarded-Proto` request headers
+//! and computes the effective client IP address, stripping the chain of trusted proxies:
+//!
+//! ```ignore
+//! let trusted = TrustedProxies::new().trust("10.0.0.0/8")?.trust("192.168.0.0/16")?;
+//! let client_ip = forwarded::client_ip(&txn, &trusted)?;
+//! ```
+
+use std::net::IpAddr;
+
+use mlua::{Error, Result};
+
+use crate::{Headers, Txn};
+
+/// A single element of the `Forwarded` header (one proxy hop).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardedElement {
+    /// The `for` parameter: the client-facing node of the proxy.
+    pub for_: Option<String>,
+    /// The `by` parameter: the interface where the request came in to the proxy.
+    pub by: Option<String>,
+    /// The `host` parameter: the original `Host` header value.
+    pub host: Option<String>,
+    /// The `proto` parameter: the protocol used to make the request.
+    pub proto: Option<String>,
+}
+
+impl ForwardedElement {
+    /// Returns the IP address of the `for` node, if it is not obfuscated or `unknown`.
+    pub fn for_ip(&self) -> Option<IpAddr> {
+        self.for_.as_deref().and_then(parse_node)
+    }
+}
+
+/// Parses the `Forwarded` header values into elements, in order of appearance.
+///
+/// Unknown parameters are ignored.
+pub fn parse_forwarded<S: AsRef<str>>(values: &[S]) -> Vec<ForwardedElement> {
+    values
+        .iter()
+        .flat_map(|v| split_quoted(v.as_ref(), ','))
+        .map(|element| {
+            let mut result = ForwardedElement::default();
+            for pair in split_quoted(element, ';') {
+                let Some((key, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = unquote(value.trim());
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "for" => result.for_ = Some(value),
+                    "by" => result.by = Some(value),
+                    "host" => result.host = Some(value),
+                    "proto" => result.proto = Some(value.to_ascii_lowercase()),
+                    _ => {}
+                }
+            }
+            result
+        })
+        .collect()
+}
+
+/// Parses the `X-Forwarded-For` header values into a list of nodes, in order of appearance.
+///
+/// Nodes that are not valid IP addresses are returned as `None`.
+pub fn parse_x_forwarded_for<S: AsRef<str>>(values: &[S]) -> Vec<Option<IpAddr>> {
+    values
+        .iter()
+        .flat_map(|v| v.as_ref().split(','))
+        .map(str::trim)
+        .filter(|node| !node.is_empty())
+        .map(parse_node)
+        .collect()
+}
+
+/// Returns the protocol from the `X-Forwarded-Proto` header values (the first one, lowercased).
+pub fn parse_x_forwarded_proto<S: AsRef<str>>(values: &[S]) -> Option<String> {
+    values
+        .iter()
+        .flat_map(|v| v.as_ref().split(','))
+        .map(str::trim)
+        .find(|proto| !proto.is_empty())
+        .map(|proto| proto.to_ascii_lowercase())
+}
+
+/// A list of networks whose addresses are trusted to append forwarding headers.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<(IpAddr, u8)>);
+
+impl TrustedProxies {
+    /// Creates an empty list (no proxies are trusted).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a network in CIDR notation (eg. `10.0.0.0/8`) or a single IP address.
+    pub fn trust(mut self, network: &str) -> Result<Self> {
+        let invalid = || Error::runtime(format!("invalid network '{network}'"));
+        let (addr, prefix) = match network.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (network, None),
+        };
+        let addr = addr.trim().parse::<IpAddr>().map_err(|_| invalid())?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().map_err(|_| invalid())?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err(invalid());
+        }
+        self.0.push((addr, prefix));
+        Ok(self)
+    }
+
+    /// Returns true if the `ip` belongs to one of the trusted networks.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = to_canonical(ip);
+        self.0.iter().any(|&(net, prefix)| match (net, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        })
+    }
+
+    /// Walks the chain of nodes (from the closest hop to the client) starting at the
+    /// directly connected `peer`, skipping trusted proxies.
+    ///
+    /// Returns the first untrusted address, or the furthest known one if all nodes are trusted.
+    /// An invalid node stops the walk, as nothing before it can be trusted.
+    pub fn resolve(&self, peer: IpAddr, chain: &[Option<IpAddr>]) -> IpAddr {
+        let mut client = peer;
+        for node in chain.iter().rev() {
+            if !self.contains(client) {
+                break;
+            }
+            match node {
+                Some(ip) => client = *ip,
+                None => break,
+            }
+        }
+        client
+    }
+}
+
+/// Returns the effective client IP address of the request.
+///
+/// The forwarding chain is taken from the `Forwarded` header if present, otherwise from
+/// `X-Forwarded-For`. Headers are honored only when the connection source (the `src` fetch)
+/// is a trusted proxy. Returns `None` if the source address is not available.
+pub fn client_ip(txn: &Txn, trusted: &TrustedProxies) -> Result<Option<IpAddr>> {
+    let src: Option<String> = txn.f.get("src", ())?;
+    let Some(peer) = src.and_then(|src| src.parse::<IpAddr>().ok()) else {
+        return Ok(None);
+    };
+    if !trusted.contains(peer) {
+        return Ok(Some(peer));
+    }
+    let chain = forwarding_chain(&txn.http()?.req_get_headers()?)?;
+    Ok(Some(trusted.resolve(peer, &chain)))
+}
+
+/// Returns the forwarding chain from the `Forwarded` header, or `X-Forwarded-For` if absent.
+pub fn forwarding_chain(headers: &Headers) -> Result<Vec<Option<IpAddr>>> {
+    let forwarded = headers.get::<String>("forwarded")?;
+    if !forwarded.is_empty() {
+        let elements = parse_forwarded(&forwarded);
+        return Ok(elements.iter().map(|e| e.for_ip()).collect());
+    }
+    Ok(parse_x_forwarded_for(
+        &headers.get::<String>("x-forwarded-for")?,
+    ))
+}
+
+// Parses a node (`192.0.2.1`, `192.0.2.1:8080`, `[2001:db8::1]:8080`, `2001:db8::1`)
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok().map(to_canonical);
+    }
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(to_canonical(ip));
+    }
+    // IPv4 with port
+    let (addr, _) = node.rsplit_once(':')?;
+    addr.parse::<std::net::Ipv4Addr>().ok().map(IpAddr::V4)
+}
+
+// Converts IPv4-mapped IPv6 addresses to IPv4
+fn to_canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        ip => ip,
+    }
+}
+
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(value) => value.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => value.to_string(),
+    }
+}
+
+// Splits `s` by `sep` ignoring separators inside quoted strings
+fn split_quoted(s: &str, sep: char) -> impl Iterator<Item = &str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts.into_iter().filter(|p| !p.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_forwarded() {
+        let values = [
+            r#"for=192.0.2.60;proto=HTTPS;by=203.0.113.43, for="[2001:db8::1]:4711""#,
+            r#"for=unknown;host="example.com;a,b", for=_hidden"#,
+        ];
+        let elements = parse_forwarded(&values);
+        assert_eq!(elements.len(), 4);
+        assert_eq!(elements[0].for_ip(), Some(ip("192.0.2.60")));
+        assert_eq!(elements[0].proto.as_deref(), Some("https"));
+        assert_eq!(elements[0].by.as_deref(), Some("203.0.113.43"));
+        assert_eq!(elements[1].for_ip(), Some(ip("2001:db8::1")));
+        assert_eq!(elements[2].for_ip(), None);
+        assert_eq!(elements[2].host.as_deref(), Some("example.com;a,b"));
+        assert_eq!(elements[3].for_.as_deref(), Some("_hidden"));
+        assert_eq!(elements[3].for_ip(), None);
+    }
+
+    #[test]
+    fn test_parse_x_forwarded() {
+        let chain =
+            parse_x_forwarded_for(&["203.0.113.1, 10.0.0.1:8080", "garbage,::ffff:10.0.0.2"]);
+        assert_eq!(
+            chain,
+            vec![
+                Some(ip("203.0.113.1")),
+                Some(ip("10.0.0.1")),
+                None,
+                Some(ip("10.0.0.2"))
+            ]
+        );
+        assert_eq!(
+            parse_x_forwarded_proto(&[" HTTPS , http"]).as_deref(),
+            Some("https")
+        );
+        assert_eq!(parse_x_forwarded_proto::<&str>(&[]), None);
+    }
+
+    #[test]
+    fn test_trusted_proxies() {
+        let trusted = (TrustedProxies::new().trust("10.0.0.0/8"))
+            .and_then(|t| t.trust("2001:db8::/32"))
+            .and_then(|t| t.trust("192.0.2.1"))
+            .unwrap();
+        assert!(trusted.contains(ip("10.1.2.3")));
+        assert!(trusted.contains(ip("::ffff:10.1.2.3")));
+        assert!(trusted.contains(ip("2001:db8::5")));
+        assert!(trusted.contains(ip("192.0.2.1")));
+        assert!(!trusted.contains(ip("192.0.2.2")));
+        assert!(TrustedProxies::new().trust("10.0.0.0/33").is_err());
+        assert!(TrustedProxies::new().trust("nope").is_err());
+
+        let chain = [Some(ip("203.0.113.1")), Some(ip("10.0.0.2"))];
+        assert_eq!(trusted.resolve(ip("10.0.0.1"), &chain), ip("203.0.113.1"));
+        // Untrusted peer: headers are ignored
+        assert_eq!(
+            trusted.resolve(ip("198.51.100.1"), &chain),
+            ip("198.51.100.1")
+        );
+        // Invalid node stops the walk
+        let chain = [Some(ip("203.0.113.1")), None, Some(ip("10.0.0.2"))];
+        assert_eq!(trusted.resolve(ip("10.0.0.1"), &chain), ip("10.0.0.2"));
+        // All nodes trusted: the furthest one
+        let chain = [Some(ip("10.0.0.3"))];
+        assert_eq!(trusted.resolve(ip("10.0.0.1"), &chain), ip("10.0.0.3"));
+    }
+}
@@ -0,0 +1,366 @@
+//! Helpers to work with gRPC messages flowing through HAProxy.
+//!
+//! gRPC messages are transferred as a sequence of length-prefixed frames in the HTTP/2 body:
+//! a 1-byte compression flag, a 4-byte big-endian message length and the message itself.
+//! A single frame can be split across several `http_payload` calls, so [`MessageDecoder`]
+//! keeps the incomplete data between calls.
+
+use std::borrow::Cow;
+
+use mlua::{Error, Result};
+
+use crate::{percent, Headers, HttpMessage};
+
+/// Size of the gRPC message prefix (compression flag + length).
+pub const PREFIX_LEN: usize = 5;
+
+/// Default maximum size of a single gRPC message (4 MiB, the same as in gRPC implementations).
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// A single gRPC message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// True if the message is compressed using the `grpc-encoding` algorithm.
+    pub compressed: bool,
+    /// The message payload (without prefix).
+    pub data: Vec<u8>,
+}
+
+impl Message {
+    /// Creates a new uncompressed message.
+    pub fn new(data: impl Into<Vec<u8>>) -> Self {
+        Message {
+            compressed: false,
+            data: data.into(),
+        }
+    }
+
+    /// Encodes the message back to the length-prefixed wire format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(PREFIX_LEN + self.data.len());
+        buf.push(self.compressed as u8);
+        buf.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&self.data);
+        buf
+    }
+}
+
+/// Incremental decoder of the gRPC length-prefixed message framing.
+///
+/// When fed from an HTTP message, the decoder remembers how much data it has already copied,
+/// so the filter must report the amount of data it forwards with [`MessageDecoder::forward`]:
+///
+/// ```ignore
+/// fn http_payload(&mut self, _: &Lua, _: Txn, msg: HttpMessage) -> Result<Option<usize>> {
+///     self.decoder.feed_message(&msg)?;
+///     for message in self.decoder.decode_all()? {
+///         // ...
+///     }
+///     Ok(self.decoder.forward(None))
+/// }
+/// ```
+#[derive(Debug)]
+pub struct MessageDecoder {
+    buf: Vec<u8>,
+    pos: usize,
+    // Bytes copied from the HTTP message but still present in its buffer
+    unforwarded: usize,
+    max_message_size: usize,
+    messages: u64,
+}
+
+impl Default for MessageDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageDecoder {
+    /// Creates a new decoder with the default maximum message size.
+    pub fn new() -> Self {
+        Self::with_max_message_size(DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    /// Creates a new decoder that rejects messages larger than `max_message_size` bytes.
+    pub fn with_max_message_size(max_message_size: usize) -> Self {
+        MessageDecoder {
+            buf: Vec::new(),
+            pos: 0,
+            unforwarded: 0,
+            max_message_size,
+            messages: 0,
+        }
+    }
+
+    /// Appends a chunk of the body to the decoder.
+    pub fn feed(&mut self, data: impl AsRef<[u8]>) {
+        // Reclaim space occupied by already decoded messages
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(data.as_ref());
+    }
+
+    /// Copies the new incoming data of the HTTP message into the decoder.
+    ///
+    /// The data are not removed from the HTTP message. Data copied by the previous calls
+    /// and not forwarded yet are skipped.
+    ///
+    /// Returns the number of bytes added.
+    pub fn feed_message(&mut self, msg: &HttpMessage) -> Result<usize> {
+        let offset = self.unforwarded as isize;
+        let added = msg.body_with(Some(offset), Some(-1), |data| {
+            self.feed(data);
+            data.len()
+        })?;
+        self.unforwarded += added;
+        Ok(added)
+    }
+
+    /// Records that the filter forwards `len` bytes of the HTTP message (`None` means all input data)
+    /// and returns `len` back.
+    ///
+    /// The result is intended to be returned from [`UserFilter::http_payload`].
+    ///
+    /// [`UserFilter::http_payload`]: crate::UserFilter::http_payload
+    pub fn forward(&mut self, len: Option<usize>) -> Option<usize> {
+        match len {
+            Some(len) => self.unforwarded = self.unforwarded.saturating_sub(len),
+            None => self.unforwarded = 0,
+        }
+        len
+    }
+
+    /// Returns the next complete message, or `None` if more data is needed.
+    pub fn decode(&mut self) -> Result<Option<Message>> {
+        let data = &self.buf[self.pos..];
+        if data.len() < PREFIX_LEN {
+            return Ok(None);
+        }
+        let compressed = match data[0] {
+            0 => false,
+            1 => true,
+            flag => {
+                return Err(Error::runtime(format!(
+                    "invalid gRPC compression flag: {flag}"
+                )))
+            }
+        };
+        let len = u32::from_be_bytes([data[1], data[2], data[3], data[4]]) as usize;
+        if len > self.max_message_size {
+            return Err(Error::runtime(format!(
+                "gRPC message is too large: {len} > {}",
+                self.max_message_size
+            )));
+        }
+        if data.len() < PREFIX_LEN + len {
+            return Ok(None);
+        }
+        let message = Message {
+            compressed,
+            data: data[PREFIX_LEN..PREFIX_LEN + len].to_vec(),
+        };
+        self.pos += PREFIX_LEN + len;
+        self.messages += 1;
+        Ok(Some(message))
+    }
+
+    /// Decodes all complete messages available in the buffer.
+    pub fn decode_all(&mut self) -> Result<Vec<Message>> {
+        let mut messages = Vec::new();
+        while let Some(message) = self.decode()? {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    /// Returns the number of buffered bytes that do not form a complete message yet.
+    pub fn pending(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Returns the total number of decoded messages.
+    pub fn messages(&self) -> u64 {
+        self.messages
+    }
+
+    /// Returns an error if there is an incomplete message left in the buffer.
+    ///
+    /// Should be called at the end of the stream.
+    pub fn finish(&self) -> Result<()> {
+        match self.pending() {
+            0 => Ok(()),
+            n => Err(Error::runtime(format!(
+                "incomplete gRPC message: {n} bytes left"
+            ))),
+        }
+    }
+}
+
+/// gRPC status codes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Code {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+impl Code {
+    /// Converts a numeric status code to `Code`.
+    /// Unknown values are mapped to [`Code::Unknown`].
+    pub fn from_i32(code: i32) -> Self {
+        match code {
+            0 => Code::Ok,
+            1 => Code::Cancelled,
+            3 => Code::InvalidArgument,
+            4 => Code::DeadlineExceeded,
+            5 => Code::NotFound,
+            6 => Code::AlreadyExists,
+            7 => Code::PermissionDenied,
+            8 => Code::ResourceExhausted,
+            9 => Code::FailedPrecondition,
+            10 => Code::Aborted,
+            11 => Code::OutOfRange,
+            12 => Code::Unimplemented,
+            13 => Code::Internal,
+            14 => Code::Unavailable,
+            15 => Code::DataLoss,
+            16 => Code::Unauthenticated,
+            _ => Code::Unknown,
+        }
+    }
+}
+
+/// gRPC call status, as sent in the `grpc-status` and `grpc-message` trailers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub code: Code,
+    pub message: Option<String>,
+}
+
+impl Status {
+    /// Reads the status from the `grpc-status` and `grpc-message` fields.
+    ///
+    /// Returns `None` if `grpc-status` is not present.
+    pub fn from_headers(headers: &Headers) -> Result<Option<Self>> {
+        let code = match headers.get_first::<String>("grpc-status")? {
+            Some(code) => code
+                .trim()
+                .parse()
+                .map(Code::from_i32)
+                .unwrap_or(Code::Unknown),
+            None => return Ok(None),
+        };
+        let message = headers
+            .get_first::<String>("grpc-message")?
+            .map(|msg| percent_decode(&msg).into_owned());
+        Ok(Some(Status { code, message }))
+    }
+
+    /// Returns true if the call completed successfully.
+    pub fn is_ok(&self) -> bool {
+        self.code == Code::Ok
+    }
+}
+
+/// Returns true if the `content-type` header value denotes a gRPC request or response.
+pub fn is_grpc_content_type(content_type: &str) -> bool {
+    let content_type = content_type.trim_start().as_bytes();
+    (content_type.get(..16)).is_some_and(|prefix| prefix.eq_ignore_ascii_case(b"application/grpc"))
+        && matches!(content_type.get(16), None | Some(b'+' | b';'))
+}
+
+// `grpc-message` is percent-encoded
+fn percent_decode(s: &str) -> Cow<'_, str> {
+    match percent::decode(s.as_bytes()) {
+        Cow::Borrowed(_) => Cow::Borrowed(s),
+        Cow::Owned(out) => Cow::Owned(String::from_utf8_lossy(&out).into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_split_frames() {
+        let mut data = Message::new(b"hello".to_vec()).encode();
+        data.extend(Message::new(Vec::new()).encode());
+
+        let mut decoder = MessageDecoder::new();
+        decoder.feed(&data[..3]);
+        assert_eq!(decoder.decode().unwrap(), None);
+        decoder.feed(&data[3..8]);
+        assert_eq!(decoder.decode().unwrap(), None);
+        assert_eq!(decoder.pending(), 8);
+        decoder.feed(&data[8..]);
+        let messages = decoder.decode_all().unwrap();
+        assert_eq!(
+            messages,
+            [Message::new(b"hello".to_vec()), Message::new(Vec::new())]
+        );
+        assert_eq!(decoder.messages(), 2);
+        assert!(decoder.finish().is_ok());
+    }
+
+    #[test]
+    fn test_forward() {
+        let mut decoder = MessageDecoder::new();
+        decoder.unforwarded = 10;
+        assert_eq!(decoder.forward(Some(4)), Some(4));
+        assert_eq!(decoder.unforwarded, 6);
+        assert_eq!(decoder.forward(Some(0)), Some(0));
+        assert_eq!(decoder.unforwarded, 6);
+        assert_eq!(decoder.forward(None), None);
+        assert_eq!(decoder.unforwarded, 0);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut decoder = MessageDecoder::new();
+        decoder.feed([2, 0, 0, 0, 0]);
+        assert!(decoder.decode().is_err());
+
+        let mut decoder = MessageDecoder::with_max_message_size(4);
+        decoder.feed(Message::new(b"hello".to_vec()).encode());
+        assert!(decoder.decode().is_err());
+
+        let mut decoder = MessageDecoder::new();
+        decoder.feed([0, 0, 0]);
+        assert!(decoder.finish().is_err());
+    }
+
+    #[test]
+    fn test_grpc_content_type() {
+        assert!(is_grpc_content_type("application/grpc"));
+        assert!(is_grpc_content_type(" Application/GRPC+proto"));
+        assert!(is_grpc_content_type("application/grpc; charset=utf-8"));
+        assert!(!is_grpc_content_type("application/grpc-web"));
+        assert!(!is_grpc_content_type("application/json"));
+        assert!(!is_grpc_content_type("application/grp"));
+        // Multibyte character crossing the prefix boundary
+        assert!(!is_grpc_content_type("application/grp\u{e9}"));
+        assert!(!is_grpc_content_type("\u{e9}pplication/grpc"));
+    }
+
+    #[test]
+    fn test_percent_decode_message() {
+        assert_eq!(percent_decode("no escapes"), "no escapes");
+        assert_eq!(percent_decode("bad%20request%21"), "bad request!");
+    }
+}
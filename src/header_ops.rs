@@ -0,0 +1,168 @@
+use mlua::{Function, Lua, Result, Value};
+
+use crate::{Http, HttpMessage};
+
+#[derive(Debug, Clone)]
+enum Op {
+    Add(String, Vec<u8>),
+    Set(String, Vec<u8>),
+    Del(String),
+    Rename(String, String),
+}
+
+/// A batch of header modifications applied in a single Lua call.
+///
+/// Every `add_header`/`set_header`/`del_header` call crosses the Rust/Lua boundary,
+/// so filters that rewrite many headers can collect modifications first and apply them at once.
+/// Operations are applied in order.
+///
+/// ```ignore
+/// HeaderOps::new()
+///     .del("server")
+///     .set("cache-control", "no-store")
+///     .rename("x-real-ip", "x-client-ip")
+///     .apply(lua, &msg)?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HeaderOps {
+    ops: Vec<Op>,
+}
+
+impl HeaderOps {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a header field `name` with `value`.
+    pub fn add(mut self, name: &str, value: impl AsRef<[u8]>) -> Self {
+        self.ops
+            .push(Op::Add(name.to_string(), value.as_ref().to_vec()));
+        self
+    }
+
+    /// Replaces all occurrences of header `name` by only one containing `value`.
+    pub fn set(mut self, name: &str, value: impl AsRef<[u8]>) -> Self {
+        self.ops
+            .push(Op::Set(name.to_string(), value.as_ref().to_vec()));
+        self
+    }
+
+    /// Removes all occurrences of header `name`.
+    pub fn del(mut self, name: &str) -> Self {
+        self.ops.push(Op::Del(name.to_string()));
+        self
+    }
+
+    /// Renames header `from` to `to`, keeping all values.
+    pub fn rename(mut self, from: &str, to: &str) -> Self {
+        self.ops.push(Op::Rename(from.to_string(), to.to_string()));
+        self
+    }
+
+    /// Returns the number of operations in the batch.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns true if the batch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Applies the batch to the HTTP message.
+    pub fn apply<'lua>(&self, lua: &'lua Lua, msg: &HttpMessage<'lua>) -> Result<()> {
+        let names = ("add_header", "set_header", "del_header", "get_headers");
+        self.call(lua, Value::Table((**msg).clone()), names)
+    }
+
+    /// Applies the batch to the request headers.
+    pub fn apply_req<'lua>(&self, lua: &'lua Lua, http: &Http<'lua>) -> Result<()> {
+        let names = (
+            "req_add_header",
+            "req_set_header",
+            "req_del_header",
+            "req_get_headers",
+        );
+        self.call(lua, Value::Table((**http).clone()), names)
+    }
+
+    /// Applies the batch to the response headers.
+    pub fn apply_res<'lua>(&self, lua: &'lua Lua, http: &Http<'lua>) -> Result<()> {
+        let names = (
+            "res_add_header",
+            "res_set_header",
+            "res_del_header",
+            "res_get_headers",
+        );
+        self.call(lua, Value::Table((**http).clone()), names)
+    }
+
+    fn call<'lua>(
+        &self,
+        lua: &'lua Lua,
+        target: Value<'lua>,
+        names: (&str, &str, &str, &str),
+    ) -> Result<()> {
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+
+        // Operations are encoded as a flat list of (op, name, value) triples
+        let ops = lua.create_table_with_capacity(self.ops.len() * 3, 0)?;
+        for op in &self.ops {
+            let (code, name, value) = match op {
+                Op::Add(name, value) => (1, name, Value::String(lua.create_string(value)?)),
+                Op::Set(name, value) => (2, name, Value::String(lua.create_string(value)?)),
+                Op::Del(name) => (3, name, Value::Boolean(false)),
+                Op::Rename(from, to) => (4, from, Value::String(lua.create_string(to)?)),
+            };
+            ops.raw_push(code)?;
+            ops.raw_push(name.as_str())?;
+            ops.raw_push(value)?;
+        }
+
+        let helper = match lua.named_registry_value::<Option<Function>>("__HAPROXY_HEADER_OPS")? {
+            Some(helper) => helper,
+            None => {
+                let helper = lua.load(HELPER).into_function()?;
+                lua.set_named_registry_value("__HAPROXY_HEADER_OPS", &helper)?;
+                helper
+            }
+        };
+        helper.call((target, names.0, names.1, names.2, names.3, ops))
+    }
+}
+
+impl<'lua> HttpMessage<'lua> {
+    /// Applies the batch of header modifications `ops` to the HTTP message.
+    #[inline]
+    pub fn apply_header_ops(&self, ops: &HeaderOps) -> Result<()> {
+        ops.apply(self.lua, self)
+    }
+}
+
+const HELPER: &str = r#"
+    local obj, add, set, del, get, ops = ...
+    for i = 1, #ops, 3 do
+        local op, name, value = ops[i], ops[i + 1], ops[i + 2]
+        if op == 1 then
+            obj[add](obj, name, value)
+        elseif op == 2 then
+            obj[set](obj, name, value)
+        elseif op == 3 then
+            obj[del](obj, name)
+        else
+            -- Header values are indexed from 0
+            local values = obj[get](obj)[name:lower()]
+            if values ~= nil then
+                obj[del](obj, name)
+                local j = 0
+                while values[j] ~= nil do
+                    obj[add](obj, value, values[j])
+                    j = j + 1
+                end
+            end
+        end
+    end
+"#;
@@ -0,0 +1,365 @@
+//! Declarative request and response header rewriting.
+//!
+//! [`HeaderRewriteFilter`] applies a list of [`HeaderRule`]s in `http_headers`. Rules can be
+//! built in Rust and registered with [`HeaderRewriteRules::register`], or parsed from the
+//! filter arguments (see [`HeaderRewriteRules::from_args`]):
+//!
+//! ```text
+//! filter lua.header-rewrite req-set:x-forwarded-proto=https res-del:server res-set:cache-control=no-store@status=5xx
+//! ```
+
+use std::sync::Arc;
+
+use mlua::{AnyUserData, Error, Lua, Result, String as LuaString, Table, UserData, Value};
+
+use crate::{Core, FilterMethod, FilterResult, HttpMessage, Txn, UserFilter};
+
+/// A header operation applied by [`HeaderRewriteFilter`].
+#[derive(Debug, Clone)]
+enum Op {
+    Add(String, String),
+    Set(String, String),
+    Del(String),
+    Rename(String, String),
+    Replace(String, String, String),
+}
+
+/// A single header rewrite rule with optional conditions.
+#[derive(Debug, Clone)]
+pub struct HeaderRule {
+    op: Op,
+    methods: Vec<String>,
+    path_prefix: Option<String>,
+    statuses: Vec<(u16, u16)>,
+}
+
+impl HeaderRule {
+    fn new(op: Op) -> Self {
+        HeaderRule {
+            op,
+            methods: Vec::new(),
+            path_prefix: None,
+            statuses: Vec::new(),
+        }
+    }
+
+    /// Appends a header `name` with `value`.
+    pub fn add(name: &str, value: &str) -> Self {
+        Self::new(Op::Add(name.to_ascii_lowercase(), value.to_string()))
+    }
+
+    /// Replaces all occurrences of header `name` by only one containing `value`.
+    pub fn set(name: &str, value: &str) -> Self {
+        Self::new(Op::Set(name.to_ascii_lowercase(), value.to_string()))
+    }
+
+    /// Removes all occurrences of header `name`.
+    pub fn del(name: &str) -> Self {
+        Self::new(Op::Del(name.to_ascii_lowercase()))
+    }
+
+    /// Renames header `from` to `to`, keeping all values.
+    pub fn rename(from: &str, to: &str) -> Self {
+        Self::new(Op::Rename(
+            from.to_ascii_lowercase(),
+            to.to_ascii_lowercase(),
+        ))
+    }
+
+    /// Matches the regular expression `regex` in all occurrences of header `name`
+    /// and replaces them with `replace` (see [`HttpMessage::rep_header`]).
+    pub fn replace(name: &str, regex: &str, replace: &str) -> Self {
+        Self::new(Op::Replace(
+            name.to_ascii_lowercase(),
+            regex.to_string(),
+            replace.to_string(),
+        ))
+    }
+
+    /// Applies the rule only to requests with one of the given methods.
+    pub fn when_method(mut self, method: &str) -> Self {
+        self.methods.push(method.to_ascii_uppercase());
+        self
+    }
+
+    /// Applies the rule only to requests which path starts with `prefix`.
+    pub fn when_path(mut self, prefix: &str) -> Self {
+        self.path_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Applies the rule only to responses with status in the range `from..=to`.
+    pub fn when_status(mut self, from: u16, to: u16) -> Self {
+        self.statuses.push((from, to));
+        self
+    }
+
+    // Parses a rule argument in the format `<op>:<args>[@<cond>=<value>...]`
+    fn parse(op: &str, spec: &str) -> Result<Self> {
+        let mut parts = spec.split('@');
+        let args = parts.next().unwrap_or_default();
+        let mut kv = args.splitn(3, '=');
+        let name = kv.next().unwrap_or_default();
+        let (value, extra) = (kv.next(), kv.next());
+        if name.is_empty() {
+            return Err(Error::runtime(format!(
+                "header rewrite: empty header name in '{spec}'"
+            )));
+        }
+        let value = || {
+            value
+                .ok_or_else(|| Error::runtime(format!("header rewrite: missing value in '{spec}'")))
+        };
+        let mut rule = match op {
+            "add" => Self::add(name, args.split_once('=').map_or("", |x| x.1)),
+            "set" => Self::set(name, args.split_once('=').map_or("", |x| x.1)),
+            "del" => Self::del(name),
+            "rename" => Self::rename(name, value()?),
+            "replace" => Self::replace(name, value()?, extra.unwrap_or_default()),
+            _ => {
+                return Err(Error::runtime(format!(
+                    "header rewrite: unknown operation '{op}'"
+                )))
+            }
+        };
+        for cond in parts {
+            match cond.split_once('=') {
+                Some(("method", methods)) => {
+                    for method in methods.split(',') {
+                        rule = rule.when_method(method);
+                    }
+                }
+                Some(("path", prefix)) => rule = rule.when_path(prefix),
+                Some(("status", statuses)) => {
+                    for status in statuses.split(',') {
+                        let (from, to) = parse_status_range(status).ok_or_else(|| {
+                            Error::runtime(format!("header rewrite: invalid status '{status}'"))
+                        })?;
+                        rule = rule.when_status(from, to);
+                    }
+                }
+                _ => {
+                    return Err(Error::runtime(format!(
+                        "header rewrite: invalid condition '{cond}'"
+                    )))
+                }
+            }
+        }
+        Ok(rule)
+    }
+
+    fn matches(&self, method: &str, path: &str, status: Option<u16>) -> bool {
+        if !self.methods.is_empty() && !self.methods.iter().any(|m| m == method) {
+            return false;
+        }
+        if let Some(prefix) = &self.path_prefix {
+            if !path.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        // Status conditions never match requests
+        if !self.statuses.is_empty() {
+            let Some(status) = status else {
+                return false;
+            };
+            if !(self.statuses.iter()).any(|&(from, to)| (from..=to).contains(&status)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn apply(&self, msg: &HttpMessage) -> Result<()> {
+        match &self.op {
+            Op::Add(name, value) => msg.add_header(name, value),
+            Op::Set(name, value) => msg.set_header(name, value),
+            Op::Del(name) => msg.del_header(name),
+            Op::Rename(from, to) => {
+                let values = msg.get_headers()?.get::<LuaString>(from)?;
+                if values.is_empty() {
+                    return Ok(());
+                }
+                msg.del_header(from)?;
+                for value in values {
+                    msg.add_header(to, value.as_bytes())?;
+                }
+                Ok(())
+            }
+            Op::Replace(name, regex, replace) => msg.rep_header(name, regex, replace),
+        }
+    }
+}
+
+// Parses `200`, `2xx` or `200-299`
+fn parse_status_range(s: &str) -> Option<(u16, u16)> {
+    let s = s.trim();
+    if let Some((from, to)) = s.split_once('-') {
+        return Some((from.parse().ok()?, to.parse().ok()?));
+    }
+    if s.len() == 3 && s.is_ascii() && s[1..].eq_ignore_ascii_case("xx") {
+        let class = s[..1].parse::<u16>().ok()?;
+        return Some((class * 100, class * 100 + 99));
+    }
+    let status = s.parse().ok()?;
+    Some((status, status))
+}
+
+/// A set of header rewrite rules for requests and responses.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderRewriteRules {
+    request: Vec<HeaderRule>,
+    response: Vec<HeaderRule>,
+}
+
+impl HeaderRewriteRules {
+    /// Creates an empty set of rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule applied to request headers.
+    pub fn request(mut self, rule: HeaderRule) -> Self {
+        self.request.push(rule);
+        self
+    }
+
+    /// Adds a rule applied to response headers.
+    pub fn response(mut self, rule: HeaderRule) -> Self {
+        self.response.push(rule);
+        self
+    }
+
+    /// Parses rules from the filter arguments.
+    ///
+    /// Every argument has the format `<req|res>-<op>:<name>[=<value>][@<cond>=<value>...]` where
+    /// `op` is one of `add`, `set`, `del`, `rename` (`<from>=<to>`) or `replace` (`<name>=<regex>=<replace>`),
+    /// and conditions are `method=<M1>[,<M2>...]`, `path=<prefix>`, `status=<200|2xx|200-299>[,...]`.
+    ///
+    /// Example: `res-set:cache-control=no-store@status=5xx@path=/api`.
+    pub fn from_args(args: &Table) -> Result<Self> {
+        let args = args.clone().sequence_values::<String>();
+        Self::parse_args(&args.collect::<Result<Vec<_>>>()?)
+    }
+
+    pub(crate) fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<Self> {
+        let mut rules = Self::new();
+        for arg in args {
+            let arg = arg.as_ref();
+            let (kind, spec) = arg
+                .split_once(':')
+                .ok_or_else(|| Error::runtime(format!("header rewrite: invalid rule '{arg}'")))?;
+            match kind.split_once('-') {
+                Some(("req", op)) => rules.request.push(HeaderRule::parse(op, spec)?),
+                Some(("res", op)) => rules.response.push(HeaderRule::parse(op, spec)?),
+                _ => {
+                    return Err(Error::runtime(format!(
+                        "header rewrite: invalid rule '{arg}'"
+                    )))
+                }
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Registers [`HeaderRewriteFilter`] with these rules under the `name`.
+    ///
+    /// Rules from the filter arguments (if any) are ignored.
+    pub fn register(self, core: &Core, name: &str) -> Result<()> {
+        let rules = core.lua.create_userdata(CompiledRules(Arc::new(self)))?;
+        core.register_filter_with::<HeaderRewriteFilter>(name, 1.0, Some(Value::UserData(rules)))
+    }
+}
+
+// Rules shared between filter instances
+struct CompiledRules(Arc<HeaderRewriteRules>);
+
+impl UserData for CompiledRules {}
+
+/// A filter that rewrites request and response headers according to [`HeaderRewriteRules`].
+///
+/// Rules are compiled once (per filter declaration) and applied in `http_headers`:
+///
+/// ```text
+/// filter lua.header-rewrite req-set:x-forwarded-proto=https res-del:server res-set:cache-control=no-store@status=5xx
+/// ```
+pub struct HeaderRewriteFilter {
+    rules: Arc<HeaderRewriteRules>,
+    method: String,
+    path: String,
+}
+
+impl UserFilter for HeaderRewriteFilter {
+    const METHODS: u8 = FilterMethod::HTTP_HEADERS;
+
+    fn new(_: &Lua, args: Table) -> Result<Self> {
+        // Fetch ready parsed rules
+        let rules = match args.raw_get::<_, Option<AnyUserData>>(0)? {
+            Some(ud) => ud.borrow::<CompiledRules>()?.0.clone(),
+            None => {
+                let rules = Arc::new(HeaderRewriteRules::from_args(&args)?);
+                args.raw_set(0, CompiledRules(rules.clone()))?;
+                rules
+            }
+        };
+        Ok(HeaderRewriteFilter {
+            rules,
+            method: String::new(),
+            path: String::new(),
+        })
+    }
+
+    fn http_headers(&mut self, _: &Lua, txn: Txn, msg: HttpMessage) -> Result<FilterResult> {
+        if !msg.is_resp()? {
+            self.method = txn.f.get_str("method", ())?;
+            self.path = txn.f.get_str("path", ())?;
+            for rule in &self.rules.request {
+                if rule.matches(&self.method, &self.path, None) {
+                    rule.apply(&msg)?;
+                }
+            }
+        } else if !self.rules.response.is_empty() {
+            let status = txn.f.get::<_, Option<u16>>("status", ())?;
+            for rule in &self.rules.response {
+                if rule.matches(&self.method, &self.path, status) {
+                    rule.apply(&msg)?;
+                }
+            }
+        }
+        Ok(FilterResult::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status_range() {
+        assert_eq!(parse_status_range("204"), Some((204, 204)));
+        assert_eq!(parse_status_range("5xx"), Some((500, 599)));
+        assert_eq!(parse_status_range(" 200-299 "), Some((200, 299)));
+        assert_eq!(parse_status_range("x"), None);
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let rule = HeaderRule::parse("set", "x-api=1@method=GET,post@path=/api").unwrap();
+        assert!(rule.matches("GET", "/api/items", None));
+        assert!(rule.matches("POST", "/api", None));
+        assert!(!rule.matches("PUT", "/api", None));
+        assert!(!rule.matches("GET", "/", None));
+
+        let rule = HeaderRule::parse("del", "server@status=5xx").unwrap();
+        assert!(rule.matches("GET", "/", Some(503)));
+        assert!(!rule.matches("GET", "/", Some(200)));
+        assert!(!rule.matches("GET", "/", None));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(HeaderRule::parse("set", "=value").is_err());
+        assert!(HeaderRule::parse("rename", "x-a").is_err());
+        assert!(HeaderRule::parse("move", "x-a=x-b").is_err());
+        assert!(HeaderRule::parse("del", "x-a@status=abc").is_err());
+    }
+}
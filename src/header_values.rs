@@ -0,0 +1,80 @@
+use mlua::Result;
+
+use crate::{Headers, HttpMessage, HttpRequest, HttpResponse};
+
+// Splits comma-separated header values into trimmed non-empty tokens
+fn tokens(values: &[String]) -> Vec<&str> {
+    values
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+// Returns the new list of tokens if `value` must be appended
+fn appended(headers: &Headers, name: &str, value: &str) -> Result<Option<String>> {
+    let values = headers.get::<String>(name)?;
+    let mut tokens = tokens(&values);
+    if tokens.iter().any(|t| t.eq_ignore_ascii_case(value)) {
+        return Ok(None);
+    }
+    tokens.push(value);
+    Ok(Some(tokens.join(", ")))
+}
+
+// Returns the new list of tokens (possibly empty) if `value` must be removed
+fn removed(headers: &Headers, name: &str, value: &str) -> Result<Option<String>> {
+    let values = headers.get::<String>(name)?;
+    let tokens = tokens(&values);
+    let len = tokens.len();
+    let tokens = tokens
+        .into_iter()
+        .filter(|t| !t.eq_ignore_ascii_case(value))
+        .collect::<Vec<_>>();
+    Ok((tokens.len() != len).then(|| tokens.join(", ")))
+}
+
+macro_rules! impl_header_values {
+    ($ty:ident) => {
+        impl<'lua> $ty<'lua> {
+            /// Adds the header `name` with `value` only if the header is not present.
+            ///
+            /// Returns true if the header was added.
+            pub fn set_header_if_absent(&self, name: &str, value: &str) -> Result<bool> {
+                if self.get_headers()?.get_first::<String>(name)?.is_some() {
+                    return Ok(false);
+                }
+                self.add_header(name, value)?;
+                Ok(true)
+            }
+
+            /// Appends `value` to the comma-separated list in the header `name` (eg. `Vary`),
+            /// unless the list already contains it (compared case-insensitively).
+            ///
+            /// Multiple header fields are merged into one. Returns true if the header was modified.
+            pub fn append_header_value(&self, name: &str, value: &str) -> Result<bool> {
+                match appended(&self.get_headers()?, name, value)? {
+                    Some(list) => self.set_header(name, list).map(|_| true),
+                    None => Ok(false),
+                }
+            }
+
+            /// Removes `value` from the comma-separated list in the header `name`
+            /// (compared case-insensitively). The header is deleted if the list becomes empty.
+            ///
+            /// Returns true if the header was modified.
+            pub fn remove_header_value(&self, name: &str, value: &str) -> Result<bool> {
+                match removed(&self.get_headers()?, name, value)? {
+                    Some(list) if list.is_empty() => self.del_header(name).map(|_| true),
+                    Some(list) => self.set_header(name, list).map(|_| true),
+                    None => Ok(false),
+                }
+            }
+        }
+    };
+}
+
+impl_header_values!(HttpMessage);
+impl_header_values!(HttpRequest);
+impl_header_values!(HttpResponse);
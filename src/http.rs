@@ -1,5 +1,8 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::ops::Deref;
+use std::rc::Rc;
 
 use mlua::{
     FromLua, IntoLua, Lua, Result, String as LuaString, Table, TableExt, TablePairs, Value,
@@ -110,6 +113,125 @@ impl<'lua> Http<'lua> {
     pub fn res_set_status(&self, status: u16, reason: Option<&str>) -> Result<()> {
         self.0.call_method("res_set_status", (status, reason))
     }
+
+    /// Returns a view of the HTTP request.
+    #[inline]
+    pub fn request(&self) -> HttpRequest<'lua> {
+        HttpRequest(self.clone())
+    }
+
+    /// Returns a view of the HTTP response.
+    #[inline]
+    pub fn response(&self) -> HttpResponse<'lua> {
+        HttpResponse(self.clone())
+    }
+}
+
+/// A view of the HTTP request part of [`Http`] with direction-free method names.
+#[derive(Clone)]
+pub struct HttpRequest<'lua>(Http<'lua>);
+
+/// A view of the HTTP response part of [`Http`] with direction-free method names.
+#[derive(Clone)]
+pub struct HttpResponse<'lua>(Http<'lua>);
+
+macro_rules! impl_http_view {
+    ($view:ident, $get_headers:literal, $add_header:literal, $del_header:literal, $set_header:literal, $rep_header:literal) => {
+        impl<'lua> $view<'lua> {
+            /// Returns a `Headers` table containing all the headers.
+            #[inline]
+            pub fn get_headers(&self) -> Result<Headers<'lua>> {
+                self.0.call_method($get_headers, ())
+            }
+
+            /// Appends an HTTP header field `name` with `value`.
+            #[inline]
+            pub fn add_header<V: IntoLua<'lua>>(&self, name: &str, value: V) -> Result<()> {
+                self.0.call_method($add_header, (name, value))
+            }
+
+            /// Removes all HTTP header fields by `name`.
+            #[inline]
+            pub fn del_header(&self, name: &str) -> Result<()> {
+                self.0.call_method($del_header, name)
+            }
+
+            /// Replaces all occurrence of HTTP header `name`, by only one containing the `value`.
+            #[inline]
+            pub fn set_header<V: IntoLua<'lua>>(&self, name: &str, value: V) -> Result<()> {
+                self.0.call_method($set_header, (name, value))
+            }
+
+            /// Matches the regular expression in all occurrences of HTTP header `name` according to `regex`,
+            /// and replaces them with the `replace` argument.
+            ///
+            /// The replacement value can contain back references like 1, 2, ...
+            #[inline]
+            pub fn rep_header(&self, name: &str, regex: &str, replace: &str) -> Result<()> {
+                self.0.call_method($rep_header, (name, regex, replace))
+            }
+
+            /// Returns the underlying `Http` object.
+            #[inline]
+            pub fn http(&self) -> &Http<'lua> {
+                &self.0
+            }
+        }
+    };
+}
+
+impl_http_view!(
+    HttpRequest,
+    "req_get_headers",
+    "req_add_header",
+    "req_del_header",
+    "req_set_header",
+    "req_rep_header"
+);
+
+impl_http_view!(
+    HttpResponse,
+    "res_get_headers",
+    "res_add_header",
+    "res_del_header",
+    "res_set_header",
+    "res_rep_header"
+);
+
+impl<'lua> HttpRequest<'lua> {
+    /// Rewrites the request method with the `method`.
+    #[inline]
+    pub fn set_method(&self, method: &str) -> Result<()> {
+        self.0.req_set_method(method)
+    }
+
+    /// Rewrites the request path with the `path`.
+    #[inline]
+    pub fn set_path(&self, path: &str) -> Result<()> {
+        self.0.req_set_path(path)
+    }
+
+    /// Rewrites the request’s query string which appears after the first question mark (`?`)
+    /// with the `query`.
+    #[inline]
+    pub fn set_query(&self, query: &str) -> Result<()> {
+        self.0.req_set_query(query)
+    }
+
+    /// Rewrites the request URI with the `uri`.
+    #[inline]
+    pub fn set_uri(&self, uri: &str) -> Result<()> {
+        self.0.req_set_uri(uri)
+    }
+}
+
+impl<'lua> HttpResponse<'lua> {
+    /// Rewrites the response status code.
+    /// If no custom reason is provided, it will be generated from the status.
+    #[inline]
+    pub fn set_status(&self, status: u16, reason: Option<&str>) -> Result<()> {
+        self.0.res_set_status(status, reason)
+    }
 }
 
 impl<'lua> Deref for Http<'lua> {
@@ -128,6 +250,13 @@ impl<'lua> FromLua<'lua> for Http<'lua> {
     }
 }
 
+impl<'lua> IntoLua<'lua> for Http<'lua> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(self.0))
+    }
+}
+
 impl<'lua> Headers<'lua> {
     #[inline]
     pub fn pairs<V: FromLua<'lua>>(self) -> HeaderPairs<'lua, V> {
@@ -140,9 +269,8 @@ impl<'lua> Headers<'lua> {
     /// Returns all header fields by `name`.
     #[inline]
     pub fn get<V: FromLua<'lua>>(&self, name: &str) -> Result<Vec<V>> {
-        let name = name.to_ascii_lowercase();
         let mut result = Vec::new();
-        if let Some(values) = self.0.get::<_, Option<Table>>(name)? {
+        if let Some(values) = with_lowercase(name, |name| self.0.get::<_, Option<Table>>(name))? {
             let mut pairs = values.pairs::<i32, V>().collect::<Result<Vec<_>>>()?;
             pairs.sort_by_key(|x| x.0);
             result = pairs.into_iter().map(|(_, v)| v).collect();
@@ -153,12 +281,50 @@ impl<'lua> Headers<'lua> {
     /// Returns first header field by `name`.
     #[inline]
     pub fn get_first<V: FromLua<'lua>>(&self, name: &str) -> Result<Option<V>> {
-        let name = name.to_ascii_lowercase();
-        if let Some(values) = self.0.get::<_, Option<Table>>(name)? {
+        if let Some(values) = with_lowercase(name, |name| self.0.get::<_, Option<Table>>(name))? {
             return values.get(0); // Indexes starts from "0"
         }
         Ok(None)
     }
+
+    /// Returns an iterator over all (name, value) header fields.
+    ///
+    /// HAProxy does not expose the relative order of different header names,
+    /// so fields are ordered by name and then by their position within the same name.
+    /// The order is stable across calls for the same set of headers.
+    pub fn iter_ordered<V: FromLua<'lua>>(&self) -> Result<std::vec::IntoIter<(String, V)>> {
+        let mut headers = self.clone().pairs::<V>().collect::<Result<Vec<_>>>()?;
+        headers.sort_by(|a, b| a.0.cmp(&b.0));
+        let fields = headers
+            .into_iter()
+            .flat_map(|(name, values)| values.into_iter().map(move |v| (name.clone(), v)))
+            .collect::<Vec<_>>();
+        Ok(fields.into_iter())
+    }
+
+    /// Returns the total number of header fields (counting each value of the same name).
+    pub fn len(&self) -> Result<usize> {
+        let mut len = 0;
+        for item in self.0.clone().pairs::<Value, Table>() {
+            len += item?.1.pairs::<Value, Value>().count();
+        }
+        Ok(len)
+    }
+
+    /// Returns true if there are no header fields.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Returns (lowercased) header names in sorted order.
+    pub fn names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for item in self.0.clone().pairs::<LuaString, Value>() {
+            names.push(item?.0.to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
 }
 
 impl<'lua> Deref for Headers<'lua> {
@@ -177,6 +343,36 @@ impl<'lua> FromLua<'lua> for Headers<'lua> {
     }
 }
 
+// Calls `f` with the lowercased header `name`.
+//
+// Already lowercased names (the common case) are passed as is, otherwise the lowercased
+// version is cached per thread to avoid allocations on repeated lookups.
+fn with_lowercase<R>(name: &str, f: impl FnOnce(&str) -> R) -> R {
+    const MAX_CACHED_NAMES: usize = 128;
+
+    thread_local! {
+        static CACHE: RefCell<HashMap<Box<str>, Rc<str>>> = RefCell::new(HashMap::new());
+    }
+
+    if !name.bytes().any(|b| b.is_ascii_uppercase()) {
+        return f(name);
+    }
+    let lowercased = CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some(lowercased) = cache.get(name) {
+            return lowercased.clone();
+        }
+        let lowercased: Rc<str> = Rc::from(name.to_ascii_lowercase());
+        if cache.len() >= MAX_CACHED_NAMES {
+            cache.clear();
+        }
+        cache.insert(Box::from(name), lowercased.clone());
+        lowercased
+    });
+    // The cache must not be borrowed while calling `f` as it can reenter
+    f(&lowercased)
+}
+
 pub struct HeaderPairs<'lua, V: FromLua<'lua>> {
     pairs: TablePairs<'lua, LuaString<'lua>, Table<'lua>>,
     phantom: PhantomData<V>,
@@ -0,0 +1,170 @@
+use std::error::Error as StdError;
+use std::pin::pin;
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes};
+use futures_util::future::poll_fn;
+use http::{Request, Response};
+use http_body::Body;
+use mlua::{AnyUserData, Error, FromLua, Function, IntoLua, Lua, Result, TableExt, Value};
+use tokio::sync::{mpsc, Mutex};
+use tower_service::Service;
+
+use crate::build_info::ComponentKind;
+use crate::tower_adapter::{call_service, read_request, response_head, RawRequest};
+use crate::Core;
+
+// Number of body chunks buffered ahead of the applet
+const CHUNKS_AHEAD: usize = 4;
+
+type ChunkReceiver = mpsc::Receiver<std::result::Result<Bytes, String>>;
+
+// Response head with the receiver of the body chunks
+struct StreamingResponse {
+    parts: http::response::Parts,
+    length: Option<u64>,
+    body: BodyHandle,
+}
+
+// Receiver of the body chunks, stored in the Lua userdata
+#[derive(Clone)]
+struct BodyHandle(Arc<Mutex<ChunkReceiver>>);
+
+struct Chunk(Bytes);
+
+impl<'lua> IntoLua<'lua> for StreamingResponse {
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        let head = response_head(lua, &self.parts)?;
+        head.raw_set("length", self.length)?;
+        head.raw_set("body", lua.create_any_userdata(self.body)?)?;
+        Ok(Value::Table(head))
+    }
+}
+
+impl<'lua> FromLua<'lua> for BodyHandle {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let ud = AnyUserData::from_lua(value, lua)?;
+        let handle = ud.borrow::<BodyHandle>()?;
+        Ok(handle.clone())
+    }
+}
+
+impl<'lua> IntoLua<'lua> for Chunk {
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        lua.create_string(&self.0).map(Value::String)
+    }
+}
+
+// Reads the body frames on the async runtime, sending the data to the applet
+async fn pump_body<B>(body: B, tx: mpsc::Sender<std::result::Result<Bytes, String>>)
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let mut body = pin!(body);
+    while let Some(frame) = poll_fn(|cx| body.as_mut().poll_frame(cx)).await {
+        let chunk = match frame {
+            // Trailers are not supported by the applets
+            Ok(frame) => match frame.into_data() {
+                Ok(mut data) if data.has_remaining() => Ok(data.copy_to_bytes(data.remaining())),
+                _ => continue,
+            },
+            Err(err) => Err(err.into().to_string()),
+        };
+        let is_err = chunk.is_err();
+        // The applet is gone (eg. the client disconnected)
+        if tx.send(chunk).await.is_err() || is_err {
+            return;
+        }
+    }
+}
+
+impl<'lua> Core<'lua> {
+    /// Registers a [`tower_service::Service`] with a streaming [`http_body::Body`] response
+    /// executed as an HTTP service.
+    ///
+    /// Unlike [`Core::register_tower_service`], the response body is not buffered: it's polled
+    /// on the async runtime and sent to the client chunk by chunk, yielding between the chunks.
+    /// The `content-length` header is set if the body size is known in advance.
+    ///
+    /// Service errors are logged and reported as `500 Internal Server Error`. Body errors
+    /// are logged and abort the response.
+    ///
+    /// All the registered service can be used in HAProxy with the prefix `lua.`.
+    pub fn register_streaming_service<S, B>(&self, name: &str, service: S) -> Result<()>
+    where
+        S: Service<Request<Bytes>, Response = Response<B>> + Clone + Send + Sync + 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        S::Future: Send + 'static,
+        B: Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn StdError + Send + Sync>> + Send,
+    {
+        let call = crate::r#async::create_async_function(self.lua, move |req: RawRequest| {
+            let service = service.clone();
+            async move {
+                let (parts, body) = call_service(service, req).await?.into_parts();
+                let length = body.size_hint().exact();
+                let (tx, rx) = mpsc::channel(CHUNKS_AHEAD);
+                tokio::spawn(pump_body(body, tx));
+                Ok(StreamingResponse {
+                    parts,
+                    length,
+                    body: BodyHandle(Arc::new(Mutex::new(rx))),
+                })
+            }
+        })?;
+        let next_chunk =
+            crate::r#async::create_async_function(self.lua, |body: BodyHandle| async move {
+                match body.0.lock().await.recv().await {
+                    Some(Ok(data)) => Ok(Some(Chunk(data))),
+                    Some(Err(err)) => Err(Error::runtime(err)),
+                    None => Ok(None),
+                }
+            })?;
+        let func: Function = self
+            .lua
+            .load(
+                r#"
+                local call, next_chunk, read_request, name = ...
+                return function(applet)
+                    local ok, res = pcall(call, read_request(applet))
+                    if not ok then
+                        core.log(core.err, "service '" .. name .. "': " .. tostring(res))
+                        local body = "Internal Server Error\n"
+                        applet:set_status(500)
+                        applet:add_header("content-length", #body)
+                        applet:start_response()
+                        applet:send(body)
+                        return
+                    end
+                    applet:set_status(res.status)
+                    local has_length = false
+                    for _, header in ipairs(res.headers) do
+                        has_length = has_length or header[1] == "content-length"
+                        applet:add_header(header[1], header[2])
+                    end
+                    if not has_length and res.length ~= nil then
+                        applet:add_header("content-length", res.length)
+                    end
+                    applet:start_response()
+                    while true do
+                        local ok, chunk = pcall(next_chunk, res.body)
+                        if not ok then
+                            core.log(core.err, "service '" .. name .. "': " .. tostring(chunk))
+                            return
+                        end
+                        if chunk == nil then
+                            break
+                        end
+                        applet:send(chunk)
+                    end
+                end
+                "#,
+            )
+            .set_name("=streaming_service")
+            .call((call, next_chunk, read_request(self.lua)?, name))?;
+        self.track_registration(ComponentKind::Service, name)?;
+        self.call_function("register_service", (name, "http", func))
+    }
+}
@@ -0,0 +1,101 @@
+use std::io::{self, Error, ErrorKind};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+// Timeout of a single request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Maximum size of the response
+const MAX_RESPONSE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// A parsed `http://` URL.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpUrl {
+    // Address to connect to (with the port)
+    authority: String,
+    host: String,
+    path: String,
+}
+
+impl HttpUrl {
+    pub(crate) fn parse(url: &str) -> io::Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            let msg = format!("unsupported URL '{url}', only http:// is supported");
+            Error::new(ErrorKind::InvalidInput, msg)
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "URL without host"));
+        }
+        let has_port = (authority.rsplit_once(':')).is_some_and(|(_, port)| !port.contains(']'));
+        Ok(HttpUrl {
+            authority: match has_port {
+                true => authority.to_string(),
+                false => format!("{authority}:80"),
+            },
+            host: authority.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+/// A minimal HTTP response.
+pub(crate) struct HttpResponse {
+    pub(crate) status: u16,
+    headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        (self.headers.iter())
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Sends a `GET` request (HTTP/1.0, so the body is not chunked) with the extra `headers`.
+pub(crate) async fn get(url: &HttpUrl, headers: &[(&str, &str)]) -> io::Result<HttpResponse> {
+    timeout(REQUEST_TIMEOUT, get_inner(url, headers))
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "request timed out"))?
+}
+
+async fn get_inner(url: &HttpUrl, headers: &[(&str, &str)]) -> io::Result<HttpResponse> {
+    let mut stream = TcpStream::connect(&url.authority).await?;
+    let mut request = format!("GET {} HTTP/1.0\r\nHost: {}\r\n", url.path, url.host);
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str("Connection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    (stream.take(MAX_RESPONSE_SIZE))
+        .read_to_end(&mut response)
+        .await?;
+
+    let invalid = || Error::new(ErrorKind::InvalidData, "invalid HTTP response");
+    let split = (response.windows(4))
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(invalid)?;
+    let head = String::from_utf8_lossy(&response[..split]).into_owned();
+    let mut lines = head.split("\r\n");
+    let status = (lines.next().and_then(|line| line.split_whitespace().nth(1)))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(invalid)?;
+    let headers = (lines.filter_map(|line| line.split_once(':')))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    response.drain(..split + 4);
+    Ok(HttpResponse {
+        status,
+        headers,
+        body: response,
+    })
+}
@@ -0,0 +1,146 @@
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::{request, response, Method, StatusCode, Uri, Version};
+use mlua::{Error, Result, String as LuaString};
+
+use crate::{Headers, Http, HttpMessage, StartLine};
+
+impl<'lua> Headers<'lua> {
+    /// Converts the headers into [`http::HeaderMap`].
+    ///
+    /// Values of the same header keep their order, but the order of different headers is not preserved.
+    pub fn to_header_map(&self) -> Result<HeaderMap> {
+        let mut map = HeaderMap::new();
+        for item in self.clone().pairs::<LuaString>() {
+            let (name, values) = item?;
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(Error::external)?;
+            for value in values {
+                let value = HeaderValue::from_bytes(value.as_bytes()).map_err(Error::external)?;
+                map.append(name.clone(), value);
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl<'lua> HttpMessage<'lua> {
+    /// Updates headers of the HTTP message to match the `map`.
+    ///
+    /// Only the differences are applied: headers missing in the `map` are removed,
+    /// and headers with different values are replaced.
+    pub fn apply_header_map(&self, map: &HeaderMap) -> Result<()> {
+        let current = self.get_headers()?.to_header_map()?;
+        apply_diff(
+            &current,
+            map,
+            |name| self.del_header(name),
+            |name, value| self.add_header(name, value.as_bytes()),
+        )
+    }
+
+    /// Builds [`http::request::Parts`] from the start-line and headers of the HTTP request.
+    pub fn request_parts(&self) -> Result<request::Parts> {
+        let (method, uri, version) = match self.get_stline()? {
+            StartLine::Request {
+                method,
+                uri,
+                version,
+            } => (method, uri, version),
+            StartLine::Response { .. } => return Err(Error::runtime("not an HTTP request")),
+        };
+        let (mut parts, _) = http::Request::builder()
+            .method(Method::from_bytes(method.as_bytes()).map_err(Error::external)?)
+            .uri(Uri::try_from(uri).map_err(Error::external)?)
+            .version(parse_version(&version)?)
+            .body(())
+            .map_err(Error::external)?
+            .into_parts();
+        parts.headers = self.get_headers()?.to_header_map()?;
+        Ok(parts)
+    }
+
+    /// Builds [`http::response::Parts`] from the start-line and headers of the HTTP response.
+    pub fn response_parts(&self) -> Result<response::Parts> {
+        let (code, version) = match self.get_stline()? {
+            StartLine::Response { code, version, .. } => (code, version),
+            StartLine::Request { .. } => return Err(Error::runtime("not an HTTP response")),
+        };
+        let (mut parts, _) = http::Response::builder()
+            .status(StatusCode::from_u16(code).map_err(Error::external)?)
+            .version(parse_version(&version)?)
+            .body(())
+            .map_err(Error::external)?
+            .into_parts();
+        parts.headers = self.get_headers()?.to_header_map()?;
+        Ok(parts)
+    }
+}
+
+impl<'lua> Http<'lua> {
+    /// Updates the request headers to match the `map`.
+    ///
+    /// See [`HttpMessage::apply_header_map`] for details.
+    pub fn req_apply_header_map(&self, map: &HeaderMap) -> Result<()> {
+        let current = self.req_get_headers()?.to_header_map()?;
+        apply_diff(
+            &current,
+            map,
+            |name| self.req_del_header(name),
+            |name, value| self.req_add_header(name, header_value_str(value)?),
+        )
+    }
+
+    /// Updates the response headers to match the `map`.
+    ///
+    /// See [`HttpMessage::apply_header_map`] for details.
+    pub fn res_apply_header_map(&self, map: &HeaderMap) -> Result<()> {
+        let current = self.res_get_headers()?.to_header_map()?;
+        apply_diff(
+            &current,
+            map,
+            |name| self.res_del_header(name),
+            |name, value| self.res_add_header(name, header_value_str(value)?),
+        )
+    }
+}
+
+fn apply_diff(
+    current: &HeaderMap,
+    new: &HeaderMap,
+    mut del: impl FnMut(&str) -> Result<()>,
+    mut add: impl FnMut(&str, &HeaderValue) -> Result<()>,
+) -> Result<()> {
+    for name in current.keys() {
+        if !new.contains_key(name) {
+            del(name.as_str())?;
+        }
+    }
+    for name in new.keys() {
+        if current.get_all(name).iter().ne(new.get_all(name).iter()) {
+            if current.contains_key(name) {
+                del(name.as_str())?;
+            }
+            for value in new.get_all(name) {
+                add(name.as_str(), value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn header_value_str(value: &HeaderValue) -> Result<&str> {
+    std::str::from_utf8(value.as_bytes()).map_err(Error::external)
+}
+
+// HAProxy reports version as `HTTP/1.1`, `HTTP/2.0`, ...
+pub(crate) fn parse_version(version: &str) -> Result<Version> {
+    match version.trim_start_matches("HTTP/") {
+        "0.9" => Ok(Version::HTTP_09),
+        "1.0" => Ok(Version::HTTP_10),
+        "1.1" => Ok(Version::HTTP_11),
+        "2" | "2.0" => Ok(Version::HTTP_2),
+        "3" | "3.0" => Ok(Version::HTTP_3),
+        _ => Err(Error::runtime(format!(
+            "unsupported HTTP version '{version}'"
+        ))),
+    }
+}
@@ -0,0 +1,38 @@
+use mlua::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::HttpMessage;
+
+impl<'lua> HttpMessage<'lua> {
+    /// Deserializes the buffered HTTP message body as JSON.
+    ///
+    /// Returns `None` until the end of message is reached, so the filter must keep the data
+    /// (eg. return `Some(0)` from `http_payload`) until the whole body is received.
+    /// Fails if the body is larger than `max_size` bytes.
+    pub fn body_json<T: DeserializeOwned>(&self, max_size: usize) -> Result<Option<T>> {
+        let input = self.input()?;
+        if input > max_size {
+            return Err(Error::runtime(format!(
+                "body size exceeds the limit of {max_size} bytes"
+            )));
+        }
+        if !self.eom()? {
+            return Ok(None);
+        }
+        let body = self.body(None, Some(input as isize))?;
+        let data = body.as_ref().map(|b| b.as_bytes()).unwrap_or_default();
+        serde_json::from_slice(data)
+            .map(Some)
+            .map_err(Error::external)
+    }
+
+    /// Replaces the incoming data of the HTTP message with the `value` serialized as JSON.
+    ///
+    /// Only the current input is replaced and framing headers are not updated,
+    /// see [`HttpMessage::replace_body`].
+    pub fn set_body_json<T: Serialize + ?Sized>(&self, value: &T) -> Result<()> {
+        let data = serde_json::to_vec(value).map_err(Error::external)?;
+        self.replace_body(data)
+    }
+}
@@ -1,14 +1,15 @@
 use std::ops::Deref;
 
-use mlua::{FromLua, Lua, Result, String as LuaString, Table, TableExt, Value};
+use mlua::{Error, FromLua, Lua, Result, String as LuaString, Table, TableExt, Value};
 
-use crate::{Channel, Headers};
+use crate::intern::header_name;
+use crate::{Channel, Headers, LuaBytes};
 
 /// This class contains all functions to manipulate an HTTP message.
 /// For now, this class is only available from a filter context.
 #[derive(Clone)]
 pub struct HttpMessage<'lua> {
-    lua: &'lua Lua,
+    pub(crate) lua: &'lua Lua,
     class: Table<'lua>,
 }
 
@@ -16,6 +17,7 @@ impl<'lua> HttpMessage<'lua> {
     /// Appends an HTTP header field in the HTTP message whose name is specified in `name` and value is defined in `value`.
     #[inline]
     pub fn add_header(&self, name: &str, value: impl AsRef<[u8]>) -> Result<()> {
+        let name = header_name(self.lua, name)?;
         let value = self.lua.create_string(value.as_ref())?;
         self.class.call_method("add_header", (name, value))
     }
@@ -43,6 +45,32 @@ impl<'lua> HttpMessage<'lua> {
         }
     }
 
+    /// Returns `length` bytes of incoming data from the HTTP message, starting at the `offset`,
+    /// as a [`LuaBytes`] byte string borrowed from Lua.
+    #[inline]
+    pub fn body_bytes(
+        &self,
+        offset: Option<isize>,
+        length: Option<isize>,
+    ) -> Result<Option<LuaBytes<'lua>>> {
+        Ok(self.body(offset, length)?.map(LuaBytes::from))
+    }
+
+    /// Calls `f` with `length` bytes of incoming data from the HTTP message, starting at the `offset`,
+    /// without copying them out of the Lua string.
+    ///
+    /// If there is no data, `f` receives an empty slice.
+    #[inline]
+    pub fn body_with<R>(
+        &self,
+        offset: Option<isize>,
+        length: Option<isize>,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R> {
+        let data = self.body(offset, length)?;
+        Ok(f(data.as_ref().map(|s| s.as_bytes()).unwrap_or_default()))
+    }
+
     /// Returns a corresponding channel attached to the HTTP message.
     #[inline]
     pub fn channel(&self) -> Result<Channel<'lua>> {
@@ -58,7 +86,8 @@ impl<'lua> HttpMessage<'lua> {
     /// Removes all HTTP header fields in the HTTP message whose name is specified in name.
     #[inline]
     pub fn del_header(&self, name: &str) -> Result<()> {
-        self.class.call_method("del_header", name)
+        self.class
+            .call_method("del_header", header_name(self.lua, name)?)
     }
 
     /// Returns a table containing all the headers of the HTTP message.
@@ -67,9 +96,9 @@ impl<'lua> HttpMessage<'lua> {
         self.class.call_method("get_headers", ())
     }
 
-    /// Returns a table containing the start-line of the HTTP message.
+    /// Returns the start-line of the HTTP message.
     #[inline]
-    pub fn get_stline(&self) -> Result<Table<'lua>> {
+    pub fn get_stline(&self) -> Result<StartLine> {
         self.class.call_method("get_stline", ())
     }
 
@@ -190,6 +219,31 @@ impl<'lua> HttpMessage<'lua> {
         }
     }
 
+    /// Prepares the framing headers for a body that will be replaced in `http_payload`.
+    ///
+    /// Must be called from `http_headers`, before the headers are forwarded: `content-length`
+    /// is removed and `transfer-encoding: chunked` is set, so the new body length does not need
+    /// to be known in advance. See [`HttpMessage::replace_body`].
+    pub fn prepare_body_replacement(&self) -> Result<()> {
+        self.del_header("content-length")?;
+        self.set_header("transfer-encoding", "chunked")
+    }
+
+    /// Replaces the incoming data currently available in the HTTP message by the `data`.
+    ///
+    /// Only the current input is replaced, data received later are not affected. To replace
+    /// the whole body, wait for the end of message (see [`HttpMessage::eom`]) before calling it.
+    ///
+    /// Framing headers are not updated: by the time the payload is processed the headers have
+    /// already been forwarded. Call [`HttpMessage::prepare_body_replacement`] from `http_headers`
+    /// when the body length can change.
+    pub fn replace_body(&self, data: impl AsRef<[u8]>) -> Result<()> {
+        if self.set(data, None, Some(self.input()?))? < 0 {
+            return Err(Error::runtime("not enough room in the buffer"));
+        }
+        Ok(())
+    }
+
     /// Sets or removes the flag that indicates end of message.
     #[inline]
     pub fn set_eom(&self, eom: bool) -> Result<()> {
@@ -202,6 +256,7 @@ impl<'lua> HttpMessage<'lua> {
     /// Replaces all occurrence of all header matching the `name`, by only one containing the `value`.
     #[inline]
     pub fn set_header(&self, name: &str, value: impl AsRef<[u8]>) -> Result<()> {
+        let name = header_name(self.lua, name)?;
         let value = self.lua.create_string(value.as_ref())?;
         self.class.call_method("set_header", (name, value))
     }
@@ -238,6 +293,84 @@ impl<'lua> HttpMessage<'lua> {
     }
 }
 
+/// The start-line of an HTTP message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartLine {
+    /// The request line.
+    Request {
+        method: String,
+        uri: String,
+        version: String,
+    },
+    /// The status line.
+    Response {
+        version: String,
+        code: u16,
+        reason: String,
+    },
+}
+
+impl StartLine {
+    /// Returns the HTTP version (eg. `HTTP/1.1`).
+    pub fn version(&self) -> &str {
+        match self {
+            StartLine::Request { version, .. } | StartLine::Response { version, .. } => version,
+        }
+    }
+
+    /// Returns the request method, if this is a request line.
+    pub fn method(&self) -> Option<&str> {
+        match self {
+            StartLine::Request { method, .. } => Some(method),
+            StartLine::Response { .. } => None,
+        }
+    }
+
+    /// Returns the request URI, if this is a request line.
+    pub fn uri(&self) -> Option<&str> {
+        match self {
+            StartLine::Request { uri, .. } => Some(uri),
+            StartLine::Response { .. } => None,
+        }
+    }
+
+    /// Returns the status code, if this is a status line.
+    pub fn code(&self) -> Option<u16> {
+        match self {
+            StartLine::Request { .. } => None,
+            StartLine::Response { code, .. } => Some(*code),
+        }
+    }
+
+    /// Returns the reason phrase, if this is a status line.
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            StartLine::Request { .. } => None,
+            StartLine::Response { reason, .. } => Some(reason),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for StartLine {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let stline = Table::from_lua(value, lua)?;
+        match stline.get::<_, Option<String>>("method")? {
+            Some(method) => Ok(StartLine::Request {
+                method,
+                uri: stline.get("uri")?,
+                version: stline.get("version")?,
+            }),
+            None => Ok(StartLine::Response {
+                version: stline.get("version")?,
+                code: stline.get("code")?,
+                reason: stline
+                    .get::<_, Option<String>>("reason")?
+                    .unwrap_or_default(),
+            }),
+        }
+    }
+}
+
 impl<'lua> FromLua<'lua> for HttpMessage<'lua> {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
@@ -0,0 +1,157 @@
+use mlua::{Error, Lua, Result, String as LuaString, Table};
+
+use crate::{FilterMethod, FilterResult, HttpMessage, StartLine, Txn, UserFilter};
+
+/// A decision made by [`BodyInspector`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Let the request through, no further inspection is required.
+    Pass,
+    /// Block the request replying with the given status code.
+    Block(u16),
+    /// More body data is required to make a decision.
+    NeedMore,
+}
+
+/// The request start line and headers passed to [`BodyInspector`].
+#[derive(Debug, Clone)]
+pub struct RequestHead {
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    /// Header fields in the lowercase form.
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+impl RequestHead {
+    fn from_message(msg: &HttpMessage) -> Result<Self> {
+        let stline = msg.get_stline()?;
+        let mut headers = Vec::new();
+        for kv in msg.get_headers()?.pairs::<LuaString>() {
+            let (name, values) = kv?;
+            for value in values {
+                headers.push((name.clone(), value.as_bytes().to_vec()));
+            }
+        }
+        match stline {
+            StartLine::Request {
+                method,
+                uri,
+                version,
+            } => Ok(RequestHead {
+                method,
+                uri,
+                version,
+                headers,
+            }),
+            StartLine::Response { .. } => Err(Error::runtime("not an HTTP request")),
+        }
+    }
+
+    /// Returns the first value of header `name`.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_slice())
+    }
+}
+
+/// A trait to implement request inspection (WAF-style) without dealing with the filter API.
+///
+/// Use it together with [`InspectorFilter`]:
+///
+/// ```ignore
+/// core.register_filter::<InspectorFilter<MyRules>>("waf")?;
+/// ```
+pub trait BodyInspector: Sized {
+    /// Maximum number of body bytes to buffer for inspection.
+    /// If the body is larger, the inspection is performed on the first `MAX_INSPECT_BYTES` bytes.
+    const MAX_INSPECT_BYTES: usize = 64 * 1024;
+
+    /// Creates a new instance of inspector for a stream.
+    fn new(lua: &Lua, args: Table) -> Result<Self>;
+
+    /// Inspects the request.
+    ///
+    /// It's first called with an empty body when headers are received, then every time
+    /// new body data arrive, as long as [`Verdict::NeedMore`] is returned.
+    /// The `complete` flag is set when the whole body (or `MAX_INSPECT_BYTES`) is received;
+    /// returning [`Verdict::NeedMore`] at this point is treated as [`Verdict::Pass`].
+    fn inspect_request(&mut self, head: &RequestHead, body: &[u8], complete: bool) -> Verdict;
+}
+
+/// A filter that buffers the request body and passes it to [`BodyInspector`].
+///
+/// Blocked requests are replied using `txn:done()`, the rest of the request is forwarded untouched.
+pub struct InspectorFilter<I: BodyInspector> {
+    inspector: I,
+    head: Option<RequestHead>,
+}
+
+impl<I: BodyInspector> InspectorFilter<I> {
+    fn block(txn: &Txn, status: u16) -> Result<()> {
+        let reply = txn.reply()?;
+        reply.set_status(status, None)?;
+        reply.add_header("content-length", "0")?;
+        txn.done(Some(reply))
+    }
+}
+
+impl<I: BodyInspector> UserFilter for InspectorFilter<I> {
+    const METHODS: u8 = FilterMethod::HTTP_HEADERS | FilterMethod::HTTP_PAYLOAD;
+
+    // `txn:done()` must be propagated to HAProxy
+    const CONTINUE_IF_ERROR: bool = false;
+
+    fn new(lua: &Lua, args: Table) -> Result<Self> {
+        Ok(InspectorFilter {
+            inspector: I::new(lua, args)?,
+            head: None,
+        })
+    }
+
+    fn http_headers(&mut self, lua: &Lua, txn: Txn, msg: HttpMessage) -> Result<FilterResult> {
+        if msg.is_resp()? {
+            return Ok(FilterResult::Continue);
+        }
+
+        let head = RequestHead::from_message(&msg)?;
+        let complete = msg.eom()?;
+        match self.inspector.inspect_request(&head, &[], complete) {
+            Verdict::Pass => {}
+            Verdict::Block(status) => Self::block(&txn, status)?,
+            Verdict::NeedMore if complete => {}
+            Verdict::NeedMore => {
+                self.head = Some(head);
+                Self::register_data_filter(lua, txn, msg.channel()?)?;
+            }
+        }
+        Ok(FilterResult::Continue)
+    }
+
+    fn http_payload(&mut self, lua: &Lua, txn: Txn, msg: HttpMessage) -> Result<Option<usize>> {
+        let Some(head) = self.head.as_ref() else {
+            return Ok(None);
+        };
+
+        // Data that are not forwarded stay in the buffer and are returned again on the next call
+        let body = msg.body(None, Some(I::MAX_INSPECT_BYTES as isize))?;
+        let body = body.as_ref().map(|b| b.as_bytes()).unwrap_or_default();
+        let complete = msg.eom()? || body.len() >= I::MAX_INSPECT_BYTES || msg.is_full()?;
+
+        match self.inspector.inspect_request(head, body, complete) {
+            Verdict::NeedMore if !complete => Ok(Some(0)),
+            Verdict::Pass | Verdict::NeedMore => {
+                self.head = None;
+                Self::unregister_data_filter(lua, txn, msg.channel()?)?;
+                Ok(None)
+            }
+            Verdict::Block(status) => {
+                self.head = None;
+                Self::block(&txn, status)?;
+                Ok(Some(0))
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::ffi::c_void;
+
+use mlua::{LightUserData, Lua, Result, String as LuaString, Table};
+
+const REGISTRY_KEY: &str = "__HAPROXY_INTERNED_STRINGS";
+
+// Header names frequently written by filters
+const WELL_KNOWN_HEADERS: &[&str] = &[
+    "accept",
+    "accept-encoding",
+    "authorization",
+    "cache-control",
+    "connection",
+    "content-encoding",
+    "content-length",
+    "content-type",
+    "cookie",
+    "date",
+    "etag",
+    "forwarded",
+    "host",
+    "location",
+    "server",
+    "set-cookie",
+    "transfer-encoding",
+    "user-agent",
+    "vary",
+    "via",
+    "x-forwarded-for",
+    "x-forwarded-proto",
+    "x-request-id",
+];
+
+/// Returns a Lua string for the static `s`, creating it only once per Lua state.
+///
+/// Interned strings are stored in the Lua registry, keyed by the address of `s`,
+/// and live as long as the Lua state.
+pub fn intern<'lua>(lua: &'lua Lua, s: &'static str) -> Result<LuaString<'lua>> {
+    let cache = match lua.named_registry_value::<Option<Table>>(REGISTRY_KEY)? {
+        Some(cache) => cache,
+        None => {
+            let cache = lua.create_table()?;
+            lua.set_named_registry_value(REGISTRY_KEY, &cache)?;
+            cache
+        }
+    };
+
+    let key = LightUserData(s.as_ptr() as *mut c_void);
+    if let Some(interned) = cache.raw_get::<_, Option<LuaString>>(key)? {
+        // Different static strings can share the same address (eg. a prefix of another one)
+        if interned.as_bytes() == s.as_bytes() {
+            return Ok(interned);
+        }
+        return lua.create_string(s);
+    }
+    let interned = lua.create_string(s)?;
+    cache.raw_set(key, &interned)?;
+    Ok(interned)
+}
+
+// Returns the interned Lua string for well-known header `name`, or a new string otherwise
+pub(crate) fn header_name<'lua>(lua: &'lua Lua, name: &str) -> Result<LuaString<'lua>> {
+    match WELL_KNOWN_HEADERS.binary_search(&name) {
+        Ok(i) => intern(lua, WELL_KNOWN_HEADERS[i]),
+        Err(_) => lua.create_string(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_well_known_headers_sorted() {
+        // Required by `binary_search` in `header_name`
+        assert!(WELL_KNOWN_HEADERS.windows(2).all(|w| w[0] < w[1]));
+        assert!(WELL_KNOWN_HEADERS
+            .iter()
+            .all(|name| *name == name.to_ascii_lowercase()));
+    }
+}
@@ -7,32 +7,208 @@
 //! [Lua API]: http://www.arpalert.org/src/haproxy-lua-api/2.2/index.html
 //! [mlua]: https://crates.io/crates/mlua
 
+mod acl_file;
 #[cfg(feature = "async")]
 mod r#async;
+pub mod authorization;
+mod body_limit;
+mod body_reader;
+mod body_writer;
+mod build_info;
 mod channel;
+mod client_cert;
+#[cfg(feature = "config")]
+pub mod config;
+mod conn_info;
 mod converters;
+pub mod cookies;
 mod core;
+mod date;
+mod deferred_log;
+#[cfg(feature = "async")]
+pub mod discovery;
+mod dump;
+mod environment;
+mod error;
+pub mod etag;
+mod extensions;
+#[cfg(feature = "faults")]
+pub mod faults;
 mod fetches;
 mod filter;
+mod filter_chain;
+pub mod forwarded;
+pub mod grpc;
+mod header_ops;
+mod header_rewrite;
+mod header_values;
 mod http;
+#[cfg(feature = "http-body")]
+mod http_body_adapter;
+#[cfg(feature = "async")]
+mod http_fetch;
+#[cfg(feature = "http")]
+mod http_interop;
+#[cfg(feature = "serde")]
+mod http_json;
 mod http_message;
+mod inspector;
+mod intern;
 mod listener;
+mod log_macros;
+mod log_record;
+#[cfg(feature = "log")]
+pub mod logger;
+mod lua_bytes;
+mod map_file;
+pub mod metrics;
+pub mod mime;
+#[cfg(feature = "async")]
+pub mod mirror;
+pub mod multipart;
+pub mod negotiate;
+mod owned;
+mod pairs;
+mod panic;
+mod payload_cursor;
+mod payload_gate;
+pub mod percent;
+mod protocol;
 mod proxy;
+pub mod query;
+pub mod ratelimit;
+mod reply;
+pub mod router;
+#[cfg(feature = "async")]
+pub mod runtime_api;
+mod sample;
 mod server;
+pub mod shared;
+pub mod stats;
 mod stick_table;
+mod tls_info;
+#[cfg(feature = "tower")]
+mod tower_adapter;
+#[cfg(feature = "tracing")]
+pub mod tracing;
+mod trailers;
 mod txn;
+#[cfg(feature = "serde")]
+mod txn_serde;
+mod var;
+mod version;
+pub mod websocket;
 
+pub use crate::acl_file::{AclFile, BulkError, Changes};
+pub use crate::body_limit::{BodyLimitAction, BodyLimitFilter, BodyLimits};
+pub use crate::body_reader::BodyReader;
+pub use crate::body_writer::BodyWriter;
+pub use crate::build_info::{BuildInfo, Component, ComponentKind};
 pub use crate::channel::Channel;
-pub use crate::converters::Converters;
+pub use crate::client_cert::ClientCertInfo;
+pub use crate::conn_info::{ConnInfo, TransportInfo};
+pub use crate::converters::{ConverterChain, Converters};
 pub use crate::core::{Action, Core, LogLevel, ServiceMode, Time};
-pub use crate::fetches::Fetches;
-pub use crate::filter::{FilterMethod, FilterResult, UserFilter};
-pub use crate::http::{Headers, Http};
-pub use crate::http_message::HttpMessage;
-pub use crate::proxy::Proxy;
-pub use crate::server::Server;
-pub use crate::stick_table::StickTable;
+pub use crate::environment::Requirements;
+pub use crate::error::{CallbackKind, Error};
+pub use crate::fetches::{Fetches, MemoFetches, TrackedCounters};
+pub use crate::filter::{
+    Direction, FilterAttachment, FilterContext, FilterHandle, FilterMethod, FilterResult,
+    UserFilter,
+};
+pub use crate::filter_chain::{ChainStages, FilterChain};
+pub use crate::header_ops::HeaderOps;
+pub use crate::header_rewrite::{HeaderRewriteFilter, HeaderRewriteRules, HeaderRule};
+pub use crate::http::{Headers, Http, HttpRequest, HttpResponse};
+pub use crate::http_message::{HttpMessage, StartLine};
+pub use crate::inspector::{BodyInspector, InspectorFilter, RequestHead, Verdict};
+pub use crate::intern::intern;
+pub use crate::listener::{Listener, ListenerStats};
+#[doc(hidden)]
+pub use crate::log_macros::__log_fmt;
+pub use crate::log_macros::{log_enabled, set_max_log_level, LogTarget};
+pub use crate::log_record::{LogRecord, LogValue};
+pub use crate::lua_bytes::LuaBytes;
+pub use crate::map_file::MapFile;
+pub use crate::owned::{
+    ChannelOwned, HeadersOwned, HttpMessageOwned, HttpOwned, ProxyHandle, ProxyOwned, ReplyOwned,
+    ServerHandle, ServerOwned, StickTableHandle, StickTableOwned, TxnOwned,
+};
+pub use crate::panic::install_panic_hook;
+pub use crate::payload_cursor::PayloadCursor;
+pub use crate::payload_gate::PayloadGate;
+pub use crate::protocol::HttpProtocol;
+pub use crate::proxy::{CapacitySummary, Proxy, ProxyCapability, ProxyMode, Slot, SlotPool};
+pub use crate::reply::Reply;
+pub use crate::sample::Sample;
+pub use crate::server::{
+    AdminError, AdminOp, DrainOutcome, DrainReport, Server, Weight, WeightDetails,
+};
+#[cfg(feature = "haproxy28")]
+pub use crate::server::{ServerState, ServerWatcher, StateTransition};
+pub use crate::stick_table::{
+    DataType, DataValue, KeyType, StickTable, StickTableEntry, StickTableInfo, StoredDataType,
+};
+pub use crate::tls_info::{ClientHello, TlsInfo};
 pub use crate::txn::Txn;
+pub use crate::var::{Scope, Var};
+pub use crate::version::{Capability, HaproxyVersion};
+
+#[cfg(feature = "macros")]
+pub use haproxy_api_macros::{action, converter, fetch, module, service};
 
 #[cfg(feature = "async")]
 pub use crate::r#async::{create_async_function, runtime};
+
+/// Lua version the crate is built for, selected by the `lua54`, `lua53` or `luajit` feature.
+///
+/// Lua 5.4 has a 64-bit integer subtype, so integers passed to or from HAProxy keep full precision.
+#[cfg(feature = "lua54")]
+pub const LUA_VERSION: &str = "Lua 5.4";
+/// Lua version the crate is built for, selected by the `lua54`, `lua53` or `luajit` feature.
+///
+/// Lua 5.3 has a 64-bit integer subtype, so integers passed to or from HAProxy keep full precision.
+#[cfg(all(feature = "lua53", not(feature = "lua54")))]
+pub const LUA_VERSION: &str = "Lua 5.3";
+/// Lua version the crate is built for, selected by the `lua54`, `lua53` or `luajit` feature.
+///
+/// LuaJIT has no integer subtype, so integers above 2^53 passed to or from HAProxy lose precision.
+#[cfg(all(feature = "luajit", not(any(feature = "lua54", feature = "lua53"))))]
+pub const LUA_VERSION: &str = "LuaJIT";
+
+/// A marker trait that requires `Send` when the `send` feature is enabled.
+///
+/// Values stored in Lua (filters, async function closures) must satisfy it.
+#[cfg(feature = "send")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "send")]
+impl<T: Send> MaybeSend for T {}
+
+/// A marker trait that requires `Send` when the `send` feature is enabled.
+///
+/// Values stored in Lua (filters, async function closures) must satisfy it.
+#[cfg(not(feature = "send"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "send"))]
+impl<T> MaybeSend for T {}
+
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use inventory;
+
+    use crate::Core;
+
+    /// A function registration collected by attribute macros.
+    pub struct Registration {
+        pub(crate) register: fn(&Core) -> mlua::Result<()>,
+    }
+
+    impl Registration {
+        pub const fn new(register: fn(&Core) -> mlua::Result<()>) -> Self {
+            Registration { register }
+        }
+    }
+
+    inventory::collect!(Registration);
+}
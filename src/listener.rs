@@ -4,12 +4,104 @@ use mlua::{FromLua, Lua, Result, Table, TableExt, Value};
 #[derive(Clone)]
 pub struct Listener<'lua>(Table<'lua>);
 
+/// Typed listener statistics.
+///
+/// Fields are named after the stats page columns, missing values are reported as `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListenerStats {
+    /// The proxy name (`pxname`).
+    pub proxy_name: String,
+    /// The listener name (`svname`).
+    pub name: String,
+    /// The listener address (`addr`).
+    pub addr: Option<String>,
+    /// The listener status, eg. `OPEN` or `FULL` (`status`).
+    pub status: Option<String>,
+    /// The number of current sessions (`scur`).
+    pub cur_sess: u64,
+    /// The maximum number of concurrent sessions (`smax`).
+    pub max_sess: u64,
+    /// The configured sessions limit (`slim`).
+    pub sess_limit: Option<u64>,
+    /// The total number of sessions (`stot`).
+    pub total_sess: u64,
+    /// The number of bytes received (`bin`).
+    pub bytes_in: u64,
+    /// The number of bytes sent (`bout`).
+    pub bytes_out: u64,
+    /// The number of requests denied by security rules (`dreq`).
+    pub denied_req: u64,
+    /// The number of responses denied by security rules (`dresp`).
+    pub denied_resp: u64,
+    /// The number of connections rejected by `tcp-request connection` rules (`dcon`).
+    pub denied_conn: u64,
+    /// The number of sessions rejected by `tcp-request session` rules (`dses`).
+    pub denied_sess: u64,
+    /// The number of request errors (`ereq`).
+    pub req_errors: u64,
+}
+
+impl ListenerStats {
+    /// Returns the total number of denied connections, sessions, requests and responses.
+    pub fn denied(&self) -> u64 {
+        self.denied_conn + self.denied_sess + self.denied_req + self.denied_resp
+    }
+}
+
 impl<'lua> Listener<'lua> {
-    /// Returns server statistics.
+    /// Returns listener statistics.
     #[inline]
     pub fn get_stats(&self) -> Result<Table<'lua>> {
         self.0.call_method("get_stats", ())
     }
+
+    /// Returns typed listener statistics.
+    pub fn stats(&self) -> Result<ListenerStats> {
+        let stats = self.get_stats()?;
+        let count = |key| -> Result<u64> { Ok(stats.get::<_, Option<u64>>(key)?.unwrap_or(0)) };
+        Ok(ListenerStats {
+            proxy_name: stats.get::<_, Option<_>>("pxname")?.unwrap_or_default(),
+            name: stats.get::<_, Option<_>>("svname")?.unwrap_or_default(),
+            addr: stats.get("addr")?,
+            status: stats.get("status")?,
+            cur_sess: count("scur")?,
+            max_sess: count("smax")?,
+            sess_limit: stats.get("slim")?,
+            total_sess: count("stot")?,
+            bytes_in: count("bin")?,
+            bytes_out: count("bout")?,
+            denied_req: count("dreq")?,
+            denied_resp: count("dresp")?,
+            denied_conn: count("dcon")?,
+            denied_sess: count("dses")?,
+            req_errors: count("ereq")?,
+        })
+    }
+
+    /// Returns the listener name.
+    pub fn get_name(&self) -> Result<String> {
+        Ok(self.stats()?.name)
+    }
+
+    /// Returns the listener address, if reported by HAProxy.
+    pub fn get_addr(&self) -> Result<Option<String>> {
+        Ok(self.stats()?.addr)
+    }
+
+    /// Returns the number of current sessions.
+    pub fn get_cur_sess(&self) -> Result<u64> {
+        Ok(self.stats()?.cur_sess)
+    }
+
+    /// Returns the maximum number of concurrent sessions.
+    pub fn get_max_sess(&self) -> Result<u64> {
+        Ok(self.stats()?.max_sess)
+    }
+
+    /// Returns the total number of denied connections, sessions, requests and responses.
+    pub fn get_denied(&self) -> Result<u64> {
+        Ok(self.stats()?.denied())
+    }
 }
 
 impl<'lua> FromLua<'lua> for Listener<'lua> {
@@ -0,0 +1,105 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use mlua::Result;
+
+use crate::{Core, LogLevel, Txn};
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Sets the maximum level of messages logged by the `ha_*` macros (`Info` by default).
+pub fn set_max_log_level(level: LogLevel) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns true if messages with the `level` are logged by the `ha_*` macros.
+#[inline]
+pub fn log_enabled(level: LogLevel) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// An object that can send log messages (`Core` or `Txn`).
+pub trait LogTarget {
+    fn log_str(&self, level: LogLevel, msg: &str) -> Result<()>;
+}
+
+impl<'lua> LogTarget for Core<'lua> {
+    #[inline]
+    fn log_str(&self, level: LogLevel, msg: &str) -> Result<()> {
+        self.log(level, msg)
+    }
+}
+
+impl<'lua> LogTarget for Txn<'lua> {
+    #[inline]
+    fn log_str(&self, level: LogLevel, msg: &str) -> Result<()> {
+        self.log(level, msg)
+    }
+}
+
+#[doc(hidden)]
+pub fn __log_fmt<T: LogTarget + ?Sized>(
+    target: &T,
+    level: LogLevel,
+    args: fmt::Arguments,
+) -> Result<()> {
+    // Messages without arguments are passed as is
+    match args.as_str() {
+        Some(msg) => target.log_str(level, msg),
+        None => target.log_str(level, &fmt::format(args)),
+    }
+}
+
+/// Logs a message with the level to `Core` or `Txn`, if the level is enabled.
+///
+/// The message is not formatted if the level is disabled (see [`set_max_log_level`]).
+/// Evaluates to `mlua::Result<()>`.
+///
+/// ```ignore
+/// ha_log!(txn, LogLevel::Notice, "blocked request from {}", addr)?;
+/// ```
+///
+/// [`set_max_log_level`]: crate::set_max_log_level
+#[macro_export]
+macro_rules! ha_log {
+    ($target:expr, $level:expr, $($arg:tt)+) => {{
+        let level: $crate::LogLevel = $level;
+        if $crate::log_enabled(level) {
+            $crate::__log_fmt(&$target, level, ::std::format_args!($($arg)+))
+        } else {
+            ::std::result::Result::Ok(())
+        }
+    }};
+}
+
+/// Logs a message with the `Debug` level (see [`ha_log!`]).
+#[macro_export]
+macro_rules! ha_debug {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::ha_log!($target, $crate::LogLevel::Debug, $($arg)+)
+    };
+}
+
+/// Logs a message with the `Info` level (see [`ha_log!`]).
+#[macro_export]
+macro_rules! ha_info {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::ha_log!($target, $crate::LogLevel::Info, $($arg)+)
+    };
+}
+
+/// Logs a message with the `Warning` level (see [`ha_log!`]).
+#[macro_export]
+macro_rules! ha_warn {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::ha_log!($target, $crate::LogLevel::Warning, $($arg)+)
+    };
+}
+
+/// Logs a message with the `Err` level (see [`ha_log!`]).
+#[macro_export]
+macro_rules! ha_err {
+    ($target:expr, $($arg:tt)+) => {
+        $crate::ha_log!($target, $crate::LogLevel::Err, $($arg)+)
+    };
+}
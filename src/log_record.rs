@@ -0,0 +1,166 @@
+use std::fmt::{self, Write as _};
+use std::time::SystemTime;
+
+use mlua::Result;
+
+use crate::date::format_rfc3339;
+use crate::{LogLevel, LogTarget, Txn};
+
+/// A value of the [`LogRecord`] field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+}
+
+macro_rules! impl_from_log_value {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$ty> for LogValue {
+                #[inline]
+                fn from(value: $ty) -> Self {
+                    LogValue::$variant(value.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_log_value!(
+    bool => Bool,
+    i8 => Int, i16 => Int, i32 => Int, i64 => Int,
+    u8 => UInt, u16 => UInt, u32 => UInt, u64 => UInt,
+    f32 => Float, f64 => Float,
+    String => String, &str => String,
+);
+
+impl<T: Into<LogValue>> From<Option<T>> for LogValue {
+    #[inline]
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(LogValue::Null)
+    }
+}
+
+/// A structured log record rendered as a single line compact JSON object.
+///
+/// ```ignore
+/// LogRecord::new(LogLevel::Info, "request blocked")
+///     .with_txn(&txn)?
+///     .field("rule", "geo")
+///     .field("score", 42)
+///     .send(&txn)?;
+/// // {"ts":"2024-01-31T08:49:37.123Z","level":"info","msg":"request blocked",
+/// //  "unique_id":"...","client_ip":"192.0.2.1","frontend":"fe","backend":"be","rule":"geo","score":42}
+/// ```
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    level: LogLevel,
+    fields: Vec<(String, LogValue)>,
+}
+
+impl LogRecord {
+    /// Creates a new record with the timestamp, level and message fields.
+    pub fn new(level: LogLevel, msg: impl Into<String>) -> Self {
+        let fields = vec![
+            ("ts".into(), format_rfc3339(SystemTime::now()).into()),
+            ("level".into(), level_name(level).into()),
+            ("msg".into(), msg.into().into()),
+        ];
+        LogRecord { level, fields }
+    }
+
+    /// Adds the field `key`. A field with the same key is replaced.
+    pub fn field(mut self, key: impl Into<String>, value: impl Into<LogValue>) -> Self {
+        let (key, value) = (key.into(), value.into());
+        match self.fields.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.fields.push((key, value)),
+        }
+        self
+    }
+
+    /// Adds the transaction correlation fields: `unique_id`, `client_ip`, `frontend` and `backend`.
+    ///
+    /// `unique_id` is set only if `unique-id-format` is configured.
+    pub fn with_txn(self, txn: &Txn) -> Result<Self> {
+        let unique_id = txn.f.unique_id()?;
+        let client_ip = txn.f.src()?.map(|ip| ip.to_string());
+        Ok(self
+            .field("unique_id", unique_id.filter(|id| !id.is_empty()))
+            .field("client_ip", client_ip)
+            .field("frontend", txn.f.fe_name()?)
+            .field("backend", txn.f.be_name()?))
+    }
+
+    /// Returns the record level.
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    /// Renders the record as JSON.
+    pub fn to_json(&self) -> String {
+        self.to_string()
+    }
+
+    /// Sends the record to the `Core` or `Txn` log.
+    pub fn send<T: LogTarget + ?Sized>(&self, target: &T) -> Result<()> {
+        target.log_str(self.level, &self.to_json())
+    }
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('{')?;
+        for (i, (key, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            write_json_str(f, key)?;
+            f.write_char(':')?;
+            match value {
+                LogValue::Null => f.write_str("null")?,
+                LogValue::Bool(b) => write!(f, "{b}")?,
+                LogValue::Int(n) => write!(f, "{n}")?,
+                LogValue::UInt(n) => write!(f, "{n}")?,
+                // JSON has no representation for NaN and infinities
+                LogValue::Float(n) if !n.is_finite() => f.write_str("null")?,
+                LogValue::Float(n) => write!(f, "{n}")?,
+                LogValue::String(s) => write_json_str(f, s)?,
+            }
+        }
+        f.write_char('}')
+    }
+}
+
+fn level_name(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Emerg => "emerg",
+        LogLevel::Alert => "alert",
+        LogLevel::Crit => "crit",
+        LogLevel::Err => "err",
+        LogLevel::Warning => "warning",
+        LogLevel::Notice => "notice",
+        LogLevel::Info => "info",
+        LogLevel::Debug => "debug",
+    }
+}
+
+fn write_json_str(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
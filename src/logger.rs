@@ -0,0 +1,60 @@
+//! [`log`] crate backend forwarding records to the HAProxy log.
+//!
+//! Records are queued (from any thread, including Tokio workers) and written through
+//! `core.log()` by a HAProxy task, which runs every 100ms.
+//!
+//! ```ignore
+//! #[mlua::lua_module(skip_memory_check)]
+//! fn haproxy_my_module(lua: &Lua) -> Result<bool> {
+//!     haproxy_api::logger::init(lua, log::LevelFilter::Info)?;
+//!     log::info!("module loaded");
+//!     Ok(true)
+//! }
+//! ```
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use mlua::{Error, Lua, Result};
+
+use crate::{deferred_log, LogLevel};
+
+struct Logger;
+
+static LOGGER: Logger = Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let msg = format!("[{}] {}", record.target(), record.args());
+        deferred_log::push(log_level(record.level()), msg);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Maps the [`log`] level to the HAProxy log level.
+pub fn log_level(level: Level) -> LogLevel {
+    match level {
+        Level::Error => LogLevel::Err,
+        Level::Warn => LogLevel::Warning,
+        Level::Info => LogLevel::Info,
+        Level::Debug | Level::Trace => LogLevel::Debug,
+    }
+}
+
+/// Installs the HAProxy logger with the maximum `level`.
+///
+/// Must be called during the module loading, as it registers a HAProxy task.
+/// Calling it again only updates the level. Returns an error if other logger is already installed.
+pub fn init(lua: &Lua, level: LevelFilter) -> Result<()> {
+    if log::set_logger(&LOGGER).is_err() && !std::ptr::addr_eq(log::logger(), &LOGGER) {
+        return Err(Error::runtime("another logger is already installed"));
+    }
+    log::set_max_level(level);
+    deferred_log::register_flush_task(lua)
+}
@@ -0,0 +1,52 @@
+use std::fmt;
+use std::ops::Deref;
+
+use bstr::BStr;
+use mlua::String as LuaString;
+
+/// Bytes of a Lua string, viewed as a [`BStr`] without copying them out of Lua.
+///
+/// Returned by [`HttpMessage::body_bytes`] and [`Channel::data_bytes`], it can be searched
+/// and sliced with the [`bstr::ByteSlice`] methods.
+///
+/// [`HttpMessage::body_bytes`]: crate::HttpMessage::body_bytes
+/// [`Channel::data_bytes`]: crate::Channel::data_bytes
+#[derive(Clone, PartialEq)]
+pub struct LuaBytes<'lua>(LuaString<'lua>);
+
+impl<'lua> LuaBytes<'lua> {
+    /// Returns the underlying Lua string.
+    #[inline]
+    pub fn into_inner(self) -> LuaString<'lua> {
+        self.0
+    }
+}
+
+impl<'lua> From<LuaString<'lua>> for LuaBytes<'lua> {
+    #[inline]
+    fn from(s: LuaString<'lua>) -> Self {
+        LuaBytes(s)
+    }
+}
+
+impl Deref for LuaBytes<'_> {
+    type Target = BStr;
+
+    #[inline]
+    fn deref(&self) -> &BStr {
+        BStr::new(self.0.as_bytes())
+    }
+}
+
+impl AsRef<[u8]> for LuaBytes<'_> {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl fmt::Debug for LuaBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
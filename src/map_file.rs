@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use mlua::{Error, Lua, Result, Table, TableExt};
+
+use crate::acl_file::{merge_results, read_lines};
+use crate::{BulkError, Capability, Changes, Core};
+
+mod sync;
+
+// Registry table with the "Map" objects created by `MapFile::register_lookup`, by file name
+const LOOKUP_MAPS_KEY: &str = "__HAPROXY_MAP_FILES";
+
+/// A handle to a map file loaded by HAProxy, tracking its entries.
+///
+/// Like [`AclFile`](crate::AclFile), the handle keeps the known entries: the ones read from
+/// the file (see [`MapFile::from_file`]) and the ones changed through the handle.
+/// The values actually used by HAProxy can be read back with [`MapFile::lookup`].
+///
+/// ```ignore
+/// let mut routes = MapFile::from_file("/etc/haproxy/routes.map")?;
+/// routes.register_lookup(&core)?;
+/// let changes = routes.replace_all(&core, fetched_routes)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct MapFile {
+    filename: String,
+    entries: BTreeMap<String, String>,
+}
+
+impl MapFile {
+    /// Creates a handle to the map file `filename` without known entries.
+    ///
+    /// The `filename` must be the same as used in the HAProxy configuration.
+    pub fn new(filename: impl Into<String>) -> Self {
+        MapFile {
+            filename: filename.into(),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a handle to the map file `filename`, reading the entries from it.
+    pub fn from_file(filename: impl Into<String>) -> io::Result<Self> {
+        let filename = filename.into();
+        let entries = parse_entries(read_lines(Path::new(&filename))?);
+        Ok(MapFile { filename, entries })
+    }
+
+    /// Returns the map file name.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Returns the known value of the `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Returns the number of known entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if there are no known entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over the known entries, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        (self.entries.iter()).map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Creates the "Map" object used by [`MapFile::lookup`].
+    ///
+    /// HAProxy allows to create it only during the initialization (when the module is loaded).
+    pub fn register_lookup(&self, core: &Core) -> Result<()> {
+        let lua = core.lua;
+        let map_class: Table = lua.globals().get("Map")?;
+        let method: u32 = map_class.get("_str")?;
+        let map: Table = map_class.call_function("new", (self.filename.as_str(), method))?;
+        lookup_maps(lua)?.raw_set(self.filename.as_str(), map)
+    }
+
+    /// Looks up the `key` in the map loaded by HAProxy (exact string match).
+    ///
+    /// Requires [`MapFile::register_lookup`] to be called during the initialization.
+    pub fn lookup(&self, lua: &Lua, key: &str) -> Result<Option<String>> {
+        let map: Option<Table> = lookup_maps(lua)?.raw_get(self.filename.as_str())?;
+        let map = map.ok_or_else(|| {
+            let msg = format!("lookup is not registered for map '{}'", self.filename);
+            Error::runtime(msg)
+        })?;
+        map.call_method("lookup", key)
+    }
+
+    /// Sets the `value` of the `key`.
+    pub fn set(&mut self, core: &Core, key: &str, value: &str) -> Result<()> {
+        core.set_map(&self.filename, key, value)?;
+        self.entries.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Deletes the `key`.
+    pub fn del(&mut self, core: &Core, key: &str) -> Result<()> {
+        core.del_map(&self.filename, key)?;
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    /// Sets the entries whose known value differs.
+    pub fn set_many<I, K, V>(&mut self, core: &Core, entries: I) -> Result<Changes>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut changes = Changes::default();
+        let mut failures = Vec::new();
+        for (key, value) in entries {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            if self.get(key) == Some(value) {
+                continue;
+            }
+            match self.set(core, key, value) {
+                Ok(()) => changes.added += 1,
+                Err(err) => failures.push((key.to_string(), err)),
+            }
+        }
+        BulkError::check(failures, changes)
+    }
+
+    /// Deletes the known keys.
+    pub fn del_many<I, K>(&mut self, core: &Core, keys: I) -> Result<Changes>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let mut changes = Changes::default();
+        let mut failures = Vec::new();
+        for key in keys {
+            let key = key.as_ref();
+            if self.get(key).is_none() {
+                continue;
+            }
+            match self.del(core, key) {
+                Ok(()) => changes.removed += 1,
+                Err(err) => failures.push((key.to_string(), err)),
+            }
+        }
+        BulkError::check(failures, changes)
+    }
+
+    /// Replaces the entries, setting only the changed ones and deleting the stale ones.
+    ///
+    /// New values are set before the stale keys are deleted.
+    pub fn replace_all<I, K, V>(&mut self, core: &Core, entries: I) -> Result<Changes>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let entries = (entries.into_iter())
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect::<BTreeMap<String, String>>();
+        let stale = (self.entries.keys())
+            .filter(|key| !entries.contains_key(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        let set = self.set_many(core, &entries);
+        let removed = self.del_many(core, &stale);
+        merge_results(set, removed)
+    }
+
+    /// Replaces the entries atomically, so lookups never see a partially updated map.
+    ///
+    /// Uses the "Patref" class prepare/commit when available (HAProxy 3.2+),
+    /// otherwise falls back to [`MapFile::replace_all`].
+    pub fn swap<I, K, V>(&mut self, core: &Core, entries: I) -> Result<Changes>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        if !core.supports(Capability::Patref)? {
+            return self.replace_all(core, entries);
+        }
+        let entries = (entries.into_iter())
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect::<BTreeMap<String, String>>();
+        let changes = diff(&self.entries, &entries);
+
+        let patref: Table = core.call_function("get_patref", self.filename.as_str())?;
+        patref.call_method::<_, ()>("prepare", ())?;
+        let bulk = core
+            .lua
+            .create_table_from(entries.iter().map(|(k, v)| (k.as_str(), v.as_str())))?;
+        let result = (patref.call_method::<_, ()>("add_bulk", bulk))
+            .and_then(|()| patref.call_method::<_, ()>("commit", ()));
+        if let Err(err) = result {
+            let _ = patref.call_method::<_, ()>("giveup", ());
+            return Err(err);
+        }
+        self.entries = entries;
+        Ok(changes)
+    }
+}
+
+fn lookup_maps(lua: &Lua) -> Result<Table<'_>> {
+    match lua.named_registry_value::<Option<Table>>(LOOKUP_MAPS_KEY)? {
+        Some(maps) => Ok(maps),
+        None => {
+            let maps = lua.create_table()?;
+            lua.set_named_registry_value(LOOKUP_MAPS_KEY, &maps)?;
+            Ok(maps)
+        }
+    }
+}
+
+// Parses the map lines: the key is the first word, the value is the rest of the line
+pub(crate) fn parse_entries(lines: Vec<String>) -> BTreeMap<String, String> {
+    (lines.into_iter())
+        .map(|line| match line.split_once(char::is_whitespace) {
+            Some((key, value)) => (key.to_string(), value.trim().to_string()),
+            None => (line, String::new()),
+        })
+        .collect()
+}
+
+// Counts the entries changed between the two versions of the map
+fn diff(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> Changes {
+    Changes {
+        added: (new.iter())
+            .filter(|(k, v)| old.get(*k) != Some(*v))
+            .count(),
+        removed: old.keys().filter(|k| !new.contains_key(*k)).count(),
+    }
+}
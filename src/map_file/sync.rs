@@ -0,0 +1,176 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+#[cfg(feature = "async")]
+use std::sync::Arc;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use mlua::{Function, Lua, Result, TableExt};
+
+use super::{parse_entries, MapFile};
+use crate::acl_file::parse_lines;
+#[cfg(feature = "async")]
+use crate::deferred_log;
+#[cfg(feature = "async")]
+use crate::http_fetch::{self, HttpUrl};
+use crate::{Core, LogLevel};
+
+// The synchronized map with the last applied content
+struct SyncState {
+    map: MapFile,
+    modified: Option<SystemTime>,
+    hash: Option<u64>,
+}
+
+impl SyncState {
+    fn new(map: MapFile) -> Self {
+        SyncState {
+            map,
+            modified: None,
+            hash: None,
+        }
+    }
+
+    // Applies the content to the map if it changed since the last time
+    fn apply(&mut self, lua: &Lua, content: &str, source: &str) -> Result<()> {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        let hash = hasher.finish();
+        if self.hash == Some(hash) {
+            return Ok(());
+        }
+        self.hash = Some(hash);
+
+        let core = Core::new(lua)?;
+        let entries = parse_entries(parse_lines(content));
+        let filename = self.map.filename().to_string();
+        match self.map.replace_all(&core, entries) {
+            Ok(changes) if changes.is_empty() => Ok(()),
+            Ok(changes) => core.log(
+                LogLevel::Notice,
+                format!(
+                    "map '{filename}' synchronized from '{source}': {} set, {} deleted",
+                    changes.added, changes.removed
+                ),
+            ),
+            Err(err) => core.log(
+                LogLevel::Err,
+                format!("failed to synchronize map '{filename}' from '{source}': {err}"),
+            ),
+        }
+    }
+}
+
+impl MapFile {
+    /// Synchronizes the map with the file at `path`, checking it every `interval`.
+    ///
+    /// The file is re-read when its modification time changes, and the differences are applied
+    /// to the HAProxy map (see [`MapFile::replace_all`]) and logged.
+    pub fn sync_from_path(
+        self,
+        core: &Core,
+        path: impl Into<PathBuf>,
+        interval: Duration,
+    ) -> Result<()> {
+        let path = path.into();
+        let state = Mutex::new(SyncState::new(self));
+        let check = core.lua.create_function(move |lua, ()| {
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+            if modified.is_none() || modified == state.modified {
+                return Ok(());
+            }
+            state.modified = modified;
+            let source = path.display().to_string();
+            match fs::read_to_string(&path) {
+                Ok(content) => state.apply(lua, &content, &source),
+                Err(err) => Core::new(lua)?.log(
+                    LogLevel::Err,
+                    format!("failed to read map file '{source}': {err}"),
+                ),
+            }
+        })?;
+        register_sync_task(core, check, interval)
+    }
+
+    /// Synchronizes the map with the content downloaded from the (`http://`) `url`,
+    /// checking it every `interval`.
+    ///
+    /// The content is downloaded on the async runtime (using the `ETag` header to skip
+    /// unchanged content), then the differences are applied to the HAProxy map
+    /// (see [`MapFile::replace_all`]) and logged.
+    #[cfg(feature = "async")]
+    pub fn sync_from_url(self, core: &Core, url: &str, interval: Duration) -> Result<()> {
+        let source = url.to_string();
+        let url = HttpUrl::parse(url).map_err(mlua::Error::external)?;
+        // The downloaded content, waiting to be applied
+        let latest = Arc::new(Mutex::new(None::<String>));
+
+        let downloaded = latest.clone();
+        let download_source = source.clone();
+        core.register_async_task(move || {
+            let (url, latest) = (url.clone(), downloaded.clone());
+            let source = download_source.clone();
+            async move {
+                let mut ticker = tokio::time::interval(interval);
+                let mut etag = None::<String>;
+                loop {
+                    ticker.tick().await;
+                    let headers = match &etag {
+                        Some(etag) => vec![("If-None-Match", etag.as_str())],
+                        None => vec![],
+                    };
+                    let error = match http_fetch::get(&url, &headers).await {
+                        Ok(response) if response.status == 304 => continue,
+                        Ok(response) if response.status == 200 => {
+                            etag = response.header("ETag").map(String::from);
+                            let content = String::from_utf8_lossy(&response.body).into_owned();
+                            *latest.lock().unwrap_or_else(PoisonError::into_inner) = Some(content);
+                            continue;
+                        }
+                        Ok(response) => format!("unexpected HTTP status {}", response.status),
+                        Err(err) => err.to_string(),
+                    };
+                    let msg = format!("failed to download map from '{source}': {error}");
+                    deferred_log::push(LogLevel::Err, msg);
+                }
+            }
+        })?;
+
+        let state = Mutex::new(SyncState::new(self));
+        let check = core.lua.create_function(move |lua, ()| {
+            let content = latest.lock().unwrap_or_else(PoisonError::into_inner).take();
+            match content {
+                Some(content) => {
+                    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+                    state.apply(lua, &content, &source)
+                }
+                None => Ok(()),
+            }
+        })?;
+        deferred_log::register_flush_task(core.lua)?;
+        register_sync_task(core, check, interval)
+    }
+}
+
+fn register_sync_task(core: &Core, check: Function, interval: Duration) -> Result<()> {
+    let task: Function = core
+        .lua
+        .load(
+            r#"
+            local check, interval = ...
+            local msleep = core.msleep
+            return function()
+                while true do
+                    check()
+                    msleep(interval)
+                end
+            end
+            "#,
+        )
+        .set_name("=map_sync")
+        .call((check, interval.as_millis().max(1) as u64))?;
+    core.call_function("register_task", task)
+}
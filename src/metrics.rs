@@ -0,0 +1,617 @@
+//! Module metrics exported in the Prometheus text format.
+//!
+//! Metrics are stored in a process-wide [`Registry`] and updated with atomic operations,
+//! so they can be used from callbacks running on any HAProxy thread or from async tasks.
+//!
+//! ```ignore
+//! let requests =
+//!     metrics::registry().counter_family("myapp_requests_total", "Handled requests", &["action"])?;
+//!
+//! core.register_action("auth", &[Action::HttpReq], 0, move |_, txn: Txn| {
+//!     requests.with(&["auth"])?.inc();
+//!     Ok(())
+//! })?;
+//! metrics::register_service(&core)?;
+//! ```
+//!
+//! ```text
+//! frontend prometheus
+//!     bind :8405
+//!     http-request use-service prometheus-exporter if { path /metrics }
+//!     http-request use-service lua.rust_metrics if { path /rust-metrics }
+//! ```
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use mlua::{Error, Result, Table, TableExt};
+
+use crate::{Core, ServiceMode};
+
+/// The default histogram buckets (in seconds), suitable for request latencies.
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The name of the service registered by [`register_service`].
+pub const SERVICE_NAME: &str = "rust_metrics";
+
+/// Returns the process-wide registry.
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::new)
+}
+
+/// Registers the `lua.rust_metrics` HTTP service rendering the process-wide registry.
+pub fn register_service(core: &Core) -> Result<()> {
+    registry().register_service(core, SERVICE_NAME)
+}
+
+/// A monotonically increasing counter.
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    /// Increments the counter by one.
+    #[inline]
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Increments the counter by `n`.
+    #[inline]
+    pub fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the counter value.
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that can go up and down.
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicF64>);
+
+impl Gauge {
+    /// Sets the gauge value.
+    #[inline]
+    pub fn set(&self, value: f64) {
+        self.0.set(value);
+    }
+
+    /// Adds `delta` (which can be negative) to the gauge.
+    #[inline]
+    pub fn add(&self, delta: f64) {
+        self.0.add(delta);
+    }
+
+    /// Increments the gauge by one.
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1.0);
+    }
+
+    /// Decrements the gauge by one.
+    #[inline]
+    pub fn dec(&self) {
+        self.add(-1.0);
+    }
+
+    /// Returns the gauge value.
+    #[inline]
+    pub fn get(&self) -> f64 {
+        self.0.get()
+    }
+}
+
+/// Observations counted in configurable buckets (eg. request latencies).
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<HistogramInner>);
+
+#[derive(Debug)]
+struct HistogramInner {
+    bounds: Arc<[f64]>,
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    sum: AtomicF64,
+}
+
+impl Histogram {
+    fn new(bounds: Arc<[f64]>) -> Self {
+        let buckets = bounds.iter().map(|_| AtomicU64::new(0)).collect();
+        Histogram(Arc::new(HistogramInner {
+            bounds,
+            buckets,
+            count: AtomicU64::new(0),
+            sum: AtomicF64::default(),
+        }))
+    }
+
+    /// Records the observed `value`.
+    pub fn observe(&self, value: f64) {
+        let inner = &self.0;
+        if let Some(i) = inner.bounds.iter().position(|&bound| value <= bound) {
+            inner.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        inner.count.fetch_add(1, Ordering::Relaxed);
+        inner.sum.add(value);
+    }
+
+    /// Returns the number of observations.
+    pub fn count(&self) -> u64 {
+        self.0.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the sum of the observed values.
+    pub fn sum(&self) -> f64 {
+        self.0.sum.get()
+    }
+}
+
+// `f64` stored as bits in `AtomicU64`
+#[derive(Debug, Default)]
+struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    fn add(&self, delta: f64) {
+        let _ = (self.0).fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + delta).to_bits())
+        });
+    }
+}
+
+/// A kind of metric stored in a [`Family`].
+pub trait Metric: Clone + Send + Sync + 'static {
+    #[doc(hidden)]
+    const TYPE: &'static str;
+
+    #[doc(hidden)]
+    fn create(buckets: &Arc<[f64]>) -> Self;
+
+    #[doc(hidden)]
+    fn render(&self, out: &mut String, name: &str, labels: &str) -> fmt::Result;
+}
+
+impl Metric for Counter {
+    const TYPE: &'static str = "counter";
+
+    fn create(_: &Arc<[f64]>) -> Self {
+        Counter::default()
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) -> fmt::Result {
+        writeln!(out, "{name}{} {}", braced(labels), self.get())
+    }
+}
+
+impl Metric for Gauge {
+    const TYPE: &'static str = "gauge";
+
+    fn create(_: &Arc<[f64]>) -> Self {
+        Gauge::default()
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) -> fmt::Result {
+        writeln!(out, "{name}{} {}", braced(labels), format_float(self.get()))
+    }
+}
+
+impl Metric for Histogram {
+    const TYPE: &'static str = "histogram";
+
+    fn create(buckets: &Arc<[f64]>) -> Self {
+        Histogram::new(buckets.clone())
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) -> fmt::Result {
+        let inner = &self.0;
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, bucket) in inner.bounds.iter().zip(inner.buckets.iter()) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = format_float(*bound);
+            writeln!(
+                out,
+                "{name}_bucket{{{labels}{sep}le=\"{le}\"}} {cumulative}"
+            )?;
+        }
+        let count = self.count();
+        writeln!(out, "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {count}")?;
+        writeln!(
+            out,
+            "{name}_sum{} {}",
+            braced(labels),
+            format_float(self.sum())
+        )?;
+        writeln!(out, "{name}_count{} {count}", braced(labels))
+    }
+}
+
+/// A set of metrics with the same name, distinguished by the label values.
+#[derive(Clone)]
+pub struct Family<M>(Arc<FamilyInner<M>>);
+
+struct FamilyInner<M> {
+    help: String,
+    labels: Vec<String>,
+    buckets: Arc<[f64]>,
+    series: RwLock<BTreeMap<Vec<String>, M>>,
+}
+
+impl<M> fmt::Debug for Family<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Family")
+            .field("help", &self.0.help)
+            .field("labels", &self.0.labels)
+            .finish()
+    }
+}
+
+impl<M: Metric> Family<M> {
+    /// Returns the metric with the label `values` (in the order of the family labels).
+    ///
+    /// Returns an error if the number of values does not match the number of labels.
+    pub fn with(&self, values: &[&str]) -> Result<M> {
+        let inner = &self.0;
+        if values.len() != inner.labels.len() {
+            return Err(Error::runtime(format!(
+                "expected {} label value(s), got {}",
+                inner.labels.len(),
+                values.len()
+            )));
+        }
+        let key = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let series = inner.series.read().unwrap_or_else(|err| err.into_inner());
+        if let Some(metric) = series.get(&key) {
+            return Ok(metric.clone());
+        }
+        drop(series);
+        let mut series = inner.series.write().unwrap_or_else(|err| err.into_inner());
+        let metric = series
+            .entry(key)
+            .or_insert_with(|| M::create(&inner.buckets));
+        Ok(metric.clone())
+    }
+
+    /// Removes the metric with the label `values`.
+    pub fn remove(&self, values: &[&str]) -> bool {
+        let key = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let mut series = self.0.series.write().unwrap_or_else(|err| err.into_inner());
+        series.remove(&key).is_some()
+    }
+}
+
+// Type-erased family stored in the registry
+trait Collect: Send + Sync {
+    fn metric_type(&self) -> &'static str;
+    fn render(&self, out: &mut String, name: &str) -> fmt::Result;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<M: Metric> Collect for Family<M> {
+    fn metric_type(&self) -> &'static str {
+        M::TYPE
+    }
+
+    fn render(&self, out: &mut String, name: &str) -> fmt::Result {
+        let inner = &self.0;
+        let series = inner.series.read().unwrap_or_else(|err| err.into_inner());
+        if series.is_empty() {
+            return Ok(());
+        }
+        writeln!(out, "# HELP {name} {}", escape(&inner.help, false))?;
+        writeln!(out, "# TYPE {name} {}", M::TYPE)?;
+        for (values, metric) in series.iter() {
+            let mut labels = String::new();
+            for (i, (label, value)) in inner.labels.iter().zip(values).enumerate() {
+                if i > 0 {
+                    labels.push(',');
+                }
+                write!(labels, "{label}=\"{}\"", escape(value, true))?;
+            }
+            metric.render(out, name, &labels)?;
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A registry of metrics, rendered in the Prometheus text format.
+///
+/// Registering a metric with the name of an existing one returns the existing metric.
+/// The metric methods return an error if a name is invalid, or the metric is already registered
+/// with another type, labels or buckets.
+#[derive(Default)]
+pub struct Registry {
+    families: RwLock<BTreeMap<String, Arc<dyn Collect>>>,
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let families = self.families.read().unwrap_or_else(|err| err.into_inner());
+        f.debug_struct("Registry")
+            .field("families", &families.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// Returns the counter `name`.
+    pub fn counter(&self, name: &str, help: &str) -> Result<Counter> {
+        self.counter_family(name, help, &[])?.with(&[])
+    }
+
+    /// Returns the family of counters `name` with the `labels`.
+    pub fn counter_family(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+    ) -> Result<Family<Counter>> {
+        self.family(name, help, labels, &[])
+    }
+
+    /// Returns the gauge `name`.
+    pub fn gauge(&self, name: &str, help: &str) -> Result<Gauge> {
+        self.gauge_family(name, help, &[])?.with(&[])
+    }
+
+    /// Returns the family of gauges `name` with the `labels`.
+    pub fn gauge_family(&self, name: &str, help: &str, labels: &[&str]) -> Result<Family<Gauge>> {
+        self.family(name, help, labels, &[])
+    }
+
+    /// Returns the histogram `name` with the bucket upper `bounds`
+    /// (eg. [`DEFAULT_BUCKETS`]).
+    pub fn histogram(&self, name: &str, help: &str, bounds: &[f64]) -> Result<Histogram> {
+        self.histogram_family(name, help, &[], bounds)?.with(&[])
+    }
+
+    /// Returns the family of histograms `name` with the `labels` and the bucket upper `bounds`.
+    ///
+    /// The `le` label is reserved for the bucket bounds.
+    pub fn histogram_family(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        bounds: &[f64],
+    ) -> Result<Family<Histogram>> {
+        if labels.contains(&"le") {
+            return Err(Error::runtime(format!(
+                "histogram '{name}' cannot have the 'le' label"
+            )));
+        }
+        let mut bounds = (bounds.iter().copied())
+            .filter(|bound| bound.is_finite())
+            .collect::<Vec<_>>();
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        self.family(name, help, labels, &bounds)
+    }
+
+    // Returns the metric family, registering it if needed
+    fn family<M: Metric>(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        buckets: &[f64],
+    ) -> Result<Family<M>> {
+        if !is_valid_name(name, true) {
+            return Err(Error::runtime(format!("invalid metric name '{name}'")));
+        }
+        for label in labels {
+            if !is_valid_name(label, false) {
+                return Err(Error::runtime(format!("invalid label name '{label}'")));
+            }
+        }
+
+        let mut families = self.families.write().unwrap_or_else(|err| err.into_inner());
+        if let Some(family) = families.get(name) {
+            let Some(family) = family.as_any().downcast_ref::<Family<M>>() else {
+                return Err(Error::runtime(format!(
+                    "metric '{name}' is already registered as {}",
+                    family.metric_type()
+                )));
+            };
+            if family.0.labels != labels {
+                return Err(Error::runtime(format!(
+                    "metric '{name}' is already registered with labels {:?}",
+                    family.0.labels
+                )));
+            }
+            if *family.0.buckets != *buckets {
+                return Err(Error::runtime(format!(
+                    "metric '{name}' is already registered with buckets {:?}",
+                    family.0.buckets
+                )));
+            }
+            return Ok(family.clone());
+        }
+        let family = Family(Arc::new(FamilyInner {
+            help: help.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            buckets: buckets.into(),
+            series: RwLock::new(BTreeMap::new()),
+        }));
+        families.insert(name.to_string(), Arc::new(family.clone()));
+        Ok(family)
+    }
+
+    /// Renders all the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let families = self.families.read().unwrap_or_else(|err| err.into_inner());
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let _ = family.render(&mut out, name);
+        }
+        out
+    }
+
+    /// Registers the HTTP service `name` rendering the registry metrics.
+    pub fn register_service(&'static self, core: &Core, name: &str) -> Result<()> {
+        core.register_service(name, ServiceMode::Http, move |lua, applet: Table| {
+            let body = lua.create_string(self.render())?;
+            applet.call_method::<_, ()>("set_status", 200)?;
+            applet.call_method::<_, ()>(
+                "add_header",
+                ("content-type", "text/plain; version=0.0.4; charset=utf-8"),
+            )?;
+            applet.call_method::<_, ()>("add_header", ("content-length", body.as_bytes().len()))?;
+            applet.call_method::<_, ()>("start_response", ())?;
+            applet.call_method::<_, ()>("send", body)
+        })
+    }
+}
+
+fn is_valid_name(name: &str, allow_colon: bool) -> bool {
+    let valid = |c: char, first: bool| {
+        c.is_ascii_alphabetic()
+            || c == '_'
+            || (allow_colon && c == ':')
+            || (!first && c.is_ascii_digit())
+    };
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| valid(c, true)) && chars.all(|c| valid(c, false))
+}
+
+fn escape(value: &str, quote: bool) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '"' if quote => out.push_str("\\\""),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn format_float(value: f64) -> String {
+    match value {
+        v if v.is_nan() => "NaN".into(),
+        v if v == f64::INFINITY => "+Inf".into(),
+        v if v == f64::NEG_INFINITY => "-Inf".into(),
+        v => v.to_string(),
+    }
+}
+
+fn braced(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{labels}}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let registry = Registry::new();
+        let requests = registry
+            .counter_family("requests_total", "Handled\nrequests", &["action"])
+            .unwrap();
+        requests.with(&["auth"]).unwrap().inc_by(3);
+        requests.with(&["say \"hi\""]).unwrap().inc();
+        registry
+            .gauge("in_flight", "In-flight requests")
+            .unwrap()
+            .set(2.5);
+        let latency = registry
+            .histogram("latency_seconds", "Latency", &[1.0, 0.1])
+            .unwrap();
+        latency.observe(0.05);
+        latency.observe(0.5);
+        latency.observe(5.0);
+        // Families without series are not rendered
+        registry.counter_family("empty", "", &["x"]).unwrap();
+
+        assert_eq!(
+            registry.render(),
+            concat!(
+                "# HELP in_flight In-flight requests\n",
+                "# TYPE in_flight gauge\n",
+                "in_flight 2.5\n",
+                "# HELP latency_seconds Latency\n",
+                "# TYPE latency_seconds histogram\n",
+                "latency_seconds_bucket{le=\"0.1\"} 1\n",
+                "latency_seconds_bucket{le=\"1\"} 2\n",
+                "latency_seconds_bucket{le=\"+Inf\"} 3\n",
+                "latency_seconds_sum 5.55\n",
+                "latency_seconds_count 3\n",
+                "# HELP requests_total Handled\\nrequests\n",
+                "# TYPE requests_total counter\n",
+                "requests_total{action=\"auth\"} 3\n",
+                "requests_total{action=\"say \\\"hi\\\"\"} 1\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_histogram_labels() {
+        let registry = Registry::new();
+        let family = registry
+            .histogram_family("size_bytes", "Size", &["dir"], &[10.0])
+            .unwrap();
+        family.with(&["in"]).unwrap().observe(1.0);
+        assert_eq!(
+            registry.render(),
+            concat!(
+                "# HELP size_bytes Size\n",
+                "# TYPE size_bytes histogram\n",
+                "size_bytes_bucket{dir=\"in\",le=\"10\"} 1\n",
+                "size_bytes_bucket{dir=\"in\",le=\"+Inf\"} 1\n",
+                "size_bytes_sum{dir=\"in\"} 1\n",
+                "size_bytes_count{dir=\"in\"} 1\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_registration_errors() {
+        let registry = Registry::new();
+        assert!(registry.counter("1st", "").is_err());
+        assert!(registry.counter_family("ok", "", &["a:b"]).is_err());
+        assert!(registry
+            .histogram_family("h", "", &["le"], DEFAULT_BUCKETS)
+            .is_err());
+
+        let family = registry.counter_family("requests", "", &["a"]).unwrap();
+        assert!(family.with(&[]).is_err());
+        assert!(family.with(&["x", "y"]).is_err());
+        assert!(registry.gauge_family("requests", "", &["a"]).is_err());
+        assert!(registry.counter_family("requests", "", &["b"]).is_err());
+        assert!(registry.counter_family("requests", "", &["a"]).is_ok());
+
+        registry.histogram("latency", "", &[0.1, 1.0]).unwrap();
+        assert!(registry.histogram("latency", "", &[1.0, 0.1]).is_ok());
+        assert!(registry.histogram("latency", "", &[0.5]).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! Media type (`Content-Type`) parsing and matching.
+//!
+//! [`Mime`] parses a media type with its parameters, [`MimeMatcher`] is an allowlist of
+//! media type patterns:
+//!
+//! ```ignore
+//! let matcher = MimeMatcher::new().allow("text/*").allow("application/json");
+//! if let Some(mime) = msg.content_type()? {
+//!     if matcher.matches(&mime) && !mime.is_multipart() { /* process the body */ }
+//! }
+//! ```
+
+use std::fmt;
+
+use mlua::Result;
+
+use crate::{Headers, HttpMessage};
+
+/// A parsed media type, eg. `text/html; charset=utf-8`.
+///
+/// Type, subtype and parameter names are lowercased.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mime {
+    pub type_: String,
+    pub subtype: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl Mime {
+    /// Parses a media type. Returns `None` if the value has no `type/subtype`.
+    ///
+    /// Malformed parameters are skipped, quoted parameter values are unquoted.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (essence, params) = split_params(value);
+        let (type_, subtype) = essence.split_once('/')?;
+        let (type_, subtype) = (type_.trim(), subtype.trim());
+        if type_.is_empty() || subtype.is_empty() {
+            return None;
+        }
+        Some(Mime {
+            type_: type_.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            params,
+        })
+    }
+
+    /// Returns the media type without parameters (eg. `text/html`).
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.type_, self.subtype)
+    }
+
+    /// Returns the value of the parameter `name` (case-insensitive).
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the `charset` parameter.
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+
+    /// Returns the `boundary` parameter (used by multipart types).
+    pub fn boundary(&self) -> Option<&str> {
+        self.param("boundary")
+    }
+
+    /// Returns true for `multipart/*` types.
+    pub fn is_multipart(&self) -> bool {
+        self.type_ == "multipart"
+    }
+
+    /// Returns true if the media type matches the `pattern`.
+    ///
+    /// See [`MimeMatcher::allow`] for the pattern syntax.
+    pub fn matches(&self, pattern: &str) -> bool {
+        Pattern::parse(pattern).matches(self)
+    }
+}
+
+impl fmt::Display for Mime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.type_, self.subtype)?;
+        for (name, value) in &self.params {
+            let quote = value.is_empty() || value.contains([' ', ';', ',', '"', '=', '\\']);
+            match quote {
+                true => {
+                    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+                    write!(f, "; {name}=\"{value}\"")?
+                }
+                false => write!(f, "; {name}={value}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+    Any,
+    Type(String),
+    Exact(String, String),
+    Suffix(String),
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Self {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        match pattern.split_once('/') {
+            _ if pattern == "*" => Pattern::Any,
+            Some(("*", "*")) => Pattern::Any,
+            Some(("*", subtype)) if subtype.starts_with("*+") => {
+                Pattern::Suffix(subtype[1..].to_string())
+            }
+            Some((type_, "*" | "")) => Pattern::Type(type_.to_string()),
+            Some((type_, subtype)) => Pattern::Exact(type_.to_string(), subtype.to_string()),
+            None => Pattern::Type(pattern),
+        }
+    }
+
+    fn matches(&self, mime: &Mime) -> bool {
+        match self {
+            Pattern::Any => true,
+            Pattern::Type(type_) => mime.type_ == *type_,
+            Pattern::Exact(type_, subtype) => mime.type_ == *type_ && mime.subtype == *subtype,
+            Pattern::Suffix(suffix) => mime.subtype.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+/// An allowlist of media type patterns.
+#[derive(Debug, Clone, Default)]
+pub struct MimeMatcher(Vec<Pattern>);
+
+impl MimeMatcher {
+    /// Creates an empty matcher.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a comma-separated list of patterns.
+    pub fn from_list(list: &str) -> Self {
+        let mut matcher = Self::new();
+        for pattern in list.split(',').filter(|p| !p.trim().is_empty()) {
+            matcher = matcher.allow(pattern);
+        }
+        matcher
+    }
+
+    /// Adds a pattern to the allowlist.
+    ///
+    /// Supported patterns are `type/subtype`, `type/*` (or `type/`, `type`),
+    /// structured syntax suffixes `*/*+json` and `*/*`. Parameters are not compared.
+    pub fn allow(mut self, pattern: &str) -> Self {
+        self.0.push(Pattern::parse(pattern));
+        self
+    }
+
+    /// Returns true if no patterns were added.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns true if the media type matches any pattern.
+    pub fn matches(&self, mime: &Mime) -> bool {
+        self.0.iter().any(|p| p.matches(mime))
+    }
+}
+
+// Splits a header value into its first item and the `;`-separated parameters, ignoring
+// separators inside quoted strings. Parameter names are lowercased, values are unquoted.
+pub(crate) fn split_params(value: &str) -> (&str, Vec<(String, String)>) {
+    let mut items = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                items.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&value[start..]);
+    let params = (items[1..].iter())
+        .filter_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            Some((name.trim().to_ascii_lowercase(), unquote(value.trim())))
+        })
+        .collect();
+    (items[0].trim(), params)
+}
+
+fn unquote(value: &str) -> String {
+    let Some(value) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+        return value.to_string();
+    };
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+impl<'lua> Headers<'lua> {
+    /// Returns the parsed `Content-Type` header.
+    pub fn content_type(&self) -> Result<Option<Mime>> {
+        let value = self.get_first::<String>("content-type")?;
+        Ok(value.as_deref().and_then(Mime::parse))
+    }
+}
+
+impl<'lua> HttpMessage<'lua> {
+    /// Returns the parsed `Content-Type` header of the HTTP message.
+    pub fn content_type(&self) -> Result<Option<Mime>> {
+        self.get_headers()?.content_type()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mime = Mime::parse(" Text/HTML ; Charset=\"utf-8\"; q").unwrap();
+        assert_eq!(mime.essence(), "text/html");
+        assert_eq!(mime.charset(), Some("utf-8"));
+        assert_eq!(mime.params.len(), 1);
+
+        let mime = Mime::parse(r#"multipart/form-data; boundary="a;b=c\"d""#).unwrap();
+        assert!(mime.is_multipart());
+        assert_eq!(mime.boundary(), Some(r#"a;b=c"d"#));
+
+        assert_eq!(Mime::parse("text"), None);
+        assert_eq!(Mime::parse("/html"), None);
+        assert_eq!(Mime::parse("text/ ; charset=utf-8"), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let value = r#"multipart/mixed; boundary="a b"; x="q\"\\"; charset=utf-8"#;
+        let mime = Mime::parse(value).unwrap();
+        assert_eq!(mime.to_string(), value);
+        assert_eq!(Mime::parse(&mime.to_string()), Some(mime));
+    }
+
+    #[test]
+    fn test_matcher() {
+        let json = Mime::parse("application/problem+json").unwrap();
+        let html = Mime::parse("text/html").unwrap();
+        assert!(json.matches("*/*+json"));
+        assert!(json.matches("application/*"));
+        assert!(json.matches("Application"));
+        assert!(!json.matches("application/json"));
+        assert!(html.matches("*"));
+
+        let matcher = MimeMatcher::from_list("text/, application/json,,");
+        assert!(!matcher.is_empty());
+        assert!(matcher.matches(&html));
+        assert!(!matcher.matches(&json));
+        assert!(MimeMatcher::new().allow("*/*+json").matches(&json));
+        assert!(!MimeMatcher::new().matches(&html));
+    }
+}
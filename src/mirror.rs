@@ -0,0 +1,424 @@
+//! Request mirroring (shadow traffic).
+//!
+//! Duplicates a share of requests and replays them in background to a shadow backend
+//! using the async runtime. Responses from the shadow backend are discarded.
+//!
+//! Mirroring can be used as an action (headers only) or as a filter (headers and buffered body):
+//!
+//! ```text
+//! filter lua.mirror addr:10.0.0.5:8080 percent:10 body max-body:65536 tag:x-mirrored kill:/etc/haproxy/mirror.map,enabled
+//! ```
+//!
+//! The number of mirrored requests in flight is limited (see [`MirrorConfig::max_in_flight`]),
+//! requests above the limit are dropped and counted in [`Mirror::dropped`].
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use mlua::{AnyUserData, Lua, Result, String as LuaString, Table, UserData};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
+
+use crate::protocol::origin_form;
+use crate::{
+    Action, Core, FilterMethod, FilterResult, Headers, HttpMessage, LogLevel, Txn, UserFilter,
+};
+
+// Headers that are not forwarded to the shadow backend
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "content-length",
+    "te",
+    "trailer",
+    "upgrade",
+];
+
+/// Mirroring configuration.
+#[derive(Debug, Clone)]
+pub struct MirrorConfig {
+    addr: String,
+    host: Option<String>,
+    percent: f64,
+    body: bool,
+    max_body: usize,
+    tag: Option<(String, String)>,
+    kill_switch: Option<(String, String)>,
+    timeout: Duration,
+    max_in_flight: usize,
+}
+
+impl MirrorConfig {
+    /// Creates a new configuration mirroring all requests to the `addr` (`host:port`).
+    pub fn new(addr: impl Into<String>) -> Self {
+        MirrorConfig {
+            addr: addr.into(),
+            host: None,
+            percent: 100.0,
+            body: false,
+            max_body: 64 * 1024,
+            tag: None,
+            kill_switch: None,
+            timeout: Duration::from_secs(5),
+            max_in_flight: 100,
+        }
+    }
+
+    /// Overrides the `host` header sent to the shadow backend.
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Sets the percentage (0-100) of requests to mirror.
+    pub fn percent(mut self, percent: f64) -> Self {
+        self.percent = percent.clamp(0.0, 100.0);
+        self
+    }
+
+    /// Enables mirroring of request bodies up to `max_body` bytes.
+    ///
+    /// Requests with larger bodies are not mirrored. Works only in the filter mode.
+    pub fn body(mut self, max_body: usize) -> Self {
+        self.body = true;
+        self.max_body = max_body;
+        self
+    }
+
+    /// Adds the header `name` with `value` to the mirrored requests.
+    pub fn tag(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tag = Some((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the map entry that acts as a kill switch.
+    ///
+    /// Mirroring is disabled when the `key` in the map `filename` is set to `0`, `off` or `false`.
+    pub fn kill_switch(mut self, filename: impl Into<String>, key: impl Into<String>) -> Self {
+        self.kill_switch = Some((filename.into(), key.into()));
+        self
+    }
+
+    /// Sets the timeout for the whole mirrored request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of mirrored requests in flight (100 by default).
+    ///
+    /// Requests above the limit are dropped.
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = max.min(Semaphore::MAX_PERMITS);
+        self
+    }
+
+    /// Parses the configuration from the filter arguments.
+    ///
+    /// Supported arguments: `addr:<host:port>`, `host:<name>`, `percent:<0-100>`, `body`,
+    /// `max-body:<bytes>`, `tag:<name>[=<value>]`, `kill:<map file>,<key>`, `timeout:<ms>`,
+    /// `max-in-flight:<number>`.
+    pub fn from_args(args: &Table) -> Result<Self> {
+        let mut config = MirrorConfig::new("");
+        for arg in args.clone().sequence_values::<String>() {
+            let arg = arg?;
+            let (key, value) = arg.split_once(':').unwrap_or((&arg, ""));
+            match key {
+                "addr" => config.addr = value.to_string(),
+                "host" => config.host = Some(value.to_string()),
+                "percent" => config = config.percent(value.parse().unwrap_or(100.0)),
+                "body" => config.body = true,
+                "max-body" => config.max_body = value.parse().unwrap_or(config.max_body),
+                "tag" => {
+                    let (name, value) = value.split_once('=').unwrap_or((value, "1"));
+                    config.tag = Some((name.to_string(), value.to_string()));
+                }
+                "kill" => {
+                    if let Some((filename, key)) = value.split_once(',') {
+                        config.kill_switch = Some((filename.to_string(), key.to_string()));
+                    }
+                }
+                "timeout" => {
+                    if let Ok(ms) = value.parse() {
+                        config.timeout = Duration::from_millis(ms);
+                    }
+                }
+                "max-in-flight" => {
+                    if let Ok(max) = value.parse() {
+                        config = config.max_in_flight(max);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if config.addr.is_empty() {
+            return Err(mlua::Error::runtime("mirror: `addr` is not set"));
+        }
+        Ok(config)
+    }
+}
+
+/// A shared handle to the mirroring configuration.
+#[derive(Debug, Clone)]
+pub struct Mirror(Arc<MirrorInner>);
+
+#[derive(Debug)]
+struct MirrorInner {
+    config: MirrorConfig,
+    in_flight: Arc<Semaphore>,
+    dropped: AtomicU64,
+}
+
+impl UserData for Mirror {}
+
+impl Mirror {
+    /// Creates a new mirror using the `config`.
+    pub fn new(config: MirrorConfig) -> Self {
+        let in_flight = Arc::new(Semaphore::new(config.max_in_flight));
+        Mirror(Arc::new(MirrorInner {
+            config,
+            in_flight,
+            dropped: AtomicU64::new(0),
+        }))
+    }
+
+    /// Returns the mirroring configuration.
+    pub fn config(&self) -> &MirrorConfig {
+        &self.0.config
+    }
+
+    /// Returns the number of requests dropped because too many mirrored requests were in flight.
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Registers an `http-req` action that mirrors request headers (without body).
+    pub fn register_action(&self, core: &Core, name: &str) -> Result<()> {
+        let mirror = self.clone();
+        core.register_action(name, &[Action::HttpReq], 0, move |_, txn: Txn| {
+            if !mirror.should_mirror(&txn)? {
+                return Ok(());
+            }
+            let headers = txn.http()?.req_get_headers()?;
+            let request = MirroredRequest {
+                method: txn.f.get_str("method", ())?,
+                // Origin-form, as the request is replayed over HTTP/1.1
+                uri: txn.f.get_str("pathq", ())?,
+                headers: collect_headers(headers, txn.authority()?)?,
+                body: Vec::new(),
+            };
+            mirror.send(request);
+            Ok(())
+        })
+    }
+
+    /// Returns true if the current request should be mirrored (sampling and kill switch).
+    pub fn should_mirror(&self, txn: &Txn) -> Result<bool> {
+        let config = &self.0.config;
+        if config.percent <= 0.0 {
+            return Ok(false);
+        }
+        if config.percent < 100.0 {
+            let sample: u32 = txn.f.get("rand", 10000)?;
+            if sample as f64 >= config.percent * 100.0 {
+                return Ok(false);
+            }
+        }
+        if let Some((filename, key)) = &config.kill_switch {
+            let value: Option<String> = txn.c.get("map", (key.as_str(), filename.as_str()))?;
+            if let Some("0" | "off" | "false") = value.as_deref().map(str::trim) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Replays the request in background, discarding the response
+    fn send(&self, request: MirroredRequest) {
+        let Some(permit) = self.try_acquire() else {
+            return;
+        };
+        let inner = self.0.clone();
+        let payload = request.encode(&inner.config);
+        crate::runtime().spawn(async move {
+            let config = &inner.config;
+            let fut = async {
+                let mut stream = TcpStream::connect(&config.addr).await?;
+                stream.write_all(&payload).await?;
+                let mut buf = [0; 4096];
+                while stream.read(&mut buf).await? > 0 {}
+                std::io::Result::Ok(())
+            };
+            let _ = timeout(config.timeout, fut).await;
+            drop(permit);
+        });
+    }
+
+    // Reserves a slot for a mirrored request, counting the request as dropped if there are none
+    fn try_acquire(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = self.0.in_flight.clone().try_acquire_owned().ok();
+        if permit.is_none() {
+            self.0.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        permit
+    }
+}
+
+struct MirroredRequest {
+    method: String,
+    uri: String,
+    headers: Vec<(String, Vec<u8>)>,
+    body: Vec<u8>,
+}
+
+impl MirroredRequest {
+    fn encode(&self, config: &MirrorConfig) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(512 + self.body.len());
+        buf.extend_from_slice(format!("{} {} HTTP/1.1\r\n", self.method, self.uri).as_bytes());
+        for (name, value) in &self.headers {
+            if config.host.is_some() && name == "host" {
+                continue;
+            }
+            buf.extend_from_slice(name.as_bytes());
+            buf.extend_from_slice(b": ");
+            buf.extend_from_slice(value);
+            buf.extend_from_slice(b"\r\n");
+        }
+        if let Some(host) = &config.host {
+            buf.extend_from_slice(format!("host: {host}\r\n").as_bytes());
+        }
+        if let Some((name, value)) = &config.tag {
+            buf.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+        }
+        if !self.body.is_empty() {
+            buf.extend_from_slice(format!("content-length: {}\r\n", self.body.len()).as_bytes());
+        }
+        buf.extend_from_slice(b"connection: close\r\n\r\n");
+        buf.extend_from_slice(&self.body);
+        buf
+    }
+}
+
+// Collects the request headers to replay, skipping the hop-by-hop ones (including those listed
+// in `connection`) and adding `host` from the `authority` if it's missing (HTTP/2 and HTTP/3)
+fn collect_headers(headers: Headers, authority: Option<String>) -> Result<Vec<(String, Vec<u8>)>> {
+    let connection = headers.get::<String>("connection")?;
+    let is_hop_by_hop = |name: &str| {
+        HOP_BY_HOP_HEADERS.contains(&name)
+            || (connection.iter().flat_map(|v| v.split(',')))
+                .any(|token| token.trim().eq_ignore_ascii_case(name))
+    };
+    let mut result = Vec::new();
+    for kv in headers.pairs::<LuaString>() {
+        let (name, values) = kv?;
+        if name.starts_with(':') || is_hop_by_hop(&name) {
+            continue;
+        }
+        for value in values {
+            result.push((name.clone(), value.as_bytes().to_vec()));
+        }
+    }
+    if !result.iter().any(|(name, _)| name == "host") {
+        if let Some(authority) = authority {
+            result.push(("host".to_string(), authority.into_bytes()));
+        }
+    }
+    Ok(result)
+}
+
+/// A filter that mirrors requests including (optionally) their bodies.
+///
+/// The configuration is parsed from the filter arguments, see [`MirrorConfig::from_args`].
+pub struct MirrorFilter {
+    mirror: Mirror,
+    request: Option<MirroredRequest>,
+}
+
+impl UserFilter for MirrorFilter {
+    const METHODS: u8 = FilterMethod::HTTP_HEADERS | FilterMethod::HTTP_PAYLOAD;
+
+    fn new(_: &Lua, args: Table) -> Result<Self> {
+        // Fetch ready parsed configuration
+        let mirror = match args.raw_get::<_, Option<AnyUserData>>(0)? {
+            Some(ud) => ud.borrow::<Mirror>()?.clone(),
+            None => {
+                let mirror = Mirror::new(MirrorConfig::from_args(&args)?);
+                args.raw_set(0, mirror.clone())?;
+                mirror
+            }
+        };
+        Ok(MirrorFilter {
+            mirror,
+            request: None,
+        })
+    }
+
+    fn http_headers(&mut self, lua: &Lua, txn: Txn, msg: HttpMessage) -> Result<FilterResult> {
+        if msg.is_resp()? || !self.mirror.should_mirror(&txn)? {
+            return Ok(FilterResult::Continue);
+        }
+
+        let stline = msg.get_stline()?;
+        let uri = stline.uri().unwrap_or_default();
+        let request = MirroredRequest {
+            method: stline.method().unwrap_or_default().to_string(),
+            // HTTP/2 and HTTP/3 requests have absolute-form URIs
+            uri: origin_form(uri).into_owned(),
+            headers: collect_headers(msg.get_headers()?, msg.authority()?)?,
+            body: Vec::new(),
+        };
+
+        if self.mirror.0.config.body && !msg.eom()? {
+            self.request = Some(request);
+            Self::register_data_filter(lua, txn, msg.channel()?)?;
+        } else {
+            self.mirror.send(request);
+        }
+        Ok(FilterResult::Continue)
+    }
+
+    fn http_payload(&mut self, lua: &Lua, _: Txn, msg: HttpMessage) -> Result<Option<usize>> {
+        let Some(request) = self.request.as_mut() else {
+            return Ok(None);
+        };
+        if let Some(chunk) = msg.body(None, Some(-1))? {
+            request.body.extend_from_slice(chunk.as_bytes());
+        }
+        if request.body.len() > self.mirror.0.config.max_body {
+            // Body is too large, skip mirroring
+            self.request = None;
+            if let Ok(core) = Core::new(lua) {
+                let _ = core.log(LogLevel::Debug, "mirror: request body is too large");
+            }
+        } else if msg.eom()? {
+            let request = self.request.take().unwrap();
+            self.mirror.send(request);
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_in_flight() {
+        let mirror = Mirror::new(MirrorConfig::new("127.0.0.1:1").max_in_flight(2));
+        let first = mirror.try_acquire();
+        let second = mirror.try_acquire();
+        assert!(first.is_some() && second.is_some());
+        assert!(mirror.try_acquire().is_none());
+        assert!(mirror.try_acquire().is_none());
+        assert_eq!(mirror.dropped(), 2);
+
+        drop(first);
+        assert!(mirror.try_acquire().is_some());
+        assert_eq!(mirror.dropped(), 2);
+    }
+}
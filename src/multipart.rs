@@ -0,0 +1,346 @@
+//! Incremental `multipart/*` body scanner.
+//!
+//! [`MultipartScanner`] is fed with body chunks (as they arrive in `http_payload`) and reports
+//! part boundaries, part headers and part data without buffering whole parts. Boundaries split
+//! across chunks are handled transparently.
+//!
+//! ```ignore
+//! let mime = msg.content_type()?.filter(|m| m.is_multipart());
+//! let mut scanner = mime.and_then(|m| MultipartScanner::from_mime(&m)).unwrap();
+//! msg.body_with(None, Some(-1), |data| {
+//!     scanner.feed(data, |event| {
+//!         if let Event::PartStart(part) = event {
+//!             if part.filename.as_deref().is_some_and(|f| f.ends_with(".exe")) {
+//!                 return Err(Error::runtime("executable uploads are not allowed"));
+//!             }
+//!         }
+//!         Ok(())
+//!     })
+//! })??;
+//! ```
+
+use mlua::{Error, Result};
+
+use crate::mime::{split_params, Mime};
+
+const DEFAULT_MAX_HEADERS_SIZE: usize = 8 * 1024;
+
+/// Metadata of a multipart part.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartInfo {
+    /// The `name` parameter of `Content-Disposition`.
+    pub name: Option<String>,
+    /// The `filename` parameter of `Content-Disposition`.
+    pub filename: Option<String>,
+    /// The `Content-Type` of the part.
+    pub content_type: Option<String>,
+    /// All part headers (names are lowercased).
+    pub headers: Vec<(String, String)>,
+}
+
+/// An event reported by the [`MultipartScanner`].
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A new part begins.
+    PartStart(PartInfo),
+    /// A chunk of the current part body.
+    Data(&'a [u8]),
+    /// The current part ends.
+    PartEnd,
+    /// The closing boundary was found. Any data after it are ignored.
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Preamble,
+    AfterBoundary,
+    Headers,
+    Body,
+    Epilogue,
+}
+
+/// Incremental scanner of a `multipart/*` body.
+#[derive(Debug)]
+pub struct MultipartScanner {
+    // The delimiter is `\r\n--boundary`
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    state: State,
+    max_headers_size: usize,
+}
+
+impl MultipartScanner {
+    /// Creates a new scanner for the `boundary`.
+    pub fn new(boundary: &str) -> Self {
+        MultipartScanner {
+            delimiter: [b"\r\n--", boundary.as_bytes()].concat(),
+            // The first boundary may be at the very beginning of the body
+            buf: b"\r\n".to_vec(),
+            state: State::Preamble,
+            max_headers_size: DEFAULT_MAX_HEADERS_SIZE,
+        }
+    }
+
+    /// Creates a new scanner using the `boundary` parameter of a multipart media type.
+    pub fn from_mime(mime: &Mime) -> Option<Self> {
+        mime.boundary()
+            .filter(|b| mime.is_multipart() && !b.is_empty())
+            .map(Self::new)
+    }
+
+    /// Sets the maximum size of part headers (8 KiB by default).
+    pub fn with_max_headers_size(mut self, size: usize) -> Self {
+        self.max_headers_size = size;
+        self
+    }
+
+    /// Returns true if the closing boundary was found.
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Epilogue
+    }
+
+    /// Feeds a chunk of the body to the scanner, calling `on_event` for every event found.
+    ///
+    /// Errors returned by `on_event` stop the scanning and are propagated.
+    pub fn feed<F>(&mut self, data: &[u8], mut on_event: F) -> Result<()>
+    where
+        F: FnMut(Event) -> Result<()>,
+    {
+        if self.state == State::Epilogue {
+            return Ok(());
+        }
+        self.buf.extend_from_slice(data);
+        let mut pos = 0;
+        let result = loop {
+            let data = &self.buf[pos..];
+            match self.state {
+                State::Preamble => match find(data, &self.delimiter) {
+                    Some(i) => {
+                        pos += i + self.delimiter.len();
+                        self.state = State::AfterBoundary;
+                    }
+                    None => {
+                        pos += data.len().saturating_sub(self.delimiter.len() - 1);
+                        break Ok(());
+                    }
+                },
+                State::AfterBoundary => {
+                    if data.starts_with(b"--") {
+                        self.state = State::Epilogue;
+                        pos = self.buf.len();
+                        break on_event(Event::End);
+                    }
+                    // Transport padding is allowed before the line break
+                    let Some(eol) = find(data, b"\r\n") else {
+                        if data.len() > self.max_headers_size {
+                            break Err(Error::runtime("multipart boundary line is too long"));
+                        }
+                        break Ok(());
+                    };
+                    if !data[..eol].iter().all(|&b| b == b' ' || b == b'\t') {
+                        break Err(Error::runtime("invalid multipart boundary"));
+                    }
+                    pos += eol + 2;
+                    self.state = State::Headers;
+                }
+                State::Headers => {
+                    // Part without headers
+                    if data.starts_with(b"\r\n") {
+                        pos += 2;
+                        self.state = State::Body;
+                        if let Err(err) = on_event(Event::PartStart(PartInfo::default())) {
+                            break Err(err);
+                        }
+                        continue;
+                    }
+                    let Some(end) = find(data, b"\r\n\r\n") else {
+                        if data.len() > self.max_headers_size {
+                            break Err(Error::runtime("multipart part headers are too large"));
+                        }
+                        break Ok(());
+                    };
+                    let part = parse_part_headers(&data[..end]);
+                    pos += end + 4;
+                    self.state = State::Body;
+                    if let Err(err) = on_event(Event::PartStart(part)) {
+                        break Err(err);
+                    }
+                }
+                State::Body => match find(data, &self.delimiter) {
+                    Some(i) => {
+                        if i > 0 {
+                            if let Err(err) = on_event(Event::Data(&data[..i])) {
+                                break Err(err);
+                            }
+                        }
+                        pos += i + self.delimiter.len();
+                        self.state = State::AfterBoundary;
+                        if let Err(err) = on_event(Event::PartEnd) {
+                            break Err(err);
+                        }
+                    }
+                    None => {
+                        // Keep a tail that can be the beginning of the delimiter
+                        let len = data.len().saturating_sub(self.delimiter.len() - 1);
+                        pos += len;
+                        if len > 0 {
+                            break on_event(Event::Data(&data[..len]));
+                        }
+                        break Ok(());
+                    }
+                },
+                State::Epilogue => break Ok(()),
+            }
+        };
+        self.buf.drain(..pos.min(self.buf.len()));
+        result
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn parse_part_headers(data: &[u8]) -> PartInfo {
+    let mut part = PartInfo::default();
+    for line in String::from_utf8_lossy(data).split("\r\n") {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+        match name.as_str() {
+            "content-disposition" => {
+                for (key, val) in split_params(value).1 {
+                    match key.as_str() {
+                        "name" => part.name = Some(val),
+                        "filename" => part.filename = Some(val),
+                        _ => {}
+                    }
+                }
+            }
+            "content-type" => part.content_type = Some(value.to_string()),
+            _ => {}
+        }
+        part.headers.push((name, value.to_string()));
+    }
+    part
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = b"preamble\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"field\"\r\n\r\n\
+        value\r\n--XyZ  \r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a;b.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        line1\r\n-- XyZ\r\n\
+        \r\n--XyZ\r\n\
+        \r\nno headers\r\n--XyZ--\r\nepilogue";
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Part {
+        info: PartInfo,
+        data: Vec<u8>,
+        ended: bool,
+    }
+
+    fn scan(chunks: &[&[u8]]) -> (Vec<Part>, bool) {
+        let mut scanner = MultipartScanner::new("XyZ");
+        let (mut parts, mut end) = (Vec::<Part>::new(), false);
+        for chunk in chunks {
+            (scanner.feed(chunk, |event| {
+                match event {
+                    Event::PartStart(info) => parts.push(Part {
+                        info,
+                        ..Part::default()
+                    }),
+                    Event::Data(data) => parts.last_mut().unwrap().data.extend_from_slice(data),
+                    Event::PartEnd => parts.last_mut().unwrap().ended = true,
+                    Event::End => end = true,
+                }
+                Ok(())
+            }))
+            .unwrap();
+        }
+        assert_eq!(end, scanner.is_finished());
+        (parts, end)
+    }
+
+    fn check(parts: &[Part]) {
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].info.name.as_deref(), Some("field"));
+        assert_eq!(parts[0].info.filename, None);
+        assert_eq!(parts[0].data, b"value");
+        assert_eq!(parts[1].info.name.as_deref(), Some("file"));
+        assert_eq!(parts[1].info.filename.as_deref(), Some("a;b.txt"));
+        assert_eq!(parts[1].info.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(parts[1].info.headers.len(), 2);
+        assert_eq!(parts[1].data, b"line1\r\n-- XyZ\r\n");
+        assert_eq!(parts[2].info, PartInfo::default());
+        assert_eq!(parts[2].data, b"no headers");
+        assert!(parts.iter().all(|p| p.ended));
+    }
+
+    #[test]
+    fn test_single_chunk() {
+        let (parts, end) = scan(&[BODY]);
+        assert!(end);
+        check(&parts);
+    }
+
+    #[test]
+    fn test_split_chunks() {
+        // Every possible split point, including inside of the boundaries
+        for i in 0..BODY.len() {
+            let (parts, end) = scan(&[&BODY[..i], &BODY[i..]]);
+            assert!(end, "split at {i}");
+            check(&parts);
+        }
+        // Byte by byte
+        let chunks = BODY.chunks(1).collect::<Vec<_>>();
+        let (parts, end) = scan(&chunks);
+        assert!(end);
+        check(&parts);
+    }
+
+    #[test]
+    fn test_boundary_at_start() {
+        let (parts, end) = scan(&[b"--XyZ\r\n\r\ndata\r\n--XyZ--"]);
+        assert!(end);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].data, b"data");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut scanner = MultipartScanner::new("XyZ");
+        assert!(scanner.feed(b"--XyZjunk\r\n", |_| Ok(())).is_err());
+
+        let mut scanner = MultipartScanner::new("XyZ").with_max_headers_size(16);
+        let data = b"--XyZ\r\nX-Long-Header: 0123456789";
+        assert!(scanner.feed(data, |_| Ok(())).is_err());
+
+        let mut scanner = MultipartScanner::new("XyZ");
+        let result = scanner.feed(BODY, |event| match event {
+            Event::Data(_) => Err(Error::runtime("stop")),
+            _ => Ok(()),
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_from_mime() {
+        let mime = Mime::parse("multipart/form-data; boundary=\"a;b\"").unwrap();
+        assert_eq!(
+            MultipartScanner::from_mime(&mime).unwrap().delimiter,
+            b"\r\n--a;b"
+        );
+        let mime = Mime::parse("text/plain; boundary=x").unwrap();
+        assert!(MultipartScanner::from_mime(&mime).is_none());
+        let mime = Mime::parse("multipart/mixed").unwrap();
+        assert!(MultipartScanner::from_mime(&mime).is_none());
+    }
+}
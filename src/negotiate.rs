@@ -0,0 +1,155 @@
+//! Content negotiation utilities.
+//!
+//! Parses `Accept`, `Accept-Encoding` and `Accept-Language` request headers (with q-values and
+//! wildcards) and picks the best match from a list of candidates supported by the caller.
+//! Candidates are listed in order of the server preference, which is used to break ties.
+//!
+//! ```ignore
+//! let accept_encoding = msg.get_headers()?.get::<String>("accept-encoding")?;
+//! match negotiate::best_encoding(&accept_encoding, &["br", "gzip"]) {
+//!     Some("br") => { /* compress with brotli */ }
+//!     ...
+//! }
+//! ```
+
+/// A header item with its quality value (eg. `gzip;q=0.8`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityItem<'a> {
+    /// The item value without parameters, eg. `gzip` or `text/html`.
+    pub value: &'a str,
+    /// The quality value in the range `0.0..=1.0`.
+    pub q: f32,
+}
+
+/// Parses comma-separated header values into items with their quality values.
+///
+/// Items with invalid q-values are skipped. The order of items is preserved.
+pub fn parse<S: AsRef<str>>(values: &[S]) -> Vec<QualityItem<'_>> {
+    values
+        .iter()
+        .flat_map(|v| v.as_ref().split(','))
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let value = params.next()?.trim();
+            if value.is_empty() {
+                return None;
+            }
+            let mut q = 1.0;
+            for param in params {
+                if let Some((key, val)) = param.split_once('=') {
+                    if key.trim().eq_ignore_ascii_case("q") {
+                        q = match val.trim().parse::<f32>() {
+                            Ok(q) if (0.0..=1.0).contains(&q) => q,
+                            _ => return None, // q-values over 1 are unacceptable
+                        };
+                    }
+                }
+            }
+            Some(QualityItem { value, q })
+        })
+        .collect()
+}
+
+// Picks the first candidate with the highest (non-zero) q-value returned by `quality`
+fn best<'c, F>(candidates: &[&'c str], mut quality: F) -> Option<&'c str>
+where
+    F: FnMut(&str) -> Option<f32>,
+{
+    let mut result: Option<(&str, f32)> = None;
+    for &candidate in candidates {
+        match quality(candidate) {
+            Some(q) if q > 0.0 && result.is_none_or(|(_, best)| q > best) => {
+                result = Some((candidate, q))
+            }
+            _ => {}
+        }
+    }
+    result.map(|(c, _)| c)
+}
+
+// Returns q-value of the most specific matching item
+fn match_quality(
+    items: &[QualityItem],
+    mut specificity: impl FnMut(&str) -> Option<u8>,
+) -> Option<f32> {
+    items
+        .iter()
+        .filter_map(|item| Some((specificity(item.value)?, item.q)))
+        .max_by_key(|(spec, _)| *spec)
+        .map(|(_, q)| q)
+}
+
+/// Returns the best content coding from `candidates` acceptable according to
+/// the `Accept-Encoding` header values.
+///
+/// `identity` is acceptable unless excluded explicitly (or by `*;q=0`).
+pub fn best_encoding<'c, S: AsRef<str>>(values: &[S], candidates: &[&'c str]) -> Option<&'c str> {
+    let items = parse(values);
+    best(candidates, |candidate| {
+        let q = match_quality(&items, |v| {
+            if v.eq_ignore_ascii_case(candidate) {
+                Some(1)
+            } else if v == "*" {
+                Some(0)
+            } else {
+                None
+            }
+        });
+        match q {
+            None if candidate.eq_ignore_ascii_case("identity") => Some(0.001),
+            q => q,
+        }
+    })
+}
+
+/// Returns the best media type from `candidates` acceptable according to
+/// the `Accept` header values. Supports `type/*` and `*/*` wildcards.
+///
+/// If the header is missing (`values` is empty), any media type is acceptable.
+pub fn best_media_type<'c, S: AsRef<str>>(values: &[S], candidates: &[&'c str]) -> Option<&'c str> {
+    if values.is_empty() {
+        return candidates.first().copied();
+    }
+    let items = parse(values);
+    best(candidates, |candidate| {
+        let (ctype, csub) = candidate.split_once('/')?;
+        match_quality(&items, |v| {
+            let (vtype, vsub) = v.split_once('/')?;
+            if vtype == "*" && vsub == "*" {
+                Some(0)
+            } else if vtype.eq_ignore_ascii_case(ctype) && vsub == "*" {
+                Some(1)
+            } else if vtype.eq_ignore_ascii_case(ctype) && vsub.eq_ignore_ascii_case(csub) {
+                Some(2)
+            } else {
+                None
+            }
+        })
+    })
+}
+
+/// Returns the best language tag from `candidates` acceptable according to
+/// the `Accept-Language` header values.
+///
+/// A language range matches a tag if it equals the tag or its prefix followed by `-`
+/// (eg. `en` matches `en-US`), `*` matches any tag.
+/// If the header is missing (`values` is empty), the first candidate is returned.
+pub fn best_language<'c, S: AsRef<str>>(values: &[S], candidates: &[&'c str]) -> Option<&'c str> {
+    if values.is_empty() {
+        return candidates.first().copied();
+    }
+    let items = parse(values);
+    best(candidates, |candidate| {
+        match_quality(&items, |range| {
+            if range == "*" {
+                return Some(0);
+            }
+            let matched = candidate.len() >= range.len()
+                && candidate.is_char_boundary(range.len())
+                && candidate[..range.len()].eq_ignore_ascii_case(range)
+                && (candidate.len() == range.len() || candidate.as_bytes()[range.len()] == b'-');
+            // Longer ranges are more specific
+            matched.then(|| range.len().min(u8::MAX as usize) as u8)
+        })
+    })
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use mlua::{Lua, RegistryKey, Result};
+
+use crate::{Channel, Headers, Http, HttpMessage, Proxy, Reply, Server, StickTable, Txn};
+
+macro_rules! owned_handle {
+    ($ty:ident, $owned:ident) => {
+        #[doc = concat!("An owned handle to [`", stringify!($ty), "`] without the Lua lifetime.")]
+        ///
+        /// The handle keeps the underlying Lua object in the registry, so it can be stored in
+        /// struct fields or moved into async tasks, and resolved back with `get`.
+        /// Registry slots of dropped handles are reclaimed by [`Lua::expire_registry_values`].
+        #[derive(Debug, Clone)]
+        pub struct $owned(Arc<RegistryKey>);
+
+        impl $owned {
+            #[doc = concat!("Returns the [`", stringify!($ty), "`] bound to the Lua state.")]
+            ///
+            /// The Lua state must be the same the handle was created from.
+            #[inline]
+            pub fn get<'lua>(&self, lua: &'lua Lua) -> Result<$ty<'lua>> {
+                lua.registry_value(&self.0)
+            }
+        }
+
+        impl<'lua> $ty<'lua> {
+            /// Converts the object into an owned handle.
+            #[inline]
+            pub fn into_owned(self, lua: &'lua Lua) -> Result<$owned> {
+                let key = lua.create_registry_value((*self).clone())?;
+                Ok($owned(Arc::new(key)))
+            }
+        }
+    };
+}
+
+owned_handle!(Channel, ChannelOwned);
+owned_handle!(Headers, HeadersOwned);
+owned_handle!(Http, HttpOwned);
+owned_handle!(HttpMessage, HttpMessageOwned);
+owned_handle!(Proxy, ProxyOwned);
+owned_handle!(Reply, ReplyOwned);
+owned_handle!(Server, ServerOwned);
+owned_handle!(StickTable, StickTableOwned);
+owned_handle!(Txn, TxnOwned);
+
+macro_rules! handle_alias {
+    ($ty:ident, $owned:ident, $handle:ident) => {
+        #[doc = concat!("A registry-backed handle to [`", stringify!($ty), "`], an alias of [`", stringify!($owned), "`].")]
+        pub type $handle = $owned;
+
+        impl<'lua> $ty<'lua> {
+            /// Stores the object in the Lua registry, so it can be resolved back in a later
+            /// callback on the same Lua state (see [`Self::into_owned`]).
+            #[inline]
+            pub fn into_handle(self, lua: &'lua Lua) -> Result<$handle> {
+                self.into_owned(lua)
+            }
+        }
+    };
+}
+
+handle_alias!(Proxy, ProxyOwned, ProxyHandle);
+handle_alias!(Server, ServerOwned, ServerHandle);
+handle_alias!(StickTable, StickTableOwned, StickTableHandle);
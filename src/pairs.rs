@@ -0,0 +1,58 @@
+use std::marker::PhantomData;
+
+use mlua::{FromLua, Function, Lua, Result, Table, Value};
+
+/// A lazy iterator over the table entries, using the Lua `pairs` function.
+///
+/// Unlike [`Table::pairs`], it respects the `__pairs` metamethod, which HAProxy uses
+/// for the lists that are not materialized as plain tables (eg. proxy servers).
+pub(crate) struct Pairs<'lua, K, V> {
+    lua: &'lua Lua,
+    next: Function<'lua>,
+    state: Value<'lua>,
+    control: Value<'lua>,
+    done: bool,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<'lua, K, V> Pairs<'lua, K, V> {
+    pub(crate) fn new(lua: &'lua Lua, table: Table<'lua>) -> Result<Self> {
+        let pairs: Function = lua.globals().raw_get("pairs")?;
+        let (next, state, control) = pairs.call(table)?;
+        Ok(Pairs {
+            lua,
+            next,
+            state,
+            control,
+            done: false,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<'lua, K: FromLua<'lua>, V: FromLua<'lua>> Iterator for Pairs<'lua, K, V> {
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let args = (self.state.clone(), self.control.clone());
+        match self.next.call::<_, (Value, Value)>(args) {
+            Ok((Value::Nil, _)) => {
+                self.done = true;
+                None
+            }
+            Ok((key, value)) => {
+                self.control = key.clone();
+                let item = K::from_lua(key, self.lua)
+                    .and_then(|key| Ok((key, V::from_lua(value, self.lua)?)));
+                Some(item)
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::backtrace::Backtrace;
+use std::panic::{self, PanicHookInfo};
+use std::sync::{Once, OnceLock};
+use std::thread;
+
+use mlua::{Lua, Result};
+
+use crate::{deferred_log, LogLevel};
+
+static MODULE_NAME: OnceLock<String> = OnceLock::new();
+static INSTALL_HOOK: Once = Once::new();
+
+/// Installs a panic hook that logs panics through `core.log()` with the `Crit` level.
+///
+/// Panics from any thread (including Tokio workers running async functions) are captured
+/// together with a backtrace and prefixed by the `module` name. Messages are logged by a
+/// HAProxy task, so the function must be called during the module loading.
+///
+/// The hook is process-wide: the previously installed hook (by default, printing to stderr)
+/// is still called after queueing the message, so the panic is reported even if the process
+/// aborts before the message is logged.
+pub fn install_panic_hook(lua: &Lua, module: &str) -> Result<()> {
+    let _ = MODULE_NAME.set(module.to_string());
+    INSTALL_HOOK.call_once(|| {
+        let prev_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            panic_hook(info);
+            prev_hook(info);
+        }));
+    });
+    deferred_log::register_flush_task(lua)
+}
+
+fn panic_hook(info: &PanicHookInfo) {
+    let payload = info.payload();
+    let msg = match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(msg), _) => msg,
+        (_, Some(msg)) => msg.as_str(),
+        _ => "Box<dyn Any>",
+    };
+    let location = info
+        .location()
+        .map(|loc| format!(" at {}:{}", loc.file(), loc.line()))
+        .unwrap_or_default();
+    let thread = thread::current();
+    let thread = thread.name().unwrap_or("<unnamed>");
+    let module = MODULE_NAME
+        .get()
+        .map(String::as_str)
+        .unwrap_or("haproxy-api");
+    let backtrace = Backtrace::force_capture();
+    let msg = format!(
+        "Module '{module}': thread '{thread}' panicked{location}: {msg}\nstack backtrace:\n{backtrace}"
+    );
+    deferred_log::push(LogLevel::Crit, msg);
+}
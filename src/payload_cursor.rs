@@ -0,0 +1,111 @@
+use mlua::{Error, Result, String as LuaString};
+
+use crate::HttpMessage;
+
+/// A cursor over incoming data of the HTTP message during a single `http_payload` call.
+///
+/// The cursor position splits the incoming data into the part already processed by the filter
+/// (that will be forwarded) and the remaining part. Modifications are applied at the cursor
+/// position, which keeps the offset math consistent. Data after the cursor are retained in
+/// the buffer and returned again on the next call.
+///
+/// ```ignore
+/// fn http_payload(&mut self, _: &Lua, _: Txn, msg: HttpMessage) -> Result<Option<usize>> {
+///     let mut cursor = PayloadCursor::new(&msg)?;
+///     while let Some(line) = cursor.peek_until(b'\n')? {
+///         match line.as_bytes().starts_with(b"secret") {
+///             true => cursor.remove(line.as_bytes().len())?,
+///             false => cursor.advance(line.as_bytes().len()),
+///         }
+///     }
+///     Ok(cursor.finish())
+/// }
+/// ```
+pub struct PayloadCursor<'a, 'lua> {
+    msg: &'a HttpMessage<'lua>,
+    pos: usize,
+    input: usize,
+}
+
+impl<'a, 'lua> PayloadCursor<'a, 'lua> {
+    /// Creates a cursor at the beginning of incoming data of the HTTP message.
+    pub fn new(msg: &'a HttpMessage<'lua>) -> Result<Self> {
+        Ok(PayloadCursor {
+            msg,
+            pos: 0,
+            input: msg.input()?,
+        })
+    }
+
+    /// Returns the current position relative to the beginning of incoming data.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the number of bytes after the cursor.
+    pub fn remaining(&self) -> usize {
+        self.input - self.pos
+    }
+
+    /// Returns up to `length` bytes after the cursor without advancing it.
+    pub fn peek(&self, length: usize) -> Result<Option<LuaString<'lua>>> {
+        let length = length.min(self.remaining());
+        if length == 0 {
+            return Ok(None);
+        }
+        self.msg
+            .body(Some(self.pos as isize), Some(length as isize))
+    }
+
+    /// Returns data after the cursor up to and including the first `delim` byte,
+    /// or `None` if there is no complete delimited chunk.
+    pub fn peek_until(&self, delim: u8) -> Result<Option<LuaString<'lua>>> {
+        let Some(data) = self.peek(self.remaining())? else {
+            return Ok(None);
+        };
+        match data.as_bytes().iter().position(|&b| b == delim) {
+            Some(i) => self.peek(i + 1),
+            None => Ok(None),
+        }
+    }
+
+    /// Marks `length` bytes after the cursor as processed.
+    pub fn advance(&mut self, length: usize) {
+        self.pos += length.min(self.remaining());
+    }
+
+    /// Replaces `length` bytes after the cursor by `data` and moves the cursor past them.
+    pub fn replace(&mut self, length: usize, data: impl AsRef<[u8]>) -> Result<()> {
+        let data = data.as_ref();
+        let length = length.min(self.remaining());
+        if self.msg.set(data, Some(self.pos as isize), Some(length))? < 0 {
+            return Err(Error::runtime("not enough room in the buffer"));
+        }
+        self.input = self.input - length + data.len();
+        self.pos += data.len();
+        Ok(())
+    }
+
+    /// Inserts `data` at the cursor and moves the cursor past them.
+    pub fn insert(&mut self, data: impl AsRef<[u8]>) -> Result<()> {
+        self.replace(0, data)
+    }
+
+    /// Removes `length` bytes after the cursor. The cursor position does not change.
+    pub fn remove(&mut self, length: usize) -> Result<()> {
+        let length = length.min(self.remaining());
+        if length > 0 {
+            self.msg.remove(Some(self.pos as isize), Some(length))?;
+            self.input -= length;
+        }
+        Ok(())
+    }
+
+    /// Consumes the cursor and returns the number of bytes to forward,
+    /// suitable to be returned from [`UserFilter::http_payload`].
+    ///
+    /// [`UserFilter::http_payload`]: crate::UserFilter::http_payload
+    pub fn finish(self) -> Option<usize> {
+        Some(self.pos)
+    }
+}
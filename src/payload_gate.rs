@@ -0,0 +1,157 @@
+use mlua::{Lua, Result};
+
+use crate::mime::MimeMatcher;
+use crate::{Direction, HttpMessage, Txn, UserFilter};
+
+/// A helper that decides (in `http_headers`) whether the payload of an HTTP message should be
+/// filtered, based on its content type and status code, and registers data filtering accordingly.
+///
+/// The decision is recorded per direction and can be queried from later callbacks.
+///
+/// By default messages are skipped if:
+/// - the content type is missing or is `multipart/*`
+/// - `cache-control` contains `no-transform`
+#[derive(Debug, Clone)]
+pub struct PayloadGate {
+    content_types: MimeMatcher,
+    statuses: Vec<(u16, u16)>,
+    allow_multipart: bool,
+    respect_no_transform: bool,
+    skip_encoded: bool,
+    request: Option<bool>,
+    response: Option<bool>,
+}
+
+impl Default for PayloadGate {
+    fn default() -> Self {
+        PayloadGate {
+            content_types: MimeMatcher::new(),
+            statuses: Vec::new(),
+            allow_multipart: false,
+            respect_no_transform: true,
+            skip_encoded: false,
+            request: None,
+            response: None,
+        }
+    }
+}
+
+impl PayloadGate {
+    /// Creates a new gate that accepts any content type and status.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a content type pattern to accept (eg. `text/*` or `application/json`).
+    /// If no patterns are set, any content type is accepted.
+    ///
+    /// See [`MimeMatcher::allow`] for the pattern syntax.
+    pub fn content_type(mut self, pattern: &str) -> Self {
+        self.content_types = self.content_types.allow(pattern);
+        self
+    }
+
+    /// Adds a response status code to accept.
+    /// If no statuses are set, any status is accepted.
+    pub fn status(mut self, status: u16) -> Self {
+        self.statuses.push((status, status));
+        self
+    }
+
+    /// Adds a range of response status codes to accept.
+    pub fn status_range(mut self, from: u16, to: u16) -> Self {
+        self.statuses.push((from, to));
+        self
+    }
+
+    /// Accepts `multipart/*` content types (skipped by default).
+    pub fn allow_multipart(mut self) -> Self {
+        self.allow_multipart = true;
+        self
+    }
+
+    /// Ignores `cache-control: no-transform` (respected by default).
+    pub fn ignore_no_transform(mut self) -> Self {
+        self.respect_no_transform = false;
+        self
+    }
+
+    /// Skips messages that already have `content-encoding`.
+    pub fn skip_encoded(mut self) -> Self {
+        self.skip_encoded = true;
+        self
+    }
+
+    /// Evaluates the HTTP message `msg` and registers data filtering for the filter `F`
+    /// if the message is accepted.
+    ///
+    /// Must be called from [`UserFilter::http_headers`]. Returns the decision.
+    pub fn apply<F: UserFilter>(&mut self, lua: &Lua, txn: Txn, msg: &HttpMessage) -> Result<bool> {
+        let dir = Direction::from_resp(msg.is_resp()?);
+        let accepted = self.evaluate(&txn, msg, dir)?;
+        match dir {
+            Direction::Request => self.request = Some(accepted),
+            Direction::Response => self.response = Some(accepted),
+        }
+        if accepted {
+            F::register_data_filter(lua, txn, msg.channel()?)?;
+        }
+        Ok(accepted)
+    }
+
+    /// Returns the recorded decision for the direction `dir`, or `None` if it was not made yet.
+    pub fn decision(&self, dir: Direction) -> Option<bool> {
+        match dir {
+            Direction::Request => self.request,
+            Direction::Response => self.response,
+        }
+    }
+
+    /// Returns true if the payload is filtered in the direction `dir`.
+    pub fn is_enabled(&self, dir: Direction) -> bool {
+        self.decision(dir).unwrap_or(false)
+    }
+
+    fn evaluate(&self, txn: &Txn, msg: &HttpMessage, dir: Direction) -> Result<bool> {
+        if dir.is_response() && !self.statuses.is_empty() {
+            let status = txn.f.get::<_, Option<u16>>("status", ())?.unwrap_or(0);
+            if !self
+                .statuses
+                .iter()
+                .any(|&(from, to)| (from..=to).contains(&status))
+            {
+                return Ok(false);
+            }
+        }
+        if msg.eom()? {
+            // No payload
+            return Ok(false);
+        }
+
+        let headers = msg.get_headers()?;
+        if self.skip_encoded
+            && headers
+                .get_first::<mlua::Value>("content-encoding")?
+                .is_some()
+        {
+            return Ok(false);
+        }
+        if self.respect_no_transform {
+            let no_transform = headers.get::<String>("cache-control")?.iter().any(|v| {
+                v.split(',')
+                    .any(|d| d.trim().eq_ignore_ascii_case("no-transform"))
+            });
+            if no_transform {
+                return Ok(false);
+            }
+        }
+
+        let Some(content_type) = headers.content_type()? else {
+            return Ok(false);
+        };
+        if !self.allow_multipart && content_type.is_multipart() {
+            return Ok(false);
+        }
+        Ok(self.content_types.is_empty() || self.content_types.matches(&content_type))
+    }
+}
@@ -0,0 +1,141 @@
+//! Binary-safe URL percent-encoding.
+//!
+//! HAProxy hands over URLs, paths and query strings as raw bytes, so the helpers operate
+//! on byte slices (eg. [`mlua::String::as_bytes`]) and never do lossy UTF-8 conversions.
+//!
+//! ```ignore
+//! let path = txn.f.get_bytes("path", ())?;
+//! let decoded = percent::decode(path.as_bytes());
+//! let segment = percent::encode_path_segment(b"a/b c");
+//! assert_eq!(segment, "a%2Fb%20c");
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// Percent-encodes everything except the unreserved characters (`A-Z a-z 0-9 - . _ ~`).
+///
+/// Suitable for query parameter names and values.
+pub fn encode(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len());
+    let _ = write_encoded(&mut out, input, Set::Component);
+    out
+}
+
+/// Encodes as `application/x-www-form-urlencoded`: like [`encode`], but spaces become `+`.
+pub fn encode_form(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len());
+    let _ = write_encoded(&mut out, input, Set::Form);
+    out
+}
+
+/// Encodes a single path segment, keeping the characters allowed in paths except `/`.
+pub fn encode_path_segment(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len());
+    let _ = write_encoded(&mut out, input, Set::PathSegment);
+    out
+}
+
+/// Decodes `%XX` sequences. Invalid sequences are kept as is.
+pub fn decode(input: &[u8]) -> Cow<'_, [u8]> {
+    decode_inner(input, false)
+}
+
+/// Decodes `application/x-www-form-urlencoded` data: like [`decode`], but `+` becomes a space.
+pub fn decode_form(input: &[u8]) -> Cow<'_, [u8]> {
+    decode_inner(input, true)
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Set {
+    Component,
+    Form,
+    PathSegment,
+}
+
+pub(crate) fn write_encoded(out: &mut impl fmt::Write, input: &[u8], set: Set) -> fmt::Result {
+    for &b in input {
+        let keep = match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => true,
+            b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' | b':'
+            | b'@' => matches!(set, Set::PathSegment),
+            _ => false,
+        };
+        match b {
+            _ if keep => out.write_char(b as char)?,
+            b' ' if matches!(set, Set::Form) => out.write_char('+')?,
+            _ => write!(out, "%{b:02X}")?,
+        }
+    }
+    Ok(())
+}
+
+fn decode_inner(input: &[u8], form: bool) -> Cow<'_, [u8]> {
+    if !input.iter().any(|&b| b == b'%' || (form && b == b'+')) {
+        return Cow::Borrowed(input);
+    }
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'+' if form => out.push(b' '),
+            b'%' if i + 2 < input.len() => {
+                match (hex_value(input[i + 1]), hex_value(input[i + 2])) {
+                    (Some(hi), Some(lo)) => {
+                        out.push(hi << 4 | lo);
+                        i += 3;
+                        continue;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    Cow::Owned(out)
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(b"a b/c~"), "a%20b%2Fc~");
+        assert_eq!(encode(b"\xff\x00"), "%FF%00");
+        assert_eq!(encode_form(b"a b+c"), "a+b%2Bc");
+        assert_eq!(encode_path_segment(b"a/b c;d=e@f"), "a%2Fb%20c;d=e@f");
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"a%20b%2fc"), &b"a b/c"[..]);
+        assert!(matches!(
+            decode(b"plain+text"),
+            Cow::Borrowed(b"plain+text")
+        ));
+        assert_eq!(decode_form(b"a+b%2B"), &b"a b+"[..]);
+        // Non-UTF-8 bytes are decoded as is
+        assert_eq!(decode(b"%FF%fe\x80"), &b"\xff\xfe\x80"[..]);
+        // Invalid sequences are kept
+        assert_eq!(decode(b"%zz%4%"), &b"%zz%4%"[..]);
+        assert_eq!(decode(b"100%"), &b"100%"[..]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = (0..=255).collect::<Vec<u8>>();
+        assert_eq!(decode(encode(&input).as_bytes()), &input[..]);
+        assert_eq!(decode_form(encode_form(&input).as_bytes()), &input[..]);
+    }
+}
@@ -0,0 +1,211 @@
+#[cfg(feature = "async")]
+use std::borrow::Cow;
+use std::fmt;
+
+use mlua::Result;
+
+use crate::{Headers, HttpMessage, Txn};
+
+/// HTTP protocol version of the client (frontend) connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HttpProtocol {
+    Http10,
+    Http11,
+    Http2,
+    Http3,
+}
+
+impl HttpProtocol {
+    /// Returns the major version number.
+    pub fn major(self) -> u8 {
+        match self {
+            HttpProtocol::Http10 | HttpProtocol::Http11 => 1,
+            HttpProtocol::Http2 => 2,
+            HttpProtocol::Http3 => 3,
+        }
+    }
+
+    /// Returns true if the protocol multiplexes streams over a connection (HTTP/2 and HTTP/3).
+    ///
+    /// Such requests carry the `:authority` pseudo-header instead of `Host`.
+    pub fn is_multiplexed(self) -> bool {
+        self.major() >= 2
+    }
+
+    /// Returns the protocol name as used in the start-line (eg. `HTTP/1.1`).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HttpProtocol::Http10 => "HTTP/1.0",
+            HttpProtocol::Http11 => "HTTP/1.1",
+            HttpProtocol::Http2 => "HTTP/2.0",
+            HttpProtocol::Http3 => "HTTP/3.0",
+        }
+    }
+}
+
+impl fmt::Display for HttpProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<'lua> Txn<'lua> {
+    /// Returns the HTTP protocol of the client connection, or `None` if it is not an HTTP one.
+    ///
+    /// Uses the `fc_http_major` fetch, and `req.ver` to distinguish HTTP/1.0 from HTTP/1.1.
+    pub fn protocol(&self) -> Result<Option<HttpProtocol>> {
+        let major: Option<u8> = self.f.get("fc_http_major", ())?;
+        Ok(match major {
+            Some(1) => match self.f.get::<_, Option<String>>("req.ver", ())?.as_deref() {
+                Some("1.0") => Some(HttpProtocol::Http10),
+                _ => Some(HttpProtocol::Http11),
+            },
+            Some(2) => Some(HttpProtocol::Http2),
+            Some(3) => Some(HttpProtocol::Http3),
+            _ => None,
+        })
+    }
+
+    /// Returns the protocol negotiated with TLS ALPN on the client connection (eg. `h2`).
+    pub fn alpn(&self) -> Result<Option<String>> {
+        let alpn: Option<String> = self.f.get("ssl_fc_alpn", ())?;
+        Ok(alpn.filter(|alpn| !alpn.is_empty()))
+    }
+
+    /// Returns the request authority (target host and optional port).
+    ///
+    /// The `Host` header is used if present, otherwise the authority is taken from the
+    /// absolute-form request URI, which is how HAProxy represents the `:authority`
+    /// pseudo-header of HTTP/2 and HTTP/3 requests.
+    pub fn authority(&self) -> Result<Option<String>> {
+        let host: Option<String> = self.f.get("req.hdr", "host")?;
+        if let Some(host) = host.filter(|h| !h.is_empty()) {
+            return Ok(Some(host));
+        }
+        let url: Option<String> = self.f.get("url", ())?;
+        Ok(url.as_deref().and_then(uri_authority).map(str::to_string))
+    }
+
+    /// Sets the request authority.
+    ///
+    /// The `Host` header is set and, if the request URI is in the absolute-form (HTTP/2 and
+    /// HTTP/3 requests), its authority is replaced too, so both stay consistent.
+    pub fn set_authority(&self, authority: &str) -> Result<()> {
+        let http = self.http()?;
+        let url: Option<String> = self.f.get("url", ())?;
+        if let Some(uri) = url
+            .as_deref()
+            .and_then(|uri| replace_authority(uri, authority))
+        {
+            http.req_set_uri(&uri)?;
+        }
+        http.req_set_header("host", authority)
+    }
+}
+
+impl<'lua> Headers<'lua> {
+    /// Returns the request authority from the `host` header, falling back to the authority
+    /// of the request `uri` if it's in the absolute-form (the HTTP/2 and HTTP/3 `:authority`).
+    pub fn authority(&self, uri: &str) -> Result<Option<String>> {
+        let host: Option<String> = self.get_first("host")?;
+        if let Some(host) = host.filter(|h| !h.is_empty()) {
+            return Ok(Some(host));
+        }
+        Ok(uri_authority(uri).map(str::to_string))
+    }
+}
+
+impl<'lua> HttpMessage<'lua> {
+    /// Returns the request authority, see [`Headers::authority`].
+    ///
+    /// Returns `None` for responses.
+    pub fn authority(&self) -> Result<Option<String>> {
+        match self.get_stline()?.uri() {
+            Some(uri) => self.get_headers()?.authority(uri),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the request authority, see [`Txn::set_authority`].
+    pub fn set_authority(&self, authority: &str) -> Result<()> {
+        let stline = self.get_stline()?;
+        if let Some(uri) = stline
+            .uri()
+            .and_then(|uri| replace_authority(uri, authority))
+        {
+            self.set_uri(&uri)?;
+        }
+        self.set_header("host", authority)
+    }
+}
+
+// Splits an absolute-form URI (`scheme://authority/path`) into the prefix, authority and rest
+fn split_absolute(uri: &str) -> Option<(&str, &str, &str)> {
+    let (scheme, rest) = uri.split_once("://")?;
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let prefix_len = scheme.len() + 3;
+    // Keep userinfo in the prefix
+    let start = rest[..end].rfind('@').map_or(0, |i| i + 1);
+    let (prefix, authority) = uri.split_at(prefix_len + start);
+    let (authority, rest) = authority.split_at(end - start);
+    Some((prefix, authority, rest))
+}
+
+// Extracts the authority from an absolute-form URI
+fn uri_authority(uri: &str) -> Option<&str> {
+    let (_, authority, _) = split_absolute(uri)?;
+    (!authority.is_empty()).then_some(authority)
+}
+
+// Replaces the authority of an absolute-form URI
+fn replace_authority(uri: &str, authority: &str) -> Option<String> {
+    let (prefix, _, rest) = split_absolute(uri)?;
+    Some(format!("{prefix}{authority}{rest}"))
+}
+
+// Returns the origin-form (path and query) of a request URI
+#[cfg(feature = "async")]
+pub(crate) fn origin_form(uri: &str) -> Cow<'_, str> {
+    match split_absolute(uri) {
+        Some((_, _, rest)) if rest.starts_with('/') => Cow::Borrowed(rest),
+        Some((_, _, rest)) => Cow::Owned(format!("/{rest}")),
+        None => Cow::Borrowed(uri),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_authority() {
+        assert_eq!(
+            uri_authority("https://example.com/path?q"),
+            Some("example.com")
+        );
+        assert_eq!(
+            uri_authority("http://user@example.com:8080"),
+            Some("example.com:8080")
+        );
+        assert_eq!(uri_authority("/path"), None);
+        assert_eq!(uri_authority("http:///path"), None);
+    }
+
+    #[test]
+    fn test_replace_authority() {
+        assert_eq!(
+            replace_authority("https://user@a.com:443/x?y", "b.com").as_deref(),
+            Some("https://user@b.com/x?y")
+        );
+        assert_eq!(replace_authority("/x", "b.com"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_origin_form() {
+        assert_eq!(origin_form("https://example.com/path?q=1"), "/path?q=1");
+        assert_eq!(origin_form("https://example.com"), "/");
+        assert_eq!(origin_form("https://example.com?q=1"), "/?q=1");
+        assert_eq!(origin_form("/path"), "/path");
+    }
+}
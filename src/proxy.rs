@@ -1,18 +1,28 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::ops::Deref;
+use std::str::FromStr;
 
-use mlua::{FromLua, Lua, Result, String as LuaString, Table, TableExt, Value};
+use mlua::{Error, FromLua, IntoLua, Lua, Result, String as LuaString, Table, TableExt, Value};
 
-use crate::{listener::Listener, Server, StickTable};
+use crate::pairs::Pairs;
+use crate::{Listener, Server, StickTable};
+
+mod capacity;
+mod slots;
+
+pub use capacity::CapacitySummary;
+pub use slots::{Slot, SlotPool};
 
 /// The "Proxy" class provides a way for manipulating proxy
 /// and retrieving information like statistics.
 #[derive(Clone)]
 pub struct Proxy<'lua> {
+    lua: &'lua Lua,
     class: Table<'lua>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ProxyCapability {
     Frontend,
     Backend,
@@ -20,7 +30,7 @@ pub enum ProxyCapability {
     Ruleset,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ProxyMode {
     Tcp,
     Http,
@@ -28,6 +38,84 @@ pub enum ProxyMode {
     Unknown,
 }
 
+impl ProxyCapability {
+    /// Returns the capability name as reported by HAProxy.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ProxyCapability::Frontend => "frontend",
+            ProxyCapability::Backend => "backend",
+            ProxyCapability::Proxy => "proxy",
+            ProxyCapability::Ruleset => "ruleset",
+        }
+    }
+}
+
+impl fmt::Display for ProxyCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ProxyCapability {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "frontend" => Ok(ProxyCapability::Frontend),
+            "backend" => Ok(ProxyCapability::Backend),
+            "proxy" => Ok(ProxyCapability::Proxy),
+            "ruleset" => Ok(ProxyCapability::Ruleset),
+            _ => Err(Error::runtime(format!("invalid proxy capability '{s}'"))),
+        }
+    }
+}
+
+impl<'lua> IntoLua<'lua> for ProxyCapability {
+    #[inline]
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.as_str().into_lua(lua)
+    }
+}
+
+impl ProxyMode {
+    /// Returns the mode name as reported by HAProxy.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ProxyMode::Tcp => "tcp",
+            ProxyMode::Http => "http",
+            ProxyMode::Health => "health",
+            ProxyMode::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for ProxyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ProxyMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tcp" => Ok(ProxyMode::Tcp),
+            "http" => Ok(ProxyMode::Http),
+            "health" => Ok(ProxyMode::Health),
+            "unknown" => Ok(ProxyMode::Unknown),
+            _ => Err(Error::runtime(format!("invalid proxy mode '{s}'"))),
+        }
+    }
+}
+
+impl<'lua> IntoLua<'lua> for ProxyMode {
+    #[inline]
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.as_str().into_lua(lua)
+    }
+}
+
 impl<'lua> Proxy<'lua> {
     /// Returns the name of the proxy.
     #[inline]
@@ -43,9 +131,28 @@ impl<'lua> Proxy<'lua> {
 
     /// Returns a map with the attached servers.
     /// The map is indexed by server name.
+    ///
+    /// Prefer [`Proxy::servers_iter`] or [`Proxy::get_server`] for large backends.
     #[inline]
     pub fn get_servers(&self) -> Result<HashMap<String, Server<'lua>>> {
-        self.class.get("servers")
+        self.servers_iter()?.collect()
+    }
+
+    /// Returns the attached server with the given `name`.
+    ///
+    /// Unlike [`Proxy::get_servers`], does not fetch the other servers.
+    #[inline]
+    pub fn get_server(&self, name: &str) -> Result<Option<Server<'lua>>> {
+        match self.class.get::<_, Option<Table>>("servers")? {
+            Some(servers) => servers.get(name),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns a lazy iterator over the attached servers, yielding `(name, server)` pairs.
+    pub fn servers_iter(&self) -> Result<impl Iterator<Item = Result<(String, Server<'lua>)>>> {
+        let servers: Table = self.class.get("servers")?;
+        Pairs::new(self.lua, servers)
     }
 
     /// Returns the stick table attached to the proxy.
@@ -56,9 +163,27 @@ impl<'lua> Proxy<'lua> {
 
     /// Returns a table with the attached listeners.
     /// The table is indexed by listener name.
+    ///
+    /// Prefer [`Proxy::listeners_iter`] to avoid building the map.
     #[inline]
     pub fn get_listeners(&self) -> Result<HashMap<String, Listener<'lua>>> {
-        self.class.get("listeners")
+        self.listeners_iter()?.collect()
+    }
+
+    /// Returns a lazy iterator over the attached listeners, yielding `(name, listener)` pairs.
+    pub fn listeners_iter(&self) -> Result<impl Iterator<Item = Result<(String, Listener<'lua>)>>> {
+        let listeners: Table = self.class.get("listeners")?;
+        Pairs::new(self.lua, listeners)
+    }
+
+    /// Returns a lazy iterator over the attached server names.
+    ///
+    /// Server objects are not converted, which makes it cheaper than [`Proxy::servers_iter`]
+    /// when only the names are needed.
+    pub fn server_names(&self) -> Result<impl Iterator<Item = Result<String>> + 'lua> {
+        let servers: Table = self.class.get("servers")?;
+        let iter = Pairs::<String, Value>::new(self.lua, servers)?;
+        Ok(iter.map(|item| item.map(|(name, _)| name)))
     }
 
     /// Pauses the proxy.
@@ -93,24 +218,14 @@ impl<'lua> Proxy<'lua> {
     #[inline]
     pub fn get_cap(&self) -> Result<ProxyCapability> {
         let cap: LuaString = self.class.call_method::<_, LuaString>("get_cap", ())?;
-        match cap.to_str()? {
-            "frontend" => Ok(ProxyCapability::Frontend),
-            "backend" => Ok(ProxyCapability::Backend),
-            "proxy" => Ok(ProxyCapability::Proxy),
-            _ => Ok(ProxyCapability::Ruleset),
-        }
+        Ok(cap.to_str()?.parse().unwrap_or(ProxyCapability::Ruleset))
     }
 
     /// Returns a enum describing the mode of the current proxy.
     #[inline]
     pub fn get_mode(&self) -> Result<ProxyMode> {
         let mode: LuaString = self.class.call_method("get_mode", ())?;
-        match mode.to_str()? {
-            "tcp" => Ok(ProxyMode::Tcp),
-            "http" => Ok(ProxyMode::Http),
-            "health" => Ok(ProxyMode::Health),
-            _ => Ok(ProxyMode::Unknown),
-        }
+        Ok(mode.to_str()?.parse().unwrap_or(ProxyMode::Unknown))
     }
 
     /// Returns the number of current active servers for the current proxy
@@ -138,7 +253,14 @@ impl<'lua> FromLua<'lua> for Proxy<'lua> {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let class = Table::from_lua(value, lua)?;
-        Ok(Proxy { class })
+        Ok(Proxy { lua, class })
+    }
+}
+
+impl<'lua> IntoLua<'lua> for Proxy<'lua> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(self.class))
     }
 }
 
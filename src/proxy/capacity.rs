@@ -0,0 +1,102 @@
+use mlua::Result;
+
+use crate::Proxy;
+
+/// Aggregated capacity of a backend, returned by [`Proxy::capacity_summary`].
+///
+/// Only the servers able to take traffic are counted as usable: the active servers
+/// that are up, or the backup servers that are up when no active server is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapacitySummary {
+    /// The number of servers in the backend.
+    pub servers: usize,
+    /// The number of active servers eligible for load balancing (`get_srv_act`).
+    pub active_servers: usize,
+    /// The number of backup servers eligible for load balancing (`get_srv_bck`).
+    pub backup_servers: usize,
+    /// The number of usable servers.
+    pub usable_servers: usize,
+    /// The sum of the usable servers `maxconn`, or `None` if any of them is unlimited.
+    pub total_maxconn: Option<u64>,
+    /// The number of connections the usable servers can still accept,
+    /// or `None` if any of them is unlimited.
+    pub available_maxconn: Option<u64>,
+    /// The number of current sessions on all servers.
+    pub cur_sess: u64,
+    /// The number of requests waiting in the backend and servers queues.
+    pub queued: u64,
+}
+
+impl CapacitySummary {
+    /// Returns the fraction (between 0 and 1) of the usable capacity in use,
+    /// or `None` if the capacity is unlimited.
+    pub fn utilization(&self) -> Option<f64> {
+        match (self.total_maxconn, self.available_maxconn) {
+            (Some(0), _) => Some(1.0),
+            (Some(total), Some(available)) => Some(1.0 - available as f64 / total as f64),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the backend can't accept new connections without queueing them.
+    pub fn is_saturated(&self) -> bool {
+        self.usable_servers == 0 || self.queued > 0 || self.available_maxconn == Some(0)
+    }
+}
+
+// Per-server capacity, taken from the server stats
+struct ServerCapacity {
+    backup: bool,
+    up: bool,
+    maxconn: Option<u64>,
+    cur_sess: u64,
+}
+
+impl<'lua> Proxy<'lua> {
+    /// Returns the aggregated capacity of the backend servers, collected in one pass.
+    pub fn capacity_summary(&self) -> Result<CapacitySummary> {
+        let mut summary = CapacitySummary {
+            active_servers: self.get_srv_act()?,
+            backup_servers: self.get_srv_bck()?,
+            queued: self
+                .get_stats()?
+                .get::<_, Option<u64>>("qcur")?
+                .unwrap_or(0),
+            ..Default::default()
+        };
+
+        let mut servers = Vec::new();
+        for item in self.servers_iter()? {
+            let (_, server) = item?;
+            let stats = server.get_stats()?;
+            let count = |key| -> Result<u64> { Ok(stats.get::<_, Option<u64>>(key)?.unwrap_or(0)) };
+            let status = stats
+                .get::<_, Option<String>>("status")?
+                .unwrap_or_default();
+            let capacity = ServerCapacity {
+                backup: count("bck")? > 0,
+                up: status.starts_with("UP") || status == "no check",
+                maxconn: Some(count("slim")?).filter(|&n| n > 0),
+                cur_sess: count("scur")?,
+            };
+            summary.servers += 1;
+            summary.cur_sess += capacity.cur_sess;
+            summary.queued += count("qcur")?;
+            servers.push(capacity);
+        }
+
+        // Backup servers take the traffic only when no active server is up
+        let use_backup = !servers.iter().any(|s| s.up && !s.backup);
+        let mut total = Some(0);
+        let mut available = Some(0);
+        for server in servers.iter().filter(|s| s.up && s.backup == use_backup) {
+            summary.usable_servers += 1;
+            total = total.zip(server.maxconn).map(|(t, m)| t + m);
+            available =
+                (available.zip(server.maxconn)).map(|(a, m)| a + m.saturating_sub(server.cur_sess));
+        }
+        summary.total_maxconn = total;
+        summary.available_maxconn = available;
+        Ok(summary)
+    }
+}
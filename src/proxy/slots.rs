@@ -0,0 +1,160 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use mlua::{Error, Result};
+
+use crate::server::parse_addr;
+use crate::{AdminOp, Proxy, Server};
+
+/// A server slot pre-allocated by `server-template`, tracked by [`SlotPool`].
+#[derive(Debug, Clone)]
+pub struct Slot<'lua> {
+    /// The slot (server) name.
+    pub name: String,
+    /// The slot number (the server name suffix).
+    pub index: u32,
+    /// The address assigned to the slot, `None` for a free slot.
+    pub addr: Option<SocketAddr>,
+    /// The slot server.
+    pub server: Server<'lua>,
+}
+
+impl<'lua> Slot<'lua> {
+    /// Returns true if the slot has an address assigned.
+    pub fn is_occupied(&self) -> bool {
+        self.addr.is_some()
+    }
+}
+
+/// Manages the server slots created by `server-template`, filling them with addresses
+/// and emptying them in a deterministic order (the lowest free slot number first).
+///
+/// A slot is free when it's in maintenance or has a placeholder address (unspecified or
+/// the one set by [`SlotPool::placeholder`]).
+/// Released slots are set to maintenance and get the placeholder address.
+///
+/// ```text
+/// backend app
+///     server-template srv 1-20 0.0.0.0:80 check disabled
+/// ```
+///
+/// ```ignore
+/// let mut pool = SlotPool::discover(&proxy, "srv")?;
+/// let name = pool.claim("10.0.0.5:80".parse()?)?;
+/// pool.release(&name)?;
+/// ```
+#[derive(Debug)]
+pub struct SlotPool<'lua> {
+    placeholder: IpAddr,
+    slots: Vec<Slot<'lua>>,
+}
+
+impl<'lua> SlotPool<'lua> {
+    /// Discovers the slots of the `proxy` named `<prefix><number>`.
+    pub fn discover(proxy: &Proxy<'lua>, prefix: &str) -> Result<Self> {
+        let mut pool = SlotPool {
+            placeholder: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            slots: Vec::new(),
+        };
+        for item in proxy.servers_iter()? {
+            let (name, server) = item?;
+            let Some(index) = (name.strip_prefix(prefix)).and_then(|n| n.parse().ok()) else {
+                continue;
+            };
+            pool.slots.push(Slot {
+                name,
+                index,
+                addr: None,
+                server,
+            });
+        }
+        pool.slots.sort_by_key(|slot| slot.index);
+        pool.refresh()?;
+        Ok(pool)
+    }
+
+    /// Sets the address assigned to the free slots (`0.0.0.0` by default).
+    pub fn placeholder(mut self, addr: IpAddr) -> Result<Self> {
+        self.placeholder = addr;
+        self.refresh()?;
+        Ok(self)
+    }
+
+    /// Re-reads the slots state from HAProxy.
+    pub fn refresh(&mut self) -> Result<()> {
+        for slot in &mut self.slots {
+            let status: Option<String> = slot.server.get_stats()?.get("status")?;
+            let maint = status.is_some_and(|s| s.starts_with("MAINT"));
+            slot.addr = parse_addr(&slot.server.get_addr()?)
+                .filter(|addr| !maint && !is_placeholder(addr.ip(), self.placeholder));
+        }
+        Ok(())
+    }
+
+    /// Returns all the slots, ordered by number.
+    pub fn slots(&self) -> &[Slot<'lua>] {
+        &self.slots
+    }
+
+    /// Returns the slots having an address assigned.
+    pub fn occupied(&self) -> impl Iterator<Item = &Slot<'lua>> {
+        self.slots.iter().filter(|slot| slot.is_occupied())
+    }
+
+    /// Returns the free slots.
+    pub fn free(&self) -> impl Iterator<Item = &Slot<'lua>> {
+        self.slots.iter().filter(|slot| !slot.is_occupied())
+    }
+
+    /// Returns the slot having the address `addr`.
+    pub fn find(&self, addr: SocketAddr) -> Option<&Slot<'lua>> {
+        self.slots.iter().find(|slot| slot.addr == Some(addr))
+    }
+
+    /// Assigns `addr` to the first free slot and sets it ready, returning the slot name.
+    ///
+    /// If the address is already assigned, returns that slot without changes.
+    /// Fails if there are no free slots left.
+    pub fn claim(&mut self, addr: SocketAddr) -> Result<String> {
+        if let Some(slot) = self.find(addr) {
+            return Ok(slot.name.clone());
+        }
+        let Some(slot) = self.slots.iter_mut().find(|slot| !slot.is_occupied()) else {
+            return Err(Error::runtime(format!("no free slot for '{addr}'")));
+        };
+        slot.server.apply(&[
+            AdminOp::SetAddr {
+                addr: addr.ip().to_string(),
+                port: Some(addr.port()),
+            },
+            AdminOp::SetReady,
+        ])?;
+        slot.addr = Some(addr);
+        Ok(slot.name.clone())
+    }
+
+    /// Empties the slot `name`: sets it to maintenance and assigns the placeholder address.
+    ///
+    /// Returns false if the slot is unknown or already free.
+    pub fn release(&mut self, name: &str) -> Result<bool> {
+        let placeholder = self.placeholder;
+        let Some(slot) = self.slots.iter_mut().find(|slot| slot.name == name) else {
+            return Ok(false);
+        };
+        if !slot.is_occupied() {
+            return Ok(false);
+        }
+        slot.server.apply(&[
+            AdminOp::SetMaint,
+            AdminOp::SetAddr {
+                addr: placeholder.to_string(),
+                port: None,
+            },
+        ])?;
+        slot.addr = None;
+        Ok(true)
+    }
+}
+
+fn is_placeholder(ip: IpAddr, placeholder: IpAddr) -> bool {
+    ip.is_unspecified() || ip == placeholder
+}
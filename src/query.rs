@@ -0,0 +1,272 @@
+//! Query string parsing and mutation helpers.
+//!
+//! [`Query`] is an ordered multimap of decoded query parameters. It can be parsed from
+//! the `query` sample fetch and written back via [`Http::req_set_query_params`] or
+//! [`HttpMessage::set_query_params`]:
+//!
+//! ```ignore
+//! let mut query = Query::from_txn(&txn)?;
+//! query.retain(|name, _| !name.starts_with(b"utm_"));
+//! txn.http()?.req_set_query_params(&query)?;
+//! ```
+//!
+//! Names and values are kept as bytes, since decoded parameters are not necessarily
+//! valid UTF-8. Parameters that are not modified are written back as received.
+
+use std::fmt;
+
+use mlua::Result;
+
+use crate::{percent, Http, HttpMessage, Txn};
+
+/// An ordered list of decoded query parameters. A parameter can appear multiple times.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query(Vec<Param>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Param {
+    name: Vec<u8>,
+    // `None` for parameters without `=` (eg. `?debug`)
+    value: Option<Vec<u8>>,
+    // The parameter as received, `None` once modified
+    raw: Option<Vec<u8>>,
+}
+
+impl Param {
+    fn new(name: &[u8], value: &[u8]) -> Self {
+        Param {
+            name: name.to_vec(),
+            value: Some(value.to_vec()),
+            raw: None,
+        }
+    }
+
+    fn value(&self) -> &[u8] {
+        self.value.as_deref().unwrap_or_default()
+    }
+}
+
+impl Query {
+    /// Creates an empty query.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the query string (without the leading `?`).
+    ///
+    /// Keys and values are percent-decoded, `+` is decoded as a space.
+    pub fn parse(query: impl AsRef<[u8]>) -> Self {
+        let query = query.as_ref();
+        let query = query.strip_prefix(b"?").unwrap_or(query);
+        let params = (query.split(|&b| b == b'&'))
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let (name, value) = match p.iter().position(|&b| b == b'=') {
+                    Some(i) => (&p[..i], Some(&p[i + 1..])),
+                    None => (p, None),
+                };
+                Param {
+                    name: percent::decode_form(name).into_owned(),
+                    value: value.map(|v| percent::decode_form(v).into_owned()),
+                    raw: Some(p.to_vec()),
+                }
+            })
+            .collect();
+        Query(params)
+    }
+
+    /// Parses the query string of the current request using the `query` sample fetch.
+    pub fn from_txn(txn: &Txn) -> Result<Self> {
+        let query: Option<mlua::String> = txn.f.get("query", ())?;
+        Ok(query.map(|q| Self::parse(q.as_bytes())).unwrap_or_default())
+    }
+
+    /// Returns the first value of the parameter `name`.
+    ///
+    /// Parameters without value (eg. `?debug`) have an empty value.
+    pub fn get(&self, name: impl AsRef<[u8]>) -> Option<&[u8]> {
+        let name = name.as_ref();
+        self.0.iter().find(|p| p.name == name).map(Param::value)
+    }
+
+    /// Returns all values of the parameter `name`.
+    pub fn get_all(&self, name: impl AsRef<[u8]>) -> Vec<&[u8]> {
+        let name = name.as_ref();
+        (self.0.iter())
+            .filter(|p| p.name == name)
+            .map(Param::value)
+            .collect()
+    }
+
+    /// Returns true if the parameter `name` is present.
+    pub fn contains(&self, name: impl AsRef<[u8]>) -> bool {
+        let name = name.as_ref();
+        self.0.iter().any(|p| p.name == name)
+    }
+
+    /// Appends the parameter `name` with `value`.
+    pub fn append(&mut self, name: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        (self.0).push(Param::new(name.as_ref(), value.as_ref()));
+    }
+
+    /// Replaces all occurrences of the parameter `name` by only one containing `value`.
+    ///
+    /// The parameter keeps the position of its first occurrence.
+    pub fn set(&mut self, name: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        let (name, value) = (name.as_ref(), value.as_ref());
+        let mut found = false;
+        self.0.retain_mut(|p| {
+            if p.name != name {
+                return true;
+            }
+            if found {
+                return false;
+            }
+            found = true;
+            *p = Param::new(name, value);
+            true
+        });
+        if !found {
+            self.append(name, value);
+        }
+    }
+
+    /// Removes all occurrences of the parameter `name`. Returns true if any was removed.
+    pub fn remove(&mut self, name: impl AsRef<[u8]>) -> bool {
+        let name = name.as_ref();
+        let len = self.0.len();
+        self.0.retain(|p| p.name != name);
+        self.0.len() != len
+    }
+
+    /// Retains only the parameters specified by the predicate.
+    ///
+    /// The predicate receives the name and the value (`None` for parameters without `=`).
+    pub fn retain(&mut self, mut f: impl FnMut(&[u8], Option<&[u8]>) -> bool) {
+        self.0.retain(|p| f(&p.name, p.value.as_deref()));
+    }
+
+    /// Returns an iterator over (name, value) pairs.
+    ///
+    /// The value is `None` for parameters without `=`.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.0.iter().map(|p| (&*p.name, p.value.as_deref()))
+    }
+
+    /// Returns the number of parameters.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if there are no parameters.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Formats the query string (without the leading `?`).
+///
+/// Unmodified parameters are written as received (non-ASCII bytes are percent-encoded),
+/// the others with percent-encoded keys and values.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, param) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("&")?;
+            }
+            if let Some(raw) = &param.raw {
+                write_raw(f, raw)?;
+                continue;
+            }
+            encode(f, &param.name)?;
+            if let Some(value) = &param.value {
+                f.write_str("=")?;
+                encode(f, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<N: AsRef<[u8]>, V: AsRef<[u8]>> FromIterator<(N, V)> for Query {
+    fn from_iter<T: IntoIterator<Item = (N, V)>>(iter: T) -> Self {
+        Query(
+            iter.into_iter()
+                .map(|(n, v)| Param::new(n.as_ref(), v.as_ref()))
+                .collect(),
+        )
+    }
+}
+
+fn encode(f: &mut fmt::Formatter<'_>, s: &[u8]) -> fmt::Result {
+    percent::write_encoded(f, s, percent::Set::Component)
+}
+
+fn write_raw(f: &mut fmt::Formatter<'_>, s: &[u8]) -> fmt::Result {
+    for chunk in s.utf8_chunks() {
+        f.write_str(chunk.valid())?;
+        for b in chunk.invalid() {
+            write!(f, "%{b:02X}")?;
+        }
+    }
+    Ok(())
+}
+
+impl<'lua> Http<'lua> {
+    /// Rewrites the request's query string with the encoded `query`.
+    #[inline]
+    pub fn req_set_query_params(&self, query: &Query) -> Result<()> {
+        self.req_set_query(&query.to_string())
+    }
+}
+
+impl<'lua> HttpMessage<'lua> {
+    /// Rewrites the request's query string with the encoded `query`.
+    #[inline]
+    pub fn set_query_params(&self, query: &Query) -> Result<()> {
+        self.set_query(&query.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let query = Query::parse("?a=1&b=x+y%21&a=2&debug&empty=&&c=%ff");
+        assert_eq!(query.len(), 6);
+        assert_eq!(query.get("a"), Some(&b"1"[..]));
+        assert_eq!(query.get_all("a"), vec![&b"1"[..], b"2"]);
+        assert_eq!(query.get("b"), Some(&b"x y!"[..]));
+        assert_eq!(query.get("debug"), Some(&b""[..]));
+        assert_eq!(query.get("c"), Some(&b"\xff"[..]));
+        assert!(query.contains("empty"));
+        assert!(!query.contains("missing"));
+
+        let values = query.iter().map(|(_, v)| v).collect::<Vec<_>>();
+        assert_eq!(values[3], None);
+        assert_eq!(values[4], Some(&b""[..]));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let raw = "a=x+y%21&debug&empty=&c=%ff";
+        assert_eq!(Query::parse(raw).to_string(), raw);
+        assert_eq!(Query::parse(b"v=\xff\xfe").to_string(), "v=%FF%FE");
+    }
+
+    #[test]
+    fn test_modify() {
+        let mut query = Query::parse("utm_source=x&debug&a=1&a=2&b=%2a");
+        query.retain(|name, _| !name.starts_with(b"utm_"));
+        query.set("a", "x y");
+        query.append("c", b"\xff");
+        assert!(query.remove("b"));
+        assert!(!query.remove("b"));
+        assert_eq!(query.to_string(), "debug&a=x%20y&c=%FF");
+
+        let query = [("k", "v/1"), ("é", "")].into_iter().collect::<Query>();
+        assert_eq!(query.to_string(), "k=v%2F1&%C3%A9=");
+    }
+}
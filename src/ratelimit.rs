@@ -0,0 +1,197 @@
+//! Rate limiting over the stick table counters.
+//!
+//! HAProxy does the counting: the requests are tracked in a stick table storing a rate
+//! (eg. `http_req_rate`), and the limiter compares the current rate with the limit.
+//!
+//! ```text
+//! backend per_ip_rates
+//!     stick-table type ip size 1m expire 1m store http_req_rate(10s)
+//!
+//! frontend www
+//!     http-request track-sc0 src table per_ip_rates
+//!     http-request lua.ratelimit
+//! ```
+//!
+//! ```ignore
+//! RateLimiter::tracked(0, 100, Duration::from_secs(10))
+//!     .burst(20)
+//!     .register_action(&core, "ratelimit")?;
+//! ```
+
+use std::time::Duration;
+
+use mlua::{Error, Result};
+
+use crate::{Action, Core, DataType, Txn};
+
+/// The key of the rate counter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateKey {
+    /// The entry tracked by the `track-sc<index>` rule.
+    Tracked(u8),
+    /// The entry of the `table` with the key returned by the sample fetch `expr`
+    /// (eg. `src` or `req.hdr(x-api-key)`).
+    Fetch { table: String, expr: String },
+}
+
+/// The decision of a [`RateLimiter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The request is allowed.
+    Allow {
+        /// The number of requests left in the current period.
+        remaining: u64,
+    },
+    /// The request exceeds the limit.
+    Deny {
+        /// The estimated time until the rate drops below the limit.
+        retry_after: Duration,
+    },
+}
+
+impl Decision {
+    /// Returns true if the request is allowed.
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Decision::Allow { .. })
+    }
+}
+
+/// A rate limiter reading a stick table rate counter.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    key: RateKey,
+    counter: DataType,
+    limit: u64,
+    burst: u64,
+    period: Duration,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `limit` requests per `period` for the entry tracked by
+    /// the `track-sc<index>` rule.
+    ///
+    /// The `period` must match the one of the stored rate (eg. `http_req_rate(10s)`).
+    pub fn tracked(index: u8, limit: u64, period: Duration) -> Self {
+        Self::new(RateKey::Tracked(index), limit, period)
+    }
+
+    /// Creates a limiter allowing `limit` requests per `period` for the entry of the `table`
+    /// with the key returned by the sample fetch `expr`.
+    ///
+    /// The entries must be tracked by the HAProxy configuration, the limiter only reads them.
+    pub fn table(table: &str, expr: &str, limit: u64, period: Duration) -> Self {
+        let key = RateKey::Fetch {
+            table: table.to_string(),
+            expr: expr.to_string(),
+        };
+        Self::new(key, limit, period)
+    }
+
+    fn new(key: RateKey, limit: u64, period: Duration) -> Self {
+        RateLimiter {
+            key,
+            counter: DataType::HttpReqRate,
+            limit,
+            burst: 0,
+            period,
+        }
+    }
+
+    /// Allows `burst` requests above the limit.
+    pub fn burst(mut self, burst: u64) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    /// Sets the rate counter (`http_req_rate` by default).
+    pub fn counter(mut self, counter: DataType) -> Self {
+        self.counter = counter;
+        self
+    }
+
+    /// Returns the limit (requests per period).
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Checks the current rate of the transaction entry.
+    ///
+    /// Requests without an entry (eg. not tracked yet) are allowed.
+    pub fn check(&self, txn: &Txn) -> Result<Decision> {
+        if !self.counter.is_rate() || self.counter.is_array() {
+            let msg = format!("'{}' is not a rate counter", self.counter);
+            return Err(Error::runtime(msg));
+        }
+        let rate = match &self.key {
+            RateKey::Tracked(index) => {
+                let name = format!("sc_{}", self.counter);
+                txn.f.get_opt::<_, u64>(&name, *index)?
+            }
+            RateKey::Fetch { table, expr } => {
+                let (name, args) = parse_expr(expr);
+                match txn.f.get_opt::<_, String>(&name, args)? {
+                    Some(key) => {
+                        let name = format!("table_{}", self.counter);
+                        txn.c.get::<_, Option<u64>>(&name, (key, table.as_str()))?
+                    }
+                    None => None,
+                }
+            }
+        };
+        Ok(self.decide(rate.unwrap_or(0)))
+    }
+
+    fn decide(&self, rate: u64) -> Decision {
+        let allowed = self.limit.saturating_add(self.burst);
+        if rate <= allowed {
+            return Decision::Allow {
+                remaining: allowed - rate,
+            };
+        }
+        // The rate decreases roughly linearly over the period when no more requests come
+        let excess = (rate - allowed) as f64 / rate as f64;
+        let retry_after = Duration::from_secs_f64(self.period.as_secs_f64() * excess);
+        Decision::Deny {
+            retry_after: retry_after.max(Duration::from_secs(1)),
+        }
+    }
+
+    /// Registers the `http-req` action `name` replying `429 Too Many Requests` to the requests
+    /// over the limit.
+    ///
+    /// The reply has the `retry-after` and `ratelimit-*` headers.
+    /// The action arguments are ignored.
+    pub fn register_action(self, core: &Core, name: &str) -> Result<()> {
+        core.register_action(name, &[Action::HttpReq], 0, move |_, txn: Txn| {
+            match self.check(&txn)? {
+                Decision::Allow { .. } => Ok(()),
+                Decision::Deny { retry_after } => {
+                    // Round up, so clients do not retry too early
+                    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                    let reply = txn.reply()?;
+                    reply.set_status(429, None)?;
+                    reply.add_header("retry-after", secs.to_string())?;
+                    reply.add_header("ratelimit-limit", self.limit.to_string())?;
+                    reply.add_header("ratelimit-remaining", "0")?;
+                    reply.add_header("ratelimit-reset", secs.to_string())?;
+                    reply.add_header("content-length", "0")?;
+                    txn.done(Some(reply))
+                }
+            }
+        })
+    }
+}
+
+// Splits the sample fetch expression `name(arg1,arg2)` into the Lua fetch name and arguments
+fn parse_expr(expr: &str) -> (String, Vec<String>) {
+    let (name, args) = match expr.split_once('(') {
+        Some((name, args)) => (name, args.trim_end_matches(')')),
+        None => (expr, ""),
+    };
+    let args = (args.split(','))
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .map(String::from)
+        .collect();
+    (name.trim().replace('.', "_"), args)
+}
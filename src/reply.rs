@@ -0,0 +1,65 @@
+use std::ops::Deref;
+
+use mlua::{FromLua, IntoLua, Lua, Result, Table, TableExt, Value};
+
+/// The "Reply" class represents an HTTP response message.
+/// It provides a way to enrich the message with headers and a body.
+/// It's used to terminate the transaction with [`Txn::done`](crate::Txn::done).
+#[derive(Clone)]
+pub struct Reply<'lua> {
+    lua: &'lua Lua,
+    class: Table<'lua>,
+}
+
+impl<'lua> Reply<'lua> {
+    /// Rewrites the reply status code with the new `status` and optional `reason`.
+    /// If no custom reason is provided, it will be generated from the status.
+    #[inline]
+    pub fn set_status(&self, status: u16, reason: Option<&str>) -> Result<()> {
+        self.class.call_method("set_status", (status, reason))
+    }
+
+    /// Appends an HTTP header field `name` with `value` in the reply.
+    #[inline]
+    pub fn add_header(&self, name: &str, value: impl AsRef<[u8]>) -> Result<()> {
+        let value = self.lua.create_string(value.as_ref())?;
+        self.class.call_method("add_header", (name, value))
+    }
+
+    /// Removes all HTTP header fields in the reply by `name`.
+    #[inline]
+    pub fn del_header(&self, name: &str) -> Result<()> {
+        self.class.call_method("del_header", name)
+    }
+
+    /// Sets the reply payload.
+    #[inline]
+    pub fn set_body(&self, body: impl AsRef<[u8]>) -> Result<()> {
+        let body = self.lua.create_string(body.as_ref())?;
+        self.class.call_method("set_body", body)
+    }
+}
+
+impl<'lua> FromLua<'lua> for Reply<'lua> {
+    #[inline]
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let class = Table::from_lua(value, lua)?;
+        Ok(Reply { lua, class })
+    }
+}
+
+impl<'lua> IntoLua<'lua> for Reply<'lua> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(self.class))
+    }
+}
+
+impl<'lua> Deref for Reply<'lua> {
+    type Target = Table<'lua>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.class
+    }
+}
@@ -0,0 +1,478 @@
+//! A small HTTP router for services (applets).
+//!
+//! Routes match the request method and path. Path patterns consist of literal segments,
+//! parameters (`:name`) and an optional trailing wildcard (`*name`) capturing the rest
+//! of the path. Requests not matching any route get `404 Not Found`, or
+//! `405 Method Not Allowed` if the path matches a route with another method.
+//!
+//! ```ignore
+//! Router::new()
+//!     .get("/health", |_| Ok(Response::text(200, "ok")))
+//!     .post("/api/items/:id", |req| {
+//!         let id = req.param("id").unwrap_or_default();
+//!         let body = req.body()?;
+//!         Ok(Response::text(201, format!("stored {id} ({} bytes)", body.len())))
+//!     })
+//!     .register(&core, "api")?;
+//! ```
+//!
+//! ```text
+//! frontend internal
+//!     http-request use-service lua.api
+//! ```
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+
+use mlua::{Lua, Result, String as LuaString, Table, TableExt};
+
+use crate::pairs::Pairs;
+use crate::{percent, Core, LogLevel, MaybeSend, ServiceMode};
+
+// A route handler (`dyn` objects cannot have `MaybeSend` as an extra bound)
+trait HandlerFn: for<'a, 'lua> Fn(&'a Request<'lua>) -> Result<Response> + MaybeSend {}
+
+impl<F> HandlerFn for F where F: for<'a, 'lua> Fn(&'a Request<'lua>) -> Result<Response> + MaybeSend {}
+
+type Handler = Box<dyn HandlerFn>;
+
+// Path parameters (name, decoded value) in the pattern order
+type Params = Vec<(String, String)>;
+
+/// An HTTP request received by the service.
+pub struct Request<'lua> {
+    lua: &'lua Lua,
+    applet: Table<'lua>,
+    method: String,
+    path: String,
+    params: Params,
+    // Set when the handler accessed the applet and could start the response
+    applet_used: Cell<bool>,
+}
+
+impl<'lua> Request<'lua> {
+    /// Returns the request method.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns the request path (without the query string).
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the query string (without `?`).
+    pub fn query(&self) -> Result<Option<String>> {
+        let qs: Option<String> = self.applet.get("qs")?;
+        Ok(qs.filter(|qs| !qs.is_empty()))
+    }
+
+    /// Returns the (percent-decoded) value of the path parameter `name`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        (self.params.iter())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns all the path parameters, in the pattern order.
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// Returns the first value of the request header `name` (case-insensitive).
+    pub fn header(&self, name: &str) -> Result<Option<LuaString<'lua>>> {
+        let headers: Table = self.applet.get("headers")?;
+        let name = name.to_ascii_lowercase();
+        match headers.get::<_, Option<Table>>(name)? {
+            Some(values) => values.get(0),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns all the request headers, with the names in lower case.
+    pub fn headers(&self) -> Result<HashMap<String, Vec<LuaString<'lua>>>> {
+        let headers: Table = self.applet.get("headers")?;
+        let mut result = HashMap::new();
+        for item in Pairs::<String, Table>::new(self.lua, headers)? {
+            let (name, values) = item?;
+            // Values are indexed from 0
+            let mut values =
+                Pairs::<i64, LuaString>::new(self.lua, values)?.collect::<Result<Vec<_>>>()?;
+            values.sort_by_key(|(i, _)| *i);
+            result.insert(name, values.into_iter().map(|(_, value)| value).collect());
+        }
+        Ok(result)
+    }
+
+    /// Reads the whole request body.
+    pub fn body(&self) -> Result<LuaString<'lua>> {
+        self.applet.call_method("receive", ())
+    }
+
+    /// Returns the underlying `AppletHTTP` object.
+    ///
+    /// If the handler fails after accessing the applet, the `500` response is not sent,
+    /// as the handler could have started its own response.
+    pub fn applet(&self) -> &Table<'lua> {
+        self.applet_used.set(true);
+        &self.applet
+    }
+}
+
+impl fmt::Debug for Request<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("path", &self.path)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+/// An HTTP response sent by the service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// Creates an empty response with the `status` code.
+    pub fn new(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Creates a `text/plain` response.
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Response::new(status)
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(body.into())
+    }
+
+    /// Adds a response header.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the response body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Returns the response status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn send(&self, lua: &Lua, applet: &Table, head: bool) -> Result<()> {
+        applet.call_method::<_, ()>("set_status", self.status)?;
+        let mut has_length = false;
+        for (name, value) in &self.headers {
+            has_length |= name.eq_ignore_ascii_case("content-length");
+            applet.call_method::<_, ()>("add_header", (name.as_str(), value.as_str()))?;
+        }
+        if !has_length {
+            applet.call_method::<_, ()>("add_header", ("content-length", self.body.len()))?;
+        }
+        applet.call_method::<_, ()>("start_response", ())?;
+        if !head && !self.body.is_empty() {
+            applet.call_method::<_, ()>("send", lua.create_string(&self.body)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+struct Route {
+    method: String,
+    segments: Vec<Segment>,
+    handler: Handler,
+}
+
+impl Route {
+    // Returns the path parameters if the route matches the `path` segments
+    fn matches(&self, path: &[&str]) -> Option<Params> {
+        let mut params = Vec::new();
+        let mut path = path.iter();
+        for segment in &self.segments {
+            match segment {
+                Segment::Wildcard(name) => {
+                    let rest = path.by_ref().copied().collect::<Vec<_>>().join("/");
+                    params.push((name.clone(), decode(&rest)));
+                    return Some(params);
+                }
+                Segment::Literal(literal) => {
+                    if path.next()? != literal {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    let value = path.next().filter(|value| !value.is_empty())?;
+                    params.push((name.clone(), decode(value)));
+                }
+            }
+        }
+        path.next().is_none().then_some(params)
+    }
+}
+
+/// Routes the HTTP requests of a service to the handlers.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let routes = (self.routes.iter())
+            .map(|route| (&route.method, &route.segments))
+            .collect::<Vec<_>>();
+        f.debug_struct("Router").field("routes", &routes).finish()
+    }
+}
+
+impl Router {
+    /// Creates a router without routes.
+    pub fn new() -> Self {
+        Router::default()
+    }
+
+    /// Adds a route for the `method` and `path` pattern.
+    ///
+    /// Routes are matched in the order they are added.
+    pub fn route<F>(mut self, method: &str, path: &str, handler: F) -> Self
+    where
+        F: for<'a, 'lua> Fn(&'a Request<'lua>) -> Result<Response> + MaybeSend + 'static,
+    {
+        let segments = split_path(path)
+            .into_iter()
+            .map(|segment| match segment.as_bytes().first() {
+                Some(b':') => Segment::Param(segment[1..].to_string()),
+                Some(b'*') => Segment::Wildcard(segment[1..].to_string()),
+                _ => Segment::Literal(segment.to_string()),
+            })
+            .collect();
+        self.routes.push(Route {
+            method: method.to_ascii_uppercase(),
+            segments,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Adds a `GET` route (also used for `HEAD` requests).
+    pub fn get<F>(self, path: &str, handler: F) -> Self
+    where
+        F: for<'a, 'lua> Fn(&'a Request<'lua>) -> Result<Response> + MaybeSend + 'static,
+    {
+        self.route("GET", path, handler)
+    }
+
+    /// Adds a `POST` route.
+    pub fn post<F>(self, path: &str, handler: F) -> Self
+    where
+        F: for<'a, 'lua> Fn(&'a Request<'lua>) -> Result<Response> + MaybeSend + 'static,
+    {
+        self.route("POST", path, handler)
+    }
+
+    /// Adds a `PUT` route.
+    pub fn put<F>(self, path: &str, handler: F) -> Self
+    where
+        F: for<'a, 'lua> Fn(&'a Request<'lua>) -> Result<Response> + MaybeSend + 'static,
+    {
+        self.route("PUT", path, handler)
+    }
+
+    /// Adds a `PATCH` route.
+    pub fn patch<F>(self, path: &str, handler: F) -> Self
+    where
+        F: for<'a, 'lua> Fn(&'a Request<'lua>) -> Result<Response> + MaybeSend + 'static,
+    {
+        self.route("PATCH", path, handler)
+    }
+
+    /// Adds a `DELETE` route.
+    pub fn delete<F>(self, path: &str, handler: F) -> Self
+    where
+        F: for<'a, 'lua> Fn(&'a Request<'lua>) -> Result<Response> + MaybeSend + 'static,
+    {
+        self.route("DELETE", path, handler)
+    }
+
+    /// Registers the router as the HTTP service `name` (used in HAProxy as `lua.<name>`).
+    pub fn register(self, core: &Core, name: &str) -> Result<()> {
+        let service = name.to_string();
+        core.register_service(name, ServiceMode::Http, move |lua, applet| {
+            self.serve(lua, &service, applet)
+        })
+    }
+
+    fn serve<'lua>(&self, lua: &'lua Lua, service: &str, applet: Table<'lua>) -> Result<()> {
+        let method: String = applet.get("method")?;
+        let path: String = applet.get("path")?;
+        let head = method == "HEAD";
+        let segments = split_path(&path);
+
+        let (route, params) = match self.find(&method, &segments) {
+            Ok(found) => found,
+            Err(allowed) if allowed.is_empty() => {
+                return Response::text(404, "Not Found\n").send(lua, &applet, head);
+            }
+            Err(allowed) => {
+                let response = Response::text(405, "Method Not Allowed\n");
+                let response = response.header("allow", allowed.join(", "));
+                return response.send(lua, &applet, head);
+            }
+        };
+        let request = Request {
+            lua,
+            applet: applet.clone(),
+            method,
+            path,
+            params,
+            applet_used: Cell::new(false),
+        };
+        match (route.handler)(&request) {
+            Ok(response) => response.send(lua, &applet, head),
+            Err(err) => {
+                let msg = format!(
+                    "service '{service}': {} {}: {err}",
+                    request.method, request.path
+                );
+                let _ = Core::new(lua).and_then(|core| core.log(LogLevel::Err, msg));
+                if request.applet_used.get() {
+                    return Ok(());
+                }
+                Response::text(500, "Internal Server Error\n").send(lua, &applet, head)
+            }
+        }
+    }
+
+    // Returns the route matching the request and the path parameters,
+    // or the methods allowed for the path if no route matches the method
+    fn find(
+        &self,
+        method: &str,
+        segments: &[&str],
+    ) -> std::result::Result<(&Route, Params), Vec<&str>> {
+        let head = method == "HEAD";
+        let mut allowed = Vec::new();
+        for route in &self.routes {
+            let Some(params) = route.matches(segments) else {
+                continue;
+            };
+            if route.method != method && !(head && route.method == "GET") {
+                allowed.push(route.method.as_str());
+                continue;
+            }
+            return Ok((route, params));
+        }
+        allowed.sort_unstable();
+        allowed.dedup();
+        Err(allowed)
+    }
+}
+
+// Splits the path into segments, ignoring the leading slash
+fn split_path(path: &str) -> Vec<&str> {
+    match path.strip_prefix('/').unwrap_or(path) {
+        "" => Vec::new(),
+        path => path.split('/').collect(),
+    }
+}
+
+fn decode(value: &str) -> String {
+    String::from_utf8_lossy(&percent::decode(value.as_bytes())).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(
+        router: &'a Router,
+        method: &str,
+        path: &'a str,
+    ) -> std::result::Result<(String, Params), Vec<&'a str>> {
+        let (route, params) = router.find(method, &split_path(path))?;
+        Ok((route.method.clone(), params))
+    }
+
+    fn params(params: &[(&str, &str)]) -> Params {
+        (params.iter())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(split_path("/"), Vec::<&str>::new());
+        assert_eq!(split_path(""), Vec::<&str>::new());
+        assert_eq!(split_path("/a/b"), ["a", "b"]);
+        assert_eq!(split_path("/a/"), ["a", ""]);
+    }
+
+    #[test]
+    fn test_route_matching() {
+        let ok = |_: &Request| Ok(Response::new(200));
+        let router = Router::new()
+            .get("/", ok)
+            .get("/health", ok)
+            .post("/health", ok)
+            .put("/items/:id", ok)
+            .delete("/items/:id", ok)
+            .route("get", "/items/:id/tags/:tag", ok);
+
+        assert_eq!(find(&router, "GET", "/"), Ok(("GET".into(), vec![])));
+        assert_eq!(find(&router, "HEAD", "/health"), Ok(("GET".into(), vec![])));
+        assert_eq!(
+            find(&router, "POST", "/health"),
+            Ok(("POST".into(), vec![]))
+        );
+        assert_eq!(find(&router, "GET", "/missing"), Err(vec![]));
+        assert_eq!(find(&router, "GET", "/health/"), Err(vec![]));
+        assert_eq!(find(&router, "GET", "/items/1"), Err(vec!["DELETE", "PUT"]));
+        assert_eq!(find(&router, "PATCH", "/health"), Err(vec!["GET", "POST"]));
+        assert_eq!(
+            find(&router, "GET", "/items/1/tags/a"),
+            Ok(("GET".into(), params(&[("id", "1"), ("tag", "a")])))
+        );
+    }
+
+    #[test]
+    fn test_path_params() {
+        let ok = |_: &Request| Ok(Response::new(200));
+        let router = Router::new().get("/items/:id", ok).get("/files/*path", ok);
+
+        assert_eq!(
+            find(&router, "GET", "/items/a%20b"),
+            Ok(("GET".into(), params(&[("id", "a b")])))
+        );
+        // Parameters must not be empty
+        assert_eq!(find(&router, "GET", "/items/"), Err(vec![]));
+        assert_eq!(find(&router, "GET", "/items/1/2"), Err(vec![]));
+        assert_eq!(
+            find(&router, "GET", "/files/a/b%2Fc.txt"),
+            Ok(("GET".into(), params(&[("path", "a/b/c.txt")])))
+        );
+        assert_eq!(
+            find(&router, "GET", "/files"),
+            Ok(("GET".into(), params(&[("path", "")])))
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Async client for the HAProxy runtime API and the master CLI.
+//!
+//! The client runs on the async runtime, outside of the HAProxy Lua context, so it can be used
+//! by long-running management tasks.
+//!
+//! ```ignore
+//! let master = MasterCli::new("unix@/run/haproxy-master.sock".parse()?);
+//! for proc in master.show_proc().await? {
+//!     println!("{} {:?} {}", proc.pid, proc.kind, proc.version);
+//! }
+//! // Always talks to the current worker, even after a reload
+//! let info = master.worker_command(None, "show info").await?;
+//! ```
+
+use std::fmt;
+use std::io::{self, Result};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+/// Address of the runtime API (or master CLI) socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SocketAddr {
+    /// A Unix socket path (`unix@/path` or `/path`).
+    #[cfg(unix)]
+    Unix(PathBuf),
+    /// A TCP address (`ipv4@host:port`, `ipv6@host:port` or `host:port`).
+    Tcp(String),
+}
+
+impl FromStr for SocketAddr {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix("unix@") {
+            return unix_addr(path);
+        }
+        if s.starts_with('/') {
+            return unix_addr(s);
+        }
+        let addr = (s.strip_prefix("ipv4@").or_else(|| s.strip_prefix("ipv6@"))).unwrap_or(s);
+        if addr.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty address"));
+        }
+        Ok(SocketAddr::Tcp(addr.to_string()))
+    }
+}
+
+#[cfg(unix)]
+fn unix_addr(path: &str) -> Result<SocketAddr> {
+    Ok(SocketAddr::Unix(PathBuf::from(path)))
+}
+
+#[cfg(not(unix))]
+fn unix_addr(_path: &str) -> Result<SocketAddr> {
+    let msg = "unix sockets are not supported on this platform";
+    Err(io::Error::new(io::ErrorKind::Unsupported, msg))
+}
+
+impl fmt::Display for SocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(unix)]
+            SocketAddr::Unix(path) => write!(f, "unix@{}", path.display()),
+            SocketAddr::Tcp(addr) => f.write_str(addr),
+        }
+    }
+}
+
+/// Client for the HAProxy runtime API (`stats socket`).
+///
+/// Every command is sent over a new connection, in the non-interactive mode.
+#[derive(Debug, Clone)]
+pub struct RuntimeClient {
+    addr: SocketAddr,
+}
+
+impl RuntimeClient {
+    /// Creates a new client for the socket `addr`.
+    pub fn new(addr: SocketAddr) -> Self {
+        RuntimeClient { addr }
+    }
+
+    /// Returns the socket address.
+    pub fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    /// Sends the command and returns its output.
+    pub async fn command(&self, cmd: &str) -> Result<String> {
+        if cmd.contains('\n') {
+            let msg = "command must not contain newlines";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        match &self.addr {
+            #[cfg(unix)]
+            SocketAddr::Unix(path) => exchange(UnixStream::connect(path).await?, cmd).await,
+            SocketAddr::Tcp(addr) => exchange(TcpStream::connect(addr).await?, cmd).await,
+        }
+    }
+}
+
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, cmd: &str) -> Result<String> {
+    stream.write_all(cmd.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+    stream.flush().await?;
+    let mut output = Vec::new();
+    stream.read_to_end(&mut output).await?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// The kind of process listed by the master CLI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessKind {
+    /// The master process.
+    Master,
+    /// A current worker.
+    Worker,
+    /// A worker left from a previous reload, finishing its sessions.
+    OldWorker,
+    /// An external program started by the master.
+    Program,
+}
+
+/// A process listed by `show proc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// The process ID.
+    pub pid: u32,
+    /// The kind of process.
+    pub kind: ProcessKind,
+    /// The number of reloads (for the master) or the reload the process was started at.
+    pub reloads: u32,
+    /// The process uptime as printed by HAProxy (eg. `0d00h02m07s`).
+    pub uptime: String,
+    /// The HAProxy version.
+    pub version: String,
+}
+
+/// Client for the HAProxy master CLI (`-S` option), routing commands to the workers.
+#[derive(Debug)]
+pub struct MasterCli {
+    client: RuntimeClient,
+    // Number of reloads seen by the last `has_reloaded` call, -1 if never checked
+    last_reloads: AtomicI64,
+}
+
+impl MasterCli {
+    /// Creates a new client for the master CLI socket `addr`.
+    pub fn new(addr: SocketAddr) -> Self {
+        MasterCli {
+            client: RuntimeClient::new(addr),
+            last_reloads: AtomicI64::new(-1),
+        }
+    }
+
+    /// Sends a command to the master process.
+    pub async fn command(&self, cmd: &str) -> Result<String> {
+        self.client.command(cmd).await
+    }
+
+    /// Sends a command to the worker `pid`, or to the current worker if `pid` is `None`.
+    ///
+    /// Without a `pid`, the command is routed to the first current worker (`@1`),
+    /// so it keeps working across reloads.
+    pub async fn worker_command(&self, pid: Option<u32>, cmd: &str) -> Result<String> {
+        let cmd = match pid {
+            Some(pid) => format!("@!{pid} {cmd}"),
+            None => format!("@1 {cmd}"),
+        };
+        self.client.command(&cmd).await
+    }
+
+    /// Lists the master, workers and programs (`show proc`).
+    pub async fn show_proc(&self) -> Result<Vec<ProcessInfo>> {
+        parse_show_proc(&self.command("show proc").await?)
+    }
+
+    /// Returns the current workers.
+    pub async fn workers(&self) -> Result<Vec<ProcessInfo>> {
+        let procs = self.show_proc().await?;
+        Ok((procs.into_iter())
+            .filter(|proc| proc.kind == ProcessKind::Worker)
+            .collect())
+    }
+
+    /// Returns true if HAProxy was reloaded since the previous call.
+    ///
+    /// The first call only remembers the current reloads counter and returns false.
+    pub async fn has_reloaded(&self) -> Result<bool> {
+        let procs = self.show_proc().await?;
+        let master = (procs.iter())
+            .find(|proc| proc.kind == ProcessKind::Master)
+            .ok_or_else(|| invalid_data("master process is not listed"))?;
+        let reloads = master.reloads as i64;
+        let last = self.last_reloads.swap(reloads, Ordering::Relaxed);
+        Ok(last >= 0 && last != reloads)
+    }
+}
+
+fn parse_show_proc(output: &str) -> Result<Vec<ProcessInfo>> {
+    let mut procs = Vec::new();
+    let mut section = ProcessKind::Master;
+    // HAProxy < 2.5 prints the `<relative PID>` column before `<reloads>`
+    let mut reloads_col = 2;
+    for line in output.lines().map(str::trim) {
+        match line {
+            "" => continue,
+            "# workers" => section = ProcessKind::Worker,
+            "# old workers" => section = ProcessKind::OldWorker,
+            "# programs" => section = ProcessKind::Program,
+            _ if line.starts_with("#<") => {
+                let mut columns =
+                    (line.split('<').skip(1)).map(|col| col.split('>').next().unwrap_or_default());
+                reloads_col = columns.position(|col| col == "reloads").unwrap_or(2);
+            }
+            _ if line.starts_with('#') => continue,
+            _ => {
+                let fields = split_proc_fields(line);
+                if fields.len() < 5 {
+                    return Err(invalid_data(format!("unexpected 'show proc' line: {line}")));
+                }
+                let pid = (fields[0].parse())
+                    .map_err(|_| invalid_data(format!("invalid pid in line: {line}")))?;
+                let kind = match (section, fields[1]) {
+                    (_, "master") => ProcessKind::Master,
+                    (ProcessKind::Master, _) => ProcessKind::Worker,
+                    (section, _) => section,
+                };
+                let reloads = fields.get(reloads_col).and_then(|v| v.parse().ok());
+                procs.push(ProcessInfo {
+                    pid,
+                    kind,
+                    reloads: reloads.unwrap_or(0),
+                    uptime: fields[fields.len() - 2].to_string(),
+                    version: fields[fields.len() - 1].to_string(),
+                });
+            }
+        }
+    }
+    Ok(procs)
+}
+
+// Splits a `show proc` line into fields, keeping `[was: N]` as a single field
+// and dropping the `[failed: N]` annotation of the master
+fn split_proc_fields(line: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let end = match rest.starts_with('[') {
+            true => rest.find(']').map_or(rest.len(), |i| i + 1),
+            false => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+        };
+        let (field, tail) = rest.split_at(end);
+        if !field.starts_with("[failed") {
+            fields.push(field);
+        }
+        rest = tail.trim_start();
+    }
+    fields
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proc(pid: u32, kind: ProcessKind, reloads: u32, uptime: &str, version: &str) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            kind,
+            reloads,
+            uptime: uptime.into(),
+            version: version.into(),
+        }
+    }
+
+    #[test]
+    fn test_show_proc_relative_pid() {
+        // HAProxy 2.4 (with the `<relative PID>` column)
+        let output = "\
+#<PID>          <type>          <relative PID>  <reloads>       <uptime>        <version>
+1162            master          0 [failed: 0]   5               0d00h02m07s     2.4.22-f8e3218
+# workers
+1271            worker          1               0               0d00h00m00s     2.4.22-f8e3218
+# old workers
+1233            worker          [was: 1]        3               0d00h00m43s     2.4.22-f8e3218
+# programs
+1244            foo             -               0               0d00h00m00s     -
+";
+        assert_eq!(
+            parse_show_proc(output).unwrap(),
+            [
+                proc(
+                    1162,
+                    ProcessKind::Master,
+                    5,
+                    "0d00h02m07s",
+                    "2.4.22-f8e3218"
+                ),
+                proc(
+                    1271,
+                    ProcessKind::Worker,
+                    0,
+                    "0d00h00m00s",
+                    "2.4.22-f8e3218"
+                ),
+                proc(
+                    1233,
+                    ProcessKind::OldWorker,
+                    3,
+                    "0d00h00m43s",
+                    "2.4.22-f8e3218"
+                ),
+                proc(1244, ProcessKind::Program, 0, "0d00h00m00s", "-"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_show_proc() {
+        // HAProxy 2.6+ and 3.x
+        let output = "\
+#<PID>          <type>          <reloads>       <uptime>        <version>
+4130            master          2 [failed: 1]   0d00h05m12s     3.0.5-8e879a5
+# workers
+4213            worker          0               0d00h00m03s     3.0.5-8e879a5
+4214            worker          0               0d00h00m03s     3.0.5-8e879a5
+# old workers
+4160            worker          1               0d00h02m10s     2.8.10-ec17bb0
+# programs
+4139            logger          0               0d00h05m12s     -
+4140            exporter        1               0d00h00m03s     -
+";
+        assert_eq!(
+            parse_show_proc(output).unwrap(),
+            [
+                proc(4130, ProcessKind::Master, 2, "0d00h05m12s", "3.0.5-8e879a5"),
+                proc(4213, ProcessKind::Worker, 0, "0d00h00m03s", "3.0.5-8e879a5"),
+                proc(4214, ProcessKind::Worker, 0, "0d00h00m03s", "3.0.5-8e879a5"),
+                proc(
+                    4160,
+                    ProcessKind::OldWorker,
+                    1,
+                    "0d00h02m10s",
+                    "2.8.10-ec17bb0"
+                ),
+                proc(4139, ProcessKind::Program, 0, "0d00h05m12s", "-"),
+                proc(4140, ProcessKind::Program, 1, "0d00h00m03s", "-"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_show_proc_errors() {
+        assert_eq!(parse_show_proc("").unwrap(), []);
+        assert!(parse_show_proc("1162 master 0d00h02m07s").is_err());
+        assert!(parse_show_proc("abc master 5 0d00h02m07s 3.0.5").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use mlua::{Error, FromLua, IntoLuaMulti, Lua, Result, String as LuaString, TableExt, Value};
+
+use crate::{Converters, Fetches};
+
+// Conversion of the sample fetch or converter result to the Rust type
+pub(crate) trait FromSample<'lua>: Sized {
+    fn from_sample(value: Value<'lua>, lua: &'lua Lua) -> Result<Self>;
+}
+
+macro_rules! impl_from_sample {
+    ($($ty:ty),*) => {
+        $(
+            impl<'lua> FromSample<'lua> for $ty {
+                #[inline]
+                fn from_sample(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+                    FromLua::from_lua(value, lua)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_sample!(bool, u16, u32, u64, i64, String);
+
+impl<'lua> FromSample<'lua> for Vec<u8> {
+    fn from_sample(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        Ok(LuaString::from_lua(value, lua)?.as_bytes().to_vec())
+    }
+}
+
+impl<'lua> FromSample<'lua> for IpAddr {
+    fn from_sample(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let addr = LuaString::from_lua(value, lua)?;
+        let addr = addr.to_str()?;
+        (addr.parse()).map_err(|_| Error::runtime(format!("invalid IP address '{addr}'")))
+    }
+}
+
+impl<'lua, T: FromSample<'lua>> FromSample<'lua> for Option<T> {
+    #[inline]
+    fn from_sample(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::Nil => Ok(None),
+            value => T::from_sample(value, lua).map(Some),
+        }
+    }
+}
+
+/// A raw result of a sample fetch or converter with explicit casts mirroring HAProxy
+/// sample casts.
+///
+/// All casts return `None` if the sample is missing, and an error if the value cannot be
+/// converted to the requested type.
+///
+/// ```ignore
+/// let port = txn.f.sample("req_hdr", "x-port")?.to_int()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sample<'lua>(Value<'lua>);
+
+impl<'lua> Sample<'lua> {
+    /// Returns true if the sample is missing.
+    pub fn is_none(&self) -> bool {
+        self.0.is_nil()
+    }
+
+    /// Returns the underlying Lua value.
+    pub fn into_value(self) -> Value<'lua> {
+        self.0
+    }
+
+    /// Casts the sample to an integer.
+    ///
+    /// Strings are parsed as decimal integers (surrounding whitespace is ignored),
+    /// booleans are converted to `0` or `1`.
+    pub fn to_int(&self) -> Result<Option<i64>> {
+        match &self.0 {
+            Value::Nil => Ok(None),
+            Value::Boolean(b) => Ok(Some(*b as i64)),
+            // `mlua::Integer` is not `i64` on LuaJIT
+            #[allow(clippy::unnecessary_cast)]
+            Value::Integer(i) => Ok(Some(*i as i64)),
+            Value::Number(n) if n.fract() == 0.0 => Ok(Some(*n as i64)),
+            Value::String(s) => {
+                let s = s.to_str().ok().map(str::trim);
+                match s.and_then(|s| s.parse().ok()) {
+                    Some(i) => Ok(Some(i)),
+                    None => Err(self.cast_error("int")),
+                }
+            }
+            _ => Err(self.cast_error("int")),
+        }
+    }
+
+    /// Casts the sample to an IP address.
+    ///
+    /// Integers are converted to IPv4 addresses (like HAProxy `int` to `ipv4` cast).
+    pub fn to_ip(&self) -> Result<Option<IpAddr>> {
+        match &self.0 {
+            Value::Nil => Ok(None),
+            Value::Integer(i) => match u32::try_from(*i) {
+                Ok(i) => Ok(Some(IpAddr::V4(Ipv4Addr::from(i)))),
+                Err(_) => Err(self.cast_error("ip")),
+            },
+            Value::String(s) => match s.to_str().ok().and_then(|s| s.trim().parse().ok()) {
+                Some(ip) => Ok(Some(ip)),
+                None => Err(self.cast_error("ip")),
+            },
+            _ => Err(self.cast_error("ip")),
+        }
+    }
+
+    /// Casts the sample to a boolean.
+    ///
+    /// Integers (and strings parsed as integers) are `true` when non-zero.
+    pub fn to_bool(&self) -> Result<Option<bool>> {
+        match &self.0 {
+            Value::Boolean(b) => Ok(Some(*b)),
+            _ => Ok(self.to_int()?.map(|i| i != 0)),
+        }
+    }
+
+    /// Casts the sample to binary data.
+    ///
+    /// Strings are returned as is (without UTF-8 validation), numbers and booleans are
+    /// converted to their string representation.
+    pub fn to_bin(&self) -> Result<Option<Vec<u8>>> {
+        match &self.0 {
+            Value::Nil => Ok(None),
+            Value::String(s) => Ok(Some(s.as_bytes().to_vec())),
+            Value::Boolean(b) => Ok(Some(if *b { b"1".to_vec() } else { b"0".to_vec() })),
+            Value::Integer(i) => Ok(Some(i.to_string().into_bytes())),
+            Value::Number(n) => Ok(Some(n.to_string().into_bytes())),
+            _ => Err(self.cast_error("bin")),
+        }
+    }
+
+    fn cast_error(&self, to: &str) -> Error {
+        let value = match &self.0 {
+            Value::String(s) => format!("'{}'", s.to_string_lossy()),
+            value => value.type_name().to_string(),
+        };
+        Error::runtime(format!("cannot cast {value} to {to}"))
+    }
+}
+
+impl<'lua> Fetches<'lua> {
+    /// Executes an internal haproxy sample fetch, returning the raw [`Sample`].
+    #[inline]
+    pub fn sample<A: IntoLuaMulti<'lua>>(&self, name: &str, args: A) -> Result<Sample<'lua>> {
+        self.class.call_method(name, args).map(Sample)
+    }
+}
+
+impl<'lua> Converters<'lua> {
+    /// Executes an internal haproxy sample converter, returning the raw [`Sample`].
+    #[inline]
+    pub fn sample<A: IntoLuaMulti<'lua>>(&self, name: &str, args: A) -> Result<Sample<'lua>> {
+        self.class.call_method(name, args).map(Sample)
+    }
+}
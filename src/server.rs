@@ -1,9 +1,22 @@
+use std::net::SocketAddr;
 use std::ops::Deref;
 
-use mlua::{AsChunk, FromLua, Lua, Result, Table, TableExt, Value};
+use mlua::{AsChunk, FromLua, IntoLua, Lua, Result, Table, TableExt, Value};
 
 use crate::Proxy;
 
+mod admin;
+mod drain;
+#[cfg(feature = "haproxy28")]
+mod watch;
+mod weight;
+
+pub use admin::{AdminError, AdminOp};
+pub use drain::{DrainOutcome, DrainReport};
+#[cfg(feature = "haproxy28")]
+pub use watch::{ServerState, ServerWatcher, StateTransition};
+pub use weight::{Weight, WeightDetails};
+
 /// The "Server" class provides a way for manipulating servers and retrieving information.
 #[derive(Clone)]
 pub struct Server<'lua> {
@@ -72,9 +85,10 @@ impl<'lua> Server<'lua> {
     }
 
     /// Dynamically changes the weight of the server.
-    /// See the management socket documentation for more information about the format of the string.
+    ///
+    /// Accepts a [`Weight`] or a string in the management socket format (eg. `12` or `50%`).
     #[inline]
-    pub fn set_weight(&self, weight: &str) -> Result<()> {
+    pub fn set_weight<W: IntoLua<'lua>>(&self, weight: W) -> Result<()> {
         self.class.call_method("set_weight", weight)
     }
 
@@ -201,6 +215,7 @@ impl<'lua> Server<'lua> {
     ///
     /// It works exactly like `core.event_sub()`` except that the subscription
     /// will be performed within the server dedicated subscription list instead of the global one.
+    /// This is HAProxy >=2.8 feature.
     pub fn event_sub<'a, S>(&self, event_types: &[&str], code: S) -> Result<()>
     where
         S: AsChunk<'lua, 'a>,
@@ -218,6 +233,13 @@ impl<'lua> FromLua<'lua> for Server<'lua> {
     }
 }
 
+impl<'lua> IntoLua<'lua> for Server<'lua> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(self.class))
+    }
+}
+
 impl<'lua> Deref for Server<'lua> {
     type Target = Table<'lua>;
 
@@ -226,3 +248,12 @@ impl<'lua> Deref for Server<'lua> {
         &self.class
     }
 }
+
+// Parses the address returned by `Server::get_addr` (IPv6 addresses are not bracketed)
+pub(crate) fn parse_addr(addr: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = addr.parse() {
+        return Some(addr);
+    }
+    let (ip, port) = addr.rsplit_once(':')?;
+    Some(SocketAddr::new(ip.parse().ok()?, port.parse().ok()?))
+}
@@ -0,0 +1,204 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+use mlua::{Error, Result, TableExt};
+
+use crate::{Core, LogLevel, LogRecord, Server, Weight};
+
+/// A server administrative operation, applied by [`Server::apply`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminOp {
+    /// Changes the server address (and port).
+    SetAddr { addr: String, port: Option<u16> },
+    /// Changes the server weight.
+    SetWeight(Weight),
+    /// Changes the server maximum connections.
+    SetMaxconn(u64),
+    /// Sets the server to the normal mode.
+    SetReady,
+    /// Sets the server to drain.
+    SetDrain,
+    /// Sets the server to maintenance.
+    SetMaint,
+    /// Enables health checks.
+    CheckEnable,
+    /// Disables health checks.
+    CheckDisable,
+    /// Enables agent checks.
+    AgentEnable,
+    /// Disables agent checks.
+    AgentDisable,
+}
+
+impl fmt::Display for AdminOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminOp::SetAddr { addr, port: None } => write!(f, "set_addr {addr}"),
+            AdminOp::SetAddr {
+                addr,
+                port: Some(port),
+            } => write!(f, "set_addr {addr} {port}"),
+            AdminOp::SetWeight(weight) => write!(f, "set_weight {weight}"),
+            AdminOp::SetMaxconn(maxconn) => write!(f, "set_maxconn {maxconn}"),
+            AdminOp::SetReady => f.write_str("set_ready"),
+            AdminOp::SetDrain => f.write_str("set_drain"),
+            AdminOp::SetMaint => f.write_str("set_maint"),
+            AdminOp::CheckEnable => f.write_str("check_enable"),
+            AdminOp::CheckDisable => f.write_str("check_disable"),
+            AdminOp::AgentEnable => f.write_str("agent_enable"),
+            AdminOp::AgentDisable => f.write_str("agent_disable"),
+        }
+    }
+}
+
+/// Failure of [`Server::apply`] (wrapped in [`mlua::Error::ExternalError`]).
+#[derive(Debug, Clone)]
+pub struct AdminError {
+    /// The index of the failed operation.
+    pub index: usize,
+    /// The failed operation.
+    pub op: AdminOp,
+    /// The operation error.
+    pub error: Error,
+    /// Errors of the operations restoring the previous state.
+    ///
+    /// If not empty, the server may be left partially changed.
+    pub rollback_errors: Vec<(AdminOp, Error)>,
+}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' failed: {}", self.op, self.error)?;
+        if !self.rollback_errors.is_empty() {
+            write!(f, " ({} rollback error(s))", self.rollback_errors.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for AdminError {}
+
+impl<'lua> Server<'lua> {
+    /// Applies a sequence of administrative operations.
+    ///
+    /// The state changed by every operation is saved before it is applied. If an operation fails,
+    /// the already applied ones are reverted in the reverse order and an [`AdminError`] is returned.
+    /// The health and agent checks are restored by the opposite operation.
+    ///
+    /// A single structured record describing the change is logged.
+    ///
+    /// ```ignore
+    /// server.apply(&[
+    ///     AdminOp::SetAddr { addr: "10.0.0.5".into(), port: Some(8080) },
+    ///     AdminOp::SetWeight(Weight::Absolute(10)),
+    ///     AdminOp::SetReady,
+    /// ])?;
+    /// ```
+    pub fn apply(&self, ops: &[AdminOp]) -> Result<()> {
+        let mut undo = Vec::with_capacity(ops.len());
+        let mut failure = None;
+        for (i, op) in ops.iter().enumerate() {
+            let result = (self.saved_state(op)).and_then(|saved| {
+                self.apply_op(op)?;
+                Ok(saved)
+            });
+            match result {
+                Ok(saved) => undo.push(saved),
+                Err(err) => {
+                    failure = Some((i, err));
+                    break;
+                }
+            }
+        }
+
+        let mut rollback_errors = Vec::new();
+        if failure.is_some() {
+            for op in undo.into_iter().rev() {
+                if let Err(err) = self.apply_op(&op) {
+                    rollback_errors.push((op, err));
+                }
+            }
+        }
+
+        let ops_str = ops.iter().map(|op| op.to_string()).collect::<Vec<_>>();
+        let name = format!("{}/{}", self.get_proxy()?.get_name()?, self.get_name()?);
+        let (level, msg) = match (&failure, rollback_errors.is_empty()) {
+            (None, _) => (LogLevel::Info, "server updated"),
+            (Some(_), true) => (LogLevel::Warning, "server update rolled back"),
+            (Some(_), false) => (LogLevel::Err, "server update partially rolled back"),
+        };
+        let mut record = LogRecord::new(level, msg)
+            .field("server", name)
+            .field("ops", ops_str.join("; "));
+        if let Some((i, err)) = &failure {
+            record = record.field("failed_op", ops_str[*i].as_str());
+            record = record.field("error", err.to_string());
+        }
+        record.send(&Core::new(self.lua)?)?;
+
+        match failure {
+            None => Ok(()),
+            Some((index, error)) => Err(Error::external(AdminError {
+                index,
+                op: ops[index].clone(),
+                error,
+                rollback_errors,
+            })),
+        }
+    }
+
+    fn apply_op(&self, op: &AdminOp) -> Result<()> {
+        // HAProxy reports the invalid values by returning an error message
+        let checked = |msg: Option<String>| match msg {
+            Some(msg) => Err(Error::runtime(msg.trim_end().to_string())),
+            None => Ok(()),
+        };
+        match op {
+            AdminOp::SetAddr { addr, port } => {
+                checked(self.class.call_method("set_addr", (addr.as_str(), *port))?)
+            }
+            AdminOp::SetWeight(weight) => checked(self.class.call_method("set_weight", *weight)?),
+            AdminOp::SetMaxconn(maxconn) => {
+                checked(self.class.call_method("set_maxconn", *maxconn)?)
+            }
+            AdminOp::SetReady => self.set_ready(),
+            AdminOp::SetDrain => self.set_drain(),
+            AdminOp::SetMaint => self.set_maint(),
+            AdminOp::CheckEnable => self.check_enable(),
+            AdminOp::CheckDisable => self.check_disable(),
+            AdminOp::AgentEnable => self.agent_enable(),
+            AdminOp::AgentDisable => self.agent_disable(),
+        }
+    }
+
+    // Returns the operation restoring the state changed by `op`
+    fn saved_state(&self, op: &AdminOp) -> Result<AdminOp> {
+        Ok(match op {
+            AdminOp::SetAddr { .. } => {
+                // The address is formatted as `<ip>:<port>`
+                let addr = self.get_addr()?;
+                match addr.rsplit_once(':') {
+                    Some((ip, port)) if port.parse::<u16>().is_ok() => AdminOp::SetAddr {
+                        addr: ip.to_string(),
+                        port: port.parse().ok().filter(|&port| port != 0),
+                    },
+                    _ => AdminOp::SetAddr { addr, port: None },
+                }
+            }
+            AdminOp::SetWeight(_) => AdminOp::SetWeight(Weight::Absolute(self.get_weight()?)),
+            AdminOp::SetMaxconn(_) => AdminOp::SetMaxconn(self.get_maxconn()?),
+            AdminOp::SetReady | AdminOp::SetDrain | AdminOp::SetMaint => {
+                let status: Option<String> = self.get_stats()?.get("status")?;
+                match status.as_deref().unwrap_or_default() {
+                    status if status.starts_with("MAINT") => AdminOp::SetMaint,
+                    "DRAIN" => AdminOp::SetDrain,
+                    _ => AdminOp::SetReady,
+                }
+            }
+            AdminOp::CheckEnable => AdminOp::CheckDisable,
+            AdminOp::CheckDisable => AdminOp::CheckEnable,
+            AdminOp::AgentEnable => AdminOp::AgentDisable,
+            AdminOp::AgentDisable => AdminOp::AgentEnable,
+        })
+    }
+}
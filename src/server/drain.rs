@@ -0,0 +1,82 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use mlua::{Function, Lua, Result, TableExt};
+
+use crate::{Core, MaybeSend, Server};
+
+// How often the server sessions are checked while draining
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The result of [`Server::drain_and_maint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainOutcome {
+    /// All sessions finished, the server was put in maintenance.
+    Drained,
+    /// The timeout passed, the server was put in maintenance with sessions still active.
+    TimedOut {
+        /// The number of sessions left on the server.
+        remaining: u64,
+    },
+    /// The server left the drain state (eg. it was set to ready), maintenance was not set.
+    Aborted,
+}
+
+/// A report passed to the [`Server::drain_and_maint`] callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainReport {
+    /// What happened to the server.
+    pub outcome: DrainOutcome,
+    /// The time spent waiting for the sessions to finish.
+    pub elapsed: Duration,
+}
+
+impl<'lua> Server<'lua> {
+    /// Takes the server out of service safely.
+    ///
+    /// Sets the server to drain, waits until its sessions finish or `timeout` passes,
+    /// then sets it to maintenance. The wait runs in a HAProxy task (yielding between checks),
+    /// and the `callback` is called with the report at the end.
+    pub fn drain_and_maint<F>(&self, timeout: Duration, callback: F) -> Result<()>
+    where
+        F: Fn(&Lua, DrainReport) -> Result<()> + MaybeSend + 'static,
+    {
+        let lua = self.lua;
+        let started = Cell::new(None);
+        let step = lua.create_function(move |lua, server: Server| {
+            let start = started.get().unwrap_or_else(Instant::now);
+            started.set(Some(start));
+            let elapsed = start.elapsed();
+            let outcome = if !server.is_draining()? {
+                DrainOutcome::Aborted
+            } else {
+                match server.get_cur_sess()? {
+                    0 => DrainOutcome::Drained,
+                    remaining if elapsed >= timeout => DrainOutcome::TimedOut { remaining },
+                    _ => return Ok(false),
+                }
+            };
+            if outcome != DrainOutcome::Aborted {
+                server.set_maint()?;
+            }
+            callback(lua, DrainReport { outcome, elapsed })?;
+            Ok(true)
+        })?;
+        let task: Function = lua
+            .load(
+                r#"
+                local step, server, interval = ...
+                local msleep = core.msleep
+                return function()
+                    server:set_drain()
+                    while not step(server) do
+                        msleep(interval)
+                    end
+                end
+                "#,
+            )
+            .set_name("=server_drain")
+            .call((step, &self.class, POLL_INTERVAL.as_millis() as u64))?;
+        Core::new(lua)?.call_function::<_, ()>("register_task", task)
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+use mlua::{Function, Lua, Result, Table, TableExt};
+
+use crate::{Core, Server};
+
+// Server events delivering state changes
+#[cfg(not(feature = "haproxy30"))]
+const EVENT_TYPES: &[&str] = &["SERVER_UP", "SERVER_DOWN"];
+#[cfg(feature = "haproxy30")]
+const EVENT_TYPES: &[&str] = &["SERVER_UP", "SERVER_DOWN", "SERVER_ADMIN"];
+
+// How often the coalesced transitions are checked
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+type Callback = Box<dyn Fn(&Lua, StateTransition) -> Result<()> + Send + Sync>;
+type Filter = Box<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// The operational state of a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServerState {
+    /// The server is up.
+    Up,
+    /// The server is down (eg. failing health checks).
+    Down,
+    /// The server is in maintenance.
+    Maint,
+    /// The server is draining.
+    Drain,
+}
+
+/// A server state change, delivered by a [`ServerWatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateTransition {
+    /// The proxy (backend) name.
+    pub proxy: String,
+    /// The server name.
+    pub server: String,
+    /// The previous state, or `None` if it was not observed yet.
+    pub from: Option<ServerState>,
+    /// The new state.
+    pub to: ServerState,
+    /// When the new state was observed.
+    pub at: SystemTime,
+    /// The number of intermediate changes coalesced into this transition.
+    pub coalesced: u32,
+}
+
+/// Delivers typed server state transitions, built on the HAProxy event subscriptions.
+///
+/// Maintenance and drain transitions require HAProxy 3.0 (`haproxy30` feature),
+/// otherwise only the up and down transitions are delivered.
+///
+/// ```ignore
+/// ServerWatcher::new(|lua, transition| {
+///     Core::new(lua)?.log(LogLevel::Warning, format!("{transition:?}"))
+/// })
+/// .filter(|proxy, _| proxy == "app")
+/// .coalesce(Duration::from_secs(5))
+/// .register(&core)?;
+/// ```
+pub struct ServerWatcher {
+    callback: Callback,
+    filter: Option<Filter>,
+    window: Duration,
+}
+
+impl ServerWatcher {
+    /// Creates a new watcher calling the `callback` on every transition.
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&Lua, StateTransition) -> Result<()> + Send + Sync + 'static,
+    {
+        ServerWatcher {
+            callback: Box::new(callback),
+            filter: None,
+            window: Duration::ZERO,
+        }
+    }
+
+    /// Delivers only the transitions of the servers accepted by the `filter`,
+    /// called with the proxy and server names.
+    pub fn filter(mut self, filter: impl Fn(&str, &str) -> bool + Send + Sync + 'static) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Coalesces the changes within the `window`: a transition is delivered once the state
+    /// is stable for the window, and flaps back to the previous state are not delivered.
+    pub fn coalesce(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Subscribes to the events of all servers.
+    pub fn register(self, core: &Core) -> Result<()> {
+        self.subscribe(core.lua, |func| {
+            core.call_function::<_, ()>("event_sub", (EVENT_TYPES, func))
+        })
+    }
+
+    /// Subscribes to the events of the `server` only.
+    pub fn register_server(self, server: &Server) -> Result<()> {
+        self.subscribe(server.lua, |func| {
+            server.call_method::<_, ()>("event_sub", (EVENT_TYPES, func))
+        })
+    }
+
+    fn subscribe<'lua>(
+        self,
+        lua: &'lua Lua,
+        sub: impl FnOnce(Function<'lua>) -> Result<()>,
+    ) -> Result<()> {
+        let window = self.window;
+        let watcher = Arc::new(Watcher {
+            callback: self.callback,
+            filter: self.filter,
+            window,
+            servers: Mutex::new(HashMap::new()),
+        });
+
+        let watcher2 = watcher.clone();
+        let func = lua.create_function(move |lua, (event, data): (String, Table)| {
+            let proxy: String = data.get("proxy_name")?;
+            let server: String = data.get("name")?;
+            if let Some(filter) = &watcher2.filter {
+                if !filter(&proxy, &server) {
+                    return Ok(());
+                }
+            }
+            let Some(state) = event_state(&event, data.get("reference")?)? else {
+                return Ok(());
+            };
+            let transition = watcher2.observe(proxy, server, state);
+            match transition {
+                Some(transition) => (watcher2.callback)(lua, transition),
+                None => Ok(()),
+            }
+        })?;
+        sub(func)?;
+
+        if window.is_zero() {
+            return Ok(());
+        }
+        let flush = lua.create_function(move |lua, ()| {
+            for transition in watcher.flush() {
+                (watcher.callback)(lua, transition)?;
+            }
+            Ok(())
+        })?;
+        let task: Function = lua
+            .load(
+                r#"
+                local flush, interval = ...
+                local msleep = core.msleep
+                return function()
+                    while true do
+                        flush()
+                        msleep(interval)
+                    end
+                end
+                "#,
+            )
+            .set_name("=server_watch")
+            .call((flush, FLUSH_INTERVAL.as_millis() as u64))?;
+        Core::new(lua)?.call_function("register_task", task)
+    }
+}
+
+impl<'lua> Server<'lua> {
+    /// Calls the `callback` on every state transition of the server.
+    ///
+    /// See [`ServerWatcher`] for more options.
+    pub fn watch<F>(&self, callback: F) -> Result<()>
+    where
+        F: Fn(&Lua, StateTransition) -> Result<()> + Send + Sync + 'static,
+    {
+        ServerWatcher::new(callback).register_server(self)
+    }
+}
+
+impl<'lua> Core<'lua> {
+    /// Calls the `callback` on every state transition of the servers accepted by the `filter`
+    /// (called with the proxy and server names).
+    ///
+    /// See [`ServerWatcher`] for more options.
+    pub fn watch_servers<P, F>(&self, filter: P, callback: F) -> Result<()>
+    where
+        P: Fn(&str, &str) -> bool + Send + Sync + 'static,
+        F: Fn(&Lua, StateTransition) -> Result<()> + Send + Sync + 'static,
+    {
+        ServerWatcher::new(callback).filter(filter).register(self)
+    }
+}
+
+struct Watcher {
+    callback: Callback,
+    filter: Option<Filter>,
+    window: Duration,
+    servers: Mutex<HashMap<(String, String), Tracked>>,
+}
+
+#[derive(Default)]
+struct Tracked {
+    // The last delivered state
+    delivered: Option<ServerState>,
+    pending: Option<Pending>,
+}
+
+struct Pending {
+    to: ServerState,
+    at: SystemTime,
+    changed: Instant,
+    coalesced: u32,
+}
+
+impl Watcher {
+    // Records the new state, returning the transition to deliver immediately
+    fn observe(&self, proxy: String, server: String, to: ServerState) -> Option<StateTransition> {
+        let mut servers = self.servers.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = (proxy, server);
+        let tracked = servers.entry(key.clone()).or_default();
+        if !self.window.is_zero() {
+            match &mut tracked.pending {
+                Some(pending) => {
+                    pending.to = to;
+                    pending.at = SystemTime::now();
+                    pending.changed = Instant::now();
+                    pending.coalesced += 1;
+                }
+                None => {
+                    tracked.pending = Some(Pending {
+                        to,
+                        at: SystemTime::now(),
+                        changed: Instant::now(),
+                        coalesced: 0,
+                    });
+                }
+            }
+            return None;
+        }
+        let from = tracked.delivered.replace(to);
+        (from != Some(to)).then(|| StateTransition {
+            proxy: key.0,
+            server: key.1,
+            from,
+            to,
+            at: SystemTime::now(),
+            coalesced: 0,
+        })
+    }
+
+    // Returns the coalesced transitions that are stable for the window
+    fn flush(&self) -> Vec<StateTransition> {
+        let mut servers = self.servers.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut transitions = Vec::new();
+        for ((proxy, server), tracked) in servers.iter_mut() {
+            let stable =
+                (tracked.pending.as_ref()).is_some_and(|p| p.changed.elapsed() >= self.window);
+            if !stable {
+                continue;
+            }
+            let pending = tracked.pending.take().expect("pending transition");
+            let from = tracked.delivered.replace(pending.to);
+            if from != Some(pending.to) {
+                transitions.push(StateTransition {
+                    proxy: proxy.clone(),
+                    server: server.clone(),
+                    from,
+                    to: pending.to,
+                    at: pending.at,
+                    coalesced: pending.coalesced,
+                });
+            }
+        }
+        transitions
+    }
+}
+
+// Returns the server state after the event
+fn event_state(event: &str, reference: Option<Server>) -> Result<Option<ServerState>> {
+    match event {
+        "SERVER_UP" => Ok(Some(ServerState::Up)),
+        "SERVER_DOWN" => Ok(Some(ServerState::Down)),
+        _ => {
+            let Some(server) = reference else {
+                return Ok(None);
+            };
+            let status: Option<String> = server.get_stats()?.get("status")?;
+            let state = match status.as_deref().unwrap_or_default() {
+                s if s.starts_with("MAINT") => ServerState::Maint,
+                s if s.starts_with("DRAIN") => ServerState::Drain,
+                s if s.starts_with("DOWN") => ServerState::Down,
+                _ => ServerState::Up,
+            };
+            Ok(Some(state))
+        }
+    }
+}
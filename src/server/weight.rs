@@ -0,0 +1,111 @@
+use std::fmt;
+use std::str::FromStr;
+
+use mlua::{Error, IntoLua, Lua, Result, Value};
+
+use crate::Server;
+
+/// A server weight change, as accepted by [`Server::set_weight`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Weight {
+    /// The new weight, between 0 and [`Weight::MAX`].
+    Absolute(u32),
+    /// The new weight in percents of the initial (configured) server weight.
+    Relative(u32),
+}
+
+impl Weight {
+    /// The maximum server weight accepted by HAProxy.
+    pub const MAX: u32 = 256;
+
+    /// Checks that the weight is in the range accepted by HAProxy.
+    ///
+    /// Relative weights are checked against the resulting weight only by HAProxy,
+    /// as the initial weight is not known here.
+    pub fn validate(self) -> Result<Self> {
+        match self {
+            Weight::Absolute(w) if w > Self::MAX => Err(Error::runtime(format!(
+                "absolute weight {w} is out of range 0-{}",
+                Self::MAX
+            ))),
+            Weight::Relative(p) if p > Self::MAX * 100 => {
+                Err(Error::runtime(format!("relative weight {p}% is too large")))
+            }
+            weight => Ok(weight),
+        }
+    }
+
+    /// Returns the weight the server would get, given its `initial` weight.
+    pub fn resolve(self, initial: u32) -> u32 {
+        match self {
+            Weight::Absolute(w) => w.min(Self::MAX),
+            Weight::Relative(p) => (initial as u64 * p as u64 / 100).min(Self::MAX as u64) as u32,
+        }
+    }
+}
+
+impl fmt::Display for Weight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Weight::Absolute(w) => write!(f, "{w}"),
+            Weight::Relative(p) => write!(f, "{p}%"),
+        }
+    }
+}
+
+impl FromStr for Weight {
+    type Err = Error;
+
+    /// Parses the management socket format (eg. `12` or `50%`).
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::runtime(format!("invalid server weight '{s}'"));
+        let weight = match s.trim().strip_suffix('%') {
+            Some(p) => Weight::Relative(p.parse().map_err(|_| invalid())?),
+            None => Weight::Absolute(s.trim().parse().map_err(|_| invalid())?),
+        };
+        weight.validate()
+    }
+}
+
+impl<'lua> IntoLua<'lua> for Weight {
+    #[inline]
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        self.validate()?.to_string().into_lua(lua)
+    }
+}
+
+/// The server weights, as reported in the server statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeightDetails {
+    /// The current effective weight (`weight`).
+    pub current: u32,
+    /// The initial weight from the configuration (`iweight`).
+    pub initial: u32,
+    /// The weight set by the user, before the slowstart ramp-up is applied (`uweight`).
+    ///
+    /// Reported by HAProxy >= 2.4.
+    pub user: Option<u32>,
+}
+
+impl WeightDetails {
+    /// Returns the current weight in percents of the initial weight.
+    pub fn relative(&self) -> Option<u32> {
+        (self.initial > 0).then(|| self.current * 100 / self.initial)
+    }
+}
+
+impl<'lua> Server<'lua> {
+    /// Returns the current and initial weights of the server.
+    pub fn get_weight_details(&self) -> Result<WeightDetails> {
+        let stats = self.get_stats()?;
+        let current = match stats.get::<_, Option<u32>>("weight")? {
+            Some(weight) => weight,
+            None => self.get_weight()?,
+        };
+        Ok(WeightDetails {
+            current,
+            initial: stats.get::<_, Option<u32>>("iweight")?.unwrap_or(current),
+            user: stats.get("uweight")?,
+        })
+    }
+}
@@ -0,0 +1,523 @@
+//! Process-wide state shared between HAProxy threads.
+//!
+//! Every HAProxy thread may have an independent Lua state, so Lua values cannot be shared.
+//! [`Map`] and [`Bus`] are stored on the Rust side and can be accessed from callbacks running
+//! on any thread (or from async tasks).
+//!
+//! ```ignore
+//! static BLOCKED: LazyLock<shared::Map<String, String>> = LazyLock::new(shared::Map::new);
+//!
+//! core.register_action("block_ip", &[Action::HttpReq], 1, |_, (txn, ttl): (Txn, u64)| {
+//!     let ip: String = txn.f.get("src", ())?;
+//!     BLOCKED.insert_with_ttl(ip, "manual".into(), Duration::from_secs(ttl));
+//!     Ok(())
+//! })?;
+//! core.register_fetches("is_blocked", |_, txn: Txn| {
+//!     let ip: String = txn.f.get("src", ())?;
+//!     Ok(BLOCKED.contains_key(&ip))
+//! })?;
+//! ```
+
+use std::borrow::Borrow;
+use std::collections::hash_map::{Entry as HashMapEntry, HashMap, RandomState};
+use std::collections::VecDeque;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::time::{Duration, Instant};
+
+use mlua::{Function, Lua, Result, TableExt};
+
+use crate::{Core, LogLevel, MaybeSend};
+
+const DEFAULT_SHARDS: usize = 16;
+
+/// A change of the [`Map`] entry, reported to listeners.
+#[derive(Debug)]
+pub enum Change<V> {
+    /// A new entry was inserted.
+    Inserted(Arc<V>),
+    /// The entry value was replaced.
+    Replaced { old: Arc<V>, new: Arc<V> },
+    /// The entry was removed.
+    Removed(Arc<V>),
+    /// The entry was removed because its TTL elapsed.
+    Expired(Arc<V>),
+}
+
+type Listener<K, V> = Arc<dyn Fn(&K, &Change<V>) + Send + Sync>;
+
+type Shard<K, V> = RwLock<HashMap<K, Entry<V>>>;
+
+struct Entry<V> {
+    value: Arc<V>,
+    expires: Option<Instant>,
+}
+
+impl<V> Entry<V> {
+    #[inline]
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+/// A concurrent hash map sharded by key hash, with optional TTL per entry.
+///
+/// Values are stored as `Arc<V>` and returned without cloning the value itself.
+/// Expired entries are removed lazily (on access) or by [`Map::purge_expired`].
+pub struct Map<K, V> {
+    shards: Box<[Shard<K, V>]>,
+    hasher: RandomState,
+    listeners: RwLock<Vec<Listener<K, V>>>,
+}
+
+impl<K: Eq + Hash + Clone, V> Default for Map<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for Map<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Map")
+            .field("shards", &self.shards.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Map<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::with_shards(DEFAULT_SHARDS)
+    }
+
+    /// Creates an empty map with the number of `shards` (locks).
+    pub fn with_shards(shards: usize) -> Self {
+        Map {
+            shards: (0..shards.max(1))
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            hasher: RandomState::new(),
+            listeners: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Registers a listener called on every change.
+    ///
+    /// Listeners are called without holding any lock, so they can access the map
+    /// (or register other listeners).
+    pub fn subscribe(&self, listener: impl Fn(&K, &Change<V>) + Send + Sync + 'static) {
+        write(&self.listeners).push(Arc::new(listener));
+    }
+
+    /// Returns the value of the `key`.
+    pub fn get<Q>(&self, key: &Q) -> Option<Arc<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        match read(self.shard(key)).get(key) {
+            Some(entry) if !entry.is_expired(now) => return Some(entry.value.clone()),
+            Some(_) => {}
+            None => return None,
+        }
+        self.remove_expired(key, now);
+        None
+    }
+
+    /// Returns true if the map contains the `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Inserts the `value` without expiration, returning the previous value.
+    pub fn insert(&self, key: K, value: V) -> Option<Arc<V>> {
+        self.insert_entry(key, value, None)
+    }
+
+    /// Inserts the `value` expiring after `ttl`, returning the previous value.
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<Arc<V>> {
+        self.insert_entry(key, value, Some(Instant::now() + ttl))
+    }
+
+    /// Atomically updates the value of the `key` using the function `f`,
+    /// which receives the current value (if any). The TTL of an existing entry is preserved.
+    ///
+    /// The function `f` runs while the map shard is locked, so it must not access the map
+    /// (this would deadlock). Listeners are notified after the lock is released.
+    ///
+    /// Returns the new value.
+    pub fn update(&self, key: K, f: impl FnOnce(Option<&V>) -> V) -> Arc<V> {
+        let now = Instant::now();
+        let notify_key = self.has_listeners().then(|| key.clone());
+        let (old, new) = {
+            let mut map = write(self.shard(&key));
+            match map.entry(key) {
+                HashMapEntry::Occupied(mut occupied) => {
+                    let entry = occupied.get_mut();
+                    let expired = entry.is_expired(now);
+                    let new = match expired {
+                        true => Arc::new(f(None)),
+                        false => Arc::new(f(Some(&entry.value))),
+                    };
+                    let old = mem::replace(&mut entry.value, new.clone());
+                    if expired {
+                        entry.expires = None;
+                    }
+                    (Some((old, expired)), new)
+                }
+                HashMapEntry::Vacant(vacant) => {
+                    let new = Arc::new(f(None));
+                    let value = new.clone();
+                    vacant.insert(Entry {
+                        value,
+                        expires: None,
+                    });
+                    (None, new)
+                }
+            }
+        };
+        if let Some(key) = notify_key {
+            match old {
+                Some((old, true)) => {
+                    self.notify(&key, Change::Expired(old));
+                    self.notify(&key, Change::Inserted(new.clone()));
+                }
+                Some((old, false)) => {
+                    let new = new.clone();
+                    self.notify(&key, Change::Replaced { old, new });
+                }
+                None => self.notify(&key, Change::Inserted(new.clone())),
+            }
+        }
+        new
+    }
+
+    /// Removes the `key`, returning its value.
+    pub fn remove<Q>(&self, key: &Q) -> Option<Arc<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, entry) = write(self.shard(key)).remove_entry(key)?;
+        if entry.is_expired(Instant::now()) {
+            self.notify(&key, Change::Expired(entry.value));
+            return None;
+        }
+        self.notify(&key, Change::Removed(entry.value.clone()));
+        Some(entry.value)
+    }
+
+    /// Returns the number of entries (including expired, but not yet purged ones).
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| read(shard).len()).sum()
+    }
+
+    /// Returns true if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| read(shard).is_empty())
+    }
+
+    /// Removes all expired entries, returning their number.
+    pub fn purge_expired(&self) -> usize {
+        let now = Instant::now();
+        let mut count = 0;
+        for shard in self.shards.iter() {
+            let mut expired = Vec::new();
+            write(shard).retain(|key, entry| {
+                if entry.is_expired(now) {
+                    expired.push((key.clone(), entry.value.clone()));
+                    return false;
+                }
+                true
+            });
+            count += expired.len();
+            for (key, value) in expired {
+                self.notify(&key, Change::Expired(value));
+            }
+        }
+        count
+    }
+
+    /// Removes all entries. Listeners are not notified.
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            write(shard).clear();
+        }
+    }
+
+    fn insert_entry(&self, key: K, value: V, expires: Option<Instant>) -> Option<Arc<V>> {
+        let now = Instant::now();
+        let notify_key = self.has_listeners().then(|| key.clone());
+        let value = Arc::new(value);
+        let entry = Entry {
+            value: value.clone(),
+            expires,
+        };
+        let old = write(self.shard(&key)).insert(key, entry);
+        let (old, change) = match old {
+            Some(old) if old.is_expired(now) => (None, Some(Change::Expired(old.value))),
+            Some(old) => {
+                let new = value.clone();
+                let change = Change::Replaced {
+                    old: old.value.clone(),
+                    new,
+                };
+                (Some(old.value), Some(change))
+            }
+            None => (None, None),
+        };
+        if let Some(key) = notify_key {
+            match change {
+                Some(change @ Change::Replaced { .. }) => self.notify(&key, change),
+                Some(change) => {
+                    self.notify(&key, change);
+                    self.notify(&key, Change::Inserted(value));
+                }
+                None => self.notify(&key, Change::Inserted(value)),
+            }
+        }
+        old
+    }
+
+    fn remove_expired<Q>(&self, key: &Q, now: Instant)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let removed = {
+            let mut map = write(self.shard(key));
+            match map.get(key) {
+                Some(entry) if entry.is_expired(now) => map.remove_entry(key),
+                _ => None,
+            }
+        };
+        if let Some((key, entry)) = removed {
+            self.notify(&key, Change::Expired(entry.value));
+        }
+    }
+
+    #[inline]
+    fn has_listeners(&self) -> bool {
+        !read(&self.listeners).is_empty()
+    }
+
+    fn notify(&self, key: &K, change: Change<V>) {
+        // Release the lock before calling listeners, they may register other listeners
+        let listeners = read(&self.listeners).clone();
+        for listener in listeners {
+            listener(key, &change);
+        }
+    }
+
+    #[inline]
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> &Shard<K, V> {
+        let hash = self.hasher.hash_one(key) as usize;
+        &self.shards[hash % self.shards.len()]
+    }
+}
+
+// Recover from poisoning: a panic in `Map::update` closure leaves the map consistent
+#[inline]
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|err| err.into_inner())
+}
+
+#[inline]
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|err| err.into_inner())
+}
+
+/// A publish/subscribe message bus between HAProxy threads.
+///
+/// Every subscriber receives its own copy of each message published after it subscribed.
+/// Subscribers that are dropped are removed automatically.
+///
+/// ```ignore
+/// static INVALIDATE: shared::Bus<String> = shared::Bus::new();
+///
+/// // In the module entry (runs in every per-thread Lua state)
+/// INVALIDATE.listen(lua, Duration::from_millis(100), |_, key| {
+///     CACHE.with_borrow_mut(|cache| cache.remove(&key));
+///     Ok(())
+/// })?;
+///
+/// // In any callback
+/// INVALIDATE.publish(key);
+/// ```
+pub struct Bus<T> {
+    subscribers: Mutex<Vec<Weak<Inbox<T>>>>,
+    capacity: usize,
+}
+
+struct Inbox<T> {
+    queue: Mutex<VecDeque<T>>,
+    capacity: usize,
+    #[cfg(feature = "async")]
+    notify: tokio::sync::Notify,
+}
+
+/// A subscription to the [`Bus`] messages.
+pub struct Subscription<T>(Arc<Inbox<T>>);
+
+impl<T> fmt::Debug for Bus<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bus")
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Subscription<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("pending", &self.len())
+            .finish()
+    }
+}
+
+impl<T> Default for Bus<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Bus<T> {
+    /// Creates a new bus, where every subscriber keeps up to 1024 pending messages.
+    pub const fn new() -> Self {
+        Self::with_capacity(1024)
+    }
+
+    /// Creates a new bus, where every subscriber keeps up to `capacity` pending messages.
+    ///
+    /// When the limit is reached, the oldest message is dropped.
+    pub const fn with_capacity(capacity: usize) -> Self {
+        Bus {
+            subscribers: Mutex::new(Vec::new()),
+            capacity,
+        }
+    }
+
+    /// Subscribes to the messages published from now on.
+    pub fn subscribe(&self) -> Subscription<T> {
+        let inbox = Arc::new(Inbox {
+            queue: Mutex::new(VecDeque::new()),
+            capacity: self.capacity.max(1),
+            #[cfg(feature = "async")]
+            notify: tokio::sync::Notify::new(),
+        });
+        lock(&self.subscribers).push(Arc::downgrade(&inbox));
+        Subscription(inbox)
+    }
+
+    /// Publishes the message to all subscribers, returning their number.
+    pub fn publish(&self, msg: T) -> usize
+    where
+        T: Clone,
+    {
+        let mut subscribers = lock(&self.subscribers);
+        subscribers.retain(|inbox| inbox.strong_count() > 0);
+        let mut count = 0;
+        for inbox in subscribers.iter().filter_map(Weak::upgrade) {
+            inbox.push(msg.clone());
+            count += 1;
+        }
+        count
+    }
+
+    /// Subscribes in the current Lua state and registers a HAProxy task calling `f`
+    /// for every received message, checking for new messages every `interval`.
+    ///
+    /// Must be called during the module loading. With `lua-load-per-thread`, call it in every
+    /// thread state to deliver messages to all threads.
+    ///
+    /// Errors returned by `f` are logged, and the remaining messages are still delivered.
+    pub fn listen<F>(&self, lua: &Lua, interval: Duration, f: F) -> Result<()>
+    where
+        T: MaybeSend + 'static,
+        F: Fn(&Lua, T) -> Result<()> + MaybeSend + 'static,
+    {
+        let subscription = self.subscribe();
+        let receive = lua.create_function(move |lua, ()| {
+            for msg in subscription.drain() {
+                if let Err(err) = f(lua, msg) {
+                    let core = Core::new(lua)?;
+                    core.log(LogLevel::Err, format!("bus listener: {err}"))?;
+                }
+            }
+            Ok(())
+        })?;
+        let task: Function = lua
+            .load(
+                r#"
+                local receive, interval = ...
+                local msleep = core.msleep
+                return function()
+                    while true do
+                        receive()
+                        msleep(interval)
+                    end
+                end
+                "#,
+            )
+            .set_name("=bus_listen")
+            .call((receive, interval.as_millis().max(1) as u64))?;
+        Core::new(lua)?.call_function("register_task", task)
+    }
+}
+
+impl<T> Inbox<T> {
+    fn push(&self, msg: T) {
+        let mut queue = lock(&self.queue);
+        if queue.len() >= self.capacity {
+            queue.pop_front();
+        }
+        queue.push_back(msg);
+        #[cfg(feature = "async")]
+        self.notify.notify_one();
+    }
+}
+
+impl<T> Subscription<T> {
+    /// Returns the next pending message, if any.
+    pub fn try_recv(&self) -> Option<T> {
+        lock(&self.0.queue).pop_front()
+    }
+
+    /// Takes all pending messages.
+    pub fn drain(&self) -> Vec<T> {
+        lock(&self.0.queue).drain(..).collect()
+    }
+
+    /// Returns the number of pending messages.
+    pub fn len(&self) -> usize {
+        lock(&self.0.queue).len()
+    }
+
+    /// Returns true if there are no pending messages.
+    pub fn is_empty(&self) -> bool {
+        lock(&self.0.queue).is_empty()
+    }
+
+    /// Waits for the next message.
+    #[cfg(feature = "async")]
+    pub async fn recv(&self) -> T {
+        loop {
+            if let Some(msg) = self.try_recv() {
+                return msg;
+            }
+            self.0.notify.notified().await;
+        }
+    }
+}
+
+#[inline]
+fn lock<T>(lock: &Mutex<T>) -> MutexGuard<'_, T> {
+    lock.lock().unwrap_or_else(|err| err.into_inner())
+}
@@ -0,0 +1,250 @@
+//! Rates and deltas computed from the proxy and server statistics.
+//!
+//! HAProxy statistics are cumulative counters. [`StatsTracker`] snapshots them periodically
+//! from a HAProxy task and keeps the last few snapshots on the Rust side, so callbacks running
+//! on any thread can read the derived rates (eg. for circuit breaking or adaptive behaviors).
+//!
+//! ```ignore
+//! static TRACKER: LazyLock<StatsTracker> = LazyLock::new(|| {
+//!     StatsTracker::new(Duration::from_secs(1))
+//!         .windows(10)
+//!         .track("app")
+//!         .track("app/srv1")
+//! });
+//!
+//! TRACKER.register(&core)?;
+//! core.register_fetches("app_error_ratio", |_, ()| {
+//!     Ok(TRACKER.error_ratio("app", 5).unwrap_or(0.0))
+//! })?;
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use mlua::{Function, Lua, Result, Table, TableExt, Value};
+
+use crate::pairs::Pairs;
+use crate::{Core, Proxy};
+
+// Counters summed as the number of requests and errors
+const REQUEST_COUNTERS: [&str; 1] = ["req_tot"];
+const ERROR_COUNTERS: [&str; 3] = ["hrsp_5xx", "econ", "eresp"];
+
+/// Tracks the statistics of proxies and servers, computing rates over the last snapshots.
+///
+/// Targets are named `<proxy>` or `<backend>/<server>`.
+/// The tracker is cheap to clone, clones share the snapshots.
+#[derive(Clone)]
+pub struct StatsTracker {
+    interval: Duration,
+    windows: usize,
+    targets: Vec<String>,
+    state: Arc<State>,
+}
+
+#[derive(Default)]
+struct State {
+    history: RwLock<HashMap<String, VecDeque<Snapshot>>>,
+    sampling: AtomicBool,
+}
+
+struct Snapshot {
+    taken: Instant,
+    counters: HashMap<String, u64>,
+}
+
+impl fmt::Debug for StatsTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StatsTracker")
+            .field("interval", &self.interval)
+            .field("windows", &self.windows)
+            .field("targets", &self.targets)
+            .finish()
+    }
+}
+
+impl StatsTracker {
+    /// Creates a new tracker taking snapshots every `interval`.
+    ///
+    /// By default the last 10 windows are kept.
+    pub fn new(interval: Duration) -> Self {
+        StatsTracker {
+            interval,
+            windows: 10,
+            targets: Vec::new(),
+            state: Arc::default(),
+        }
+    }
+
+    /// Sets the number of windows (intervals between snapshots) to keep.
+    pub fn windows(mut self, windows: usize) -> Self {
+        self.windows = windows.max(1);
+        self
+    }
+
+    /// Adds a proxy (`<proxy>`) or server (`<backend>/<server>`) to track.
+    pub fn track(mut self, target: impl Into<String>) -> Self {
+        self.targets.push(target.into());
+        self
+    }
+
+    /// Registers a HAProxy task taking the snapshots.
+    ///
+    /// Statistics are process-wide, so with `lua-load-per-thread` only the first
+    /// registration starts the task and the other states just read the shared snapshots.
+    pub fn register(&self, core: &Core) -> Result<()> {
+        if self.state.sampling.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let lua = core.lua;
+        let this = self.clone();
+        let sample = lua.create_function(move |lua, ()| this.sample(lua))?;
+        let task: Function = lua
+            .load(
+                r#"
+                local sample, interval = ...
+                local msleep = core.msleep
+                return function()
+                    while true do
+                        sample()
+                        msleep(interval)
+                    end
+                end
+                "#,
+            )
+            .set_name("=stats_tracker")
+            .call((sample, self.interval.as_millis().max(1) as u64))?;
+        core.call_function("register_task", task)
+    }
+
+    /// Returns the increase of the `counter` over the last `windows`.
+    ///
+    /// Returns `None` if there are not enough snapshots yet, or the counter is not reported.
+    /// A counter reset (eg. after `clear counters`) is reported as no increase.
+    pub fn delta(&self, target: &str, counter: &str, windows: usize) -> Option<u64> {
+        self.span(target, windows, |first, last| {
+            let value = |s: &Snapshot| s.counters.get(counter).copied();
+            Some(value(last)?.saturating_sub(value(first)?))
+        })
+    }
+
+    /// Returns the per-second rate of the `counter` over the last `windows`.
+    pub fn rate(&self, target: &str, counter: &str, windows: usize) -> Option<f64> {
+        let delta = self.delta(target, counter, windows)?;
+        let elapsed = self.elapsed(target, windows)?;
+        Some(delta as f64 / elapsed.as_secs_f64())
+    }
+
+    /// Returns the number of HTTP requests per second over the last `windows`.
+    pub fn req_rate(&self, target: &str, windows: usize) -> Option<f64> {
+        let delta = self.sum_delta(target, &REQUEST_COUNTERS, windows)?;
+        let elapsed = self.elapsed(target, windows)?;
+        Some(delta as f64 / elapsed.as_secs_f64())
+    }
+
+    /// Returns the fraction (between 0 and 1) of the requests that failed over the last `windows`.
+    ///
+    /// Errors are the 5xx responses, connection errors and response errors.
+    /// Returns `None` if there were no requests.
+    pub fn error_ratio(&self, target: &str, windows: usize) -> Option<f64> {
+        let requests = self.sum_delta(target, &REQUEST_COUNTERS, windows)?;
+        let errors = self.sum_delta(target, &ERROR_COUNTERS, windows)?;
+        (requests > 0).then(|| (errors as f64 / requests as f64).min(1.0))
+    }
+
+    /// Returns the time since the last snapshot of the `target`.
+    pub fn last_updated(&self, target: &str) -> Option<Duration> {
+        let history = self
+            .state
+            .history
+            .read()
+            .unwrap_or_else(|err| err.into_inner());
+        Some(history.get(target)?.back()?.taken.elapsed())
+    }
+
+    fn sum_delta(&self, target: &str, counters: &[&str], windows: usize) -> Option<u64> {
+        let deltas = counters
+            .iter()
+            .filter_map(|c| self.delta(target, c, windows));
+        deltas.reduce(|a, b| a + b)
+    }
+
+    fn elapsed(&self, target: &str, windows: usize) -> Option<Duration> {
+        self.span(target, windows, |first, last| {
+            Some(last.taken - first.taken).filter(|d| !d.is_zero())
+        })
+    }
+
+    // Calls `f` with the first and last snapshots spanning the last `windows`
+    fn span<T>(
+        &self,
+        target: &str,
+        windows: usize,
+        f: impl FnOnce(&Snapshot, &Snapshot) -> Option<T>,
+    ) -> Option<T> {
+        let history = self
+            .state
+            .history
+            .read()
+            .unwrap_or_else(|err| err.into_inner());
+        let snapshots = history.get(target)?;
+        if snapshots.len() < 2 {
+            return None;
+        }
+        let windows = windows.clamp(1, snapshots.len() - 1);
+        let last = snapshots.back()?;
+        let first = snapshots.get(snapshots.len() - 1 - windows)?;
+        f(first, last)
+    }
+
+    fn sample(&self, lua: &Lua) -> Result<()> {
+        let core = Core::new(lua)?;
+        let proxies = core.get::<_, Table>("proxies")?;
+        let now = Instant::now();
+        let mut snapshots = Vec::with_capacity(self.targets.len());
+        for target in &self.targets {
+            let (proxy, server) = match target.split_once('/') {
+                Some((proxy, server)) => (proxy, Some(server)),
+                None => (target.as_str(), None),
+            };
+            let Some(proxy) = proxies.get::<_, Option<Proxy>>(proxy)? else {
+                continue;
+            };
+            let stats = match server {
+                Some(server) => match proxy.get_server(server)? {
+                    Some(server) => server.get_stats()?,
+                    None => continue,
+                },
+                None => proxy.get_stats()?,
+            };
+            let mut counters = HashMap::new();
+            for pair in Pairs::<String, Value>::new(lua, stats)? {
+                let (name, value) = pair?;
+                if let Some(value) = value.as_u64() {
+                    counters.insert(name, value);
+                }
+            }
+            snapshots.push((target, counters));
+        }
+
+        let mut history = self
+            .state
+            .history
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+        for (target, counters) in snapshots {
+            let snapshots = history.entry(target.clone()).or_default();
+            if snapshots.len() > self.windows {
+                snapshots.pop_front();
+            }
+            snapshots.push_back(Snapshot {
+                taken: now,
+                counters,
+            });
+        }
+        Ok(())
+    }
+}
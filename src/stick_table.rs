@@ -1,6 +1,14 @@
 use std::ops::Deref;
 
-use mlua::{FromLua, Lua, Result, Table, TableExt, Value};
+use mlua::{FromLua, IntoLua, Lua, Result, Table, TableExt, Value};
+
+mod data_type;
+mod entry;
+mod info;
+
+pub use data_type::DataType;
+pub use entry::{DataValue, StickTableEntry};
+pub use info::{KeyType, StickTableInfo, StoredDataType};
 
 /// The "StickTable" class can be used to access the HAProxy stick tables.
 #[derive(Clone)]
@@ -10,12 +18,16 @@ pub struct StickTable<'lua> {
 
 impl<'lua> StickTable<'lua> {
     /// Returns stick table attributes as a Lua table.
+    ///
+    /// See [`StickTable::typed_info`] for the parsed version.
     #[inline]
     pub fn info(&self) -> Result<Table<'lua>> {
         self.class.call_method("info", ())
     }
 
     /// Returns stick table entry for given `key`.
+    ///
+    /// See [`StickTable::lookup_entry`] for the typed version.
     #[inline]
     pub fn lookup(&self, key: &str) -> Result<Table<'lua>> {
         self.class.call_method("lookup", key)
@@ -40,6 +52,13 @@ impl<'lua> FromLua<'lua> for StickTable<'lua> {
     }
 }
 
+impl<'lua> IntoLua<'lua> for StickTable<'lua> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(self.class))
+    }
+}
+
 impl<'lua> Deref for StickTable<'lua> {
     type Target = Table<'lua>;
 
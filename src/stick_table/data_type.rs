@@ -0,0 +1,125 @@
+use std::fmt;
+use std::str::FromStr;
+
+use mlua::Error;
+
+macro_rules! data_types {
+    ($($(#[$meta:meta])* $variant:ident => $name:literal,)*) => {
+        /// A stick table data type.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[non_exhaustive]
+        pub enum DataType {
+            $($(#[$meta])* $variant,)*
+        }
+
+        impl DataType {
+            /// Returns the data type name, as used in the HAProxy configuration.
+            pub const fn as_str(self) -> &'static str {
+                match self {
+                    $(DataType::$variant => $name,)*
+                }
+            }
+        }
+
+        impl FromStr for DataType {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($name => Ok(DataType::$variant),)*
+                    _ => Err(Error::runtime(format!("unknown stick table data type '{s}'"))),
+                }
+            }
+        }
+    };
+}
+
+data_types! {
+    /// The server ID (`server_id`).
+    ServerId => "server_id",
+    /// The general purpose tag 0 (`gpt0`).
+    Gpt0 => "gpt0",
+    /// The general purpose counter 0 (`gpc0`).
+    Gpc0 => "gpc0",
+    /// The general purpose counter 0 increment rate (`gpc0_rate`).
+    Gpc0Rate => "gpc0_rate",
+    /// The general purpose counter 1 (`gpc1`).
+    Gpc1 => "gpc1",
+    /// The general purpose counter 1 increment rate (`gpc1_rate`).
+    Gpc1Rate => "gpc1_rate",
+    /// The cumulative number of connections (`conn_cnt`).
+    ConnCnt => "conn_cnt",
+    /// The number of concurrent connections (`conn_cur`).
+    ConnCur => "conn_cur",
+    /// The incoming connection rate (`conn_rate`).
+    ConnRate => "conn_rate",
+    /// The cumulative number of sessions (`sess_cnt`).
+    SessCnt => "sess_cnt",
+    /// The incoming session rate (`sess_rate`).
+    SessRate => "sess_rate",
+    /// The cumulative number of HTTP requests (`http_req_cnt`).
+    HttpReqCnt => "http_req_cnt",
+    /// The HTTP request rate (`http_req_rate`).
+    HttpReqRate => "http_req_rate",
+    /// The cumulative number of HTTP errors (`http_err_cnt`).
+    HttpErrCnt => "http_err_cnt",
+    /// The HTTP error rate (`http_err_rate`).
+    HttpErrRate => "http_err_rate",
+    /// The cumulative number of bytes received from clients (`bytes_in_cnt`).
+    BytesInCnt => "bytes_in_cnt",
+    /// The incoming bytes rate (`bytes_in_rate`).
+    BytesInRate => "bytes_in_rate",
+    /// The cumulative number of bytes sent to clients (`bytes_out_cnt`).
+    BytesOutCnt => "bytes_out_cnt",
+    /// The outgoing bytes rate (`bytes_out_rate`).
+    BytesOutRate => "bytes_out_rate",
+    /// The server key (`server_key`).
+    ServerKey => "server_key",
+    /// The cumulative number of HTTP failures (`http_fail_cnt`).
+    HttpFailCnt => "http_fail_cnt",
+    /// The HTTP failure rate (`http_fail_rate`).
+    HttpFailRate => "http_fail_rate",
+    /// The array of general purpose tags (`gpt`).
+    Gpt => "gpt",
+    /// The array of general purpose counters (`gpc`).
+    Gpc => "gpc",
+    /// The array of general purpose counter increment rates (`gpc_rate`).
+    GpcRate => "gpc_rate",
+    /// The cumulative number of glitches (`glitch_cnt`).
+    GlitchCnt => "glitch_cnt",
+    /// The glitch rate (`glitch_rate`).
+    GlitchRate => "glitch_rate",
+    /// The server name (`server_name`).
+    ServerName => "server_name",
+}
+
+impl DataType {
+    /// Returns true if the data type is a rate measured over a period.
+    pub const fn is_rate(self) -> bool {
+        matches!(
+            self,
+            DataType::Gpc0Rate
+                | DataType::Gpc1Rate
+                | DataType::ConnRate
+                | DataType::SessRate
+                | DataType::HttpReqRate
+                | DataType::HttpErrRate
+                | DataType::BytesInRate
+                | DataType::BytesOutRate
+                | DataType::HttpFailRate
+                | DataType::GpcRate
+                | DataType::GlitchRate
+        )
+    }
+
+    /// Returns true if the data type is an array of values (eg. `gpc(3)`).
+    pub const fn is_array(self) -> bool {
+        matches!(self, DataType::Gpt | DataType::Gpc | DataType::GpcRate)
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use mlua::{Result, Table, TableExt, Value};
+
+use super::{DataType, StickTable};
+
+/// A value stored in a stick table entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataValue {
+    /// A counter, rate or tag.
+    Integer(u64),
+    /// A string (eg. `server_key`).
+    String(String),
+    /// An array of counters, rates or tags (eg. `gpc(3)`).
+    Array(Vec<u64>),
+}
+
+/// A stick table entry, as returned by [`StickTable::lookup`] and [`StickTable::dump`].
+///
+/// Data types unknown to this crate are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StickTableEntry {
+    values: HashMap<DataType, DataValue>,
+}
+
+macro_rules! getters {
+    ($($(#[$meta:meta])* $name:ident => $data_type:ident;)*) => {
+        impl StickTableEntry {
+            $(
+                $(#[$meta])*
+                #[inline]
+                pub fn $name(&self) -> Option<u64> {
+                    self.get(DataType::$data_type)
+                }
+            )*
+        }
+    };
+}
+
+impl StickTableEntry {
+    /// Returns the integer value of the `data_type`, if the entry has it.
+    pub fn get(&self, data_type: DataType) -> Option<u64> {
+        match self.values.get(&data_type)? {
+            DataValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the element `index` of the array `data_type` (eg. `gpc`), if the entry has it.
+    pub fn get_at(&self, data_type: DataType, index: usize) -> Option<u64> {
+        match self.values.get(&data_type)? {
+            DataValue::Array(values) => values.get(index).copied(),
+            _ => None,
+        }
+    }
+
+    /// Returns the raw value of the `data_type`.
+    pub fn value(&self, data_type: DataType) -> Option<&DataValue> {
+        self.values.get(&data_type)
+    }
+
+    /// Returns an iterator over the stored data types and values.
+    pub fn iter(&self) -> impl Iterator<Item = (DataType, &DataValue)> {
+        self.values.iter().map(|(k, v)| (*k, v))
+    }
+
+    /// Returns the server key (`server_key`).
+    pub fn server_key(&self) -> Option<&str> {
+        match self.values.get(&DataType::ServerKey)? {
+            DataValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the server name (`server_name`).
+    pub fn server_name(&self) -> Option<&str> {
+        match self.values.get(&DataType::ServerName)? {
+            DataValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn from_table(table: Table) -> Result<Self> {
+        let mut values = HashMap::new();
+        for pair in table.pairs::<String, Value>() {
+            let (name, value) = pair?;
+            let Ok(data_type) = name.parse::<DataType>() else {
+                continue;
+            };
+            let value = match value {
+                Value::Integer(i) => DataValue::Integer(i as u64),
+                Value::Number(n) => DataValue::Integer(n as u64),
+                Value::String(s) => DataValue::String(s.to_string_lossy().into_owned()),
+                Value::Table(t) => DataValue::Array(t.sequence_values().collect::<Result<_>>()?),
+                _ => continue,
+            };
+            values.insert(data_type, value);
+        }
+        Ok(StickTableEntry { values })
+    }
+}
+
+getters! {
+    /// Returns the server ID (`server_id`).
+    server_id => ServerId;
+    /// Returns the general purpose tag 0 (`gpt0`).
+    gpt0 => Gpt0;
+    /// Returns the general purpose counter 0 (`gpc0`).
+    gpc0 => Gpc0;
+    /// Returns the general purpose counter 0 increment rate (`gpc0_rate`).
+    gpc0_rate => Gpc0Rate;
+    /// Returns the general purpose counter 1 (`gpc1`).
+    gpc1 => Gpc1;
+    /// Returns the general purpose counter 1 increment rate (`gpc1_rate`).
+    gpc1_rate => Gpc1Rate;
+    /// Returns the cumulative number of connections (`conn_cnt`).
+    conn_cnt => ConnCnt;
+    /// Returns the number of concurrent connections (`conn_cur`).
+    conn_cur => ConnCur;
+    /// Returns the incoming connection rate (`conn_rate`).
+    conn_rate => ConnRate;
+    /// Returns the cumulative number of sessions (`sess_cnt`).
+    sess_cnt => SessCnt;
+    /// Returns the incoming session rate (`sess_rate`).
+    sess_rate => SessRate;
+    /// Returns the cumulative number of HTTP requests (`http_req_cnt`).
+    http_req_cnt => HttpReqCnt;
+    /// Returns the HTTP request rate (`http_req_rate`).
+    http_req_rate => HttpReqRate;
+    /// Returns the cumulative number of HTTP errors (`http_err_cnt`).
+    http_err_cnt => HttpErrCnt;
+    /// Returns the HTTP error rate (`http_err_rate`).
+    http_err_rate => HttpErrRate;
+    /// Returns the cumulative number of HTTP failures (`http_fail_cnt`).
+    http_fail_cnt => HttpFailCnt;
+    /// Returns the HTTP failure rate (`http_fail_rate`).
+    http_fail_rate => HttpFailRate;
+    /// Returns the cumulative number of bytes received from clients (`bytes_in_cnt`).
+    bytes_in_cnt => BytesInCnt;
+    /// Returns the incoming bytes rate (`bytes_in_rate`).
+    bytes_in_rate => BytesInRate;
+    /// Returns the cumulative number of bytes sent to clients (`bytes_out_cnt`).
+    bytes_out_cnt => BytesOutCnt;
+    /// Returns the outgoing bytes rate (`bytes_out_rate`).
+    bytes_out_rate => BytesOutRate;
+    /// Returns the cumulative number of glitches (`glitch_cnt`).
+    glitch_cnt => GlitchCnt;
+    /// Returns the glitch rate (`glitch_rate`).
+    glitch_rate => GlitchRate;
+}
+
+impl<'lua> StickTable<'lua> {
+    /// Returns the typed stick table entry for the given `key`, or `None` if it does not exist.
+    pub fn lookup_entry(&self, key: &str) -> Result<Option<StickTableEntry>> {
+        match self.class.call_method::<_, Option<Table>>("lookup", key)? {
+            Some(table) => StickTableEntry::from_table(table).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns all typed entries in the stick table, keyed by the entry key.
+    ///
+    /// See [`StickTable::dump`] for the `filter` format.
+    pub fn dump_entries(&self, filter: Option<&str>) -> Result<HashMap<String, StickTableEntry>> {
+        let entries = self.dump(filter)?;
+        let mut result = HashMap::new();
+        for pair in entries.pairs::<String, Table>() {
+            let (key, table) = pair?;
+            result.insert(key, StickTableEntry::from_table(table)?);
+        }
+        Ok(result)
+    }
+}
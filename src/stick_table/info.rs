@@ -0,0 +1,136 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use mlua::{Error, Result, Table};
+
+use super::{DataType, StickTable};
+
+/// The type of the stick table keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyType {
+    /// IPv4 addresses (`ip`).
+    Ip,
+    /// IPv6 addresses (`ipv6`).
+    Ipv6,
+    /// 32-bit integers (`integer`).
+    Integer,
+    /// Strings (`string`).
+    String,
+    /// Binary blocks (`binary`).
+    Binary,
+}
+
+impl KeyType {
+    /// Returns the key type name, as used in the HAProxy configuration.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            KeyType::Ip => "ip",
+            KeyType::Ipv6 => "ipv6",
+            KeyType::Integer => "integer",
+            KeyType::String => "string",
+            KeyType::Binary => "binary",
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ip" => Ok(KeyType::Ip),
+            "ipv6" => Ok(KeyType::Ipv6),
+            "integer" => Ok(KeyType::Integer),
+            "string" => Ok(KeyType::String),
+            "binary" => Ok(KeyType::Binary),
+            _ => Err(Error::runtime(format!("unknown stick table type '{s}'"))),
+        }
+    }
+}
+
+/// A data type stored in the stick table, with its parameter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredDataType {
+    /// The data type name.
+    pub name: String,
+    /// The data type, or `None` if not known to this crate.
+    pub data_type: Option<DataType>,
+    /// The declared parameter: the period (in milliseconds) for rates,
+    /// the number of elements for arrays.
+    pub param: u64,
+}
+
+impl StoredDataType {
+    /// Returns the period the rate is measured over.
+    pub fn period(&self) -> Option<Duration> {
+        (self.data_type.is_some_and(DataType::is_rate)).then(|| Duration::from_millis(self.param))
+    }
+}
+
+/// The stick table attributes, parsed from [`StickTable::info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StickTableInfo {
+    /// The type of the keys.
+    pub key_type: KeyType,
+    /// The key length (for `string` and `binary` keys).
+    pub length: u64,
+    /// The maximum number of entries.
+    pub size: u64,
+    /// The number of entries in use.
+    pub used: u64,
+    /// The entries expiration delay.
+    pub expire: Option<Duration>,
+    /// True if the oldest entries are not purged when the table is full.
+    pub nopurge: bool,
+    /// The stored data types.
+    pub data: Vec<StoredDataType>,
+}
+
+impl StickTableInfo {
+    /// Returns the stored data type info, if the table has it.
+    pub fn data_type(&self, data_type: DataType) -> Option<&StoredDataType> {
+        self.data.iter().find(|d| d.data_type == Some(data_type))
+    }
+
+    /// Returns true if the table stores the `data_type`.
+    pub fn has(&self, data_type: DataType) -> bool {
+        self.data_type(data_type).is_some()
+    }
+}
+
+impl<'lua> StickTable<'lua> {
+    /// Returns typed stick table attributes.
+    pub fn typed_info(&self) -> Result<StickTableInfo> {
+        let info = self.info()?;
+        let key_type: String = info.get("type")?;
+        let expire: u64 = info.get::<_, Option<_>>("expire")?.unwrap_or(0);
+        let mut data = Vec::new();
+        if let Some(types) = info.get::<_, Option<Table>>("data")? {
+            for pair in types.pairs::<String, Option<u64>>() {
+                let (name, param) = pair?;
+                data.push(StoredDataType {
+                    data_type: name.parse().ok(),
+                    name,
+                    param: param.unwrap_or(0),
+                });
+            }
+        }
+        data.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(StickTableInfo {
+            key_type: key_type.parse()?,
+            length: info.get::<_, Option<_>>("length")?.unwrap_or(0),
+            size: info.get::<_, Option<_>>("size")?.unwrap_or(0),
+            used: info.get::<_, Option<_>>("used")?.unwrap_or(0),
+            expire: (expire > 0).then(|| Duration::from_millis(expire)),
+            nopurge: info.get::<_, Option<_>>("nopurge")?.unwrap_or(false),
+            data,
+        })
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt::Write as _;
+
+use mlua::{Result, String as LuaString};
+
+use crate::Txn;
+
+/// Information about the TLS session of the client connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// The protocol version, eg. `TLSv1.3` (`ssl_fc_protocol`).
+    pub protocol: String,
+    /// The cipher name (`ssl_fc_cipher`).
+    pub cipher: String,
+    /// The number of bits used by the cipher (`ssl_fc_use_keysize`).
+    pub cipher_bits: Option<u32>,
+    /// The negotiated ALPN protocol (`ssl_fc_alpn`).
+    pub alpn: Option<String>,
+    /// The server name indication sent by the client (`ssl_fc_sni`).
+    pub sni: Option<String>,
+    /// True if the session was resumed (`ssl_fc_is_resumed`).
+    pub session_reused: bool,
+    /// The client hello fields, available when `tune.ssl.capture-buffer-size` is set.
+    pub client_hello: Option<ClientHello>,
+}
+
+/// Fields of the TLS client hello message (GREASE values excluded), as used by JA3.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHello {
+    /// The protocol version (`ssl_fc_protocol_hello_id`).
+    pub version: u16,
+    /// The cipher suites (`ssl_fc_cipherlist_bin(1)`).
+    pub ciphers: Vec<u16>,
+    /// The extensions (`ssl_fc_extlist_bin(1)`).
+    pub extensions: Vec<u16>,
+    /// The supported elliptic curves (`ssl_fc_eclist_bin(1)`).
+    pub curves: Vec<u16>,
+    /// The elliptic curve point formats (`ssl_fc_ecformats_bin`).
+    pub point_formats: Vec<u8>,
+}
+
+impl ClientHello {
+    /// Returns the JA3 fingerprint string (before hashing).
+    pub fn ja3_string(&self) -> String {
+        fn join<T: ToString>(out: &mut String, values: impl IntoIterator<Item = T>) {
+            for (i, value) in values.into_iter().enumerate() {
+                if i > 0 {
+                    out.push('-');
+                }
+                out.push_str(&value.to_string());
+            }
+        }
+
+        let mut out = String::new();
+        let _ = write!(out, "{},", self.version);
+        join(&mut out, &self.ciphers);
+        out.push(',');
+        join(&mut out, &self.extensions);
+        out.push(',');
+        join(&mut out, &self.curves);
+        out.push(',');
+        join(&mut out, &self.point_formats);
+        out
+    }
+}
+
+impl<'lua> Txn<'lua> {
+    /// Returns information about the TLS session, or `None` if the client connection
+    /// does not use TLS.
+    pub fn tls_info(&self) -> Result<Option<TlsInfo>> {
+        if !self.f.ssl_fc()? {
+            return Ok(None);
+        }
+        Ok(Some(TlsInfo {
+            protocol: self.f.ssl_fc_protocol()?.unwrap_or_default(),
+            cipher: self.f.get_str("ssl_fc_cipher", ())?,
+            cipher_bits: self.f.get_opt("ssl_fc_use_keysize", ())?,
+            alpn: self.f.ssl_fc_alpn()?,
+            sni: self.f.ssl_fc_sni()?,
+            session_reused: self.f.get_opt("ssl_fc_is_resumed", ())?.unwrap_or(false),
+            client_hello: self.client_hello()?,
+        }))
+    }
+
+    fn client_hello(&self) -> Result<Option<ClientHello>> {
+        let Some(version) = self.f.get_opt::<_, u16>("ssl_fc_protocol_hello_id", ())? else {
+            return Ok(None);
+        };
+        let binary = |name, args| -> Result<Vec<u8>> {
+            let data: Option<LuaString> = self.f.get_opt(name, args)?;
+            Ok(data.map(|d| d.as_bytes().to_vec()).unwrap_or_default())
+        };
+        let to_u16 = |data: Vec<u8>| {
+            (data.chunks_exact(2))
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect::<Vec<_>>()
+        };
+        Ok(Some(ClientHello {
+            version,
+            ciphers: to_u16(binary("ssl_fc_cipherlist_bin", Some(1))?),
+            extensions: to_u16(binary("ssl_fc_extlist_bin", Some(1))?),
+            curves: to_u16(binary("ssl_fc_eclist_bin", Some(1))?),
+            point_formats: binary("ssl_fc_ecformats_bin", None)?,
+        }))
+    }
+}
@@ -0,0 +1,191 @@
+use std::error::Error as StdError;
+
+use bytes::Bytes;
+use futures_util::future::poll_fn;
+use http::header::{HeaderName, HeaderValue};
+use http::{response, Method, Request, Response, Uri};
+use mlua::{
+    Error, FromLua, Function, IntoLua, Lua, Result, String as LuaString, Table, TableExt, Value,
+};
+use tower_service::Service;
+
+use crate::build_info::ComponentKind;
+use crate::http_interop::parse_version;
+use crate::Core;
+
+// The request passed from the applet to the async function
+pub(crate) struct RawRequest {
+    method: String,
+    path: String,
+    qs: Option<String>,
+    version: String,
+    headers: Vec<(LuaBytes, LuaBytes)>,
+    body: Vec<u8>,
+}
+
+// The response passed back to the applet
+struct RawResponse(Response<Bytes>);
+
+struct LuaBytes(Vec<u8>);
+
+impl<'lua> FromLua<'lua> for LuaBytes {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        Ok(LuaBytes(
+            LuaString::from_lua(value, lua)?.as_bytes().to_vec(),
+        ))
+    }
+}
+
+impl<'lua> FromLua<'lua> for RawRequest {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let table = Table::from_lua(value, lua)?;
+        let body: Option<LuaBytes> = table.get("body")?;
+        let headers = (table.get::<_, Vec<Table>>("headers")?.into_iter())
+            .map(|header| Ok((header.get(1)?, header.get(2)?)))
+            .collect::<Result<_>>()?;
+        Ok(RawRequest {
+            method: table.get("method")?,
+            path: table.get("path")?,
+            qs: table.get("qs")?,
+            version: table.get("version")?,
+            headers,
+            body: body.map(|body| body.0).unwrap_or_default(),
+        })
+    }
+}
+
+impl<'lua> IntoLua<'lua> for RawResponse {
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        let (parts, body) = self.0.into_parts();
+        let response = response_head(lua, &parts)?;
+        response.raw_set("body", lua.create_string(&body)?)?;
+        Ok(Value::Table(response))
+    }
+}
+
+impl RawRequest {
+    pub(crate) fn into_request(self) -> Result<Request<Bytes>> {
+        let uri = match self.qs.filter(|qs| !qs.is_empty()) {
+            Some(qs) => format!("{}?{qs}", self.path),
+            None => self.path,
+        };
+        let mut request = Request::builder()
+            .method(Method::from_bytes(self.method.as_bytes()).map_err(Error::external)?)
+            .uri(Uri::try_from(uri).map_err(Error::external)?)
+            .version(parse_version(&self.version)?)
+            .body(Bytes::from(self.body))
+            .map_err(Error::external)?;
+        let headers = request.headers_mut();
+        for (name, value) in self.headers {
+            let name = HeaderName::from_bytes(&name.0).map_err(Error::external)?;
+            let value = HeaderValue::from_bytes(&value.0).map_err(Error::external)?;
+            headers.append(name, value);
+        }
+        Ok(request)
+    }
+}
+
+// Creates a table with the response status and the list of headers
+pub(crate) fn response_head<'lua>(lua: &'lua Lua, parts: &response::Parts) -> Result<Table<'lua>> {
+    let headers = lua.create_table_with_capacity(parts.headers.len(), 0)?;
+    for (name, value) in &parts.headers {
+        let value = lua.create_string(value.as_bytes())?;
+        headers.raw_push([lua.create_string(name.as_str())?, value])?;
+    }
+    let head = lua.create_table()?;
+    head.raw_set("status", parts.status.as_u16())?;
+    head.raw_set("headers", headers)?;
+    Ok(head)
+}
+
+// Waits for the service readiness and calls it with the request
+pub(crate) async fn call_service<S, B>(mut service: S, req: RawRequest) -> Result<Response<B>>
+where
+    S: Service<Request<Bytes>, Response = Response<B>>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let request = req.into_request()?;
+    poll_fn(|cx| service.poll_ready(cx))
+        .await
+        .map_err(|err| Error::external(err.into()))?;
+    (service.call(request).await).map_err(|err| Error::external(err.into()))
+}
+
+// Returns a Lua function collecting the applet request into a table (read as `RawRequest`)
+pub(crate) fn read_request(lua: &Lua) -> Result<Function<'_>> {
+    lua.load(
+        r#"
+        return function(applet)
+            local headers = {}
+            for name, values in pairs(applet.headers) do
+                for i = 0, #values do
+                    headers[#headers + 1] = { name, values[i] }
+                end
+            end
+            return {
+                method = applet.method,
+                path = applet.path,
+                qs = applet.qs,
+                version = applet.version,
+                headers = headers,
+                body = applet:receive(),
+            }
+        end
+        "#,
+    )
+    .set_name("=read_request")
+    .call(())
+}
+
+impl<'lua> Core<'lua> {
+    /// Registers a [`tower_service::Service`] executed as an HTTP service.
+    ///
+    /// The request is assembled from the applet headers and the whole body, the service
+    /// runs on the async runtime and the response is sent back to the client.
+    /// The service is cloned for every request (wrap it in a `Buffer` if cloning is expensive).
+    /// Service errors are logged and reported as `500 Internal Server Error`.
+    ///
+    /// All the registered service can be used in HAProxy with the prefix `lua.`.
+    pub fn register_tower_service<S>(&self, name: &str, service: S) -> Result<()>
+    where
+        S: Service<Request<Bytes>, Response = Response<Bytes>> + Clone + Send + Sync + 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        S::Future: Send + 'static,
+    {
+        let call = crate::r#async::create_async_function(self.lua, move |req: RawRequest| {
+            let service = service.clone();
+            async move { Ok(RawResponse(call_service(service, req).await?)) }
+        })?;
+        let func: Function = self
+            .lua
+            .load(
+                r#"
+                local call, read_request, name = ...
+                return function(applet)
+                    local ok, res = pcall(call, read_request(applet))
+                    if not ok then
+                        core.log(core.err, "service '" .. name .. "': " .. tostring(res))
+                        res = { status = 500, headers = {}, body = "Internal Server Error\n" }
+                    end
+                    applet:set_status(res.status)
+                    local has_length = false
+                    for _, header in ipairs(res.headers) do
+                        has_length = has_length or header[1] == "content-length"
+                        applet:add_header(header[1], header[2])
+                    end
+                    if not has_length then
+                        applet:add_header("content-length", #res.body)
+                    end
+                    applet:start_response()
+                    if #res.body > 0 then
+                        applet:send(res.body)
+                    end
+                end
+                "#,
+            )
+            .set_name("=tower_service")
+            .call((call, read_request(self.lua)?, name))?;
+        self.track_registration(ComponentKind::Service, name)?;
+        self.call_function("register_service", (name, "http", func))
+    }
+}
@@ -0,0 +1,208 @@
+//! [`tracing`] subscriber layer forwarding events to the HAProxy log.
+//!
+//! Events are rendered as `[target] span{field=value}:span: message field=value` and logged
+//! through `core.log()` by a HAProxy task (events can be emitted from any thread).
+//! Events emitted inside of [`with_txn`] are logged through `txn.log()` instead, when the
+//! scope ends.
+//!
+//! ```ignore
+//! #[mlua::lua_module(skip_memory_check)]
+//! fn haproxy_my_module(lua: &Lua) -> Result<bool> {
+//!     haproxy_api::tracing::init(lua, LevelFilter::INFO)?;
+//!
+//!     Core::new(lua)?.register_action("check", &[Action::HttpReq], 0, |_, txn: Txn| {
+//!         haproxy_api::tracing::with_txn(&txn, || {
+//!             tracing::info!(path = "/", "checking request");
+//!         });
+//!         Ok(())
+//!     })?;
+//!     Ok(true)
+//! }
+//! ```
+//!
+//! [`tracing`]: https://docs.rs/tracing
+
+use std::cell::RefCell;
+use std::fmt::{self, Write as _};
+
+use mlua::{Error, Lua, Result};
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::{Dispatch, Event, Level, LevelFilter, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::{deferred_log, LogLevel, Txn};
+
+thread_local! {
+    // Stack of messages collected in the `with_txn` scopes
+    static TXN_SCOPE: RefCell<Vec<Vec<(LogLevel, String)>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A [`Layer`] that forwards events to the HAProxy log.
+#[derive(Debug, Clone, Default)]
+pub struct HaproxyLayer {
+    _priv: (),
+}
+
+impl HaproxyLayer {
+    /// Creates a new layer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// Rendered fields of a span, stored in the span extensions
+struct SpanFields(String);
+
+#[derive(Default)]
+struct FieldsVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for FieldsVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+            return;
+        }
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+        let _ = write!(self.fields, "{}={value:?}", field.name());
+    }
+}
+
+impl<S> Layer<S> for HaproxyLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut visitor = FieldsVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanFields(visitor.fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() else {
+            return;
+        };
+        let mut visitor = FieldsVisitor {
+            fields: std::mem::take(fields),
+            ..Default::default()
+        };
+        values.record(&mut visitor);
+        *fields = visitor.fields;
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut msg = format!("[{}] ", metadata.target());
+        if let Some(scope) = ctx.event_scope(event) {
+            for (i, span) in scope.from_root().enumerate() {
+                if i > 0 {
+                    msg.push(':');
+                }
+                msg.push_str(span.name());
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    if !fields.is_empty() {
+                        let _ = write!(msg, "{{{fields}}}");
+                    }
+                }
+            }
+            msg.push_str(": ");
+        }
+        let mut visitor = FieldsVisitor::default();
+        event.record(&mut visitor);
+        msg.push_str(&visitor.message);
+        if !visitor.fields.is_empty() {
+            if !visitor.message.is_empty() {
+                msg.push(' ');
+            }
+            msg.push_str(&visitor.fields);
+        }
+
+        let level = log_level(*metadata.level());
+        let msg = TXN_SCOPE.with(|scope| match scope.try_borrow_mut() {
+            Ok(mut scope) => match scope.last_mut() {
+                Some(messages) => {
+                    messages.push((level, msg));
+                    None
+                }
+                None => Some(msg),
+            },
+            Err(_) => Some(msg),
+        });
+        if let Some(msg) = msg {
+            deferred_log::push(level, msg);
+        }
+    }
+}
+
+/// Maps the [`tracing`] level to the HAProxy log level.
+///
+/// [`tracing`]: https://docs.rs/tracing
+pub fn log_level(level: Level) -> LogLevel {
+    match level {
+        Level::ERROR => LogLevel::Err,
+        Level::WARN => LogLevel::Warning,
+        Level::INFO => LogLevel::Info,
+        Level::DEBUG | Level::TRACE => LogLevel::Debug,
+    }
+}
+
+/// Runs `f` collecting events emitted on the current thread, then logs them through `txn.log()`.
+///
+/// Events are logged when `f` returns, so they get the transaction log context (eg. log format
+/// and log servers of the proxy).
+pub fn with_txn<R>(txn: &Txn, f: impl FnOnce() -> R) -> R {
+    struct Guard;
+
+    impl Drop for Guard {
+        // Called only on unwinding, do not lose the collected messages
+        fn drop(&mut self) {
+            for (level, msg) in pop_scope() {
+                deferred_log::push(level, msg);
+            }
+        }
+    }
+
+    TXN_SCOPE.with(|scope| scope.borrow_mut().push(Vec::new()));
+    let guard = Guard;
+    let result = f();
+    std::mem::forget(guard);
+    for (level, msg) in pop_scope() {
+        if txn.log(level, &msg).is_err() {
+            deferred_log::push(level, msg);
+        }
+    }
+    result
+}
+
+fn pop_scope() -> Vec<(LogLevel, String)> {
+    TXN_SCOPE.with(|scope| scope.borrow_mut().pop().unwrap_or_default())
+}
+
+/// Installs a global subscriber with the [`HaproxyLayer`] and the maximum `level`.
+///
+/// Must be called during the module loading, as it registers a HAProxy task.
+pub fn init(lua: &Lua, level: LevelFilter) -> Result<()> {
+    let subscriber = tracing_subscriber::registry()
+        .with(level)
+        .with(HaproxyLayer::new());
+    tracing_core::dispatcher::set_global_default(Dispatch::new(subscriber))
+        .map_err(|_| Error::runtime("another tracing subscriber is already installed"))?;
+    deferred_log::register_flush_task(lua)
+}
@@ -0,0 +1,60 @@
+use mlua::{Error, Function, Result, TableExt};
+
+use crate::intern::header_name;
+use crate::{Headers, HttpMessage};
+
+impl<'lua> HttpMessage<'lua> {
+    /// Returns true if the running HAProxy exposes trailers of HTTP messages to Lua filters.
+    pub fn trailers_supported(&self) -> Result<bool> {
+        let get_trailers = self.get::<_, Option<Function>>("get_trailers")?;
+        Ok(get_trailers.is_some())
+    }
+
+    /// Returns a table containing all the trailers of the HTTP message.
+    ///
+    /// Trailers are available only when the end of message is reached.
+    /// Returns `None` before that or if the HAProxy version does not support trailers.
+    pub fn get_trailers(&self) -> Result<Option<Headers<'lua>>> {
+        if !self.trailers_supported()? || !self.eom()? {
+            return Ok(None);
+        }
+        self.call_method("get_trailers", ())
+    }
+
+    /// Replaces all occurrences of the trailer `name`, by only one containing the `value`.
+    ///
+    /// Returns an error if the HAProxy version does not support trailers.
+    pub fn set_trailer(&self, name: &str, value: impl AsRef<[u8]>) -> Result<()> {
+        self.trailer_method("set_trailer")?;
+        let name = header_name(self.lua, name)?;
+        let value = self.lua.create_string(value.as_ref())?;
+        self.call_method("set_trailer", (name, value))
+    }
+
+    /// Appends the trailer `name` with `value`.
+    ///
+    /// Returns an error if the HAProxy version does not support trailers.
+    pub fn add_trailer(&self, name: &str, value: impl AsRef<[u8]>) -> Result<()> {
+        self.trailer_method("add_trailer")?;
+        let name = header_name(self.lua, name)?;
+        let value = self.lua.create_string(value.as_ref())?;
+        self.call_method("add_trailer", (name, value))
+    }
+
+    /// Removes all occurrences of the trailer `name`.
+    ///
+    /// Returns an error if the HAProxy version does not support trailers.
+    pub fn del_trailer(&self, name: &str) -> Result<()> {
+        self.trailer_method("del_trailer")?;
+        self.call_method("del_trailer", header_name(self.lua, name)?)
+    }
+
+    fn trailer_method(&self, name: &str) -> Result<()> {
+        match self.get::<_, Option<Function>>(name)? {
+            Some(_) => Ok(()),
+            None => Err(Error::runtime(format!(
+                "HTTP trailers are not supported by this HAProxy version ('{name}' is missing)"
+            ))),
+        }
+    }
+}
@@ -2,11 +2,12 @@ use std::ops::Deref;
 
 use mlua::{FromLua, IntoLua, Lua, Result, Table, TableExt, Value};
 
-use crate::{Converters, Fetches, Http, HttpMessage, LogLevel};
+use crate::{Converters, Fetches, FilterHandle, Http, HttpMessage, LogLevel, Reply};
 
 /// The txn class contain all the functions relative to the http or tcp transaction.
 #[derive(Clone)]
 pub struct Txn<'lua> {
+    pub(crate) lua: &'lua Lua,
     class: Table<'lua>,
     pub c: Converters<'lua>,
     pub f: Fetches<'lua>,
@@ -59,28 +60,59 @@ impl<'lua> Txn<'lua> {
         self.class.call_method("set_priv", val)
     }
 
-    /// Returns data stored in the variable `name`.
+    /// Returns data stored in the variable `name` (eg. `txn.user` or [`Var`](crate::Var)).
+    ///
+    /// Returns `None` if the variable is not set.
     #[inline]
-    pub fn get_var<R: FromLua<'lua>>(&self, name: &str) -> Result<R> {
-        self.class.call_method("get_var", name)
+    pub fn get_var<R: FromLua<'lua>>(&self, name: impl AsRef<str>) -> Result<Option<R>> {
+        self.class.call_method("get_var", name.as_ref())
     }
 
     /// Store variable `name` in an HAProxy converting the type.
     #[inline]
-    pub fn set_var<A: IntoLua<'lua>>(&self, name: &str, val: A) -> Result<()> {
-        self.class.call_method("set_var", (name, val))
+    pub fn set_var<A: IntoLua<'lua>>(&self, name: impl AsRef<str>, val: A) -> Result<()> {
+        self.class.call_method("set_var", (name.as_ref(), val))
     }
 
     /// Store variable `name` in an HAProxy if the variable already exists.
+    /// This is HAProxy >=2.4 feature.
     #[inline]
-    pub fn set_var_if_exists<A: IntoLua<'lua>>(&self, name: &str, val: A) -> Result<()> {
-        self.class.call_method("set_var", (name, val, true))
+    pub fn set_var_if_exists<A: IntoLua<'lua>>(&self, name: impl AsRef<str>, val: A) -> Result<()> {
+        self.class
+            .call_method("set_var", (name.as_ref(), val, true))
     }
 
     /// Unsets the variable `name`.
     #[inline]
-    pub fn unset_var(&self, name: &str) -> Result<()> {
-        self.class.call_method("unset_var", name)
+    pub fn unset_var(&self, name: impl AsRef<str>) -> Result<()> {
+        self.class.call_method("unset_var", name.as_ref())
+    }
+
+    /// Returns a new reply object.
+    ///
+    /// The reply is empty and must be filled before passing it to [`Txn::done`].
+    #[inline]
+    pub fn reply(&self) -> Result<Reply<'lua>> {
+        self.class.call_method("reply", ())
+    }
+
+    /// Immediately stops the current transaction processing.
+    ///
+    /// If the `reply` is provided, it's sent to the client (HTTP mode only).
+    #[inline]
+    pub fn done(&self, reply: Option<Reply<'lua>>) -> Result<()> {
+        self.class.call_method("done", reply)
+    }
+
+    /// Returns a handle to the Lua table of the filter instance the callback is called for.
+    ///
+    /// Available only inside [`UserFilter`](crate::UserFilter) callbacks.
+    #[inline]
+    pub fn filter(&self) -> Option<FilterHandle<'lua>> {
+        match &self.r#priv {
+            Value::Table(t) => Some(FilterHandle(t.clone())),
+            _ => None,
+        }
     }
 
     /// Changes the log level of the current request.
@@ -96,6 +128,7 @@ impl<'lua> FromLua<'lua> for Txn<'lua> {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let class = Table::from_lua(value, lua)?;
         Ok(Txn {
+            lua,
             c: class.get("c")?,
             f: class.get("f")?,
             class,
@@ -0,0 +1,85 @@
+use mlua::{Error, IntoLuaMulti, LuaSerdeExt, Result, String as LuaString, Value};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Fetches, Txn};
+
+impl<'lua> Txn<'lua> {
+    /// Stores the `value` converted to Lua in the current transaction (see [`Txn::set_priv`]).
+    pub fn set_priv_serde<T: Serialize + ?Sized>(&self, value: &T) -> Result<()> {
+        self.set_priv(self.lua.to_value(value)?)
+    }
+
+    /// Returns data stored in the current transaction, converted from Lua to `T`.
+    ///
+    /// Returns `None` if nothing is stored.
+    pub fn get_priv_serde<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        match self.get_priv::<Value>()? {
+            Value::Nil => Ok(None),
+            value => self.lua.from_value(value).map(Some),
+        }
+    }
+
+    /// Sets the variable `name` to the `value` converted to Lua.
+    ///
+    /// HAProxy variables hold only scalar values (strings, numbers and booleans), so `T` must
+    /// serialize to one of them (eg. a number, string or unit enum variant).
+    /// Use [`Txn::set_var_json`] for structured values.
+    pub fn set_var_serde<T>(&self, name: impl AsRef<str>, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.set_var(name, self.lua.to_value(value)?)
+    }
+
+    /// Returns the variable `name` converted from Lua to `T`.
+    ///
+    /// Returns `None` if the variable is not set.
+    pub fn get_var_serde<T: DeserializeOwned>(&self, name: impl AsRef<str>) -> Result<Option<T>> {
+        match self.get_var::<Value>(name)? {
+            Some(value) => self.lua.from_value(value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the variable `name` holding a JSON string, deserialized to `T`.
+    ///
+    /// Returns `None` if the variable is not set.
+    pub fn get_var_json<T: DeserializeOwned>(&self, name: impl AsRef<str>) -> Result<Option<T>> {
+        match self.get_var::<LuaString>(name)? {
+            Some(data) => serde_json::from_slice(data.as_bytes())
+                .map(Some)
+                .map_err(Error::external),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the variable `name` to the `value` serialized as a JSON string.
+    ///
+    /// The variable can be read by other actions, or by the `json_query` converter.
+    pub fn set_var_json<T>(&self, name: impl AsRef<str>, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let data = serde_json::to_string(value).map_err(Error::external)?;
+        self.set_var(name, data)
+    }
+}
+
+impl<'lua> Fetches<'lua> {
+    /// Executes an internal haproxy sample fetch returning a JSON string, deserialized to `T`.
+    ///
+    /// Returns `None` if the sample is not available.
+    pub fn get_json<A, T>(&self, name: &str, args: A) -> Result<Option<T>>
+    where
+        A: IntoLuaMulti<'lua>,
+        T: DeserializeOwned,
+    {
+        match self.get_opt::<_, LuaString>(name, args)? {
+            Some(data) => serde_json::from_slice(data.as_bytes())
+                .map(Some)
+                .map_err(Error::external),
+            None => Ok(None),
+        }
+    }
+}
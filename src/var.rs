@@ -0,0 +1,191 @@
+use std::fmt;
+use std::str::FromStr;
+
+use mlua::{Error, Function, IntoLua, Result as LuaResult};
+
+use crate::Core;
+
+/// Scope of an HAProxy variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Shared by the whole process.
+    Proc,
+    /// Available for the whole session.
+    Sess,
+    /// Available for the whole transaction.
+    Txn,
+    /// Available during the request processing.
+    Req,
+    /// Available during the response processing.
+    Res,
+    /// Available during the health checks.
+    Check,
+}
+
+impl Scope {
+    /// Returns the scope prefix used in variable names.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Scope::Proc => "proc",
+            Scope::Sess => "sess",
+            Scope::Txn => "txn",
+            Scope::Req => "req",
+            Scope::Res => "res",
+            Scope::Check => "check",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "proc" => Ok(Scope::Proc),
+            "sess" => Ok(Scope::Sess),
+            "txn" => Ok(Scope::Txn),
+            "req" => Ok(Scope::Req),
+            "res" => Ok(Scope::Res),
+            "check" => Ok(Scope::Check),
+            _ => Err(Error::runtime(format!("invalid variable scope '{s}'"))),
+        }
+    }
+}
+
+/// An HAProxy variable name with its scope.
+///
+/// Can be passed to [`Txn::get_var`](crate::Txn::get_var) and other variable methods
+/// instead of a `"scope.name"` string.
+///
+/// ```ignore
+/// let user = Var::txn("user");
+/// txn.set_var(&user, "alice")?;
+/// let name: Option<String> = txn.get_var(&user)?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Var {
+    scope: Scope,
+    // Full name including the scope prefix
+    full_name: String,
+}
+
+impl Var {
+    /// Creates a variable `name` in the `scope`.
+    pub fn new(scope: Scope, name: impl AsRef<str>) -> Self {
+        let full_name = format!("{scope}.{}", name.as_ref());
+        Var { scope, full_name }
+    }
+
+    /// Creates a process-wide variable.
+    pub fn proc(name: impl AsRef<str>) -> Self {
+        Self::new(Scope::Proc, name)
+    }
+
+    /// Creates a session variable.
+    pub fn sess(name: impl AsRef<str>) -> Self {
+        Self::new(Scope::Sess, name)
+    }
+
+    /// Creates a transaction variable.
+    pub fn txn(name: impl AsRef<str>) -> Self {
+        Self::new(Scope::Txn, name)
+    }
+
+    /// Creates a request variable.
+    pub fn req(name: impl AsRef<str>) -> Self {
+        Self::new(Scope::Req, name)
+    }
+
+    /// Creates a response variable.
+    pub fn res(name: impl AsRef<str>) -> Self {
+        Self::new(Scope::Res, name)
+    }
+
+    /// Returns the variable scope.
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+
+    /// Returns the variable name without the scope.
+    pub fn name(&self) -> &str {
+        &self.full_name[self.scope.as_str().len() + 1..]
+    }
+
+    /// Returns the full variable name (eg. `txn.user`).
+    pub fn as_str(&self) -> &str {
+        &self.full_name
+    }
+}
+
+impl AsRef<str> for Var {
+    fn as_ref(&self) -> &str {
+        &self.full_name
+    }
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.full_name)
+    }
+}
+
+impl FromStr for Var {
+    type Err = Error;
+
+    /// Parses the full variable name (eg. `txn.user`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('.') {
+            Some((scope, name)) if !name.is_empty() => Ok(Var::new(scope.parse()?, name)),
+            _ => Err(Error::runtime(format!("invalid variable name '{s}'"))),
+        }
+    }
+}
+
+impl<'lua> Core<'lua> {
+    /// Returns the process-wide variable `name` (eg. `proc.mode` or [`Var::proc`]).
+    ///
+    /// Process variables can be shared between tasks and transactions.
+    /// Returns `None` if the variable is not set.
+    /// This is HAProxy >=2.6 feature.
+    #[cfg(feature = "haproxy26")]
+    pub fn get_var<R: mlua::FromLua<'lua>>(&self, name: impl AsRef<str>) -> LuaResult<Option<R>> {
+        let name = proc_var_name(name.as_ref())?;
+        self.var_function("get_var")?.call(name)
+    }
+
+    /// Sets the process-wide variable `name` (eg. `proc.mode` or [`Var::proc`]).
+    pub fn set_var<A: IntoLua<'lua>>(&self, name: impl AsRef<str>, val: A) -> LuaResult<()> {
+        let name = proc_var_name(name.as_ref())?;
+        self.var_function("set_var")?.call((name, val))
+    }
+
+    /// Unsets the process-wide variable `name`.
+    pub fn unset_var(&self, name: impl AsRef<str>) -> LuaResult<()> {
+        let name = proc_var_name(name.as_ref())?;
+        self.var_function("unset_var")?.call(name)
+    }
+
+    fn var_function(&self, name: &str) -> LuaResult<Function<'lua>> {
+        if !self.has_function(name)? {
+            let msg = format!("core.{name} is not supported by this HAProxy version");
+            return Err(Error::runtime(msg));
+        }
+        self.get(name)
+    }
+}
+
+// Only process variables are available outside of transactions
+fn proc_var_name(name: &str) -> LuaResult<&str> {
+    match name.strip_prefix("proc.") {
+        Some(n) if !n.is_empty() => Ok(name),
+        _ => Err(Error::runtime(format!(
+            "invalid variable '{name}': only 'proc.' variables are available outside of transactions"
+        ))),
+    }
+}
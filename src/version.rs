@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{PoisonError, RwLock};
+
+use mlua::{Error, Result, Table, TableExt, Value};
+
+use crate::{Core, Fetches, Proxy};
+
+// Results of the function probes, keyed by `class.name`.
+// HAProxy classes are the same in every Lua state, so the results are shared by all of them.
+static FUNCTION_PROBES: RwLock<BTreeMap<String, bool>> = RwLock::new(BTreeMap::new());
+
+/// HAProxy version (without the release suffix).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HaproxyVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl HaproxyVersion {
+    /// Creates a new version.
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        HaproxyVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses a version string as reported by HAProxy (eg. `2.8.5-1ppa1~jammy` or `3.1-dev4`).
+    ///
+    /// Missing minor or patch numbers are treated as `0`.
+    pub fn parse(version: &str) -> Option<Self> {
+        let end = version
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(version.len());
+        let mut parts = version[..end].split('.').filter(|s| !s.is_empty());
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().map_or(Some(0), |s| s.parse().ok())?;
+        let patch = parts.next().map_or(Some(0), |s| s.parse().ok())?;
+        Some(HaproxyVersion::new(major, minor, patch))
+    }
+
+    /// Returns true if this version is at least `major.minor`.
+    pub fn at_least(&self, major: u16, minor: u16) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+}
+
+impl fmt::Display for HaproxyVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Optional parts of the HAProxy Lua API, not available in every version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Capability {
+    /// `core.httpclient()` (HAProxy 2.5+).
+    HttpClient,
+    /// `core.queue()` (HAProxy 2.8+).
+    Queue,
+    /// `core.get_patref()` and the "Patref" class (HAProxy 3.2+).
+    Patref,
+    /// `core.event_sub()` (HAProxy 2.8+).
+    Events,
+    /// `core.thread` attribute (HAProxy 2.4+).
+    Thread,
+}
+
+impl Capability {
+    // Name of the `core` attribute that is present when the capability is supported
+    fn core_attr(self) -> &'static str {
+        match self {
+            Capability::HttpClient => "httpclient",
+            Capability::Queue => "queue",
+            Capability::Patref => "get_patref",
+            Capability::Events => "event_sub",
+            Capability::Thread => "thread",
+        }
+    }
+}
+
+impl<'lua> Core<'lua> {
+    /// Returns the version of the running HAProxy, parsed from `core.get_info()`.
+    pub fn haproxy_version(&self) -> Result<HaproxyVersion> {
+        let info: Table = self.call_function("get_info", ())?;
+        let version: String = info.get("Version")?;
+        HaproxyVersion::parse(&version)
+            .ok_or_else(|| Error::runtime(format!("cannot parse HAProxy version '{version}'")))
+    }
+
+    /// Returns true if the running HAProxy exposes the optional API `capability`.
+    ///
+    /// Capabilities are probed at runtime, which allows to degrade gracefully instead of
+    /// failing on missing functions.
+    pub fn supports(&self, capability: Capability) -> Result<bool> {
+        let value: Value = self.get(capability.core_attr())?;
+        Ok(!value.is_nil())
+    }
+
+    /// Returns true if the `core` class has the function `name` (eg. `httpclient`).
+    ///
+    /// The class is checked once, then the result is cached.
+    pub fn has_function(&self, name: &str) -> Result<bool> {
+        probe_function("core", name, || self.get(name))
+    }
+}
+
+impl<'lua> Proxy<'lua> {
+    /// Returns true if the "Proxy" class has the method `name` (eg. `get_mode`).
+    ///
+    /// The class is checked once, then the result is cached.
+    pub fn has_method(&self, name: &str) -> Result<bool> {
+        probe_function("Proxy", name, || self.get(name))
+    }
+}
+
+impl<'lua> Fetches<'lua> {
+    /// Returns true if HAProxy provides the sample fetch `name` (eg. `fc_rtt`).
+    ///
+    /// The class is checked once, then the result is cached.
+    pub fn has(&self, name: &str) -> Result<bool> {
+        probe_function("Fetches", name, || self.class.get(name))
+    }
+}
+
+fn probe_function<'lua>(
+    class: &str,
+    name: &str,
+    lookup: impl FnOnce() -> Result<Value<'lua>>,
+) -> Result<bool> {
+    let key = format!("{class}.{name}");
+    let probes = FUNCTION_PROBES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(&found) = probes.get(&key) {
+        return Ok(found);
+    }
+    drop(probes);
+    let found = matches!(lookup()?, Value::Function(_));
+    let mut probes = FUNCTION_PROBES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    probes.insert(key, found);
+    Ok(found)
+}
@@ -0,0 +1,537 @@
+//! Helpers to inspect and inject WebSocket traffic.
+//!
+//! After a successful `101 Switching Protocols` upgrade the connection carries
+//! WebSocket frames (RFC 6455) in both directions. [`FrameDecoder`] incrementally parses
+//! frames from channel data, and [`MessageAssembler`] reassembles fragmented messages.
+
+use mlua::{Error, Result};
+
+use crate::Channel;
+
+/// Default maximum size of a single frame payload (16 MiB).
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Default maximum size of a reassembled message (16 MiB).
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// WebSocket frame opcode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+    Reserved(u8),
+}
+
+impl Opcode {
+    fn from_u8(code: u8) -> Self {
+        match code {
+            0x0 => Opcode::Continuation,
+            0x1 => Opcode::Text,
+            0x2 => Opcode::Binary,
+            0x8 => Opcode::Close,
+            0x9 => Opcode::Ping,
+            0xA => Opcode::Pong,
+            code => Opcode::Reserved(code),
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xA,
+            Opcode::Reserved(code) => code & 0x0F,
+        }
+    }
+
+    /// Returns true if the opcode denotes a control frame (close, ping, pong).
+    pub fn is_control(self) -> bool {
+        self.as_u8() & 0x8 != 0
+    }
+}
+
+/// A single WebSocket frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Indicates that this is the final fragment of a message.
+    pub fin: bool,
+    /// Reserved bits (RSV1-3), used by extensions such as permessage-deflate.
+    pub rsv: u8,
+    pub opcode: Opcode,
+    /// The masking key. Frames sent by clients must be masked.
+    pub mask: Option<[u8; 4]>,
+    /// Unmasked payload data.
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Creates a new final frame with the given `opcode` and `payload`.
+    pub fn new(opcode: Opcode, payload: impl Into<Vec<u8>>) -> Self {
+        Frame {
+            fin: true,
+            rsv: 0,
+            opcode,
+            mask: None,
+            payload: payload.into(),
+        }
+    }
+
+    /// Creates a new text frame.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(Opcode::Text, text.into().into_bytes())
+    }
+
+    /// Creates a new binary frame.
+    pub fn binary(data: impl Into<Vec<u8>>) -> Self {
+        Self::new(Opcode::Binary, data)
+    }
+
+    /// Creates a new close frame with an optional status `code` and `reason`.
+    pub fn close(code: Option<u16>, reason: &str) -> Self {
+        let mut payload = Vec::new();
+        if let Some(code) = code {
+            payload.extend_from_slice(&code.to_be_bytes());
+            payload.extend_from_slice(reason.as_bytes());
+        }
+        Self::new(Opcode::Close, payload)
+    }
+
+    /// Sets the masking key (required for frames sent towards the server).
+    pub fn with_mask(mut self, mask: [u8; 4]) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Returns the status code and reason of a close frame.
+    pub fn close_reason(&self) -> Option<(u16, &[u8])> {
+        if self.opcode != Opcode::Close || self.payload.len() < 2 {
+            return None;
+        }
+        let code = u16::from_be_bytes([self.payload[0], self.payload[1]]);
+        Some((code, &self.payload[2..]))
+    }
+
+    /// Encodes the frame to the wire format, masking the payload if the mask is set.
+    pub fn encode(&self) -> Vec<u8> {
+        let len = self.payload.len();
+        let mut buf = Vec::with_capacity(14 + len);
+        buf.push((self.fin as u8) << 7 | (self.rsv & 0x7) << 4 | self.opcode.as_u8());
+        let mask_bit = (self.mask.is_some() as u8) << 7;
+        if len < 126 {
+            buf.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            buf.push(mask_bit | 126);
+            buf.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            buf.push(mask_bit | 127);
+            buf.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+        match self.mask {
+            Some(mask) => {
+                buf.extend_from_slice(&mask);
+                let start = buf.len();
+                buf.extend_from_slice(&self.payload);
+                apply_mask(&mut buf[start..], mask);
+            }
+            None => buf.extend_from_slice(&self.payload),
+        }
+        buf
+    }
+}
+
+/// Applies (or removes) the WebSocket masking key to the `data`.
+pub fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (i, b) in data.iter_mut().enumerate() {
+        *b ^= mask[i & 3];
+    }
+}
+
+/// Incremental decoder of WebSocket frames.
+///
+/// When fed from a channel, the decoder remembers how much data it has already copied,
+/// so the caller must report the data leaving the channel with [`FrameDecoder::forwarded`].
+#[derive(Debug)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    pos: usize,
+    // Bytes copied from the channel but still present in its buffer
+    unforwarded: usize,
+    max_frame_size: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    /// Creates a new decoder with the default maximum frame size.
+    pub fn new() -> Self {
+        Self::with_max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Creates a new decoder that rejects frames with payload larger than `max_frame_size` bytes.
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        FrameDecoder {
+            buf: Vec::new(),
+            pos: 0,
+            unforwarded: 0,
+            max_frame_size,
+        }
+    }
+
+    /// Appends a chunk of data to the decoder.
+    pub fn feed(&mut self, data: impl AsRef<[u8]>) {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(data.as_ref());
+    }
+
+    /// Copies the new incoming data of the channel into the decoder.
+    ///
+    /// The data are not removed from the channel. Data copied by the previous calls
+    /// and not forwarded yet are skipped.
+    ///
+    /// Returns the number of bytes added.
+    pub fn feed_channel(&mut self, chn: &Channel) -> Result<usize> {
+        let offset = self.unforwarded as isize;
+        let added = chn.data_with(Some(offset), None, |data| {
+            self.feed(data);
+            data.len()
+        })?;
+        self.unforwarded += added;
+        Ok(added)
+    }
+
+    /// Records that `len` bytes were forwarded or removed from the channel input.
+    pub fn forwarded(&mut self, len: usize) {
+        self.unforwarded = self.unforwarded.saturating_sub(len);
+    }
+
+    /// Returns the next complete frame, or `None` if more data is needed.
+    pub fn decode(&mut self) -> Result<Option<Frame>> {
+        let data = &self.buf[self.pos..];
+        if data.len() < 2 {
+            return Ok(None);
+        }
+        let fin = data[0] & 0x80 != 0;
+        let rsv = (data[0] >> 4) & 0x7;
+        let opcode = Opcode::from_u8(data[0] & 0x0F);
+        let masked = data[1] & 0x80 != 0;
+
+        let mut offset = 2;
+        let len = match data[1] & 0x7F {
+            126 => {
+                if data.len() < offset + 2 {
+                    return Ok(None);
+                }
+                offset += 2;
+                u16::from_be_bytes([data[2], data[3]]) as u64
+            }
+            127 => {
+                if data.len() < offset + 8 {
+                    return Ok(None);
+                }
+                offset += 8;
+                let mut len = [0; 8];
+                len.copy_from_slice(&data[2..10]);
+                u64::from_be_bytes(len)
+            }
+            len => len as u64,
+        };
+        if len > self.max_frame_size as u64 {
+            return Err(Error::runtime(format!(
+                "WebSocket frame is too large: {len} > {}",
+                self.max_frame_size
+            )));
+        }
+        if opcode.is_control() && (len > 125 || !fin) {
+            return Err(Error::runtime("invalid WebSocket control frame"));
+        }
+        let len = len as usize;
+
+        let mask = if masked {
+            if data.len() < offset + 4 {
+                return Ok(None);
+            }
+            let mask = [
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ];
+            offset += 4;
+            Some(mask)
+        } else {
+            None
+        };
+
+        if data.len() < offset + len {
+            return Ok(None);
+        }
+        let mut payload = data[offset..offset + len].to_vec();
+        if let Some(mask) = mask {
+            apply_mask(&mut payload, mask);
+        }
+        self.pos += offset + len;
+
+        Ok(Some(Frame {
+            fin,
+            rsv,
+            opcode,
+            mask,
+            payload,
+        }))
+    }
+
+    /// Returns the number of buffered bytes that do not form a complete frame yet.
+    pub fn pending(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+}
+
+/// A complete (reassembled) WebSocket message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// Opcode of the first frame of the message.
+    pub opcode: Opcode,
+    pub data: Vec<u8>,
+}
+
+impl Message {
+    /// Returns the message data as text, if it's a valid UTF-8 text message.
+    pub fn as_text(&self) -> Option<&str> {
+        match self.opcode {
+            Opcode::Text => std::str::from_utf8(&self.data).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Reassembles fragmented WebSocket messages.
+///
+/// Control frames can be interleaved with fragments and are returned immediately.
+#[derive(Debug)]
+pub struct MessageAssembler {
+    max_message_size: usize,
+    max_fragments: usize,
+    current: Option<(Opcode, Vec<u8>, usize)>,
+}
+
+impl Default for MessageAssembler {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_MESSAGE_SIZE, usize::MAX)
+    }
+}
+
+impl MessageAssembler {
+    /// Creates a new assembler with the given limits on the total message size
+    /// and the number of fragments per message.
+    pub fn new(max_message_size: usize, max_fragments: usize) -> Self {
+        MessageAssembler {
+            max_message_size,
+            max_fragments,
+            current: None,
+        }
+    }
+
+    /// Pushes a frame, returning a message when it's complete.
+    pub fn push(&mut self, frame: Frame) -> Result<Option<Message>> {
+        if frame.opcode.is_control() {
+            return Ok(Some(Message {
+                opcode: frame.opcode,
+                data: frame.payload,
+            }));
+        }
+
+        match (frame.opcode, self.current.as_mut()) {
+            (Opcode::Continuation, None) => {
+                return Err(Error::runtime("unexpected WebSocket continuation frame"));
+            }
+            (Opcode::Continuation, Some((_, data, fragments))) => {
+                *fragments += 1;
+                if *fragments > self.max_fragments {
+                    return Err(Error::runtime("too many WebSocket message fragments"));
+                }
+                if data.len() + frame.payload.len() > self.max_message_size {
+                    return Err(Error::runtime(format!(
+                        "WebSocket message is too large: > {}",
+                        self.max_message_size
+                    )));
+                }
+                data.extend_from_slice(&frame.payload);
+            }
+            (_, Some(_)) => {
+                return Err(Error::runtime("unfinished fragmented WebSocket message"));
+            }
+            (opcode, None) => {
+                if frame.payload.len() > self.max_message_size {
+                    return Err(Error::runtime(format!(
+                        "WebSocket message is too large: > {}",
+                        self.max_message_size
+                    )));
+                }
+                self.current = Some((opcode, frame.payload, 1));
+            }
+        }
+
+        if frame.fin {
+            let (opcode, data, _) = self.current.take().unwrap();
+            return Ok(Some(Message { opcode, data }));
+        }
+        Ok(None)
+    }
+
+    /// Returns true if a fragmented message is being assembled.
+    pub fn in_progress(&self) -> bool {
+        self.current.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_one(data: &[u8]) -> Result<Option<Frame>> {
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(data);
+        decoder.decode()
+    }
+
+    #[test]
+    fn test_decode_unmasked_and_masked() {
+        // Examples from RFC 6455, section 5.7
+        let frame = decode_one(&[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f])
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame, Frame::text("Hello"));
+
+        let masked = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let frame = decode_one(&masked).unwrap().unwrap();
+        assert_eq!(frame.payload, b"Hello");
+        assert_eq!(frame.mask, Some([0x37, 0xfa, 0x21, 0x3d]));
+        assert_eq!(frame.encode(), masked);
+    }
+
+    #[test]
+    fn test_payload_lengths() {
+        for (len, header_len) in [(0, 2), (125, 2), (126, 4), (65535, 4), (65536, 10)] {
+            let frame = Frame::binary(vec![0xAB; len]);
+            let data = frame.encode();
+            assert_eq!(data.len(), header_len + len, "length {len}");
+            assert_eq!(decode_one(&data).unwrap(), Some(frame.clone()));
+
+            let frame = frame.with_mask([1, 2, 3, 4]);
+            let data = frame.encode();
+            assert_eq!(data.len(), header_len + 4 + len, "masked length {len}");
+            assert_eq!(decode_one(&data).unwrap(), Some(frame));
+        }
+
+        let mut decoder = FrameDecoder::with_max_frame_size(10);
+        decoder.feed(Frame::binary(vec![0; 11]).encode());
+        assert!(decoder.decode().is_err());
+    }
+
+    #[test]
+    fn test_partial_input() {
+        let mut data = Frame::binary(vec![7; 300]).with_mask([9, 8, 7, 6]).encode();
+        data.extend(Frame::text("end").encode());
+
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        for byte in data {
+            decoder.feed([byte]);
+            while let Some(frame) = decoder.decode().unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].payload, vec![7; 300]);
+        assert_eq!(frames[1], Frame::text("end"));
+        assert_eq!(decoder.pending(), 0);
+    }
+
+    #[test]
+    fn test_fragmentation() {
+        // Fragmented "Hello" with a ping between the fragments
+        let mut first = Frame::text("Hel");
+        first.fin = false;
+        let mut last = Frame::new(Opcode::Continuation, "lo");
+        last.fin = true;
+
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(first.encode());
+        decoder.feed(Frame::new(Opcode::Ping, "ping").encode());
+        decoder.feed(last.encode());
+
+        let mut assembler = MessageAssembler::default();
+        let mut messages = Vec::new();
+        while let Some(frame) = decoder.decode().unwrap() {
+            if let Some(message) = assembler.push(frame).unwrap() {
+                messages.push(message);
+            }
+        }
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].opcode, Opcode::Ping);
+        assert_eq!(messages[1].as_text(), Some("Hello"));
+        assert!(!assembler.in_progress());
+
+        // Continuation without a started message
+        let mut assembler = MessageAssembler::default();
+        assert!(assembler.push(last.clone()).is_err());
+
+        // New data frame before the end of the fragmented message
+        let mut assembler = MessageAssembler::default();
+        assert_eq!(assembler.push(first.clone()).unwrap(), None);
+        assert!(assembler.in_progress());
+        assert!(assembler.push(Frame::text("x")).is_err());
+
+        // Fragment limits
+        let mut assembler = MessageAssembler::new(4, usize::MAX);
+        assert_eq!(assembler.push(first.clone()).unwrap(), None);
+        assert!(assembler.push(last.clone()).is_err());
+        let mut assembler = MessageAssembler::new(usize::MAX, 1);
+        assert_eq!(assembler.push(first).unwrap(), None);
+        assert!(assembler.push(last).is_err());
+    }
+
+    #[test]
+    fn test_control_frames() {
+        let mut ping = Frame::new(Opcode::Ping, "");
+        ping.fin = false;
+        assert!(decode_one(&ping.encode()).is_err());
+
+        let ping = Frame::new(Opcode::Ping, vec![0; 126]);
+        assert!(decode_one(&ping.encode()).is_err());
+
+        let ping = Frame::new(Opcode::Ping, vec![0; 125]);
+        assert_eq!(decode_one(&ping.encode()).unwrap(), Some(ping));
+
+        let close = decode_one(&Frame::close(Some(1000), "bye").encode())
+            .unwrap()
+            .unwrap();
+        assert_eq!(close.close_reason(), Some((1000, &b"bye"[..])));
+    }
+
+    #[test]
+    fn test_forwarded() {
+        let mut decoder = FrameDecoder::new();
+        decoder.unforwarded = 10;
+        decoder.forwarded(4);
+        assert_eq!(decoder.unforwarded, 6);
+        decoder.forwarded(100);
+        assert_eq!(decoder.unforwarded, 0);
+    }
+}